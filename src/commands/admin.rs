@@ -10,7 +10,9 @@ use serenity::{
 use std::env;
 use std::process::Command;
 use std::time::Duration;
-use crate::commands::search::{load_lm_config, get_http_client};
+use crate::commands::search::{load_lm_config, get_http_client, model_override, model_role_key, set_model_override, MODEL_ROLES};
+use crate::commands::sum::{clear_summary_cache, summary_cache_stats};
+use crate::commands::util::{config_sources, find_and_read_config, record_config_source, style_output};
 
 #[command]
 #[aliases("reboot", "restartbot")]
@@ -36,7 +38,8 @@ pub async fn restart(ctx: &Context, msg: &Message, _args: Args) -> CommandResult
     // Log the restart request
     println!("[ADMIN] Bot restart requested by owner {} ({})", msg.author.name, msg.author.id);
     
-    // Save contexts to disk before restart
+    // Let in-flight heavy commands finish, then save contexts to disk before restart
+    crate::wait_for_heavy_commands().await;
     {
         let data = ctx.data.read().await;
         let lm_contexts = data.get::<crate::LmContextMap>().cloned().unwrap_or_default();
@@ -130,7 +133,8 @@ pub async fn shutdown(ctx: &Context, msg: &Message, _args: Args) -> CommandResul
     // Log the shutdown request
     println!("[ADMIN] Bot shutdown requested by owner {} ({})", msg.author.name, msg.author.id);
     
-    // Save contexts to disk before shutdown
+    // Let in-flight heavy commands finish, then save contexts to disk before shutdown
+    crate::wait_for_heavy_commands().await;
     {
         let data = ctx.data.read().await;
         let lm_contexts = data.get::<crate::LmContextMap>().cloned().unwrap_or_default();
@@ -180,7 +184,11 @@ pub async fn adminhelp(ctx: &Context, msg: &Message, _args: Args) -> CommandResu
                     `^restart` - Restart the bot gracefully\n\
                     `^shutdown` - Shutdown the bot gracefully\n\
                     `^forcerestart` - Force restart the bot (immediate shutdown)\n\
+                    `^diag` - Show which config files were loaded and the effective LM settings\n\
+                    `^setmodel [<role> <model> [--save]]` - List or reassign the default/reason/summarization/ranking/vision models\n\
+                    `^sumcache stats|clear` - Inspect or purge the ^sum summary cache\n\
                     `^leaveserver` - Make the bot leave the current server\n\
+                    `^disable <command>` / `^enable <command>` - Turn a command off or on for this server (server admins too)\n\
                     `^adminhelp` - Show this help message\n\n\
                     **Note:** These commands can only be used by the bot owner.";
     
@@ -368,6 +376,7 @@ pub async fn diagnose(ctx: &Context, msg: &Message, _args: Args) -> CommandResul
         "temperature": 0.1
     });
     
+    crate::commands::util::wait_for_backend_rate_limit().await;
     let api_test = client
         .post(&api_url)
         .json(&test_payload)
@@ -472,6 +481,7 @@ pub async fn diagnose(ctx: &Context, msg: &Message, _args: Args) -> CommandResul
         "temperature": 0.1
     });
     
+    crate::commands::util::wait_for_backend_rate_limit().await;
     let perf_start = std::time::Instant::now();
     let perf_test = client
         .post(&api_url)
@@ -682,16 +692,433 @@ pub async fn leaveserver(ctx: &Context, msg: &Message, _args: Args) -> CommandRe
     Ok(())
 }
 
+#[command]
+#[aliases("whoami", "config")]
+/// Report the effective configuration (owner only)
+/// Shows which config files were actually loaded, the LM base URL and model roles, and the prefix
+/// The Discord token is never shown
+pub async fn diag(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    // Get the bot owner ID from configuration
+    let bot_owner_id = env::var("BOT_OWNER_ID").unwrap_or_else(|_| {
+        // Fallback to bot user ID if owner ID not set
+        env::var("BOT_USER_ID").unwrap_or_else(|_| "1385309017881968761".to_string())
+    });
+    
+    // Check if the user is the bot owner
+    if msg.author.id.to_string() != bot_owner_id {
+        msg.reply(ctx, "❌ **Access Denied**\nThis command can only be used by the bot owner.").await?;
+        return Ok(());
+    }
+
+    println!("[ADMIN] Config diagnostics requested by owner {} ({})", msg.author.name, msg.author.id);
+
+    // lmapiconf.txt may not have been read yet if no LM command has run; resolve it the same way
+    let mut sources = config_sources();
+    if !sources.iter().any(|(label, _)| label.starts_with("lmapiconf.txt")) {
+        if let Some((_, path)) = find_and_read_config("lmapiconf.txt") {
+            record_config_source("lmapiconf.txt (resolved by ^diag)", &path);
+            sources = config_sources();
+        }
+    }
+
+    let mut report = String::from("🩺 **Effective Configuration**\n\n**Config files loaded:**\n");
+    if sources.is_empty() {
+        report.push_str("• None recorded\n");
+    }
+    for (label, path) in &sources {
+        report.push_str(&format!("• {} → `{}`\n", label, path));
+    }
+
+    report.push_str(&format!(
+        "\n**Prefix:** `{}`\n**Discord token:** {}\n",
+        env::var("PREFIX").unwrap_or_else(|_| "^".to_string()),
+        if env::var("DISCORD_TOKEN").map(|t| !t.trim().is_empty()).unwrap_or(false) { "set (redacted)" } else { "not set" }
+    ));
+
+    // Report the LM settings from the file the ^lm loader uses
+    let lm_path = sources.iter()
+        .find(|(label, _)| label.starts_with("lmapiconf.txt (lm/vis)") || label.starts_with("lmapiconf.txt (resolved"))
+        .or_else(|| sources.iter().find(|(label, _)| label.starts_with("lmapiconf.txt")))
+        .map(|(_, path)| path.clone());
+    match lm_path.and_then(|path| std::fs::read_to_string(&path).ok()) {
+        Some(content) => {
+            let values: std::collections::HashMap<&str, &str> = content.lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .filter_map(|line| line.split_once('='))
+                .map(|(key, value)| (key.trim(), value.trim()))
+                .collect();
+            // Roles reassigned with ^setmodel win over the file until restart
+            let value = |key: &str| model_override(key)
+                .map(|model| format!("{} (runtime)", model))
+                .unwrap_or_else(|| values.get(key).copied().filter(|v| !v.is_empty()).unwrap_or("(not set)").to_string());
+            report.push_str(&format!(
+                "\n**LM API:**\n\
+                • Base URL: `{}`\n\
+                • Chat model: `{}`\n\
+                • Reason model: `{}`\n\
+                • Summarization model: `{}`\n\
+                • Ranking model: `{}`\n\
+                • Vision model: `{}`\n",
+                value("LM_STUDIO_BASE_URL"),
+                value("DEFAULT_MODEL"),
+                value("DEFAULT_REASON_MODEL"),
+                value("DEFAULT_SUMMARIZATION_MODEL"),
+                value("DEFAULT_RANKING_MODEL"),
+                value("DEFAULT_VISION_MODEL"),
+            ));
+        }
+        None => report.push_str("\n**LM API:** ⚠️ lmapiconf.txt not found in any search location\n"),
+    }
+
+    msg.reply(ctx, style_output(report)).await?;
+
+    Ok(())
+}
+
+#[command]
+#[aliases("switchmodel")]
+/// Reassign a model role at runtime (owner only)
+/// Usage: ^setmodel - list the current roles
+///        ^setmodel <role> <model> [--save] - roles: default, reason, summarization, ranking, vision
+/// The model must be loaded on the LM server; --save also writes it to lmapiconf.txt
+pub async fn setmodel(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    // Get the bot owner ID from configuration
+    let bot_owner_id = env::var("BOT_OWNER_ID").unwrap_or_else(|_| {
+        // Fallback to bot user ID if owner ID not set
+        env::var("BOT_USER_ID").unwrap_or_else(|_| "1385309017881968761".to_string())
+    });
+
+    // Check if the user is the bot owner
+    if msg.author.id.to_string() != bot_owner_id {
+        msg.reply(ctx, "❌ **Access Denied**\nThis command can only be used by the bot owner.").await?;
+        return Ok(());
+    }
+
+    let config = match load_lm_config().await {
+        Ok(config) => config,
+        Err(e) => {
+            msg.reply(ctx, style_output(format!("❌ Could not load lmapiconf.txt: {}", e))).await?;
+            return Ok(());
+        }
+    };
+    let current = |key: &str| match key {
+        "DEFAULT_MODEL" => config.default_model.clone(),
+        "DEFAULT_REASON_MODEL" => config.default_reason_model.clone(),
+        "DEFAULT_SUMMARIZATION_MODEL" => config.default_summarization_model.clone(),
+        "DEFAULT_RANKING_MODEL" => config.default_ranking_model.clone(),
+        _ => config.default_vision_model.clone(),
+    };
+
+    let tokens: Vec<&str> = args.message().split_whitespace().collect();
+    let save = tokens.iter().any(|token| *token == "--save");
+    let positional: Vec<&str> = tokens.into_iter().filter(|token| *token != "--save").collect();
+
+    if positional.is_empty() {
+        let mut report = String::from("🎛️ **Model Roles**\n");
+        for (role, key) in MODEL_ROLES.iter() {
+            let marker = if model_override(key).is_some() { " *(runtime)*" } else { "" };
+            report.push_str(&format!("• `{}` → `{}`{}\n", role, current(key), marker));
+        }
+        report.push_str("\nUsage: `^setmodel <role> <model> [--save]`");
+        msg.reply(ctx, style_output(report)).await?;
+        return Ok(());
+    }
+
+    let (role, model) = match positional.as_slice() {
+        [role, model] => (*role, *model),
+        _ => {
+            msg.reply(ctx, "❌ Usage: `^setmodel <role> <model> [--save]` (roles: default, reason, summarization, ranking, vision)").await?;
+            return Ok(());
+        }
+    };
+    let key = match model_role_key(role) {
+        Some(key) => key,
+        None => {
+            msg.reply(ctx, format!("❌ Unknown role `{}`. Roles: default, reason, summarization, ranking, vision", role)).await?;
+            return Ok(());
+        }
+    };
+
+    // Only accept models the server actually has
+    match fetch_model_ids(&config.base_url).await {
+        Ok(models) if models.iter().any(|id| id == model) => {}
+        Ok(models) => {
+            let listed: Vec<String> = models.iter().take(15).map(|id| format!("`{}`", id)).collect();
+            msg.reply(ctx, style_output(format!(
+                "❌ `{}` is not loaded on the LM server.\n**Available:** {}",
+                model,
+                if listed.is_empty() { "none".to_string() } else { listed.join(", ") }
+            ))).await?;
+            return Ok(());
+        }
+        Err(e) => {
+            msg.reply(ctx, style_output(format!("❌ Could not verify the model list: {}", e))).await?;
+            return Ok(());
+        }
+    }
+
+    let old_model = current(key);
+    set_model_override(key, model);
+    println!("[ADMIN] Model role {} ({}) changed from '{}' to '{}' by {} ({})", role, key, old_model, model, msg.author.name, msg.author.id);
+
+    let persistence = if save {
+        match save_config_value(key, model) {
+            Ok(path) => format!("Saved to `{}`.", path),
+            Err(e) => format!("⚠️ Active now, but saving to lmapiconf.txt failed: {}", e),
+        }
+    } else {
+        "Active until restart - add `--save` to write it to lmapiconf.txt.".to_string()
+    };
+    msg.reply(ctx, style_output(format!(
+        "✅ **Model role `{}` updated**\n• Old: `{}`\n• New: `{}`\n{}",
+        role.to_lowercase(), old_model, model, persistence
+    ))).await?;
+
+    Ok(())
+}
+
+/// Model IDs the LM server reports as available
+async fn fetch_model_ids(base_url: &str) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let client = get_http_client().await;
+    let response = client
+        .get(format!("{}/v1/models", base_url))
+        .timeout(Duration::from_secs(15))
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(format!("HTTP {}", response.status()).into());
+    }
+    let json = response.json::<serde_json::Value>().await?;
+    Ok(json.get("data")
+        .and_then(|data| data.as_array())
+        .map(|models| models.iter().filter_map(|m| m.get("id")?.as_str().map(str::to_string)).collect())
+        .unwrap_or_default())
+}
+
+/// Write one key to the lmapiconf.txt the loaders would pick up, returning its path
+fn save_config_value(key: &str, value: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let (content, path) = find_and_read_config("lmapiconf.txt")
+        .ok_or("lmapiconf.txt not found in any search location")?;
+    std::fs::write(&path, replace_config_value(&content, key, value))?;
+    Ok(path)
+}
+
+/// Replace the first uncommented `KEY=...` line, or append one when the key is missing
+fn replace_config_value(content: &str, key: &str, value: &str) -> String {
+    let mut replaced = false;
+    let mut lines: Vec<String> = content.lines().map(|line| {
+        let trimmed = line.trim_start_matches('\u{feff}').trim();
+        let is_key = !trimmed.starts_with('#')
+            && trimmed.split_once('=').map_or(false, |(existing, _)| existing.trim() == key);
+        if is_key && !replaced {
+            replaced = true;
+            format!("{}={}", key, value)
+        } else {
+            line.to_string()
+        }
+    }).collect();
+    if !replaced {
+        lines.push(format!("{}={}", key, value));
+    }
+    let mut updated = lines.join("\n");
+    if content.ends_with('\n') || !replaced {
+        updated.push('\n');
+    }
+    updated
+}
+
+#[command]
+#[aliases("summarycache")]
+/// Inspect or purge the ^sum summary cache (owner only)
+/// Usage: ^sumcache stats - entry count, disk size, ages and the most-hit URLs
+///        ^sumcache clear - delete every cached summary
+pub async fn sumcache(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    // Get the bot owner ID from configuration
+    let bot_owner_id = env::var("BOT_OWNER_ID").unwrap_or_else(|_| {
+        // Fallback to bot user ID if owner ID not set
+        env::var("BOT_USER_ID").unwrap_or_else(|_| "1385309017881968761".to_string())
+    });
+
+    // Check if the user is the bot owner
+    if msg.author.id.to_string() != bot_owner_id {
+        msg.reply(ctx, "❌ **Access Denied**\nThis command can only be used by the bot owner.").await?;
+        return Ok(());
+    }
+
+    match args.message().trim().to_lowercase().as_str() {
+        "" | "stats" => {
+            let stats = summary_cache_stats(5);
+            if stats.entries == 0 {
+                msg.reply(ctx, style_output("🗄️ The summary cache is empty.")).await?;
+                return Ok(());
+            }
+            let mut report = format!(
+                "🗄️ **Summary Cache**\n• Entries: {}\n• Size: {:.1} KiB\n• Total hits: {}\n• Oldest: {} ago\n• Newest: {} ago\n",
+                stats.entries,
+                stats.total_bytes as f64 / 1024.0,
+                stats.total_hits,
+                format_age(stats.oldest_age_secs.unwrap_or(0)),
+                format_age(stats.newest_age_secs.unwrap_or(0)),
+            );
+            report.push_str("\n**Most used:**\n");
+            for (url, model, age, hits) in &stats.top_entries {
+                report.push_str(&format!("• <{}> (`{}`) - {} hits, {} old\n", url, model, hits, format_age(*age)));
+            }
+            msg.reply(ctx, style_output(report)).await?;
+        }
+        "clear" => {
+            println!("[ADMIN] Summary cache clear requested by owner {} ({})", msg.author.name, msg.author.id);
+            match clear_summary_cache() {
+                Ok((entries, bytes)) => {
+                    msg.reply(ctx, style_output(format!("🗑️ Cleared {} cached summaries ({:.1} KiB).", entries, bytes as f64 / 1024.0))).await?;
+                }
+                Err(e) => {
+                    msg.reply(ctx, style_output(format!("❌ Could not clear the summary cache: {}", e))).await?;
+                }
+            }
+        }
+        _ => {
+            msg.reply(ctx, "Usage: `^sumcache stats` or `^sumcache clear`").await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Compact age for cache listings, e.g. "42s", "17m", "5h 3m"
+fn format_age(secs: i64) -> String {
+    let secs = secs.max(0);
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        _ => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+#[command]
+#[aliases("disablecmd")]
+/// Disable a command in the current server (owner or server admin)
+/// Usage: ^disable <command> - with no argument, lists the commands disabled here
+pub async fn disable(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    set_command_state(ctx, msg, args.message(), true).await
+}
+
+#[command]
+#[aliases("enablecmd")]
+/// Re-enable a command in the current server (owner or server admin)
+/// Usage: ^enable <command>
+pub async fn enable(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    set_command_state(ctx, msg, args.message(), false).await
+}
+
+/// Shared implementation of ^disable and ^enable
+async fn set_command_state(ctx: &Context, msg: &Message, input: &str, disable: bool) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => {
+            msg.reply(ctx, "❌ **Error**\nThis command can only be used in a server, not in DMs.").await?;
+            return Ok(());
+        }
+    };
+
+    if !is_owner_or_server_admin(ctx, msg).await {
+        msg.reply(ctx, "❌ **Access Denied**\nThis command can only be used by the bot owner or a server administrator.").await?;
+        return Ok(());
+    }
+
+    let input = input.trim();
+    if input.is_empty() {
+        let disabled = crate::disabled_commands_for(guild_id);
+        let listing = if disabled.is_empty() {
+            "No commands are disabled on this server.".to_string()
+        } else {
+            disabled.iter().map(|name| format!("`{}`", name)).collect::<Vec<_>>().join(", ")
+        };
+        msg.reply(ctx, format!("**Disabled Commands**\n{}\n\nUsage: `^disable <command>` / `^enable <command>`", listing)).await?;
+        return Ok(());
+    }
+
+    let command_name = match crate::resolve_command_name(input) {
+        Some(name) => name,
+        None => {
+            msg.reply(ctx, format!("❌ Unknown command `{}`.", input)).await?;
+            return Ok(());
+        }
+    };
+    if command_name == "disable" || command_name == "enable" {
+        msg.reply(ctx, "❌ `disable` and `enable` cannot be disabled.").await?;
+        return Ok(());
+    }
+
+    println!("[ADMIN] {} ({}) {} command '{}' in guild {}",
+        msg.author.name, msg.author.id, if disable { "disabled" } else { "enabled" }, command_name, guild_id);
+
+    let reply = match crate::set_command_disabled(guild_id, command_name, disable) {
+        Ok(true) if disable => format!("🚫 `{}` is now disabled on this server.", command_name),
+        Ok(true) => format!("✅ `{}` is now enabled on this server.", command_name),
+        Ok(false) if disable => format!("ℹ️ `{}` was already disabled on this server.", command_name),
+        Ok(false) => format!("ℹ️ `{}` is not disabled on this server.", command_name),
+        Err(e) => format!("❌ Failed to save disabled commands: {}", e),
+    };
+    msg.reply(ctx, reply).await?;
+
+    Ok(())
+}
+
+/// Check whether the author is the bot owner or has Administrator / Manage Server in this guild
+async fn is_owner_or_server_admin(ctx: &Context, msg: &Message) -> bool {
+    let bot_owner_id = env::var("BOT_OWNER_ID").unwrap_or_else(|_| {
+        env::var("BOT_USER_ID").unwrap_or_else(|_| "1385309017881968761".to_string())
+    });
+    if msg.author.id.to_string() == bot_owner_id {
+        return true;
+    }
+
+    match msg.member(ctx).await {
+        Ok(member) => member.permissions(ctx)
+            .map(|permissions| permissions.administrator() || permissions.manage_guild())
+            .unwrap_or(false),
+        Err(_) => false,
+    }
+}
+
 // ============================================================================
 // COMMAND GROUP
 // ============================================================================
 
 #[group]
-#[commands(restart, shutdown, adminhelp, forcerestart, diagnose, leaveserver)]
+#[commands(restart, shutdown, adminhelp, forcerestart, diagnose, diag, setmodel, sumcache, leaveserver, disable, enable)]
 pub struct Admin;
 
 impl Admin {
     pub const fn new() -> Self {
         Admin
     }
-} 
\ No newline at end of file
+} 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_config_value() {
+        let content = "# DEFAULT_MODEL=commented\nDEFAULT_MODEL=old\nDEFAULT_TEMPERATURE=0.7\n";
+        assert_eq!(
+            replace_config_value(content, "DEFAULT_MODEL", "new"),
+            "# DEFAULT_MODEL=commented\nDEFAULT_MODEL=new\nDEFAULT_TEMPERATURE=0.7\n"
+        );
+        assert_eq!(
+            replace_config_value("DEFAULT_MODEL=old", "DEFAULT_VISION_MODEL", "llava"),
+            "DEFAULT_MODEL=old\nDEFAULT_VISION_MODEL=llava\n"
+        );
+    }
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(42), "42s");
+        assert_eq!(format_age(17 * 60 + 5), "17m");
+        assert_eq!(format_age(5 * 3600 + 3 * 60), "5h 3m");
+        assert_eq!(format_age(-3), "0s");
+    }
+}
@@ -26,12 +26,13 @@ use std::collections::HashMap;
 use std::time::{Duration, Instant};
 use serde::{Deserialize, Serialize};
 use serde_json;
-use regex::Regex;
 use once_cell::sync::Lazy;
 use futures_util::StreamExt;
 use tokio::sync::OnceCell;
 use chrono::{DateTime, Utc};
 use log::{debug, info, warn, error, trace};
+use crate::commands::search::{send_chat_stream, ChatByteStream, SamplingConfig};
+use crate::commands::util::{close_open_code_fence, empty_response_retry, finish_reason_note, is_empty_response, truncate_chars, truncate_at_word, tail_chars, style_output, status_icon, EditThrottle, SseLineBuffer, with_error_id};
 
 // ============================================================================
 // STAGED PROCESSING INFRASTRUCTURE
@@ -112,14 +113,79 @@ impl std::fmt::Display for TaskStatus {
 // Global staged task store
 static STAGED_TASKS: OnceCell<std::sync::Mutex<HashMap<String, StagedTask>>> = OnceCell::const_new();
 
-// Initialize and get staged tasks
+// On-disk copy of the staged task store, so ^staged --status/--approve survive a restart
+const STAGED_TASKS_FILE: &str = "contexts/staged_tasks.json";
+
+// Initialize and get staged tasks, restoring any saved by a previous run
 async fn get_staged_tasks() -> &'static std::sync::Mutex<HashMap<String, StagedTask>> {
     STAGED_TASKS.get_or_init(|| async {
         info!("[STAGED_TASKS] Initializing staged task storage");
-        std::sync::Mutex::new(HashMap::new())
+        let mut tasks = fs::read_to_string(STAGED_TASKS_FILE)
+            .ok()
+            .and_then(|json| match serde_json::from_str::<HashMap<String, StagedTask>>(&json) {
+                Ok(tasks) => Some(tasks),
+                Err(e) => {
+                    warn!("[STAGED_TASKS] Ignoring unreadable {}: {}", STAGED_TASKS_FILE, e);
+                    None
+                }
+            })
+            .unwrap_or_default();
+        let max_age_hours = match load_agent_config().await {
+            Ok(config) => config.staged_task_max_age_hours,
+            Err(e) => {
+                warn!("[STAGED_TASKS] Using the default {}h expiry, agent config unavailable: {}", DEFAULT_STAGED_TASK_MAX_AGE_HOURS, e);
+                DEFAULT_STAGED_TASK_MAX_AGE_HOURS
+            }
+        };
+        let expired = expire_stale_tasks(&mut tasks, max_age_hours, Utc::now());
+        if expired > 0 {
+            info!("[STAGED_TASKS] Expired {} staged tasks older than {}h", expired, max_age_hours);
+            if let Err(e) = persist_staged_tasks(&tasks) {
+                warn!("[STAGED_TASKS] Failed to rewrite {} after expiry: {}", STAGED_TASKS_FILE, e);
+            }
+        }
+        let interrupted = pause_interrupted_tasks(&mut tasks);
+        info!("[STAGED_TASKS] Loaded {} staged tasks ({} interrupted by the restart, now paused)", tasks.len(), interrupted);
+        std::sync::Mutex::new(tasks)
     }).await
 }
 
+// Task ids cancelled with ^staged --cancel; a run still in progress checks this between stages
+// and its later saves are dropped instead of re-adding the task
+static CANCELLED_STAGED_TASKS: Lazy<std::sync::Mutex<std::collections::HashSet<String>>> =
+    Lazy::new(|| std::sync::Mutex::new(std::collections::HashSet::new()));
+
+fn is_staged_task_cancelled(task_id: &str) -> bool {
+    CANCELLED_STAGED_TASKS.lock().map(|cancelled| cancelled.contains(task_id)).unwrap_or(false)
+}
+
+// Drop tasks whose last update is older than `max_age_hours` (0 keeps everything), returning how many went
+fn expire_stale_tasks(tasks: &mut HashMap<String, StagedTask>, max_age_hours: u64, now: DateTime<Utc>) -> usize {
+    if max_age_hours == 0 {
+        return 0;
+    }
+    let cutoff = now - chrono::Duration::hours(max_age_hours.min(i64::MAX as u64 / 3600) as i64);
+    let before = tasks.len();
+    tasks.retain(|_, task| task.updated_at >= cutoff);
+    before - tasks.len()
+}
+
+// Tasks that were mid-stage when the bot stopped are no longer running; pause them so --resume picks them up
+fn pause_interrupted_tasks(tasks: &mut HashMap<String, StagedTask>) -> usize {
+    let mut interrupted = 0;
+    for task in tasks.values_mut() {
+        if matches!(task.overall_status, TaskStatus::Complete | TaskStatus::Failed | TaskStatus::Paused) {
+            continue;
+        }
+        for stage in task.stages.iter_mut().filter(|stage| stage.status == StageStatus::InProgress) {
+            stage.status = StageStatus::Pending;
+        }
+        task.overall_status = TaskStatus::Paused;
+        interrupted += 1;
+    }
+    interrupted
+}
+
 // ============================================================================
 // LOGGING INFRASTRUCTURE
 // ============================================================================
@@ -127,8 +193,9 @@ async fn get_staged_tasks() -> &'static std::sync::Mutex<HashMap<String, StagedT
 // Logging macros for agent operations
 macro_rules! agent_log {
     ($level:ident, $user_id:expr, $operation:expr, $($arg:tt)*) => {
-        $level!("[AGENT][{}][{}] {}", 
+        $level!("[AGENT][{}][{}][{}] {}", 
             $user_id, 
+            crate::commands::util::current_request_id(),
             $operation, 
             format!($($arg)*)
         );
@@ -178,11 +245,14 @@ static RESPONSE_CACHE: OnceCell<std::sync::Mutex<HashMap<String, String>>> = Onc
 // Global context store for user conversations
 static USER_CONTEXTS: OnceCell<std::sync::Mutex<HashMap<UserId, Vec<ChatMessage>>>> = OnceCell::const_new();
 
+// Per-user agent memory (function call history), persisted to AGENT_MEMORY_FILE
+static AGENT_MEMORIES: OnceCell<std::sync::Mutex<HashMap<UserId, AgentMemory>>> = OnceCell::const_new();
+
 // Initialize shared HTTP client with optimized settings
 async fn get_http_client() -> &'static reqwest::Client {
     HTTP_CLIENT.get_or_init(|| async {
         info!("[HTTP_CLIENT] Initializing global HTTP client with optimized settings");
-        let client = reqwest::Client::builder()
+        let builder = reqwest::Client::builder()
             .timeout(Duration::from_secs(300)) // 5 minutes for agent operations
             .connect_timeout(Duration::from_secs(30))
             .pool_idle_timeout(Duration::from_secs(90))
@@ -192,7 +262,8 @@ async fn get_http_client() -> &'static reqwest::Client {
             .http2_keep_alive_interval(Duration::from_secs(30))
             .http2_keep_alive_timeout(Duration::from_secs(10))
             .http2_keep_alive_while_idle(true)
-            .user_agent("Meri-Bot-Agent-Client/1.0")
+            .user_agent("Meri-Bot-Agent-Client/1.0");
+        let client = crate::commands::util::apply_proxy_settings(builder, "Agent")
             .build()
             .expect("Failed to create HTTP client");
         
@@ -217,6 +288,153 @@ async fn get_user_contexts() -> &'static std::sync::Mutex<HashMap<UserId, Vec<Ch
     }).await
 }
 
+// Initialize and get agent memories, loading any previously saved history from disk
+async fn get_agent_memories() -> &'static std::sync::Mutex<HashMap<UserId, AgentMemory>> {
+    AGENT_MEMORIES.get_or_init(|| async {
+        info!("[AGENT_MEMORIES] Initializing agent memory storage");
+        let memories = fs::read_to_string(AGENT_MEMORY_FILE)
+            .ok()
+            .and_then(|json| match serde_json::from_str::<HashMap<UserId, AgentMemory>>(&json) {
+                Ok(memories) => Some(memories),
+                Err(e) => {
+                    warn!("[AGENT_MEMORIES] Ignoring unreadable {}: {}", AGENT_MEMORY_FILE, e);
+                    None
+                }
+            })
+            .unwrap_or_default();
+        info!("[AGENT_MEMORIES] Loaded agent memory for {} users", memories.len());
+        std::sync::Mutex::new(memories)
+    }).await
+}
+
+// Sends chat-completion requests to the LM backend
+// The bot uses HttpChatBackend; tests substitute canned SSE streams so the parsing loops run without LM Studio
+#[async_trait::async_trait]
+trait ChatBackend: Send + Sync {
+    // Probe the server before a function-calling run; failures are only logged
+    async fn check_health(&self, _base_url: &str, _user_id: UserId) {}
+
+    // Start a streaming request, failing on transport errors and non-success statuses
+    async fn stream_chat(&self, api_url: &str, request: &ChatRequest, timeout_secs: u64) -> Result<ChatByteStream, Box<dyn std::error::Error + Send + Sync>>;
+}
+
+// LM Studio / Ollama's OpenAI-compatible endpoint over the shared HTTP client
+// Requests go through search::send_chat_stream, so transient failures are retried up to LM_MAX_RETRIES times
+struct HttpChatBackend {
+    max_retries: u32,
+}
+
+#[async_trait::async_trait]
+impl ChatBackend for HttpChatBackend {
+    async fn check_health(&self, base_url: &str, user_id: UserId) {
+        let health_url = format!("{}/api/tags", base_url);
+        agent_trace!(user_id, "check_health", "Testing connectivity to: {}", health_url);
+        match get_http_client().await.get(&health_url).timeout(Duration::from_secs(10)).send().await {
+            Ok(resp) => {
+                agent_trace!(user_id, "check_health", "Connectivity test successful, status: {}", resp.status());
+            }
+            Err(e) => {
+                agent_warn!(user_id, "check_health", "Connectivity test failed: {}", e);
+            }
+        }
+    }
+
+    async fn stream_chat(&self, api_url: &str, request: &ChatRequest, timeout_secs: u64) -> Result<ChatByteStream, Box<dyn std::error::Error + Send + Sync>> {
+        let client = get_http_client().await;
+        Ok(send_chat_stream(client, api_url, request, &request.model, timeout_secs, self.max_retries).await?)
+    }
+}
+
+// Error carried by an SSE event instead of a delta, e.g. {"error":{"message":"..."}} or {"error":"..."}
+fn stream_error_message(event: &serde_json::Value) -> Option<String> {
+    let error = event.get("error")?;
+    if error.is_null() {
+        return None;
+    }
+    Some(
+        error.get("message").and_then(|m| m.as_str())
+            .or_else(|| error.as_str())
+            .map(str::to_string)
+            .unwrap_or_else(|| error.to_string()),
+    )
+}
+
+// Decodes a chat-completion SSE body into JSON events
+// Line buffering, the [DONE] sentinel and mid-stream error objects are handled here once for every agent loop
+struct ChatEventStream {
+    stream: ChatByteStream,
+    sse_lines: SseLineBuffer,
+    pending: std::collections::VecDeque<serde_json::Value>,
+    finished: bool,
+    finish_reason: Option<String>,
+}
+
+impl ChatEventStream {
+    fn new(stream: ChatByteStream) -> Self {
+        Self {
+            stream,
+            sse_lines: SseLineBuffer::new(),
+            pending: std::collections::VecDeque::new(),
+            finished: false,
+            finish_reason: None,
+        }
+    }
+
+    // choices[].finish_reason from the last event that carried one ("stop", "length", "tool_calls", ...)
+    fn finish_reason(&self) -> Option<&str> {
+        self.finish_reason.as_deref()
+    }
+
+    // Next JSON event, or None once [DONE] arrives or the body ends
+    // Transport failures and {"error": ...} events end the stream with an error
+    async fn next_event(&mut self) -> Option<Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>>> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                if let Some(message) = stream_error_message(&event) {
+                    self.pending.clear();
+                    self.finished = true;
+                    return Some(Err(format!("Model returned an error: {}", message).into()));
+                }
+                if let Some(reason) = event.get("choices")
+                    .and_then(|c| c.as_array())
+                    .and_then(|choices| choices.iter().find_map(|choice| choice.get("finish_reason").and_then(|r| r.as_str())))
+                {
+                    self.finish_reason = Some(reason.to_string());
+                }
+                return Some(Ok(event));
+            }
+            if self.finished {
+                return None;
+            }
+
+            let chunk = match self.stream.next().await {
+                Some(Ok(chunk)) => chunk,
+                Some(Err(e)) => {
+                    self.finished = true;
+                    return Some(Err(e));
+                }
+                None => {
+                    self.finished = true;
+                    continue;
+                }
+            };
+            // Only complete lines are parsed; a partial event waits for the next chunk
+            for line in self.sse_lines.push(&chunk) {
+                if let Some(data) = line.strip_prefix("data: ") {
+                    if data.trim() == "[DONE]" {
+                        self.finished = true;
+                        break;
+                    }
+                    match serde_json::from_str::<serde_json::Value>(data) {
+                        Ok(event) => self.pending.push_back(event),
+                        Err(e) => debug!("[AGENT] Skipping unparsable SSE event: {}", e),
+                    }
+                }
+            }
+        }
+    }
+}
+
 // Chat message structure for context (self-contained)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatMessage {
@@ -235,8 +453,32 @@ pub struct LMConfig {
     pub max_discord_message_length: usize,
     pub response_format_padding: usize,
     pub default_seed: Option<i64>,
+    pub staged_max_total_secs: u64,
+    pub staged_task_max_age_hours: u64,
+    pub max_retries: u32,
+    pub model_profiles: HashMap<String, crate::commands::search::ModelProfile>, // Per-model overrides (MODEL_PROFILES)
 }
 
+impl SamplingConfig for LMConfig {
+    fn sampling_defaults(&self) -> crate::commands::search::SamplingParams {
+        crate::commands::search::SamplingParams {
+            temperature: self.default_temperature,
+            max_tokens: self.default_max_tokens,
+            seed: self.default_seed,
+        }
+    }
+
+    fn model_profiles(&self) -> &HashMap<String, crate::commands::search::ModelProfile> {
+        &self.model_profiles
+    }
+}
+
+// Overall wall-clock budget for a staged task when STAGED_MAX_TOTAL_SECS isn't set
+const DEFAULT_STAGED_MAX_TOTAL_SECS: u64 = 900;
+
+// Saved staged tasks untouched for longer than this are dropped at load (STAGED_TASK_MAX_AGE_HOURS, 0 keeps them)
+const DEFAULT_STAGED_TASK_MAX_AGE_HOURS: u64 = 72;
+
 // Function calling structures for LM Studio
 #[derive(Debug, Clone, Serialize)]
 pub struct FunctionDefinition {
@@ -299,7 +541,7 @@ struct Delta {
     tool_calls: Option<Vec<ToolCall>>,
 }
 
-#[derive(Deserialize)]
+#[derive(Debug, Deserialize)]
 struct ToolCall {
     id: String,
     #[serde(rename = "type")]
@@ -307,17 +549,12 @@ struct ToolCall {
     function: FunctionCallResponse,
 }
 
-// Compile regex once for better performance - matches <think> tags
-static THINKING_TAG_REGEX: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r"(?s)<think>.*?</think>").expect("Invalid thinking tag regex pattern")
-});
-
 // ============================================================================
 // AGENT CORE INFRASTRUCTURE
 // ============================================================================
 
 // Agent memory system
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentMemory {
     pub conversation_history: Vec<ChatMessage>,
     pub function_call_history: Vec<FunctionCallRecord>,
@@ -326,7 +563,7 @@ pub struct AgentMemory {
     pub last_updated: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FunctionCallRecord {
     pub call_id: String,
     pub function_name: String,
@@ -336,6 +573,24 @@ pub struct FunctionCallRecord {
     pub user_id: UserId,
 }
 
+impl AgentMemory {
+    pub fn new() -> Self {
+        Self {
+            conversation_history: Vec::new(),
+            function_call_history: Vec::new(),
+            user_preferences: HashMap::new(),
+            persistent_knowledge: Vec::new(),
+            last_updated: Utc::now(),
+        }
+    }
+}
+
+// Maximum tool calls kept per user in the function call history
+const MAX_FUNCTION_CALL_HISTORY: usize = 50;
+
+// On-disk location of agent memory, next to the LM/Reason context files
+const AGENT_MEMORY_FILE: &str = "contexts/agent_memory.json";
+
 // Agent state management
 pub struct AgentState {
     pub memory: AgentMemory,
@@ -381,13 +636,13 @@ fn get_js_code_sandbox_functions() -> Vec<FunctionDefinition> {
             function_type: "function".to_string(),
             function: serde_json::json!({
                 "name": "calculate_math",
-                "description": "Perform mathematical calculations using JavaScript's Math library and other mathematical operations.",
+                "description": "Perform mathematical calculations. Arithmetic (+ - * / % ^), parentheses, pi, e and common functions (sqrt, sin, cos, tan, ln, log10, exp, floor, ceil, round, min, max, pow) are evaluated exactly; any other expression is rejected, so write code for execute_js_code instead.",
                 "parameters": {
                     "type": "object",
                     "properties": {
                         "expression": {
                             "type": "string",
-                            "description": "A mathematical expression or calculation to perform. Can use JavaScript Math functions like Math.sin(), Math.sqrt(), etc."
+                            "description": "A mathematical expression to evaluate, e.g. 'sqrt(2) * (3 + 4)^2'. Math.sin(), Math.sqrt() etc. are also accepted."
                         },
                         "description": {
                             "type": "string",
@@ -456,6 +711,30 @@ fn get_js_code_sandbox_functions() -> Vec<FunctionDefinition> {
 // ============================================================================
 
 async fn execute_function_call(
+    function_call: &FunctionCallResponse,
+    call_id: &str,
+    user_id: UserId,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let outcome = run_function_call(function_call, user_id).await;
+
+    // Record every call, including failures, in the user's function call history
+    let result_text = match &outcome {
+        Ok(result) => result.clone(),
+        Err(e) => format!("Error: {}", e),
+    };
+    record_function_call(FunctionCallRecord {
+        call_id: call_id.to_string(),
+        function_name: function_call.name.clone(),
+        arguments: function_call.arguments.clone(),
+        result: result_text,
+        timestamp: Utc::now(),
+        user_id,
+    }).await;
+
+    outcome
+}
+
+async fn run_function_call(
     function_call: &FunctionCallResponse,
     user_id: UserId,
 ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
@@ -492,7 +771,7 @@ async fn execute_function_call(
                 .and_then(|v| v.as_str())
                 .unwrap_or("Mathematical calculation");
 
-            calculate_math(expression, description, user_id).await?
+            calculate_math(expression, description, user_id)?
         },
         "process_text" => {
             let args = function_call.arguments.as_object()
@@ -535,7 +814,7 @@ async fn execute_function_call(
     agent_trace!(user_id, "execute_function_call", "Function: {}", function_call.name);
     agent_trace!(user_id, "execute_function_call", "Execution time: {:?}", execution_time);
     agent_trace!(user_id, "execute_function_call", "Result length: {} chars", result.len());
-    agent_trace!(user_id, "execute_function_call", "Result preview: {}", &result[..std::cmp::min(200, result.len())]);
+    agent_trace!(user_id, "execute_function_call", "Result preview: {}", truncate_chars(&result, 200));
     
     agent_info!(user_id, "execute_function_call", "Function {} completed in {:?}", 
         function_call.name, execution_time);
@@ -650,7 +929,7 @@ async fn execute_js_code(
     Ok(execution_result)
 }
 
-async fn calculate_math(
+fn calculate_math(
     expression: &str,
     description: &str,
     user_id: UserId,
@@ -658,44 +937,239 @@ async fn calculate_math(
     agent_debug!(user_id, "calculate_math", "Calculating: {}", expression);
     agent_trace!(user_id, "calculate_math", "Expression: {}", expression);
     agent_trace!(user_id, "calculate_math", "Description: {}", description);
-    
-    // Create enhanced JavaScript code for mathematical calculation
-    let js_code = format!(
-        "// Mathematical calculation: {}\n\
-         console.log('🧮 Starting calculation: {}');\n\
-         \n\
-         const expression = '{}';\n\
-         console.log('📝 Expression:', expression);\n\
-         \n\
-         try {{\n\
-             const result = eval(expression);\n\
-             console.log('✅ Result:', result);\n\
-             console.log('📊 Type:', typeof result);\n\
-             \n\
-             // Additional analysis\n\
-             const analysis = {{\n\
-                 expression: expression,\n\
-                 result: result,\n\
-                 type: typeof result,\n\
-                 isFinite: Number.isFinite(result),\n\
-                 isInteger: Number.isInteger(result),\n\
-                 scientific: result.toExponential ? result.toExponential(2) : 'N/A'\n\
-             }};\n\
-             \n\
-             console.log('📈 Analysis:', JSON.stringify(analysis, null, 2));\n\
-             \n\
-             // Return formatted result\n\
-             console.log('🎯 Final Answer:', result);\n\
-             result;\n\
-         }} catch (error) {{\n\
-             console.error('❌ Calculation Error:', error.message);\n\
-             throw error;\n\
-         }}",
-        description, expression, expression
-    );
-    
-    agent_trace!(user_id, "calculate_math", "Generated JavaScript code for calculation");
-    execute_js_code(&js_code, &format!("Mathematical calculation: {}", description), user_id).await
+
+    // Only the Rust evaluator runs, so model-written input never reaches JavaScript
+    match evaluate_math_expression(expression) {
+        Ok(result) => {
+            agent_info!(user_id, "calculate_math", "Evaluated safely: {} = {}", expression, result);
+            Ok(format!(
+                "🧮 **Calculation:** {}\n`{}` = **{}**",
+                description,
+                expression.trim(),
+                format_math_result(result)
+            ))
+        }
+        Err(e) => {
+            agent_debug!(user_id, "calculate_math", "Rejected expression: {}", e);
+            Err(format!("calculate_math can't evaluate `{}` ({}); use execute_js_code for anything beyond arithmetic", expression.trim(), e).into())
+        }
+    }
+}
+
+/// Evaluate an arithmetic expression without JavaScript
+/// Supports + - * / %, ^ and ** (power), parentheses, unary minus, the constants pi and e,
+/// and sqrt/cbrt/abs/sin/cos/tan/asin/acos/atan/ln/log/log2/log10/exp/floor/ceil/round/min/max/pow.
+/// A leading "Math." (as models tend to write) is accepted and ignored.
+fn evaluate_math_expression(expression: &str) -> Result<f64, String> {
+    let mut parser = MathParser { chars: expression.chars().collect(), pos: 0, depth: 0 };
+    let value = parser.parse_sum()?;
+    parser.skip_whitespace();
+    if parser.pos < parser.chars.len() {
+        return Err(format!("unexpected '{}' at position {}", parser.chars[parser.pos], parser.pos + 1));
+    }
+    Ok(value)
+}
+
+/// Deepest parenthesis / function nesting the safe evaluator will follow
+const MAX_MATH_NESTING: usize = 64;
+
+/// Recursive-descent parser backing evaluate_math_expression
+struct MathParser {
+    chars: Vec<char>,
+    pos: usize,
+    depth: usize,
+}
+
+impl MathParser {
+    fn skip_whitespace(&mut self) {
+        while self.chars.get(self.pos).map_or(false, |c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(c) => Err(format!("expected '{}' but found '{}'", expected, c)),
+            None => Err(format!("expected '{}' but the expression ended", expected)),
+        }
+    }
+
+    // sum := product (('+' | '-') product)*
+    fn parse_sum(&mut self) -> Result<f64, String> {
+        let mut value = self.parse_product()?;
+        loop {
+            match self.peek() {
+                Some('+') => { self.pos += 1; value += self.parse_product()?; }
+                Some('-') => { self.pos += 1; value -= self.parse_product()?; }
+                _ => return Ok(value),
+            }
+        }
+    }
+
+    // product := unary (('*' | '/' | '%') unary)*
+    fn parse_product(&mut self) -> Result<f64, String> {
+        let mut value = self.parse_unary()?;
+        loop {
+            match self.peek() {
+                Some('*') if self.chars.get(self.pos + 1) != Some(&'*') => { self.pos += 1; value *= self.parse_unary()?; }
+                Some('/') => { self.pos += 1; value /= self.parse_unary()?; }
+                Some('%') => { self.pos += 1; value %= self.parse_unary()?; }
+                _ => return Ok(value),
+            }
+        }
+    }
+
+    // unary := ('-' | '+') unary | power
+    fn parse_unary(&mut self) -> Result<f64, String> {
+        match self.peek() {
+            Some('-') => { self.pos += 1; Ok(-self.parse_unary()?) }
+            Some('+') => { self.pos += 1; self.parse_unary() }
+            _ => self.parse_power(),
+        }
+    }
+
+    // power := atom (('^' | '**') unary)?   (right-associative, binds tighter than unary minus on the left)
+    fn parse_power(&mut self) -> Result<f64, String> {
+        let base = self.parse_atom()?;
+        match self.peek() {
+            Some('^') => { self.pos += 1; }
+            Some('*') if self.chars.get(self.pos + 1) == Some(&'*') => { self.pos += 2; }
+            _ => return Ok(base),
+        }
+        let exponent = self.parse_unary()?;
+        Ok(base.powf(exponent))
+    }
+
+    // atom := number | '(' sum ')' | constant | function '(' args ')'
+    fn parse_atom(&mut self) -> Result<f64, String> {
+        match self.peek() {
+            Some(c) if c.is_ascii_digit() || c == '.' => self.parse_number(),
+            Some('(') => {
+                self.pos += 1;
+                let value = self.nested(|parser| parser.parse_sum())?;
+                self.expect(')')?;
+                Ok(value)
+            }
+            Some(c) if c.is_ascii_alphabetic() => {
+                let name = self.parse_identifier();
+                self.parse_named(&name)
+            }
+            Some(c) => Err(format!("unexpected '{}' at position {}", c, self.pos + 1)),
+            None => Err("the expression ended unexpectedly".to_string()),
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<f64, String> {
+        let start = self.pos;
+        while self.chars.get(self.pos).map_or(false, |c| c.is_ascii_digit() || *c == '.') {
+            self.pos += 1;
+        }
+        // Scientific notation such as 1.5e3 or 2E-4
+        if matches!(self.chars.get(self.pos), Some('e') | Some('E')) {
+            let mut end = self.pos + 1;
+            if matches!(self.chars.get(end), Some('+') | Some('-')) {
+                end += 1;
+            }
+            if self.chars.get(end).map_or(false, |c| c.is_ascii_digit()) {
+                self.pos = end;
+                while self.chars.get(self.pos).map_or(false, |c| c.is_ascii_digit()) {
+                    self.pos += 1;
+                }
+            }
+        }
+        let literal: String = self.chars[start..self.pos].iter().collect();
+        literal.parse::<f64>().map_err(|_| format!("invalid number '{}'", literal))
+    }
+
+    fn parse_identifier(&mut self) -> String {
+        let start = self.pos;
+        while self.chars.get(self.pos).map_or(false, |c| c.is_ascii_alphanumeric() || *c == '_' || *c == '.') {
+            self.pos += 1;
+        }
+        let name: String = self.chars[start..self.pos].iter().collect();
+        name.strip_prefix("Math.").unwrap_or(&name).to_ascii_lowercase()
+    }
+
+    fn parse_named(&mut self, name: &str) -> Result<f64, String> {
+        match name {
+            "pi" => return Ok(std::f64::consts::PI),
+            "e" => return Ok(std::f64::consts::E),
+            "tau" => return Ok(std::f64::consts::TAU),
+            _ => {}
+        }
+        self.expect('(').map_err(|_| format!("unknown name '{}'", name))?;
+        let args = self.nested(|parser| {
+            let mut args = vec![parser.parse_sum()?];
+            while parser.peek() == Some(',') {
+                parser.pos += 1;
+                args.push(parser.parse_sum()?);
+            }
+            Ok(args)
+        })?;
+        self.expect(')')?;
+
+        let unary = |f: fn(f64) -> f64| -> Result<f64, String> {
+            match args.as_slice() {
+                [x] => Ok(f(*x)),
+                _ => Err(format!("{}() takes 1 argument, got {}", name, args.len())),
+            }
+        };
+        match name {
+            "sqrt" => unary(f64::sqrt),
+            "cbrt" => unary(f64::cbrt),
+            "abs" => unary(f64::abs),
+            "sin" => unary(f64::sin),
+            "cos" => unary(f64::cos),
+            "tan" => unary(f64::tan),
+            "asin" => unary(f64::asin),
+            "acos" => unary(f64::acos),
+            "atan" => unary(f64::atan),
+            // JavaScript's Math.log is the natural logarithm, so log() matches it
+            "ln" | "log" => unary(f64::ln),
+            "log2" => unary(f64::log2),
+            "log10" => unary(f64::log10),
+            "exp" => unary(f64::exp),
+            "floor" => unary(f64::floor),
+            "ceil" => unary(f64::ceil),
+            "round" => unary(f64::round),
+            "trunc" => unary(f64::trunc),
+            "pow" => match args.as_slice() {
+                [base, exponent] => Ok(base.powf(*exponent)),
+                _ => Err(format!("pow() takes 2 arguments, got {}", args.len())),
+            },
+            "min" => Ok(args.iter().copied().fold(f64::INFINITY, f64::min)),
+            "max" => Ok(args.iter().copied().fold(f64::NEG_INFINITY, f64::max)),
+            _ => Err(format!("unknown function '{}'", name)),
+        }
+    }
+
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T, String>) -> Result<T, String> {
+        if self.depth >= MAX_MATH_NESTING {
+            return Err("expression is nested too deeply".to_string());
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+}
+
+/// Show whole numbers without a trailing ".0" and keep everything else at full precision
+fn format_math_result(value: f64) -> String {
+    if value.is_finite() && value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        format!("{}", value)
+    }
 }
 
 async fn process_text(
@@ -792,10 +1266,10 @@ async fn update_thinking_message(
     step: &str,
     user_id: UserId,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let timestamp = chrono::Utc::now().format("%H:%M:%S").to_string();
+    let timestamp = crate::commands::util::local_time("%H:%M:%S");
     let content = format!(
-        "🧠 **Agent Reasoning Process**\n\n**Step {}:** {}\n\n*This message will be updated with more reasoning steps and then deleted when complete.*",
-        timestamp, step
+        "{} **Agent Reasoning Process**\n\n**Step {}:** {}\n\n*This message will be updated with more reasoning steps and then deleted when complete.*",
+        status_icon("🧠"), timestamp, step
     );
     
     match thinking_msg.edit(&ctx.http, |m| m.content(&content)).await {
@@ -815,16 +1289,8 @@ fn write_to_response_file(
     content: &str,
     user_id: UserId,
 ) {
-    use std::io::Write;
-    if let Some(file) = response_file {
-        let timestamped_content = format!("[{}] {}\n", chrono::Utc::now().format("%H:%M:%S"), content);
-        if let Err(e) = file.write_all(timestamped_content.as_bytes()) {
-            agent_error!(user_id, "write_to_response_file", "Failed to write to response file: {}", e);
-        } else {
-            if let Err(e) = file.flush() {
-                agent_error!(user_id, "write_to_response_file", "Failed to flush response file: {}", e);
-            }
-        }
+    if let Err(e) = crate::commands::util::write_to_response_file(response_file, content) {
+        agent_error!(user_id, "write_to_response_file", "Failed to write to response file: {}", e);
     }
 }
 
@@ -886,6 +1352,49 @@ async fn add_to_user_context(user_id: UserId, message: ChatMessage) {
     }
 }
 
+async fn record_function_call(record: FunctionCallRecord) {
+    let user_id = record.user_id;
+    let memories = get_agent_memories().await;
+    let snapshot = match memories.lock() {
+        Ok(mut memories_map) => {
+            let memory = memories_map.entry(user_id).or_insert_with(AgentMemory::new);
+            memory.function_call_history.push(record);
+            if memory.function_call_history.len() > MAX_FUNCTION_CALL_HISTORY {
+                let excess = memory.function_call_history.len() - MAX_FUNCTION_CALL_HISTORY;
+                memory.function_call_history.drain(0..excess);
+            }
+            memory.last_updated = Utc::now();
+            agent_debug!(user_id, "record_function_call", "Recorded function call, history: {} entries", memory.function_call_history.len());
+            serde_json::to_string_pretty(&*memories_map).ok()
+        }
+        Err(_) => None,
+    };
+
+    // Write outside the lock so a slow disk doesn't block other agent calls
+    if let Some(json) = snapshot {
+        if let Err(e) = save_agent_memories(&json) {
+            agent_warn!(user_id, "record_function_call", "Failed to persist agent memory: {}", e);
+        }
+    }
+}
+
+fn save_agent_memories(json: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if let Some(dir) = std::path::Path::new(AGENT_MEMORY_FILE).parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(AGENT_MEMORY_FILE, json)?;
+    Ok(())
+}
+
+async fn get_function_call_history(user_id: UserId) -> Vec<FunctionCallRecord> {
+    let memories = get_agent_memories().await;
+    if let Ok(memories_map) = memories.lock() {
+        memories_map.get(&user_id).map(|m| m.function_call_history.clone()).unwrap_or_default()
+    } else {
+        Vec::new()
+    }
+}
+
 async fn clear_user_context(user_id: UserId) {
     let contexts = get_user_contexts().await;
     if let Ok(mut contexts_map) = contexts.lock() {
@@ -898,13 +1407,82 @@ async fn clear_user_context(user_id: UserId) {
 // AGENT EXECUTION FUNCTIONS
 // ============================================================================
 
+// How the final agent result is delivered to Discord
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AgentOutputMode {
+    /// File upload for long results, inline reply for short ones
+    Auto,
+    /// Always upload the response file (--file)
+    File,
+    /// Always reply inline and never create a response file (--inline)
+    Inline,
+}
+
+// Results up to this many characters are sent inline in Auto mode
+const AGENT_INLINE_MAX_CHARS: usize = 1500;
+
+// Preview length in the message posted with an uploaded result that doesn't fit in the message whole
+const AGENT_SUMMARY_PREVIEW_CHARS: usize = 500;
+
+// --explain reasoning traces up to this many characters are posted as a spoiler, longer ones are attached
+const EXPLAIN_SPOILER_MAX_CHARS: usize = 1800;
+
+// Largest --budget accepted, in tool executions
+const MAX_TOOL_BUDGET: usize = 50;
+
+// --json asks for this shape; `code` is replaced by the JavaScript the sandbox ran, when there was any
+const AGENT_JSON_INSTRUCTION: &str = "Rewrite the agent result you are given as a single JSON object: {\"answer\": string, \"code\": string, \"steps\": [string]}. `answer` is the final answer, `code` is the code that was run or proposed (empty string if none) and `steps` lists what was done, in order. Respond with only the JSON object, no code fences or commentary.";
+
+// Structured ^agent --json result
+#[derive(Debug, Serialize, Deserialize)]
+struct AgentJsonOutput {
+    answer: String,
+    #[serde(default)]
+    code: String,
+    #[serde(default)]
+    steps: Vec<String>,
+}
+
+// Message posted with the uploaded response file: the whole result when it fits in one message
+// (e.g. a short result with --file), otherwise a preview cut at a word boundary
+fn upload_message(result: &str, max_len: usize) -> String {
+    let full = format!(
+        "✅ **Agent Task Complete**\n\n**Result:**\n{}\n\n📎 **Full Response:** also attached as a file\n\n📝 **Context Saved** - Your conversation history is preserved for future ^agent commands.",
+        result.trim()
+    );
+    if full.chars().count() + crate::commands::util::response_footer_len() <= max_len {
+        return full;
+    }
+    format!(
+        "✅ **Agent Task Complete**\n\n**Summary:**\n{}\n\n📎 **Full Response:** See attached file (or rerun with `--inline` to get it as messages)\n\n📝 **Context Saved** - Your conversation history is preserved for future ^agent commands.",
+        truncate_at_word(result, AGENT_SUMMARY_PREVIEW_CHARS)
+    )
+}
+
+// Decide whether a result should be uploaded as a file
+fn should_upload_result_file(mode: AgentOutputMode, result: &str) -> bool {
+    match mode {
+        AgentOutputMode::File => true,
+        AgentOutputMode::Inline => false,
+        AgentOutputMode::Auto => result.chars().count() > AGENT_INLINE_MAX_CHARS,
+    }
+}
+
 async fn execute_agent_task(
     task: String, 
     ctx: &Context, 
-    msg: &Message
+    msg: &Message,
+    output_mode: AgentOutputMode,
+    timeout_override: Option<u64>,
+    explain: bool,
+    json_output: bool,
+    code_lang: Option<String>,
+    tool_budget: Option<usize>,
+    debug: &mut crate::commands::util::DebugLog,
 ) -> CommandResult {
     let user_id = msg.author.id;
     let start_time = Instant::now();
+    let started_at = Utc::now();
     
     agent_trace!(user_id, "execute_agent_task", "=== EXECUTE AGENT TASK START ===");
     agent_trace!(user_id, "execute_agent_task", "Task: '{}'", task);
@@ -914,7 +1492,7 @@ async fn execute_agent_task(
     
     // Load configuration
     agent_trace!(user_id, "execute_agent_task", "Loading agent configuration...");
-    let config = match load_agent_config().await {
+    let mut config = match load_agent_config().await {
         Ok(config) => {
             agent_trace!(user_id, "execute_agent_task", "Configuration loaded successfully");
             agent_trace!(user_id, "execute_agent_task", "Model: {}", config.default_model);
@@ -927,36 +1505,50 @@ async fn execute_agent_task(
         Err(e) => {
             agent_trace!(user_id, "execute_agent_task", "Configuration loading failed: {}", e);
             agent_error!(user_id, "execute_agent_task", "Failed to load agent configuration: {}", e);
-            msg.reply(ctx, "❌ Failed to load agent configuration").await?;
+            debug.log(format!("error: failed to load agent configuration: {}", e));
+            msg.reply(ctx, with_error_id(style_output("❌ Failed to load agent configuration"))).await?;
             return Ok(());
         }
     };
     
-    // Create a file to stream the agent response to
+    // --timeout applies to every request of this run (function calling and the final response)
+    if let Some(timeout) = timeout_override {
+        agent_info!(user_id, "execute_agent_task", "Timeout overridden for this task: {}s (configured {}s)", timeout, config.timeout);
+        config.timeout = timeout;
+    }
+    
+    // Create a file to stream the agent response to (skipped entirely for --inline)
     let response_filename = format!("agent_response_{}_{}.txt", user_id, chrono::Utc::now().timestamp());
-    let mut response_file = match std::fs::File::create(&response_filename) {
-        Ok(file) => {
-            agent_info!(user_id, "execute_agent_task", "Created response file: {}", response_filename);
-            file
-        }
-        Err(e) => {
-            agent_error!(user_id, "execute_agent_task", "Failed to create response file: {}", e);
-            let _ = msg.reply(ctx, "❌ Failed to create response file").await;
-            return Ok(());
+    let mut response_file = if output_mode == AgentOutputMode::Inline {
+        agent_debug!(user_id, "execute_agent_task", "Inline output requested, not creating a response file");
+        None
+    } else {
+        match std::fs::File::create(&response_filename) {
+            Ok(file) => {
+                agent_info!(user_id, "execute_agent_task", "Created response file: {}", response_filename);
+                Some(file)
+            }
+            Err(e) => {
+                agent_error!(user_id, "execute_agent_task", "Failed to create response file: {}", e);
+                let _ = msg.reply(ctx, with_error_id(style_output("❌ Failed to create response file"))).await;
+                return Ok(());
+            }
         }
     };
 
     // Write initial header to file
     use std::io::Write;
-    let header = format!("🤖 **AI Agent Response**\nUser: {} ({})\nTask: {}\nTimestamp: {}\n\n", 
-        msg.author.name, user_id, task, chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC"));
-    if let Err(e) = response_file.write_all(header.as_bytes()) {
-        agent_error!(user_id, "execute_agent_task", "Failed to write header to file: {}", e);
+    if let Some(file) = response_file.as_mut() {
+        let header = format!("🤖 **AI Agent Response**\nUser: {} ({})\nTask: {}\nTimestamp: {}\n\n", 
+            msg.author.name, user_id, task, crate::commands::util::local_time("%Y-%m-%d %H:%M:%S %Z"));
+        if let Err(e) = file.write_all(header.as_bytes()) {
+            agent_error!(user_id, "execute_agent_task", "Failed to write header to file: {}", e);
+        }
     }
 
-    // Send initial Discord message indicating file streaming
+    // Send initial Discord message indicating processing
     let mut thinking_msg = match msg.channel_id.send_message(&ctx.http, |m| {
-        m.content("🤖 **AI Agent Processing...**\n\n📝 Streaming response to file...\n⏳ This may take a moment...")
+        m.content(style_output("🤖 **AI Agent Processing...**\n\n⏳ This may take a moment..."))
     }).await {
         Ok(message) => {
             agent_debug!(user_id, "execute_agent_task", "Successfully sent status message");
@@ -970,7 +1562,11 @@ async fn execute_agent_task(
     };
 
     // Create system prompt for agent
-    let system_prompt = create_agent_system_prompt();
+    let mut system_prompt = create_agent_system_prompt();
+    if let Some(lang) = &code_lang {
+        agent_info!(user_id, "execute_agent_task", "Presenting code in {} (execution stays in the JavaScript sandbox)", lang);
+        system_prompt.push_str(&code_language_guidance(lang));
+    }
     
     // Get user's conversation history for context carryover
     agent_trace!(user_id, "execute_agent_task", "Loading user context...");
@@ -1015,28 +1611,69 @@ async fn execute_agent_task(
     // Update thinking message with next step
     let _ = update_thinking_message(ctx, &mut thinking_msg, "Sending request to AI model for function calling", user_id).await;
     
+    if debug.is_enabled() {
+        debug.log(format!("model: {} at {}", config.default_model, config.base_url));
+        debug.log(format!(
+            "sampling: temperature={} max_tokens={} timeout={}s",
+            config.default_temperature, config.default_max_tokens, config.timeout
+        ));
+        debug.log(format!(
+            "payload: {} messages, {} chars, {} tools, budget={:?}, lang={:?}, mode={:?}",
+            messages.len(),
+            messages.iter().map(|m| m.content.chars().count()).sum::<usize>(),
+            functions.len(),
+            tool_budget,
+            code_lang,
+            output_mode
+        ));
+    }
+
     // Execute function calling
     agent_trace!(user_id, "execute_agent_task", "Starting function calling execution...");
-    let result = match execute_function_calling(&messages, &functions, &config, user_id, Some(&mut response_file)).await {
+    let calling_start = Instant::now();
+    let calling_result = execute_function_calling(&messages, &functions, &config, user_id, code_lang.as_deref(), tool_budget, response_file.as_mut()).await;
+    if debug.is_enabled() {
+        debug.log(format!("function calling: {} ms", calling_start.elapsed().as_millis()));
+        for record in get_function_call_history(user_id).await.iter().filter(|record| record.timestamp >= started_at) {
+            let args = serde_json::to_string(&record.arguments).unwrap_or_else(|_| "{}".to_string());
+            debug.log(format!(
+                "tool {}: args {} -> {}",
+                record.function_name,
+                truncate_chars(&args, 300),
+                truncate_chars(&record.result.replace('\n', " "), 300)
+            ));
+        }
+    }
+    let result = match calling_result {
         Ok(result) => {
             agent_info!(user_id, "execute_agent_task", "Successfully executed function calling");
             agent_trace!(user_id, "execute_agent_task", "Function calling result length: {} chars", result.len());
-            agent_trace!(user_id, "execute_agent_task", "Function calling result preview: {}", &result[..std::cmp::min(200, result.len())]);
+            agent_trace!(user_id, "execute_agent_task", "Function calling result preview: {}", truncate_chars(&result, 200));
             result
         }
         Err(e) => {
             agent_error!(user_id, "execute_agent_task", "Failed to execute function calling: {}", e);
-            write_to_response_file(Some(&mut response_file), &format!("❌ Error: {}", e), user_id);
+            debug.log(format!("error: {}", e));
             
-            // Close the file and upload error result
-            drop(response_file);
-            let _ = msg.reply(ctx, "❌ Task failed. Check the uploaded file for details.").await;
+            // Close and remove the temporary file so it isn't left behind
+            if response_file.take().is_some() {
+                let _ = std::fs::remove_file(&response_filename);
+            }
+            let _ = msg.reply(ctx, with_error_id(style_output(format!("❌ Task failed: {}", e)))).await;
             return Ok(());
         }
     };
 
+    // The answer is delivered without <think> blocks; --explain posts the captured reasoning separately
+    let (answer, reasoning) = split_thinking(&result);
+    let result = text_or_no_output_notice(answer, config.default_max_tokens, None);
+    debug.log(format!("result: {} visible chars, {} reasoning chars", result.chars().count(), reasoning.chars().count()));
+    if !reasoning.is_empty() {
+        agent_debug!(user_id, "execute_agent_task", "Captured {} chars of reasoning from <think> blocks", reasoning.len());
+    }
+
     // Write completion status to file
-    write_to_response_file(Some(&mut response_file), "✅ Task completed successfully! Preparing final response...", user_id);
+    write_to_response_file(response_file.as_mut(), "✅ Task completed successfully! Preparing final response...", user_id);
     
     // Save the conversation to context for future use
     agent_trace!(user_id, "execute_agent_task", "Saving conversation to context...");
@@ -1046,63 +1683,89 @@ async fn execute_agent_task(
         content: result.clone(),
     }).await;
 
-    // Write final result to file
-    write_to_response_file(Some(&mut response_file), "=== FINAL RESULT ===", user_id);
-    write_to_response_file(Some(&mut response_file), &result, user_id);
+    let max_len = config.max_discord_message_length - config.response_format_padding;
+    let upload_file = should_upload_result_file(output_mode, &result);
+    let json_delivered = json_output && deliver_json_result(ctx, msg, &result, &config, max_len).await;
+
+    if json_delivered {
+        agent_info!(user_id, "execute_agent_task", "Delivered result as JSON");
+        if response_file.take().is_some() {
+            if let Err(e) = std::fs::remove_file(&response_filename) {
+                agent_warn!(user_id, "execute_agent_task", "Failed to remove temporary file {}: {}", response_filename, e);
+            }
+        }
+        let _ = thinking_msg.edit(&ctx.http, |m| m.content(style_output("✅ **Agent Task Complete**"))).await;
+    } else if !upload_file {
+        // Short result or --inline: reply inline, split to stay within Discord's limit
+        agent_info!(user_id, "execute_agent_task", "Delivering result inline ({} chars, mode {:?})", result.len(), output_mode);
+        let inline_message = crate::commands::util::with_response_footer(&format!(
+            "✅ **Agent Task Complete**\n\n{}\n\n📝 **Context Saved** - Your conversation history is preserved for future ^agent commands.",
+            result
+        ));
+        if let Err(e) = crate::commands::util::send_long_message(ctx, msg.channel_id, &inline_message, max_len, &response_filename).await {
+            agent_error!(user_id, "execute_agent_task", "Failed to send inline result: {}", e);
+        }
+        if response_file.take().is_some() {
+            if let Err(e) = std::fs::remove_file(&response_filename) {
+                agent_warn!(user_id, "execute_agent_task", "Failed to remove temporary file {}: {}", response_filename, e);
+            }
+        }
+        let _ = thinking_msg.edit(&ctx.http, |m| m.content(style_output("✅ **Agent Task Complete**"))).await;
+    } else {
+        // Write final result to file
+        write_to_response_file(response_file.as_mut(), "=== FINAL RESULT ===", user_id);
+        write_to_response_file(response_file.as_mut(), &result, user_id);
     
-    // Close the file
-    drop(response_file);
+        // Close the file
+        drop(response_file);
     
-    // Upload the response file to Discord
-    agent_info!(user_id, "execute_agent_task", "Uploading response file: {}", response_filename);
+        // Upload the response file to Discord
+        agent_info!(user_id, "execute_agent_task", "Uploading response file: {}", response_filename);
     
-    let file_content = match std::fs::read_to_string(&response_filename) {
-        Ok(content) => content,
-        Err(e) => {
-            agent_error!(user_id, "execute_agent_task", "Failed to read response file: {}", e);
-            let _ = msg.reply(ctx, "❌ Failed to read response file").await;
-            return Ok(());
-        }
-    };
+        let file_content = match std::fs::read_to_string(&response_filename) {
+            Ok(content) => content,
+            Err(e) => {
+                agent_error!(user_id, "execute_agent_task", "Failed to read response file: {}", e);
+                let _ = msg.reply(ctx, with_error_id(style_output("❌ Failed to read response file"))).await;
+                return Ok(());
+            }
+        };
 
-    // Create a summary for Discord message
-    let summary = if result.len() > 500 {
-        format!("{}...", &result[..500])
-                    } else {
-        result.clone()
-    };
-    
-    let discord_message = format!(
-        "✅ **Agent Task Complete**\n\n**Summary:**\n{}\n\n📎 **Full Response:** See attached file\n\n📝 **Context Saved** - Your conversation history is preserved for future ^agent commands.",
-        summary
-    );
+        // Word-boundary preview for the fallback; the upload message shows the whole result when it fits
+        let summary = truncate_at_word(&result, AGENT_SUMMARY_PREVIEW_CHARS);
+        let discord_message = crate::commands::util::with_response_footer(&upload_message(&result, max_len));
     
-    // Upload file to Discord
-    match msg.channel_id.send_files(&ctx.http, vec![(&*file_content.as_bytes(), response_filename.as_str())], |m| {
-        m.content(&discord_message)
-    }).await {
-        Ok(_) => {
-            agent_info!(user_id, "execute_agent_task", "Successfully uploaded response file to Discord");
+        // Upload file to Discord; oversized or rate-limited uploads are split, retried or inlined
+        match crate::commands::util::send_file_with_fallback(ctx, msg.channel_id, &file_content, &response_filename, &discord_message, max_len).await {
+            Ok(_) => {
+                agent_info!(user_id, "execute_agent_task", "Successfully uploaded response file to Discord");
+            }
+            Err(e) => {
+                agent_error!(user_id, "execute_agent_task", "Failed to upload response file: {}", e);
+                // Fallback to regular message, split so Discord can't reject it for length
+                let fallback_message = crate::commands::util::with_response_footer(&format!("✅ **Agent Task Complete**\n\n{}\n\n📝 **Context Saved**", summary));
+                if let Err(e) = crate::commands::util::send_long_message(ctx, msg.channel_id, &fallback_message, max_len, &response_filename).await {
+                    agent_error!(user_id, "execute_agent_task", "Fallback message also failed: {}", e);
                 }
-                Err(e) => {
-            agent_error!(user_id, "execute_agent_task", "Failed to upload response file: {}", e);
-            // Fallback to regular message
-            let fallback_message = format!("✅ **Agent Task Complete**\n\n{}\n\n📝 **Context Saved**", summary);
-            let _ = msg.channel_id.send_message(&ctx.http, |m| m.content(&fallback_message)).await;
+            }
         }
-    }
     
-    // Clean up the temporary file
-    if let Err(e) = std::fs::remove_file(&response_filename) {
-        agent_warn!(user_id, "execute_agent_task", "Failed to remove temporary file {}: {}", response_filename, e);
-    } else {
-        agent_debug!(user_id, "execute_agent_task", "Successfully removed temporary file: {}", response_filename);
-    }
+        // Clean up the temporary file
+        if let Err(e) = std::fs::remove_file(&response_filename) {
+            agent_warn!(user_id, "execute_agent_task", "Failed to remove temporary file {}: {}", response_filename, e);
+        } else {
+            agent_debug!(user_id, "execute_agent_task", "Successfully removed temporary file: {}", response_filename);
+        }
     
-    // Update status message to indicate completion
-    let _ = thinking_msg.edit(&ctx.http, |m| {
-        m.content("✅ **Agent Task Complete** - Response file uploaded successfully!")
-    }).await;
+        // Update status message to indicate completion
+        let _ = thinking_msg.edit(&ctx.http, |m| {
+            m.content(style_output("✅ **Agent Task Complete** - Response file uploaded successfully!"))
+        }).await;
+    }
+
+    if explain {
+        send_reasoning_trace(ctx, msg, &reasoning).await;
+    }
 
     let total_duration = start_time.elapsed();
     agent_trace!(user_id, "execute_agent_task", "=== EXECUTE AGENT TASK END ===");
@@ -1113,11 +1776,190 @@ async fn execute_agent_task(
     Ok(())
 }
 
+// Post the --explain reasoning trace: a spoiler for short traces, an attached file for long ones
+async fn send_reasoning_trace(ctx: &Context, msg: &Message, reasoning: &str) {
+    let user_id = msg.author.id;
+    // A "||" inside the trace would close the spoiler early; escaped first so the length check sees what is sent
+    let spoiler_trace = reasoning.replace("||", "| |");
+    let result = if reasoning.trim().is_empty() {
+        msg.channel_id.say(&ctx.http, style_output("ℹ️ **Reasoning:** the model did not emit a reasoning trace (`<think>` block) for this task.")).await.map(|_| ())
+    } else if spoiler_trace.chars().count() <= EXPLAIN_SPOILER_MAX_CHARS {
+        // Spoiler bars can't span code fences, so the trace is shown as plain text
+        msg.channel_id.say(&ctx.http, format!("🧠 **Reasoning** (click to reveal)\n||{}||", spoiler_trace)).await.map(|_| ())
+    } else {
+        let filename = format!("agent_reasoning_{}.txt", user_id);
+        msg.channel_id.send_files(&ctx.http, vec![(reasoning.as_bytes(), filename.as_str())], |m| {
+            m.content(style_output("🧠 **Reasoning** - the full trace is attached"))
+        }).await.map(|_| ())
+    };
+    if let Err(e) = result {
+        agent_error!(user_id, "send_reasoning_trace", "Failed to send reasoning trace: {}", e);
+    }
+}
+
+// First non-empty ```javascript block in a function result or agent response
+fn extract_executed_code(text: &str) -> Option<String> {
+    let start = text.find("```javascript")?;
+    let end = text[start..].find("```\n")?;
+    let code = text[start + "```javascript".len()..start + end].trim();
+    if code.is_empty() {
+        None
+    } else {
+        Some(code.to_string())
+    }
+}
+
+// --lang aliases, mapped onto the fence tag Discord highlights
+const CODE_LANGUAGE_ALIASES: &[(&str, &str)] = &[
+    ("js", "javascript"),
+    ("py", "python"),
+    ("ts", "typescript"),
+    ("rs", "rust"),
+    ("c++", "cpp"),
+    ("c#", "csharp"),
+    ("cs", "csharp"),
+    ("sh", "bash"),
+    ("golang", "go"),
+    ("rb", "ruby"),
+];
+
+// Validate a --lang value and return its canonical fence tag
+fn normalize_code_language(input: &str) -> Result<String, String> {
+    let lang = input.trim().to_lowercase();
+    if lang.is_empty() || lang.len() > 20 || !lang.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '#' | '-')) {
+        return Err(format!("`{}` is not a language name (e.g. `python`, `rust`, `go`)", input.trim()));
+    }
+    Ok(CODE_LANGUAGE_ALIASES.iter()
+        .find(|(alias, _)| *alias == lang)
+        .map(|(_, tag)| tag.to_string())
+        .unwrap_or(lang))
+}
+
+// System prompt addendum for --lang: computations still run as JavaScript, the presented solution uses `lang`
+fn code_language_guidance(lang: &str) -> String {
+    format!(
+        "\n\n**Presentation Language:** The user wants the solution shown in {lang}. \
+        Keep using execute_js_code (JavaScript) for anything you actually compute or test, since that is the only code that can run. \
+        In your final answer, present the solution as {lang} code in a ```{lang} block and say that it is illustrative and was not executed.",
+        lang = lang
+    )
+}
+
+// First non-empty ```<tag> block in a response
+fn extract_fenced_code(text: &str, tag: &str) -> Option<String> {
+    let fence = format!("```{}\n", tag);
+    let start = text.find(&fence)? + fence.len();
+    let end = text[start..].find("```")?;
+    let code = text[start..start + end].trim();
+    if code.is_empty() {
+        None
+    } else {
+        Some(code.to_string())
+    }
+}
+
+// The code block(s) closing an agent result
+// With --lang, the model's illustrative version leads and the sandboxed JavaScript is labelled as what actually ran
+fn ready_to_use_code_section(executed_code: &str, response: &str, code_lang: Option<&str>) -> String {
+    let lang = match code_lang {
+        Some(lang) => lang,
+        None => return format!("🚀 **Ready-to-Use Code:**\n```javascript\n{}\n```\n\n✨ **Copy the code above to use it in your project!**", executed_code),
+    };
+    match extract_fenced_code(response, lang) {
+        Some(illustrative) => format!(
+            "🚀 **Ready-to-Use Code ({lang}, illustrative - not executed):**\n```{lang}\n{}\n```\n\n🧪 **Executed in the JavaScript sandbox:**\n```javascript\n{}\n```",
+            illustrative, executed_code, lang = lang
+        ),
+        None => format!(
+            "🧪 **Executed in the JavaScript sandbox:**\n```javascript\n{}\n```\n\nℹ️ The model did not provide a {} version; the JavaScript above is the code that ran.",
+            executed_code, lang
+        ),
+    }
+}
+
+// Parse the model's --json reply, tolerating code fences or text around the object
+fn parse_agent_json_output(response: &str) -> Result<AgentJsonOutput, String> {
+    let (answer, _) = split_thinking(response);
+    let start = answer.find('{').ok_or("no JSON object in the response")?;
+    let end = answer.rfind('}').filter(|&end| end > start).ok_or("the JSON object is not closed")?;
+    serde_json::from_str(&answer[start..=end]).map_err(|e| e.to_string())
+}
+
+// Restate a finished task as { answer, code, steps }, retrying once when the JSON doesn't parse
+async fn structure_agent_result(result: &str, config: &LMConfig, user_id: UserId) -> Result<AgentJsonOutput, String> {
+    let backend = HttpChatBackend { max_retries: config.max_retries };
+    let mut messages = vec![
+        ChatMessage { role: "system".to_string(), content: AGENT_JSON_INSTRUCTION.to_string() },
+        ChatMessage { role: "user".to_string(), content: result.to_string() },
+    ];
+    let mut last_error = String::new();
+    for attempt in 1..=2 {
+        let (response, _) = get_final_response(&backend, &messages, &[], config, user_id, None).await.map_err(|e| e.to_string())?;
+        match parse_agent_json_output(&response) {
+            Ok(mut output) => {
+                // The code the sandbox actually ran wins over whatever the model restated
+                if let Some(code) = extract_executed_code(result) {
+                    output.code = code;
+                }
+                return Ok(output);
+            }
+            Err(e) => {
+                agent_warn!(user_id, "structure_agent_result", "Attempt {} did not return valid JSON: {}", attempt, e);
+                messages.push(ChatMessage { role: "assistant".to_string(), content: response });
+                messages.push(ChatMessage {
+                    role: "user".to_string(),
+                    content: format!("That was not valid JSON ({}). Reply again with only the JSON object.", e),
+                });
+                last_error = e;
+            }
+        }
+    }
+    Err(last_error)
+}
+
+// --json delivery: a ```json block when it fits, otherwise an attached .json file
+// Returns false (after posting a warning) when the model never produced valid JSON
+async fn deliver_json_result(ctx: &Context, msg: &Message, result: &str, config: &LMConfig, max_len: usize) -> bool {
+    let user_id = msg.author.id;
+    let output = match structure_agent_result(result, config, user_id).await {
+        Ok(output) => output,
+        Err(e) => {
+            agent_warn!(user_id, "deliver_json_result", "Falling back to plain text: {}", e);
+            let _ = msg.channel_id.say(&ctx.http, style_output("⚠️ The model could not produce valid JSON for this task, showing plain text instead.")).await;
+            return false;
+        }
+    };
+    let json = match serde_json::to_string_pretty(&output) {
+        Ok(json) => json,
+        Err(e) => {
+            agent_error!(user_id, "deliver_json_result", "Failed to serialize JSON output: {}", e);
+            return false;
+        }
+    };
+
+    let inline = crate::commands::util::with_response_footer(&format!("✅ **Agent Task Complete**\n```json\n{}\n```", json));
+    let sent: Result<(), Box<dyn std::error::Error + Send + Sync>> = if inline.chars().count() <= max_len {
+        msg.channel_id.say(&ctx.http, inline).await.map(|_| ()).map_err(Into::into)
+    } else {
+        // Oversized or rate-limited uploads are split, retried or inlined like the plain-text result
+        let filename = format!("agent_response_{}.json", user_id);
+        let message = crate::commands::util::with_response_footer(&style_output("✅ **Agent Task Complete** - JSON result attached"));
+        crate::commands::util::send_file_with_fallback(ctx, msg.channel_id, &json, &filename, &message, max_len).await
+    };
+    if let Err(e) = sent {
+        agent_error!(user_id, "deliver_json_result", "Failed to send JSON result: {}", e);
+        return false;
+    }
+    true
+}
+
 async fn execute_function_calling(
     messages: &[ChatMessage],
     functions: &[FunctionDefinition],
     config: &LMConfig,
     user_id: UserId,
+    code_lang: Option<&str>,
+    tool_budget: Option<usize>,
     mut response_file: Option<&mut std::fs::File>,
 ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
     agent_trace!(user_id, "execute_function_calling", "=== EXECUTE FUNCTION CALLING START ===");
@@ -1132,18 +1974,15 @@ async fn execute_function_calling(
         // Update thinking message if available
     write_to_response_file(response_file.as_deref_mut(), "🔄 Connecting to AI model and preparing request", user_id);
     
-    agent_trace!(user_id, "execute_function_calling", "Getting HTTP client...");
-        let client = get_http_client().await;
-    agent_trace!(user_id, "execute_function_calling", "HTTP client obtained successfully");
-        
     // Set stream: true for function calling
+        let sampling = config.sampling_for(&config.default_model);
         let chat_request = ChatRequest {
         model: config.default_model.clone(),
         messages: messages.to_vec(),
-            temperature: config.default_temperature,
-            max_tokens: config.default_max_tokens,
+            temperature: sampling.temperature,
+            max_tokens: sampling.max_tokens,
         stream: true, // Enable streaming for function calling
-            seed: config.default_seed,
+            seed: sampling.seed,
         tools: Some(functions.to_vec()),
         tool_choice: Some("auto".to_string()),
         };
@@ -1154,88 +1993,385 @@ async fn execute_function_calling(
     agent_trace!(user_id, "execute_function_calling", "Using Ollama OpenAI-compatible endpoint for model: {}", config.default_model);
     agent_trace!(user_id, "execute_function_calling", "Request timeout: {} seconds", config.timeout);
     
-    // Test connectivity to Ollama server
-    let health_url = format!("{}/api/tags", config.base_url);
-    agent_trace!(user_id, "execute_function_calling", "Testing connectivity to: {}", health_url);
-    match client.get(&health_url).timeout(Duration::from_secs(10)).send().await {
-        Ok(resp) => {
-            agent_trace!(user_id, "execute_function_calling", "Connectivity test successful, status: {}", resp.status());
+    let backend = HttpChatBackend { max_retries: config.max_retries };
+    backend.check_health(&config.base_url, user_id).await;
+    
+    agent_debug!(user_id, "execute_function_calling", "Sending request to: {}", api_url);
+    agent_trace!(user_id, "execute_function_calling", "Request payload: {}", serde_json::to_string_pretty(&chat_request).unwrap_or_else(|_| "Failed to serialize request".to_string()));
+    
+    // Update thinking message if available (use a different approach to avoid ownership issues)
+    write_to_response_file(response_file.as_deref_mut(), "🔄 Sending request to AI model and waiting for response...", user_id);
+        
+    let (buffer, collected_tool_calls) = collect_function_calls(&backend, &chat_request, &api_url, config.timeout, user_id, response_file.as_deref_mut()).await?;
+    
+    // After streaming, execute any collected tool calls
+    if !collected_tool_calls.is_empty() {
+        agent_debug!(user_id, "execute_function_calling", "Found {} tool calls to execute", collected_tool_calls.len());
+        
+        // Write function execution status to file
+        write_to_response_file(response_file.as_deref_mut(), "🔄 Executing functions...", user_id);
+        
+        // Execute each tool call, stopping once --budget executions have run in total
+        let mut function_results = Vec::new();
+        let mut tools_run = 0;
+        let mut skipped_calls = 0;
+        for tool_call in &collected_tool_calls {
+            if tool_budget.map_or(false, |budget| tools_run >= budget) {
+                agent_info!(user_id, "execute_function_calling", "Tool budget reached, skipping '{}'", tool_call.function.name);
+                skipped_calls += 1;
+                continue;
+            }
+            tools_run += 1;
+            agent_trace!(user_id, "execute_function_calling", "=== EXECUTE FUNCTION CALL START ===");
+            agent_trace!(user_id, "execute_function_calling", "Function: {}", tool_call.function.name);
+            agent_trace!(user_id, "execute_function_calling", "Arguments: {}", serde_json::to_string_pretty(&tool_call.function.arguments).unwrap_or_else(|_| "Failed to serialize".to_string()));
+            
+            match execute_function_call(&tool_call.function, &tool_call.id, user_id).await {
+                Ok(result) => {
+                    agent_info!(user_id, "execute_function_calling", "Function '{}' executed successfully", tool_call.function.name);
+                    agent_trace!(user_id, "execute_function_calling", "Function result: {}", result);
+                    function_results.push(format!("✅ {}: {}", tool_call.function.name, result));
+            }
+            Err(e) => {
+                    agent_error!(user_id, "execute_function_calling", "Function '{}' failed: {}", tool_call.function.name, e);
+                    function_results.push(format!("❌ {}: Error - {}", tool_call.function.name, e));
+                }
+            }
+            agent_trace!(user_id, "execute_function_calling", "=== EXECUTE FUNCTION CALL END ===");
+        }
+        
+        // Send function results back to the model for analysis and final response
+        agent_debug!(user_id, "execute_function_calling", "Sending function results back to model for final processing");
+        
+        // Write final processing status to file
+        write_to_response_file(response_file.as_deref_mut(), "🔄 Processing function results and generating final response...", user_id);
+        
+        // Create messages with function results for final response
+        let mut final_messages = messages.to_vec();
+        
+        // Add the assistant's original response (if any)
+        if !buffer.trim().is_empty() {
+            final_messages.push(ChatMessage {
+                role: "assistant".to_string(),
+                content: buffer.clone(),
+            });
+        }
+        
+        // Add function results as tool responses
+        for (i, result) in function_results.iter().enumerate() {
+            final_messages.push(ChatMessage {
+                role: "tool".to_string(),
+                content: format!("Function execution result {}: {}", i + 1, result),
+            });
+        }
+        
+        // Add a user message asking for analysis
+        let budget_note = tool_budget.filter(|_| skipped_calls > 0).map(|_| tool_budget_note(tools_run, skipped_calls));
+        let analysis_request = if budget_note.is_some() {
+            "The tool budget for this task has been reached, so no more tools will run. Conclude with the best final answer you can give from the function results above, and say what is left unverified."
+        } else {
+            "Please analyze the function results above and provide a comprehensive final answer. If there were any errors, explain what went wrong and suggest fixes."
+        };
+        final_messages.push(ChatMessage {
+            role: "user".to_string(),
+            content: analysis_request.to_string(),
+        });
+        
+        // Get final response from model with function results
+        match get_final_response(&backend, &final_messages, functions, config, user_id, response_file.as_deref_mut()).await {
+            Ok((final_response, finish_reason)) => {
+                agent_info!(user_id, "execute_function_calling", "Successfully got final response from model");
+                
+        // Extract any JavaScript code from function results for prominent display
+        let executed_code = function_results.iter().find_map(|result| extract_executed_code(result)).unwrap_or_default();
+
+        // Combine everything into a comprehensive response with code prominently displayed
+        let comprehensive_response = if final_response.trim().is_empty() {
+            // If no analysis from model, show results with code emphasis
+            if !executed_code.is_empty() {
+                format!(
+                    "**JavaScript Execution Results:**\n{}\n\n{}", 
+                    function_results.join("\n\n"),
+                    ready_to_use_code_section(&executed_code, "", code_lang)
+                )
+                    } else {
+                format!("**Execution Results:**\n{}", function_results.join("\n\n"))
+            }
+        } else {
+            // Include results, analysis, and prominently display code
+            if !executed_code.is_empty() {
+                format!(
+                    "**JavaScript Execution Results:**\n{}\n\n**AI Analysis:**\n{}\n\n{}", 
+                    function_results.join("\n\n"), 
+                    final_response,
+                    ready_to_use_code_section(&executed_code, &final_response, code_lang)
+                )
+            } else {
+                format!(
+                    "**Execution Results:**\n{}\n\n**AI Analysis:**\n{}", 
+                    function_results.join("\n\n"), 
+                    final_response
+                )
+            }
+        };
+        
+        Ok(append_budget_note(append_finish_reason_note(comprehensive_response, finish_reason.as_deref()), budget_note.as_deref()))
+                }
+                Err(e) => {
+                agent_warn!(user_id, "execute_function_calling", "Failed to get final response, using function results only: {}", e);
+                
+                // Extract code for fallback as well
+                let executed_code_fallback = function_results.iter().find_map(|result| extract_executed_code(result)).unwrap_or_default();
+                
+                // Fallback to just function results if final response fails
+                let fallback_response = if buffer.trim().is_empty() {
+                    if !executed_code_fallback.is_empty() {
+                        format!(
+                            "**JavaScript Execution Results:**\n{}\n\n{}", 
+                            function_results.join("\n\n"),
+                            ready_to_use_code_section(&executed_code_fallback, "", code_lang)
+                        )
+                    } else {
+                        format!("**Execution Results:**\n{}", function_results.join("\n\n"))
+                    }
+                } else {
+                    if !executed_code_fallback.is_empty() {
+                        format!(
+                            "**AI Response:**\n{}\n\n**JavaScript Execution Results:**\n{}\n\n{}", 
+                            buffer, 
+                            function_results.join("\n\n"),
+                            ready_to_use_code_section(&executed_code_fallback, &buffer, code_lang)
+                        )
+                    } else {
+                        format!("**AI Response:**\n{}\n\n**Execution Results:**\n{}", buffer, function_results.join("\n\n"))
+                    }
+                };
+                
+                Ok(append_budget_note(fallback_response, budget_note.as_deref()))
+            }
+        }
+    } else {
+        // No tool calls, just return the text response
+        write_to_response_file(response_file.as_deref_mut(), "✅ No function calls needed, returning text response", user_id);
+        let mut buffer = buffer;
+        let mut finish_reason = None;
+        // Nothing visible (empty, or only a <think> block): ask once more with a nudge before giving up
+        let retry_messages = empty_response_retry(messages, &split_thinking(&buffer).0, |content| ChatMessage { role: "user".to_string(), content });
+        if let Some(retry_messages) = retry_messages {
+            agent_warn!(user_id, "execute_function_calling", "Model returned neither text nor tool calls (max_tokens: {}), retrying once", config.default_max_tokens);
+            match get_final_response(&backend, &retry_messages, functions, config, user_id, response_file.as_deref_mut()).await {
+                Ok((retry_buffer, retry_finish_reason)) => {
+                    buffer = retry_buffer;
+                    finish_reason = retry_finish_reason;
+                }
+                Err(e) => agent_warn!(user_id, "execute_function_calling", "Retry after an empty response failed: {}", e),
+            }
         }
-        Err(e) => {
-            agent_warn!(user_id, "execute_function_calling", "Connectivity test failed: {}", e);
+        
+        let mut text = text_or_no_output_notice(buffer, config.default_max_tokens, finish_reason.as_deref());
+        if let Some(lang) = code_lang {
+            if text.contains("```") {
+                text.push_str(&format!("\n\nℹ️ **Illustrative {} code** - nothing in this answer was run in the JavaScript sandbox.", lang));
+            }
         }
+        Ok(text)
     }
-    
-    agent_debug!(user_id, "execute_function_calling", "Sending request to: {}", api_url);
-    agent_trace!(user_id, "execute_function_calling", "Request payload: {}", serde_json::to_string_pretty(&chat_request).unwrap_or_else(|_| "Failed to serialize request".to_string()));
-    
-    // Update thinking message if available (use a different approach to avoid ownership issues)
-    write_to_response_file(response_file.as_deref_mut(), "🔄 Sending request to AI model and waiting for response...", user_id);
-        
-    agent_trace!(user_id, "execute_function_calling", "About to send HTTP POST request...");
-    
-    // Instead of waiting for the full response, process the stream
-    let response = match tokio::time::timeout(Duration::from_secs(config.timeout as u64), client
-            .post(&api_url)
-            .json(&chat_request)
-            .timeout(Duration::from_secs(config.timeout as u64))
-            .send()
-    ).await {
-        Ok(Ok(resp)) => {
-            agent_trace!(user_id, "execute_function_calling", "HTTP request completed successfully");
-            agent_debug!(user_id, "execute_function_calling", "Received response with status: {}", resp.status());
-                resp
+}
+
+// Never hand the caller an empty buffer: a model that streamed nothing usually ran out of tokens
+// (often spent inside <think> blocks), so say so and show the limit and finish_reason that were in effect
+fn text_or_no_output_notice(buffer: String, max_tokens: i32, finish_reason: Option<&str>) -> String {
+    if !is_empty_response(&split_thinking(&buffer).0) {
+        return buffer;
+    }
+    format!(
+        "⚠️ **The model produced no output** (finish_reason: `{}`; possibly max_tokens too low - the current limit is {}). \
+        Try raising DEFAULT_MAX_TOKENS in lmapiconf.txt or simplifying the request.",
+        finish_reason.unwrap_or("not reported"),
+        max_tokens
+    )
+}
+
+// "Tool budget reached" notice for --budget, naming how many calls ran in total and how many were not run
+fn tool_budget_note(tools_run: usize, skipped_calls: usize) -> String {
+    format!(
+        "⚠️ **Tool budget reached** - {} tool execution{} ran; {} further call{} skipped (raise `--budget` to allow more).",
+        tools_run, if tools_run == 1 { "" } else { "s" },
+        skipped_calls, if skipped_calls == 1 { " was" } else { "s were" }
+    )
+}
+
+fn append_budget_note(mut text: String, note: Option<&str>) -> String {
+    if let Some(note) = note {
+        text.push_str("\n\n");
+        text.push_str(note);
+    }
+    text
+}
+
+// Tell the user when the model was cut off by max_tokens or stopped by a content filter
+fn append_finish_reason_note(mut text: String, finish_reason: Option<&str>) -> String {
+    if let Some(note) = finish_reason_note(finish_reason, None) {
+        text.push_str("\n\n");
+        text.push_str(&note);
+    }
+    text
+}
+
+// Key a streamed tool-call delta to the call it belongs to
+// Backends correlate chunks by index, by id, or (after the first chunk) not at all, in which case the delta continues the latest call
+fn resolve_tool_call_key(
+    tool_call: &serde_json::Value,
+    keys_by_id: &mut HashMap<String, String>,
+    last_key: Option<&str>,
+) -> Option<String> {
+    let id = tool_call.get("id").and_then(|i| i.as_str()).filter(|id| !id.is_empty());
+    let key = match (tool_call.get("index").and_then(|i| i.as_u64()), id) {
+        (Some(index), _) => format!("call_{}", index),
+        (None, Some(id)) => keys_by_id.get(id).cloned().unwrap_or_else(|| id.to_string()),
+        (None, None) => last_key?.to_string(),
+    };
+    if let Some(id) = id {
+        keys_by_id.entry(id.to_string()).or_insert_with(|| key.clone());
+    }
+    Some(key)
+}
+
+// Why streamed tool-call arguments could not be parsed
+#[derive(Debug, PartialEq)]
+enum ToolArgsError {
+    /// Cut off mid-JSON (usually max_tokens) and not recoverable by closing it
+    Truncated,
+    /// Complete but malformed JSON
+    Invalid(String),
+}
+
+// Close JSON that was cut off mid-stream: finish an open string (dropping a dangling escape),
+// drop a trailing comma and close every open object/array. Raw newlines and tabs inside strings
+// are escaped on the way. Returns the result and whether anything had been left open
+fn close_truncated_json(input: &str) -> (String, bool) {
+    let mut out = String::with_capacity(input.len() + 8);
+    let mut closers = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in input.chars() {
+        if in_string {
+            match c {
+                _ if escaped => {
+                    escaped = false;
+                    out.push(c);
+                }
+                '\\' => {
+                    escaped = true;
+                    out.push(c);
+                }
+                '"' => {
+                    in_string = false;
+                    out.push(c);
+                }
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\t' => out.push_str("\\t"),
+                _ => out.push(c),
             }
-        Ok(Err(e)) => {
-            agent_trace!(user_id, "execute_function_calling", "HTTP request failed with error");
-            agent_error!(user_id, "execute_function_calling", "HTTP request failed: {}", e);
-                return Err(e.into());
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' => closers.push('}'),
+            '[' => closers.push(']'),
+            '}' | ']' => {
+                closers.pop();
             }
-        Err(_) => {
-            agent_trace!(user_id, "execute_function_calling", "HTTP request timed out");
-            agent_error!(user_id, "execute_function_calling", "HTTP request timed out after {} seconds", config.timeout);
-            return Err("HTTP request timed out".into());
+            _ => {}
         }
-        };
+        out.push(c);
+    }
+
+    let truncated = in_string || !closers.is_empty();
+    if in_string {
+        if escaped {
+            out.pop();
+        }
+        out.push('"');
+    }
+    if truncated {
+        out.truncate(out.trim_end().len());
+        if out.ends_with(',') {
+            out.pop();
+        } else if out.ends_with(':') {
+            out.push_str("null");
+        }
+    }
+    while let Some(closer) = closers.pop() {
+        out.push(closer);
+    }
+    (out, truncated)
+}
 
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unable to read error response".to_string());
-        agent_error!(user_id, "execute_function_calling", "API returned error status {}: {}", status, error_text);
-        return Err(format!("Function calling failed: HTTP {} - {}", status, error_text).into());
+// Parse streamed tool-call arguments, repairing JSON that was cut off mid-stream
+// Returns the arguments and whether they had to be repaired
+fn parse_tool_arguments(args: &str) -> Result<(serde_json::Value, bool), ToolArgsError> {
+    let args = args.trim();
+    let error = match serde_json::from_str(args) {
+        Ok(value) => return Ok((value, false)),
+        Err(e) => e,
+    };
+    let (repaired, truncated) = close_truncated_json(args);
+    match serde_json::from_str(&repaired) {
+        Ok(value) => Ok((value, truncated)),
+        Err(_) if truncated || error.is_eof() => Err(ToolArgsError::Truncated),
+        Err(e) => Err(ToolArgsError::Invalid(e.to_string())),
     }
+}
+
+/// Stream one function-calling request and collect the model's text and tool calls
+/// Progress is mirrored to the response file; an error object in the stream aborts the run
+async fn collect_function_calls(
+    backend: &dyn ChatBackend,
+    chat_request: &ChatRequest,
+    api_url: &str,
+    timeout_secs: u64,
+    user_id: UserId,
+    mut response_file: Option<&mut std::fs::File>,
+) -> Result<(String, Vec<ToolCall>), Box<dyn std::error::Error + Send + Sync>> {
+    agent_trace!(user_id, "execute_function_calling", "About to send HTTP POST request...");
+    let mut events = match backend.stream_chat(api_url, chat_request, timeout_secs).await {
+        Ok(stream) => {
+            agent_trace!(user_id, "execute_function_calling", "HTTP request completed successfully");
+            ChatEventStream::new(stream)
+        }
+        Err(e) => {
+            agent_error!(user_id, "execute_function_calling", "Function calling request failed: {}", e);
+            return Err(e);
+        }
+    };
 
     // Create cache key for this request
     let cache_key = format!("{}_{}", user_id, chrono::Utc::now().timestamp_millis());
     let response_cache = get_response_cache().await;
     
     // --- SSE streaming logic ---
-    use futures_util::StreamExt;
-    let mut stream = response.bytes_stream();
     let mut buffer = String::new();
     let mut collected_tool_calls: Vec<ToolCall> = Vec::new();
     let mut function_call_buffer: std::collections::HashMap<String, (String, String)> = std::collections::HashMap::new();
+    let mut tool_call_keys: std::collections::HashMap<String, String> = std::collections::HashMap::new();
+    let mut last_call_key: Option<String> = None;
     let mut last_update = std::time::Instant::now();
     let update_interval = std::time::Duration::from_millis(250);
-    while let Some(chunk) = stream.next().await {
-        let chunk = match chunk {
-            Ok(c) => c,
+    while let Some(event) = events.next_event().await {
+        let json = match event {
+            Ok(json) => json,
             Err(e) => {
-                agent_error!(user_id, "execute_function_calling", "Stream error: {}", e);
-                break;
+                agent_error!(user_id, "execute_function_calling", "Stream failed: {}", e);
+                return Err(e);
             }
         };
-        let text = String::from_utf8_lossy(&chunk);
-        for line in text.lines() {
-            if let Some(data) = line.strip_prefix("data: ") {
-                if data.trim() == "[DONE]" { continue; }
-                // Try to parse as JSON
-                if let Ok(json) = serde_json::from_str::<serde_json::Value>(data) {
-                    // Try to extract content delta
-                    if let Some(choices) = json.get("choices").and_then(|c| c.as_array()) {
-                        for choice in choices {
-                            if let Some(delta) = choice.get("delta") {
-                                                    // Handle content deltas
+        // Try to extract content delta
+        if let Some(choices) = json.get("choices").and_then(|c| c.as_array()) {
+            for choice in choices {
+                if let Some(delta) = choice.get("delta") {
+                    // Handle content deltas
                     if let Some(content) = delta.get("content").and_then(|c| c.as_str()) {
                         buffer.push_str(content);
                         
@@ -1248,27 +2384,32 @@ async fn execute_function_calling(
                     // Handle tool_calls deltas
                     if let Some(tool_calls) = delta.get("tool_calls").and_then(|tc| tc.as_array()) {
                         for tool_call in tool_calls {
-                            if let Some(index) = tool_call.get("index").and_then(|i| i.as_u64()) {
-                                let call_id = format!("call_{}", index);
+                            if let Some(call_id) = resolve_tool_call_key(tool_call, &mut tool_call_keys, last_call_key.as_deref()) {
+                                last_call_key = Some(call_id.clone());
                                 
                                 if let Some(function) = tool_call.get("function") {
-                                    // Handle function name
-                                    if let Some(name) = function.get("name").and_then(|n| n.as_str()) {
-                                        if !function_call_buffer.contains_key(&call_id) {
+                                    // Handle function name; it may arrive before, with, or after the argument chunks
+                                    if let Some(name) = function.get("name").and_then(|n| n.as_str()).filter(|n| !n.is_empty()) {
+                                        let (existing_name, _) = function_call_buffer.entry(call_id.clone()).or_default();
+                                        if existing_name.is_empty() {
                                             buffer.push_str(&format!("\n🔧 **Function Call:** {}\n📝 **Status:** Receiving arguments via SSE stream...\n", name));
-                                            function_call_buffer.insert(call_id.clone(), (name.to_string(), String::new()));
+                                            *existing_name = name.to_string();
                                         }
                                     }
                                     
                                     // Handle function arguments (stream JavaScript code in real-time)
                                     if let Some(args_chunk) = function.get("arguments").and_then(|a| a.as_str()) {
-                                        if let Some((name, existing_args)) = function_call_buffer.get_mut(&call_id) {
-                                            existing_args.push_str(args_chunk);
-                                            
+                                        let (name, existing_args) = function_call_buffer.entry(call_id.clone()).or_default();
+                                        existing_args.push_str(args_chunk);
+
+                                        if name.is_empty() {
+                                            // Arguments are kept under the call's key until a delta names the function
+                                            agent_trace!(user_id, "execute_function_calling", "Buffered {} arg chars for unnamed call {}", existing_args.len(), call_id);
+                                        } else {
                                             // For execute_js_code, extract and preview the JavaScript code being streamed
-                                            if name == "execute_js_code" {
+                                            if name.as_str() == "execute_js_code" {
                                                 // Debug: Log the raw arguments to see what we're getting
-                                                let args_preview = if existing_args.len() > 200 { &existing_args[..200] } else { &existing_args };
+                                                let args_preview = truncate_chars(&existing_args, 200);
                                                 agent_trace!(user_id, "execute_function_calling", "Raw args buffer (first 200 chars): '{}'", args_preview);
                                                 
                                                 // Extract JavaScript code from the raw arguments string (simple approach)
@@ -1291,7 +2432,7 @@ async fn execute_function_calling(
                                                         
                                                         if code_content_start < existing_args.len() {
                                                             let remaining = &existing_args[code_content_start..];
-                                                            let remaining_preview = if remaining.len() > 100 { &remaining[..100] } else { remaining };
+                                                            let remaining_preview = truncate_chars(remaining, 100);
                                                             agent_trace!(user_id, "execute_function_calling", "Code content remaining (first 100 chars): '{}'", remaining_preview);
                                                             
                                                             // Find the end of the code string (look for unescaped quote)
@@ -1315,7 +2456,7 @@ async fn execute_function_calling(
                                                             
                                                             if code_end > 0 {
                                                                 let raw_code = &remaining[..code_end];
-                                                                let raw_code_preview = if raw_code.len() > 200 { &raw_code[..200] } else { raw_code };
+                                                                let raw_code_preview = truncate_chars(raw_code, 200);
                                                                 agent_trace!(user_id, "execute_function_calling", "Extracted raw code ({} chars): '{}'", raw_code.len(), raw_code_preview);
                                                                 
                                                                 // Simple unescape: just replace common escaped characters
@@ -1364,9 +2505,9 @@ async fn execute_function_calling(
                                                 let progress_text = if !code_preview.is_empty() && code_preview.len() > 20 {
                                                     let code_lines = code_preview.lines().count();
                                                     let code_chars = code_preview.len();
-                                                    let preview_code = if code_preview.len() > 800 {
-                                                        format!("{}...", &code_preview[..800])
-        } else {
+                                                    let preview_code = if code_preview.chars().count() > 800 {
+                                                        format!("{}...", truncate_chars(&code_preview, 800))
+                                                    } else {
                                                         code_preview.clone()
                                                     };
                                                     
@@ -1386,7 +2527,7 @@ async fn execute_function_calling(
                                                         "📊 **Status:** Receiving JavaScript arguments...\n📝 **Description:** {}\n📈 **Progress:** {} chars received\n\n🔄 **Parsing JavaScript code...**\n\n📋 **Raw Preview:** {}",
                                                         if !description_preview.is_empty() { &description_preview } else { "JavaScript execution" },
                                                         existing_args.len(),
-                                                        if existing_args.len() > 100 { format!("{}...", &existing_args[..100]) } else { existing_args.clone() }
+                                                        if existing_args.chars().count() > 100 { format!("{}...", truncate_chars(&existing_args, 100)) } else { existing_args.clone() }
                                                     )
                                                 };
                                                 
@@ -1438,9 +2579,6 @@ async fn execute_function_calling(
                                 }
                             }
                         }
-                    }
-                            }
-                        }
                     }
                 }
             }
@@ -1465,30 +2603,22 @@ async fn execute_function_calling(
 
     // Finalize tool calls from the accumulated buffer
     for (call_id, (name, args_str)) in function_call_buffer {
+        if name.is_empty() {
+            agent_warn!(user_id, "execute_function_calling", "Dropping tool call {}: {} arg chars arrived but no delta named the function", call_id, args_str.len());
+            continue;
+        }
         if !args_str.is_empty() {
-            // Try to fix common JSON issues before parsing
-            let cleaned_args = args_str
-                .trim()
-                .replace("\n", "\\n")  // Escape newlines
-                .replace("\r", "\\r")  // Escape carriage returns
-                .replace("\t", "\\t"); // Escape tabs
-            
-            agent_trace!(user_id, "execute_function_calling", "Attempting to parse args for {}: {}", name, &cleaned_args[..std::cmp::min(200, cleaned_args.len())]);
+            agent_trace!(user_id, "execute_function_calling", "Attempting to parse args for {}: {}", name, truncate_chars(&args_str, 200));
+            // Arguments cut off by max_tokens are closed up here instead of failing outright
+            let parsed_args = parse_tool_arguments(&args_str);
             
             // Update the final status in the buffer with complete JavaScript code
             let completion_text = if name == "execute_js_code" {
-                // Try to parse the complete JSON and extract the code
-                let cleaned_args = args_str
-                    .trim()
-                    .replace("\n", "\\n")
-                    .replace("\r", "\\r")
-                    .replace("\t", "\\t");
-                
                 let mut final_code = String::new();
                 let mut final_description = String::new();
                 
                 // Extract the complete JavaScript code from the final arguments
-                if let Ok(json_args) = serde_json::from_str::<serde_json::Value>(&cleaned_args) {
+                if let Ok((json_args, _)) = &parsed_args {
                     if let Some(code) = json_args.get("code").and_then(|c| c.as_str()) {
                         final_code = code.to_string();
                     }
@@ -1541,8 +2671,12 @@ async fn execute_function_calling(
                 buffer.clear();
             }
             
-            match serde_json::from_str::<serde_json::Value>(&cleaned_args) {
-                Ok(args) => {
+            match parsed_args {
+                Ok((args, repaired)) => {
+                    if repaired {
+                        agent_warn!(user_id, "execute_function_calling", "Arguments for {} were truncated and have been repaired ({} chars)", name, args_str.len());
+                        buffer.push_str(&format!("\n⚠️ **{}** arguments were cut off and repaired - consider increasing max_tokens (`DEFAULT_MAX_TOKENS`)\n", name));
+                    }
                     let tool_call = ToolCall {
                         id: call_id,
                         call_type: "function".to_string(),
@@ -1554,10 +2688,15 @@ async fn execute_function_calling(
                     collected_tool_calls.push(tool_call);
                     agent_debug!(user_id, "execute_function_calling", "Successfully parsed tool call: {}", name);
                 }
-                Err(e) => {
-                    agent_warn!(user_id, "execute_function_calling", "Failed to parse arguments for function {}: {} - Error: {}", name, &args_str[..std::cmp::min(100, args_str.len())], e);
+                Err(ToolArgsError::Truncated) => {
+                    // Dispatching a half-written call would run the wrong thing, so report it instead
+                    agent_warn!(user_id, "execute_function_calling", "Arguments for {} were truncated and could not be repaired: {}", name, truncate_chars(&args_str, 100));
+                    buffer.push_str(&format!("\n⚠️ **{}** was not run: its arguments were truncated (increase max_tokens, `DEFAULT_MAX_TOKENS`)\n", name));
+                }
+                Err(ToolArgsError::Invalid(e)) => {
+                    agent_warn!(user_id, "execute_function_calling", "Failed to parse arguments for function {}: {} - Error: {}", name, truncate_chars(&args_str, 100), e);
                     
-                    // Try to create a basic function call with the raw string as code parameter
+                    // Complete but non-JSON arguments for the JS tool are usually the bare code
                     if name == "execute_js_code" {
                         let fallback_args = serde_json::json!({
                             "code": args_str,
@@ -1573,6 +2712,8 @@ async fn execute_function_calling(
                         };
                         collected_tool_calls.push(tool_call);
                         agent_info!(user_id, "execute_function_calling", "Created fallback tool call for execute_js_code");
+                    } else {
+                        buffer.push_str(&format!("\n⚠️ **{}** was not run: its arguments were not valid JSON ({})\n", name, e));
                     }
                 }
             }
@@ -1591,251 +2732,69 @@ async fn execute_function_calling(
         }
     }
 
-    // After streaming, execute any collected tool calls
-    if !collected_tool_calls.is_empty() {
-        agent_debug!(user_id, "execute_function_calling", "Found {} tool calls to execute", collected_tool_calls.len());
-        
-        // Write function execution status to file
-        write_to_response_file(response_file.as_deref_mut(), "🔄 Executing functions...", user_id);
-        
-        // Execute each tool call
-        let mut function_results = Vec::new();
-        for tool_call in &collected_tool_calls {
-            agent_trace!(user_id, "execute_function_calling", "=== EXECUTE FUNCTION CALL START ===");
-            agent_trace!(user_id, "execute_function_calling", "Function: {}", tool_call.function.name);
-            agent_trace!(user_id, "execute_function_calling", "Arguments: {}", serde_json::to_string_pretty(&tool_call.function.arguments).unwrap_or_else(|_| "Failed to serialize".to_string()));
-            
-            match execute_function_call(&tool_call.function, user_id).await {
-                Ok(result) => {
-                    agent_info!(user_id, "execute_function_calling", "Function '{}' executed successfully", tool_call.function.name);
-                    agent_trace!(user_id, "execute_function_calling", "Function result: {}", result);
-                    function_results.push(format!("✅ {}: {}", tool_call.function.name, result));
-            }
-            Err(e) => {
-                    agent_error!(user_id, "execute_function_calling", "Function '{}' failed: {}", tool_call.function.name, e);
-                    function_results.push(format!("❌ {}: Error - {}", tool_call.function.name, e));
-                }
-            }
-            agent_trace!(user_id, "execute_function_calling", "=== EXECUTE FUNCTION CALL END ===");
-        }
-        
-        // Send function results back to the model for analysis and final response
-        agent_debug!(user_id, "execute_function_calling", "Sending function results back to model for final processing");
-        
-        // Write final processing status to file
-        write_to_response_file(response_file.as_deref_mut(), "🔄 Processing function results and generating final response...", user_id);
-        
-        // Create messages with function results for final response
-        let mut final_messages = messages.to_vec();
-        
-        // Add the assistant's original response (if any)
-        if !buffer.trim().is_empty() {
-            final_messages.push(ChatMessage {
-                role: "assistant".to_string(),
-                content: buffer.clone(),
-            });
-        }
-        
-        // Add function results as tool responses
-        for (i, result) in function_results.iter().enumerate() {
-            final_messages.push(ChatMessage {
-                role: "tool".to_string(),
-                content: format!("Function execution result {}: {}", i + 1, result),
-            });
-        }
-        
-        // Add a user message asking for analysis
-        final_messages.push(ChatMessage {
-            role: "user".to_string(),
-            content: "Please analyze the function results above and provide a comprehensive final answer. If there were any errors, explain what went wrong and suggest fixes.".to_string(),
-        });
-        
-        // Get final response from model with function results
-        match get_final_response(&final_messages, functions, config, user_id, response_file.as_deref_mut()).await {
-            Ok(final_response) => {
-                agent_info!(user_id, "execute_function_calling", "Successfully got final response from model");
-                
-                        // Extract any JavaScript code from function results for prominent display
-        let mut executed_code = String::new();
-        for result in &function_results {
-            if result.contains("```javascript") {
-                if let Some(start) = result.find("```javascript") {
-                    if let Some(end) = result[start..].find("```\n") {
-                        let code_section = &result[start + 13..start + end];
-                        if !code_section.trim().is_empty() {
-                            executed_code = code_section.trim().to_string();
-                            break;
-                        }
-                    }
-                }
-            }
-        }
-        
-        // Combine everything into a comprehensive response with code prominently displayed
-        let comprehensive_response = if final_response.trim().is_empty() {
-            // If no analysis from model, show results with code emphasis
-            if !executed_code.is_empty() {
-                format!(
-                    "**JavaScript Execution Results:**\n{}\n\n🚀 **Ready-to-Use Code:**\n```javascript\n{}\n```\n\n✨ **Copy the code above to use it in your project!**", 
-                    function_results.join("\n\n"),
-                    executed_code
-                )
-                    } else {
-                format!("**Execution Results:**\n{}", function_results.join("\n\n"))
-            }
-        } else {
-            // Include results, analysis, and prominently display code
-            if !executed_code.is_empty() {
-                format!(
-                    "**JavaScript Execution Results:**\n{}\n\n**AI Analysis:**\n{}\n\n🚀 **Ready-to-Use Code:**\n```javascript\n{}\n```\n\n✨ **Copy the code above to use it in your project!**", 
-                    function_results.join("\n\n"), 
-                    final_response,
-                    executed_code
-                )
-            } else {
-                format!(
-                    "**Execution Results:**\n{}\n\n**AI Analysis:**\n{}", 
-                    function_results.join("\n\n"), 
-                    final_response
-                )
-            }
-        };
-        
-        Ok(comprehensive_response)
-                }
-                Err(e) => {
-                agent_warn!(user_id, "execute_function_calling", "Failed to get final response, using function results only: {}", e);
-                
-                // Extract code for fallback as well
-                let mut executed_code_fallback = String::new();
-                for result in &function_results {
-                    if result.contains("```javascript") {
-                        if let Some(start) = result.find("```javascript") {
-                            if let Some(end) = result[start..].find("```\n") {
-                                let code_section = &result[start + 13..start + end];
-                                if !code_section.trim().is_empty() {
-                                    executed_code_fallback = code_section.trim().to_string();
-                                    break;
-                                }
-                            }
-                        }
-                    }
-                }
-                
-                // Fallback to just function results if final response fails
-                let fallback_response = if buffer.trim().is_empty() {
-                    if !executed_code_fallback.is_empty() {
-                        format!(
-                            "**JavaScript Execution Results:**\n{}\n\n🚀 **Ready-to-Use Code:**\n```javascript\n{}\n```\n\n✨ **Copy the code above to use it in your project!**", 
-                            function_results.join("\n\n"),
-                            executed_code_fallback
-                        )
-                    } else {
-                        format!("**Execution Results:**\n{}", function_results.join("\n\n"))
-                    }
-                } else {
-                    if !executed_code_fallback.is_empty() {
-                        format!(
-                            "**AI Response:**\n{}\n\n**JavaScript Execution Results:**\n{}\n\n🚀 **Ready-to-Use Code:**\n```javascript\n{}\n```\n\n✨ **Copy the code above to use it in your project!**", 
-                            buffer, 
-                            function_results.join("\n\n"),
-                            executed_code_fallback
-                        )
-                    } else {
-                        format!("**AI Response:**\n{}\n\n**Execution Results:**\n{}", buffer, function_results.join("\n\n"))
-                    }
-                };
-                
-                Ok(fallback_response)
-            }
-        }
-    } else {
-        // No tool calls, just return the text response
-        write_to_response_file(response_file.as_deref_mut(), "✅ No function calls needed, returning text response", user_id);
-        
-        Ok(buffer)
+    // A plain-text answer goes straight to the user; an empty one gets its own max_tokens notice
+    if collected_tool_calls.is_empty() && !buffer.trim().is_empty() {
+        buffer = append_finish_reason_note(buffer, events.finish_reason());
     }
+
+    Ok((buffer, collected_tool_calls))
 }
 
 async fn get_final_response(
+    backend: &dyn ChatBackend,
     messages: &[ChatMessage],
     _functions: &[FunctionDefinition],
     config: &LMConfig,
     user_id: UserId,
     mut response_file: Option<&mut std::fs::File>,
-) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+) -> Result<(String, Option<String>), Box<dyn std::error::Error + Send + Sync>> {
     agent_debug!(user_id, "get_final_response", "Getting final response from model");
     agent_trace!(user_id, "get_final_response", "Messages count: {}", messages.len());
     
     // Update thinking message if available
     write_to_response_file(response_file.as_deref_mut(), "🤖 AI is analyzing function results and preparing final answer...", user_id);
         
-        let client = get_http_client().await;
-        
+        let sampling = config.sampling_for(&config.default_model);
         let chat_request = ChatRequest {
         model: config.default_model.clone(),
         messages: messages.to_vec(),
-            temperature: config.default_temperature,
-            max_tokens: config.default_max_tokens,
+            temperature: sampling.temperature,
+            max_tokens: sampling.max_tokens,
         stream: true, // Enable streaming for final response
-            seed: config.default_seed,
+            seed: sampling.seed,
         tools: None, // No tools for final response
         tool_choice: None,
         };
 
         let api_url = format!("{}/v1/chat/completions", config.base_url);
         
-        let response = match client
-            .post(&api_url)
-            .json(&chat_request)
-            .timeout(Duration::from_secs(config.timeout as u64))
-            .send()
-            .await
-        {
-        Ok(resp) => resp,
+        let mut events = match backend.stream_chat(&api_url, &chat_request, config.timeout).await {
+            Ok(stream) => ChatEventStream::new(stream),
             Err(e) => {
-            agent_error!(user_id, "get_final_response", "HTTP request failed: {}", e);
-                return Err(e.into());
+                agent_error!(user_id, "get_final_response", "Final response request failed: {}", e);
+                return Err(e);
             }
         };
 
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unable to read error response".to_string());
-        agent_error!(user_id, "get_final_response", "API returned error status {}: {}", status, error_text);
-        return Err(format!("Final response failed: HTTP {} - {}", status, error_text).into());
-    }
-
     // --- SSE streaming logic for final response ---
-    use futures_util::StreamExt;
-    let mut stream = response.bytes_stream();
     let mut buffer = String::new();
     let mut last_update = std::time::Instant::now();
     let update_interval = std::time::Duration::from_millis(250);
     
-    while let Some(chunk) = stream.next().await {
-        let chunk = match chunk {
-            Ok(c) => c,
+    while let Some(event) = events.next_event().await {
+        let json = match event {
+            Ok(json) => json,
             Err(e) => {
-                agent_error!(user_id, "get_final_response", "Stream error: {}", e);
-                break;
+                agent_error!(user_id, "get_final_response", "Stream failed: {}", e);
+                return Err(e);
             }
         };
-        let text = String::from_utf8_lossy(&chunk);
-        for line in text.lines() {
-            if let Some(data) = line.strip_prefix("data: ") {
-                if data.trim() == "[DONE]" { continue; }
-                // Try to parse as JSON
-                if let Ok(json) = serde_json::from_str::<serde_json::Value>(data) {
-                    // Try to extract content delta
-                    if let Some(choices) = json.get("choices").and_then(|c| c.as_array()) {
-                        for choice in choices {
-                            if let Some(delta) = choice.get("delta") {
-                                if let Some(content) = delta.get("content").and_then(|c| c.as_str()) {
-                                    buffer.push_str(content);
-                                }
-                            }
-                        }
+        // Try to extract content delta
+        if let Some(choices) = json.get("choices").and_then(|c| c.as_array()) {
+            for choice in choices {
+                if let Some(delta) = choice.get("delta") {
+                    if let Some(content) = delta.get("content").and_then(|c| c.as_str()) {
+                        buffer.push_str(content);
                     }
                 }
             }
@@ -1875,7 +2834,7 @@ async fn get_final_response(
     // Write completion to file
     write_to_response_file(response_file.as_deref_mut(), "✅ Final response generation complete", user_id);
     
-    Ok(buffer)
+    Ok((buffer, events.finish_reason().map(str::to_string)))
 }
 
 fn create_agent_system_prompt() -> String {
@@ -2285,7 +3244,7 @@ async fn execute_stage_1_planning(
     
     // Execute planning with function calling
     let functions = get_js_code_sandbox_functions();
-    let planning_result = match execute_function_calling(&messages, &functions, config, user_id, Some(response_file)).await {
+    let planning_result = match execute_function_calling(&messages, &functions, config, user_id, None, None, Some(response_file)).await {
         Ok(result) => {
             agent_info!(user_id, "execute_stage_1_planning", "Planning stage completed successfully");
             result
@@ -2359,7 +3318,7 @@ async fn execute_stage_2_code_generation(
     
     // Execute code generation with function calling
     let functions = get_js_code_sandbox_functions();
-    let code_result = match execute_function_calling(&messages, &functions, config, user_id, Some(response_file)).await {
+    let code_result = match execute_function_calling(&messages, &functions, config, user_id, None, None, Some(response_file)).await {
                         Ok(result) => {
             agent_info!(user_id, "execute_stage_2_code_generation", "Code generation stage completed successfully");
                             result
@@ -2433,7 +3392,7 @@ async fn execute_stage_3_execution(
     
     // Execute testing with function calling
     let functions = get_js_code_sandbox_functions();
-    let execution_result = match execute_function_calling(&messages, &functions, config, user_id, Some(response_file)).await {
+    let execution_result = match execute_function_calling(&messages, &functions, config, user_id, None, None, Some(response_file)).await {
                         Ok(result) => {
             agent_info!(user_id, "execute_stage_3_execution", "Execution stage completed successfully");
                             result
@@ -2507,7 +3466,7 @@ async fn execute_stage_4_analysis(
     
     // Execute analysis with function calling
     let functions = get_js_code_sandbox_functions();
-    let analysis_result = match execute_function_calling(&messages, &functions, config, user_id, Some(response_file)).await {
+    let analysis_result = match execute_function_calling(&messages, &functions, config, user_id, None, None, Some(response_file)).await {
         Ok(result) => {
             agent_info!(user_id, "execute_stage_4_analysis", "Analysis stage completed successfully");
             result
@@ -2581,7 +3540,7 @@ async fn execute_stage_5_delivery(
     
     // Execute delivery with function calling
     let functions = get_js_code_sandbox_functions();
-    let delivery_result = match execute_function_calling(&messages, &functions, config, user_id, Some(response_file)).await {
+    let delivery_result = match execute_function_calling(&messages, &functions, config, user_id, None, None, Some(response_file)).await {
         Ok(result) => {
             agent_info!(user_id, "execute_stage_5_delivery", "Delivery stage completed successfully");
             result
@@ -2996,6 +3955,18 @@ async fn execute_stage_5_delivery_streaming(
 ///   - ^agent --tools (list available tools)
 ///   - ^agent --clear (clear context)
 pub async fn agent(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    // Everything logged for this invocation carries the same correlation ID
+    crate::commands::util::with_request_id(crate::commands::util::invocation_request_id(msg.id.0), agent_command(ctx, msg, args)).await
+}
+
+async fn agent_command(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    // Respect the ALLOWED_CHANNELS / DENIED_CHANNELS restrictions for heavy commands
+    if crate::commands::util::reply_if_channel_blocked(ctx, msg, "agent").await? {
+        return Ok(());
+    }
+    // Counted as in flight so shutdown can wait for it to finish
+    let _heavy_guard = crate::start_heavy_command();
+
     let user_id = msg.author.id;
     let input = args.message().trim();
     let start_time = Instant::now();
@@ -3013,6 +3984,10 @@ pub async fn agent(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
         return Ok(());
     }
 
+    if crate::commands::util::reply_if_prompt_too_long(ctx, msg, input, "agent").await? {
+        return Ok(());
+    }
+
     // Parse agent command
     if input == "--help" || input == "-h" {
         show_agent_help(ctx, msg).await
@@ -3020,10 +3995,68 @@ pub async fn agent(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
             list_available_tools(ctx, msg).await
     } else if input == "--clear" || input == "-c" {
             clear_agent_memory(ctx, msg).await
+    } else if input == "--history" || input.starts_with("--history ") {
+        let count = input["--history".len()..].trim();
+        show_function_history(ctx, msg, count).await
     } else {
         // Default to execute mode
         agent_trace!(user_id, "agent", "Executing agent task: '{}'", input);
-        let result = execute_agent_task(input.to_string(), ctx, msg).await;
+        let (flags, task) = crate::commands::util::parse_leading_flags(input, &["--file", "--inline", "--explain", "--json", "--debug"], &["--timeout", "--lang", "--budget"]);
+        let output_mode = if flags.iter().any(|(flag, _)| flag == "--inline") {
+            AgentOutputMode::Inline
+        } else if flags.iter().any(|(flag, _)| flag == "--file") {
+            AgentOutputMode::File
+        } else {
+            AgentOutputMode::Auto
+        };
+        // Per-invocation override of the configured request timeout
+        let timeout_override = match flags.iter().find(|(flag, _)| flag == "--timeout") {
+            Some((_, value)) => match value.as_deref().and_then(|v| v.parse::<u64>().ok()) {
+                Some(secs) if (1..=600).contains(&secs) => Some(secs),
+                _ => {
+                    msg.reply(ctx, "❌ `--timeout` must be a number of seconds between 1 and 600.").await?;
+                    return Ok(());
+                }
+            },
+            None => None,
+        };
+        // JavaScript is what the sandbox runs anyway, so --lang javascript is the default behaviour
+        let code_lang = match flags.iter().find(|(flag, _)| flag == "--lang") {
+            Some((_, value)) => match normalize_code_language(value.as_deref().unwrap_or_default()) {
+                Ok(lang) => Some(lang).filter(|lang| lang != "javascript"),
+                Err(e) => {
+                    msg.reply(ctx, format!("❌ `--lang`: {}", e)).await?;
+                    return Ok(());
+                }
+            },
+            None => None,
+        };
+        // Cap on tool executions for this task; the model is asked to conclude once it is used up
+        let tool_budget = match flags.iter().find(|(flag, _)| flag == "--budget") {
+            Some((_, value)) => match value.as_deref().and_then(|v| v.parse::<usize>().ok()) {
+                Some(budget) if (1..=MAX_TOOL_BUDGET).contains(&budget) => Some(budget),
+                _ => {
+                    msg.reply(ctx, format!("❌ `--budget` must be a number of tool executions between 1 and {}.", MAX_TOOL_BUDGET)).await?;
+                    return Ok(());
+                }
+            },
+            None => None,
+        };
+        if task.is_empty() {
+            msg.reply(ctx, "Please provide a task after the flags! Usage: `^agent [--file|--inline] [--explain] [--json] [--timeout <secs>] [--lang <language>] [--budget <n>] [--debug] <your task>`").await?;
+            return Ok(());
+        }
+        let explain = flags.iter().any(|(flag, _)| flag == "--explain");
+        let json_output = flags.iter().any(|(flag, _)| flag == "--json");
+        // Global cap on simultaneous backend requests, taken only once a task will actually run
+        let _backend_permit = match crate::commands::util::acquire_backend_permit_or_reply(ctx, msg, "agent").await? {
+            Some(permit) => permit,
+            None => return Ok(()),
+        };
+        // --debug attaches this task's key log lines as debug.txt
+        let mut debug = crate::commands::util::DebugLog::new("agent", flags.iter().any(|(flag, _)| flag == "--debug"));
+        let result = execute_agent_task(task.to_string(), ctx, msg, output_mode, timeout_override, explain, json_output, code_lang, tool_budget, &mut debug).await;
+        debug.attach(ctx, msg.channel_id).await;
         
         let duration = start_time.elapsed();
         agent_trace!(user_id, "agent", "=== AGENT COMMAND END ===");
@@ -3043,6 +4076,15 @@ async fn show_agent_help(ctx: &Context, msg: &Message) -> CommandResult {
 - `^agent <task>` - Execute a complex task with function calling
 - `^agent --tools` - List available tools
 - `^agent --clear` - Clear agent memory
+- `^agent --history [N]` - Show your last N tool calls (default 10)
+- `^agent --file <task>` - Always attach the full response as a file
+- `^agent --inline <task>` - Reply inline without creating a response file
+- `^agent --timeout <secs> <task>` - Override the request timeout for this task (1-600 seconds)
+- `^agent --explain <task>` - Also show the model's reasoning trace (spoiler, or a file when long)
+- `^agent --json <task>` - Return the result as a JSON object with `answer`, `code` and `steps`
+- `^agent --lang <language> <task>` - Show the solution in another language (e.g. `python`); computations still run as JavaScript and the output marks which code ran
+- `^agent --budget <n> <task>` - Run at most n tool executions (1-50), then have the model conclude with what it has
+- `^agent --debug <task>` - Attach this task's model, payload, timing and tool call log as `debug.txt` (secrets redacted)
 - `^agent --help` - Show this help
 
 **Examples:**
@@ -3089,14 +4131,64 @@ async fn list_available_tools(ctx: &Context, msg: &Message) -> CommandResult {
         tools.join("\n")
     );
 
-    msg.reply(ctx, &tools_text).await?;
-    
-    let duration = start_time.elapsed();
-    agent_info!(user_id, "list_available_tools", "Completed tools listing in {:?}", duration);
-    
+    msg.reply(ctx, &tools_text).await?;
+    
+    let duration = start_time.elapsed();
+    agent_info!(user_id, "list_available_tools", "Completed tools listing in {:?}", duration);
+    
+    Ok(())
+}
+
+async fn show_function_history(ctx: &Context, msg: &Message, count: &str) -> CommandResult {
+    let user_id = msg.author.id;
+    let limit = if count.is_empty() {
+        10
+    } else {
+        match count.parse::<usize>() {
+            Ok(n) if n >= 1 => n.min(MAX_FUNCTION_CALL_HISTORY),
+            _ => {
+                msg.reply(ctx, style_output("❌ Usage: `^agent --history [N]` where N is a positive number")).await?;
+                return Ok(());
+            }
+        }
+    };
+
+    agent_info!(user_id, "show_function_history", "Showing last {} tool calls", limit);
+
+    let history = get_function_call_history(user_id).await;
+    if history.is_empty() {
+        msg.reply(ctx, style_output("📜 **Tool Call History**\n\nNo tool calls recorded yet. Run `^agent <task>` to get started.")).await?;
+        return Ok(());
+    }
+
+    let history_text = format_function_call_history(&history, limit);
+    crate::commands::util::send_long_message(ctx, msg.channel_id, &history_text, 2000, "agent_history.txt").await?;
     Ok(())
 }
 
+/// Format the most recent `limit` records, newest first, with truncated arguments and results
+fn format_function_call_history(history: &[FunctionCallRecord], limit: usize) -> String {
+    let shown: Vec<String> = history.iter().rev().take(limit).map(|record| {
+        let args = serde_json::to_string(&record.arguments).unwrap_or_else(|_| "{}".to_string());
+        let result = record.result.replace('\n', " ");
+        format!(
+            "**{}** <t:{}:R>\n• Args: `{}`\n• Result: {}{}",
+            record.function_name,
+            record.timestamp.timestamp(),
+            truncate_chars(&args, 150).replace('`', "'"),
+            truncate_chars(&result, 200),
+            if result.chars().count() > 200 { "..." } else { "" },
+        )
+    }).collect();
+
+    format!(
+        "📜 **Tool Call History** (last {} of {})\n\n{}",
+        shown.len(),
+        history.len(),
+        shown.join("\n\n")
+    )
+}
+
 async fn clear_agent_memory(ctx: &Context, msg: &Message) -> CommandResult {
     let user_id = msg.author.id;
     let start_time = Instant::now();
@@ -3106,7 +4198,7 @@ async fn clear_agent_memory(ctx: &Context, msg: &Message) -> CommandResult {
     // Actually clear the user's context
     clear_user_context(user_id).await;
     
-    msg.reply(ctx, "🧹 **Agent Memory Cleared**\n\nYour agent conversation history has been reset. The next ^agent command will start fresh.").await?;
+    msg.reply(ctx, style_output("🧹 **Agent Memory Cleared**\n\nYour agent conversation history has been reset. The next ^agent command will start fresh.")).await?;
     
     let duration = start_time.elapsed();
     agent_info!(user_id, "clear_agent_memory", "Completed memory clearing in {:?}", duration);
@@ -3132,38 +4224,13 @@ pub async fn clearagentcontext(ctx: &Context, msg: &Message, _args: Args) -> Com
 
 async fn load_agent_config() -> Result<LMConfig, Box<dyn std::error::Error + Send + Sync>> {
     let user_id = UserId(0); // Use a dummy user ID for system operations
-    let config_paths = [
-        "lmapiconf.txt",
-        "../lmapiconf.txt", 
-        "../../lmapiconf.txt",
-        "src/lmapiconf.txt"
-    ];
-    
-    let mut content = String::new();
-    let mut found_file = false;
-    let mut config_source = "";
-    
-    for config_path in &config_paths {
-        match fs::read_to_string(config_path) {
-            Ok(file_content) => {
-                content = file_content;
-                found_file = true;
-                config_source = config_path;
-                agent_info!(user_id, "load_agent_config", "Found config file at {}", config_path);
-                break;
-            }
-            Err(e) => {
-                agent_debug!(user_id, "load_agent_config", "Config file not found at {}: {}", config_path, e);
-                continue;
-            }
-        }
-    }
-    
-    if !found_file {
-        return Err("lmapiconf.txt file not found in any expected location for agent".into());
-    }
+    let (content, config_source) = match crate::commands::util::find_and_read_config("lmapiconf.txt") {
+        Some(found) => found,
+        None => return Err("lmapiconf.txt file not found in any expected location for agent".into()),
+    };
+    agent_info!(user_id, "load_agent_config", "Found config file at {}", config_source);
+    crate::commands::util::record_config_source("lmapiconf.txt (agent)", &config_source);
     
-    let content = content.strip_prefix('\u{feff}').unwrap_or(&content);
     let mut config_map = HashMap::new();
 
     for line in content.lines() {
@@ -3179,6 +4246,8 @@ async fn load_agent_config() -> Result<LMConfig, Box<dyn std::error::Error + Sen
             config_map.insert(key, value);
         }
     }
+
+    crate::commands::search::apply_model_overrides(&mut config_map, &crate::commands::search::model_overrides());
     
     let required_keys = [
         "LM_STUDIO_BASE_URL",
@@ -3199,10 +4268,12 @@ async fn load_agent_config() -> Result<LMConfig, Box<dyn std::error::Error + Sen
     let config = LMConfig {
         base_url: config_map.get("LM_STUDIO_BASE_URL")
             .ok_or("LM_STUDIO_BASE_URL not found")?.clone(),
-        timeout: config_map.get("LM_STUDIO_TIMEOUT")
-            .ok_or("LM_STUDIO_TIMEOUT not found")?
-            .parse()
-            .map_err(|_| "Invalid LM_STUDIO_TIMEOUT value")?,
+        // AGENT_TIMEOUT sets the timeout for agent requests (default 300 seconds)
+        timeout: crate::commands::search::resolve_command_timeout(
+            &config_map,
+            "AGENT_TIMEOUT",
+            crate::commands::search::DEFAULT_LONG_COMMAND_TIMEOUT,
+        )?,
         default_model: config_map.get("DEFAULT_REASON_MODEL")
             .ok_or("DEFAULT_REASON_MODEL not found")?.clone(),
         default_temperature: config_map.get("DEFAULT_TEMPERATURE")
@@ -3225,6 +4296,20 @@ async fn load_agent_config() -> Result<LMConfig, Box<dyn std::error::Error + Sen
             .map(|s| s.parse::<i64>())
             .transpose()
             .map_err(|_| "DEFAULT_SEED must be a valid integer if specified")?,
+        staged_max_total_secs: match config_map.get("STAGED_MAX_TOTAL_SECS") {
+            Some(value) => match value.parse::<u64>() {
+                Ok(secs) if secs > 0 => secs,
+                _ => return Err("STAGED_MAX_TOTAL_SECS must be a positive number of seconds".into()),
+            },
+            None => DEFAULT_STAGED_MAX_TOTAL_SECS,
+        },
+        staged_task_max_age_hours: match config_map.get("STAGED_TASK_MAX_AGE_HOURS") {
+            Some(value) => value.parse::<u64>()
+                .map_err(|_| "STAGED_TASK_MAX_AGE_HOURS must be a number of hours (0 disables expiry)")?,
+            None => DEFAULT_STAGED_TASK_MAX_AGE_HOURS,
+        },
+        max_retries: crate::commands::search::parse_max_retries(&config_map)?,
+        model_profiles: crate::commands::search::parse_model_profiles(&config_map)?,
     };
 
     agent_info!(user_id, "load_agent_config", "Successfully loaded config from {} with model: '{}'", config_source, config.default_model);
@@ -3236,7 +4321,7 @@ async fn load_agent_config() -> Result<LMConfig, Box<dyn std::error::Error + Sen
 // ============================================================================
 
 fn filter_thinking_tags(content: &str) -> String {
-    let filtered = THINKING_TAG_REGEX.replace_all(content, "");
+    let filtered = crate::commands::util::strip_think_blocks(content);
     
     let lines: Vec<&str> = filtered
         .lines()
@@ -3247,6 +4332,18 @@ fn filter_thinking_tags(content: &str) -> String {
     lines.join("\n").trim().to_string()
 }
 
+// Separate <think> blocks from the answer, returning (answer, reasoning)
+// An unclosed <think> (the model ran out of tokens mid-thought) is reasoning through to the end
+fn split_thinking(content: &str) -> (String, String) {
+    let (answer, blocks) = crate::commands::util::split_think_blocks(content);
+    let reasoning: Vec<String> = blocks
+        .iter()
+        .map(|trace| trace.trim().to_string())
+        .filter(|trace| !trace.is_empty())
+        .collect();
+    (answer.trim().to_string(), reasoning.join("\n\n---\n\n"))
+}
+
 // ============================================================================
 // TESTS
 // ============================================================================
@@ -3262,6 +4359,125 @@ mod tests {
         assert_eq!(filtered, "Here is some content  and more content.");
     }
 
+    #[test]
+    fn test_agent_output_mode_threshold() {
+        let short = "done";
+        let long = "x".repeat(AGENT_INLINE_MAX_CHARS + 1);
+        assert!(!should_upload_result_file(AgentOutputMode::Auto, short));
+        assert!(should_upload_result_file(AgentOutputMode::Auto, &long));
+        assert!(should_upload_result_file(AgentOutputMode::File, short));
+        assert!(!should_upload_result_file(AgentOutputMode::Inline, &long));
+    }
+
+    #[test]
+    fn test_upload_message_shows_short_results_whole() {
+        let short = upload_message("The answer is 42.", 1900);
+        assert!(short.contains("**Result:**\nThe answer is 42."));
+        assert!(short.contains("also attached"));
+
+        let long = format!("{} tail", "word ".repeat(400));
+        let preview = upload_message(&long, 1900);
+        assert!(preview.contains("**Summary:**"));
+        assert!(preview.contains("word…") && !preview.contains("tail"));
+        assert!(preview.chars().count() < 1900);
+    }
+
+    #[test]
+    fn test_pause_interrupted_tasks() {
+        let stage = |status| AgentStage {
+            stage_id: 1,
+            name: "Task Analysis & Planning".to_string(),
+            description: String::new(),
+            status,
+            input: None,
+            output: None,
+            timestamp: Utc::now(),
+            duration: None,
+        };
+        let task = |overall_status, stage_status| StagedTask {
+            task_id: "staged_1".to_string(),
+            user_id: UserId(1),
+            original_request: "Calculate the factorial of 10".to_string(),
+            stages: vec![stage(stage_status)],
+            current_stage: 0,
+            overall_status,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+        let mut tasks = HashMap::new();
+        tasks.insert("1".to_string(), task(TaskStatus::Execution, StageStatus::InProgress));
+        tasks.insert("2".to_string(), task(TaskStatus::Complete, StageStatus::Completed));
+
+        // The store round-trips through the JSON written to disk
+        let json = serde_json::to_string(&tasks).unwrap();
+        let mut restored: HashMap<String, StagedTask> = serde_json::from_str(&json).unwrap();
+        assert_eq!(pause_interrupted_tasks(&mut restored), 1);
+        assert_eq!(restored["1"].overall_status, TaskStatus::Paused);
+        assert_eq!(restored["1"].stages[0].status, StageStatus::Pending);
+        assert_eq!(restored["2"].overall_status, TaskStatus::Complete);
+
+        let now = Utc::now();
+        restored.get_mut("2").unwrap().updated_at = now - chrono::Duration::hours(100);
+        assert_eq!(expire_stale_tasks(&mut restored, 0, now), 0);
+        assert_eq!(expire_stale_tasks(&mut restored, 72, now), 1);
+        assert!(restored.contains_key("1") && !restored.contains_key("2"));
+    }
+
+    #[test]
+    fn test_code_language_sections() {
+        assert_eq!(normalize_code_language("Py").unwrap(), "python");
+        assert_eq!(normalize_code_language("c++").unwrap(), "cpp");
+        assert_eq!(normalize_code_language("kotlin").unwrap(), "kotlin");
+        assert!(normalize_code_language("not a language").is_err());
+
+        let response = "Here it is:\n```python\nprint(6 * 7)\n```\nDone.";
+        let section = ready_to_use_code_section("console.log(6 * 7);", response, Some("python"));
+        assert!(section.contains("```python\nprint(6 * 7)\n```"));
+        assert!(section.contains("illustrative - not executed"));
+        assert!(section.contains("Executed in the JavaScript sandbox:**\n```javascript\nconsole.log(6 * 7);"));
+
+        let section = ready_to_use_code_section("console.log(1);", "no code here", Some("rust"));
+        assert!(section.contains("did not provide a rust version"));
+        assert!(ready_to_use_code_section("console.log(1);", response, None).starts_with("🚀 **Ready-to-Use Code:**\n```javascript"));
+    }
+
+    #[test]
+    fn test_parse_agent_json_output() {
+        let fenced = "```json\n{\"answer\": \"42\", \"steps\": [\"multiply 6 by 7\"]}\n```";
+        let output = parse_agent_json_output(fenced).unwrap();
+        assert_eq!(output.answer, "42");
+        assert!(output.code.is_empty());
+        assert_eq!(output.steps, vec!["multiply 6 by 7"]);
+        assert!(parse_agent_json_output("The answer is 42").is_err());
+        assert!(parse_agent_json_output("{\"steps\": []}").is_err());
+
+        let result = "**JavaScript Execution Results:**\n```javascript\nconsole.log(6 * 7);\n```\n42";
+        assert_eq!(extract_executed_code(result).as_deref(), Some("console.log(6 * 7);"));
+        assert_eq!(extract_executed_code("no code here"), None);
+    }
+
+    #[test]
+    fn test_format_function_call_history_newest_first() {
+        let record = |name: &str, result: &str| FunctionCallRecord {
+            call_id: format!("call_{}", name),
+            function_name: name.to_string(),
+            arguments: serde_json::json!({"expression": "2 + 2"}),
+            result: result.to_string(),
+            timestamp: Utc::now(),
+            user_id: UserId(1),
+        };
+        let history = vec![
+            record("calculate_math", "4"),
+            record("process_text", &"x".repeat(500)),
+        ];
+
+        let text = format_function_call_history(&history, 1);
+        assert!(text.contains("last 1 of 2"));
+        assert!(text.contains("process_text"));
+        assert!(!text.contains("calculate_math"));
+        assert!(text.contains(&format!("{}...", "x".repeat(200))));
+    }
+
     #[test]
     fn test_js_code_sandbox_functions() {
         let functions = get_js_code_sandbox_functions();
@@ -3283,6 +4499,151 @@ mod tests {
         assert_eq!(function_names[3], "analyze_data");
     }
 
+    // Replays one canned SSE body per request, already split into network chunks
+    struct MockBackend {
+        responses: std::sync::Mutex<Vec<Vec<&'static str>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl ChatBackend for MockBackend {
+        async fn stream_chat(&self, _api_url: &str, _request: &ChatRequest, _timeout_secs: u64) -> Result<ChatByteStream, Box<dyn std::error::Error + Send + Sync>> {
+            let chunks = self.responses.lock().unwrap().remove(0);
+            Ok(Box::pin(futures_util::stream::iter(chunks.into_iter().map(|chunk| Ok(chunk.as_bytes().to_vec())))))
+        }
+    }
+
+    fn mock_backend(chunks: Vec<&'static str>) -> MockBackend {
+        MockBackend { responses: std::sync::Mutex::new(vec![chunks]) }
+    }
+
+    fn function_calling_request() -> ChatRequest {
+        ChatRequest {
+            model: "mock-model".to_string(),
+            messages: vec![ChatMessage { role: "user".to_string(), content: "What is 2 + 2?".to_string() }],
+            temperature: 0.7,
+            max_tokens: 256,
+            stream: true,
+            seed: None,
+            tools: Some(get_js_code_sandbox_functions()),
+            tool_choice: Some("auto".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_parse_tool_arguments_repairs_truncation() {
+        let (args, repaired) = parse_tool_arguments("{\"code\": \"console.log(1)\"}").unwrap();
+        assert_eq!(args["code"], "console.log(1)");
+        assert!(!repaired);
+
+        // Raw newlines inside strings are escaped rather than treated as truncation
+        let (args, repaired) = parse_tool_arguments("{\"code\": \"let a = 1;\nconsole.log(a)\"}").unwrap();
+        assert_eq!(args["code"], "let a = 1;\nconsole.log(a)");
+        assert!(!repaired);
+
+        let (args, repaired) = parse_tool_arguments("{\"expression\": \"2 + 2\", \"steps\": [1, 2,").unwrap();
+        assert_eq!(args, serde_json::json!({"expression": "2 + 2", "steps": [1, 2]}));
+        assert!(repaired);
+
+        let (args, _) = parse_tool_arguments("{\"code\": \"const s = \\\"a\\").unwrap();
+        assert_eq!(args["code"], "const s = \"a");
+
+        assert_eq!(parse_tool_arguments("{\"code\": \"x\", \"descr"), Err(ToolArgsError::Truncated));
+        assert!(matches!(parse_tool_arguments("console.log(1)"), Err(ToolArgsError::Invalid(_))));
+    }
+
+    #[tokio::test]
+    async fn test_collect_function_calls_assembles_streamed_tool_call() {
+        let backend = mock_backend(vec![
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Let me check.\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"name\":\"calculate_math\",\"arguments\":\"{\\\"expression\\\":\"}}]}}]}\n\ndata: {\"choi",
+            "ces\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\" \\\"2 + 2\\\"}\"}}]}}]}\n\n",
+            "data: [DONE]\n\n",
+        ]);
+        let (text, tool_calls) = collect_function_calls(&backend, &function_calling_request(), "http://mock/v1/chat/completions", 5, UserId(1), None)
+            .await
+            .expect("stream should parse");
+
+        assert!(text.starts_with("Let me check."));
+        assert_eq!(tool_calls.len(), 1);
+        assert_eq!(tool_calls[0].function.name, "calculate_math");
+        assert_eq!(tool_calls[0].function.arguments["expression"], "2 + 2");
+    }
+
+    #[tokio::test]
+    async fn test_collect_function_calls_correlates_split_name_and_args() {
+        // The name arrives alone; argument chunks follow keyed only by id, then with no key at all
+        let backend = mock_backend(vec![
+            "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"id\":\"call_abc\",\"function\":{\"name\":\"calculate_math\"}}]}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"id\":\"call_abc\",\"function\":{\"arguments\":\"{\\\"expression\\\":\"}}]}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"function\":{\"arguments\":\" \\\"6 * 7\\\"}\"}}]}}]}\n\n",
+            "data: [DONE]\n\n",
+        ]);
+        let (_, tool_calls) = collect_function_calls(&backend, &function_calling_request(), "http://mock/v1/chat/completions", 5, UserId(1), None)
+            .await
+            .expect("stream should parse");
+
+        assert_eq!(tool_calls.len(), 1);
+        assert_eq!(tool_calls[0].function.name, "calculate_math");
+        assert_eq!(tool_calls[0].function.arguments["expression"], "6 * 7");
+    }
+
+    #[test]
+    fn test_resolve_tool_call_key_prefers_index_then_id() {
+        let mut keys = HashMap::new();
+        let first = serde_json::json!({"index": 1, "id": "abc"});
+        assert_eq!(resolve_tool_call_key(&first, &mut keys, None).as_deref(), Some("call_1"));
+        assert_eq!(resolve_tool_call_key(&serde_json::json!({"id": "abc"}), &mut keys, None).as_deref(), Some("call_1"));
+        assert_eq!(resolve_tool_call_key(&serde_json::json!({}), &mut keys, Some("call_1")).as_deref(), Some("call_1"));
+        assert_eq!(resolve_tool_call_key(&serde_json::json!({}), &mut keys, None), None);
+    }
+
+    #[tokio::test]
+    async fn test_collect_function_calls_handles_empty_stream() {
+        let backend = mock_backend(vec!["data: [DONE]\n\n"]);
+        let (text, tool_calls) = collect_function_calls(&backend, &function_calling_request(), "http://mock/v1/chat/completions", 5, UserId(1), None)
+            .await
+            .expect("an empty stream is not an error");
+        assert!(text.is_empty());
+        assert!(tool_calls.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_collect_function_calls_surfaces_error_objects() {
+        let backend = mock_backend(vec!["data: {\"error\":{\"message\":\"model crashed\"}}\n\n"]);
+        let error = collect_function_calls(&backend, &function_calling_request(), "http://mock/v1/chat/completions", 5, UserId(1), None)
+            .await
+            .expect_err("error events abort the stream");
+        assert!(error.to_string().contains("model crashed"));
+    }
+
+    #[tokio::test]
+    async fn test_collect_function_calls_notes_token_limit() {
+        let backend = mock_backend(vec![
+            "data: {\"choices\":[{\"delta\":{\"content\":\"The first half\"},\"finish_reason\":null}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{},\"finish_reason\":\"length\"}]}\n\ndata: [DONE]\n\n",
+        ]);
+        let (text, tool_calls) = collect_function_calls(&backend, &function_calling_request(), "http://mock/v1/chat/completions", 5, UserId(1), None)
+            .await
+            .expect("stream succeeds");
+        assert!(tool_calls.is_empty());
+        assert!(text.starts_with("The first half"));
+        assert!(text.contains("truncated at the token limit"));
+    }
+
+    #[tokio::test]
+    async fn test_chat_event_stream_reassembles_events_until_done() {
+        let stream: ChatByteStream = Box::pin(futures_util::stream::iter(vec![
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>(b"data: {\"choices\":[{\"delta\":{\"content\":\"He".to_vec()),
+            Ok(b"llo\"}}]}\n\ndata: not json\n\n: keep-alive\n".to_vec()),
+            Ok(b"data: [DONE]\n\ndata: {\"choices\":[]}\n\n".to_vec()),
+        ]));
+        let mut events = ChatEventStream::new(stream);
+
+        let first = events.next_event().await.expect("one event").expect("valid event");
+        assert_eq!(first["choices"][0]["delta"]["content"], "Hello");
+        assert!(events.next_event().await.is_none(), "events after [DONE] are ignored");
+    }
+
     #[test]
     fn test_create_agent_system_prompt() {
         let prompt = create_agent_system_prompt();
@@ -3290,6 +4651,59 @@ mod tests {
         assert!(prompt.contains("execute_js_code"));
         assert!(prompt.contains("calculate_math"));
     }
+
+    #[test]
+    fn test_split_thinking_captures_reasoning() {
+        let (answer, reasoning) = split_thinking("<think>step one</think>The answer is 42.<think>double-check</think>");
+        assert_eq!(answer, "The answer is 42.");
+        assert_eq!(reasoning, "step one\n\n---\n\ndouble-check");
+
+        let (answer, reasoning) = split_thinking("Partial answer <think>ran out of tok");
+        assert_eq!(answer, "Partial answer");
+        assert_eq!(reasoning, "ran out of tok");
+
+        assert_eq!(split_thinking("plain"), ("plain".to_string(), String::new()));
+    }
+
+    #[test]
+    fn test_text_or_no_output_notice() {
+        assert_eq!(text_or_no_output_notice("Done.".to_string(), 512, None), "Done.");
+        let notice = text_or_no_output_notice("  \n".to_string(), 512, Some("length"));
+        assert!(notice.contains("no output"));
+        assert!(notice.contains("512"));
+        assert!(notice.contains("finish_reason: `length`"));
+        assert!(text_or_no_output_notice("<think>only reasoning</think>".to_string(), 512, None).contains("no output"));
+    }
+
+    #[test]
+    fn test_tool_budget_note() {
+        let note = tool_budget_note(2, 1);
+        assert!(note.contains("Tool budget reached"));
+        assert!(note.contains("2 tool executions ran; 1 further call was skipped"));
+        assert!(tool_budget_note(1, 3).contains("1 tool execution ran; 3 further calls were skipped"));
+        assert_eq!(append_budget_note("Done.".to_string(), None), "Done.");
+        assert_eq!(append_budget_note("Done.".to_string(), Some("note")), "Done.\n\nnote");
+    }
+
+    #[test]
+    fn test_evaluate_math_expression() {
+        assert_eq!(evaluate_math_expression("2 + 3 * 4").unwrap(), 14.0);
+        assert_eq!(evaluate_math_expression("(2 + 3) * 4").unwrap(), 20.0);
+        assert_eq!(evaluate_math_expression("-2^2").unwrap(), -4.0);
+        assert_eq!(evaluate_math_expression("2 ** 3 ** 2").unwrap(), 512.0);
+        assert_eq!(evaluate_math_expression("Math.sqrt(16) + max(1, 7, 3)").unwrap(), 7.0 + 4.0);
+        assert_eq!(evaluate_math_expression("1.5e3 % 7").unwrap(), 2.0);
+        assert!((evaluate_math_expression("Math.PI / 2").unwrap() - std::f64::consts::FRAC_PI_2).abs() < 1e-12);
+        assert_eq!(format_math_result(evaluate_math_expression("10 / 4").unwrap()), "2.5");
+        assert_eq!(format_math_result(evaluate_math_expression("2^10").unwrap()), "1024");
+
+        // Anything outside the grammar is declined rather than evaluated
+        assert!(evaluate_math_expression("alert('x')").is_err());
+        assert!(evaluate_math_expression("1'); process.exit(); ('").is_err());
+        assert!(evaluate_math_expression("pow(2)").is_err());
+        assert!(evaluate_math_expression("(1 + 2").is_err());
+        assert!(evaluate_math_expression(&"(".repeat(100)).is_err());
+    }
 }
 
 // Command group exports
@@ -3322,6 +4736,10 @@ impl Agent {
 ///   - ^staged --pause (pause execution)
 ///   - ^staged --resume (resume execution)
 pub async fn staged(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    crate::commands::util::with_request_id(crate::commands::util::invocation_request_id(msg.id.0), staged_command(ctx, msg, args)).await
+}
+
+async fn staged_command(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     let user_id = msg.author.id;
     let input = args.message().trim();
     let start_time = Instant::now();
@@ -3345,14 +4763,17 @@ pub async fn staged(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     } else if input == "--approve" || input == "-a" {
         approve_current_stage(ctx, msg).await
     } else if input.starts_with("--modify ") || input.starts_with("-m ") {
-        let feedback = input.trim_start_matches("--modify ").trim_start_matches("-m ").trim();
-        modify_current_stage(ctx, msg, feedback.to_string()).await
+        // Feedback may be quoted: --modify "make it shorter"
+        let (_, feedback) = crate::commands::util::parse_leading_flags(input, &["--modify", "-m"], &[]);
+        modify_current_stage(ctx, msg, crate::commands::util::unquote_arg(feedback)).await
     } else if input == "--skip" || input == "-k" {
         skip_current_stage(ctx, msg).await
     } else if input == "--pause" || input == "-p" {
         pause_staged_execution(ctx, msg).await
     } else if input == "--resume" || input == "-r" {
         resume_staged_execution(ctx, msg).await
+    } else if input == "--cancel" || input == "-x" {
+        cancel_staged_task(ctx, msg).await
         } else {
         // Default to staged execution mode
         agent_trace!(user_id, "staged", "Executing staged task: '{}'", input);
@@ -3380,6 +4801,7 @@ async fn show_staged_help(ctx: &Context, msg: &Message) -> CommandResult {
 - `^staged --skip` - Skip current stage
 - `^staged --pause` - Pause execution
 - `^staged --resume` - Resume execution
+- `^staged --cancel` - Abandon and delete your staged task
 
 **Examples:**
 - `^staged "Calculate the factorial of 10"`
@@ -3409,6 +4831,8 @@ async fn show_staged_help(ctx: &Context, msg: &Message) -> CommandResult {
 - `--skip` skips the current stage
 - `--pause` pauses execution
 - `--resume` resumes execution from the paused stage
+- Staged tasks are saved to disk; a task interrupted by a bot restart comes back paused, ready for `--resume`
+- Saved tasks untouched for longer than STAGED_TASK_MAX_AGE_HOURS (default 72) are dropped at startup; `--cancel` removes yours immediately
 
 Remember: Each stage has specific instructions and guidelines. Use `--modify <feedback>` to tailor the output to your needs."#;
 
@@ -3430,7 +4854,13 @@ async fn show_staged_status(ctx: &Context, msg: &Message) -> CommandResult {
             }).collect::<Vec<String>>().join("\n");
             
             let response = format!("🤖 **Staged Task Status**\n\n{}", status_text);
-            msg.reply(ctx, &response).await?;
+            crate::commands::util::send_long_message(
+                ctx,
+                msg.channel_id,
+                &response,
+                crate::commands::util::DISCORD_MESSAGE_LIMIT,
+                "staged_status.txt",
+            ).await?;
         }
         None => {
             let response = "🤖 **Staged Task Status**\n\nNo staged task found for this user.";
@@ -3602,6 +5032,27 @@ async fn resume_staged_execution(ctx: &Context, msg: &Message) -> CommandResult
     Ok(())
 }
 
+async fn cancel_staged_task(ctx: &Context, msg: &Message) -> CommandResult {
+    let user_id = msg.author.id;
+    agent_info!(user_id, "cancel_staged_task", "Cancelling staged task");
+
+    match remove_staged_task(user_id).await {
+        Some(task) => {
+            let response = format!(
+                "🤖 **Staged Task Cancelled**\n\nRemoved your staged task for '{}'. A stage that is already running finishes, but no further stages will start.",
+                truncate_chars(&task.original_request, 200)
+            );
+            msg.reply(ctx, &response).await?;
+        }
+        None => {
+            let response = "🤖 **No Staged Task**\n\nNo staged task found for this user.";
+            msg.reply(ctx, response).await?;
+        }
+    }
+
+    Ok(())
+}
+
 async fn execute_staged_task(task: String, ctx: &Context, msg: &Message) -> CommandResult {
     let user_id = msg.author.id;
     let start_time = Instant::now();
@@ -3627,7 +5078,7 @@ async fn execute_staged_task(task: String, ctx: &Context, msg: &Message) -> Comm
         Err(e) => {
             agent_trace!(user_id, "execute_staged_task", "Configuration loading failed: {}", e);
             agent_error!(user_id, "execute_staged_task", "Failed to load agent configuration: {}", e);
-            msg.reply(ctx, "❌ Failed to load agent configuration").await?;
+            msg.reply(ctx, with_error_id(style_output("❌ Failed to load agent configuration"))).await?;
             return Ok(());
         }
     };
@@ -3641,7 +5092,7 @@ async fn execute_staged_task(task: String, ctx: &Context, msg: &Message) -> Comm
         }
         Err(e) => {
             agent_error!(user_id, "execute_staged_task", "Failed to create response file: {}", e);
-            let _ = msg.reply(ctx, "❌ Failed to create response file").await;
+            let _ = msg.reply(ctx, with_error_id(style_output("❌ Failed to create response file"))).await;
             return Ok(());
         }
     };
@@ -3649,14 +5100,14 @@ async fn execute_staged_task(task: String, ctx: &Context, msg: &Message) -> Comm
     // Write initial header to file
     use std::io::Write;
     let header = format!("🤖 **Staged AI Agent Response**\nUser: {} ({})\nTask: {}\nTimestamp: {}\n\n", 
-        msg.author.name, user_id, task, chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC"));
+        msg.author.name, user_id, task, crate::commands::util::local_time("%Y-%m-%d %H:%M:%S %Z"));
     if let Err(e) = response_file.write_all(header.as_bytes()) {
         agent_error!(user_id, "execute_staged_task", "Failed to write header to file: {}", e);
     }
 
     // Send initial Discord message indicating staged processing
     let mut thinking_msg = match msg.channel_id.send_message(&ctx.http, |m| {
-        m.content("🤖 **Staged AI Agent Processing...**\n\n📝 **Stage 1:** Task Analysis & Planning\n⏳ Starting staged execution...")
+        m.content(style_output("🤖 **Staged AI Agent Processing...**\n\n📝 **Stage 1:** Task Analysis & Planning\n⏳ Starting staged execution..."))
     }).await {
         Ok(message) => {
             agent_debug!(user_id, "execute_staged_task", "Successfully sent status message");
@@ -3739,11 +5190,29 @@ async fn execute_staged_task(task: String, ctx: &Context, msg: &Message) -> Comm
     // Save initial staged task
     save_staged_task(&staged_task).await?;
 
-    // Execute each stage sequentially
+    // Execute each stage sequentially within the overall time budget
     let mut current_stage_index = 0;
     let mut previous_stage_output: Option<String> = None;
+    let staged_budget = Duration::from_secs(config.staged_max_total_secs);
+    let mut time_budget_exceeded = false;
 
     while current_stage_index < staged_task.stages.len() {
+        if is_staged_task_cancelled(&staged_task.task_id) {
+            agent_info!(user_id, "execute_staged_task", "Task cancelled before stage {}", current_stage_index + 1);
+            for stage in staged_task.stages.iter_mut().skip(current_stage_index) {
+                stage.status = StageStatus::Skipped;
+                stage.output = Some("Skipped - task cancelled".to_string());
+                stage.timestamp = Utc::now();
+            }
+            write_to_response_file(Some(&mut response_file), "🛑 Task cancelled with ^staged --cancel - remaining stages skipped", user_id);
+            break;
+        }
+        if start_time.elapsed() >= staged_budget {
+            agent_warn!(user_id, "execute_staged_task", "Time budget of {:?} exceeded before stage {}", staged_budget, current_stage_index + 1);
+            time_budget_exceeded = true;
+            break;
+        }
+
         staged_task.current_stage = current_stage_index as u8;
         staged_task.overall_status = match current_stage_index {
             0 => TaskStatus::Planning,
@@ -3781,85 +5250,99 @@ async fn execute_staged_task(task: String, ctx: &Context, msg: &Message) -> Comm
             }
         };
 
-        // Execute the current stage with streaming
-        let stage_result = match current_stage_index {
-            0 => {
-                // Stage 1: Planning
-                execute_stage_1_planning_streaming(&task, user_id, &config, &mut stage_streaming_msg, ctx).await
-            }
-            1 => {
-                // Stage 2: Code Generation (using planning output)
-                if let Some(plan_output) = &previous_stage_output {
-                    let plan_stage = AgentStage {
-                        stage_id: 1,
-                        name: "Task Analysis & Planning".to_string(),
-                        description: "Analyze user request and create execution plan".to_string(),
-                        status: StageStatus::Completed,
-                        input: Some(task.clone()),
-                        output: Some(plan_output.clone()),
-                        timestamp: Utc::now(),
-                        duration: None,
-                    };
-                    execute_stage_2_code_generation_streaming(&plan_stage, user_id, &config, &mut stage_streaming_msg, ctx).await
-        } else {
-                    Err("No planning output available for code generation".into())
+        // Execute the current stage with streaming, cut off when the overall time budget runs out
+        let remaining_budget = staged_budget.saturating_sub(start_time.elapsed());
+        let stage_future = async {
+            match current_stage_index {
+                0 => {
+                    // Stage 1: Planning
+                    execute_stage_1_planning_streaming(&task, user_id, &config, &mut stage_streaming_msg, ctx).await
                 }
-            }
-            2 => {
-                // Stage 3: Execution (using code generation output)
-                if let Some(code_output) = &previous_stage_output {
-                    let code_stage = AgentStage {
-                        stage_id: 2,
-                        name: "Code Generation & Validation".to_string(),
-                        description: "Generate JavaScript code based on planning stage".to_string(),
-                        status: StageStatus::Completed,
-                        input: None,
-                        output: Some(code_output.clone()),
-                        timestamp: Utc::now(),
-                        duration: None,
-                    };
-                    execute_stage_3_execution_streaming(&code_stage, user_id, &config, &mut stage_streaming_msg, ctx).await
-                } else {
-                    Err("No code generation output available for execution".into())
+                1 => {
+                    // Stage 2: Code Generation (using planning output)
+                    if let Some(plan_output) = &previous_stage_output {
+                        let plan_stage = AgentStage {
+                            stage_id: 1,
+                            name: "Task Analysis & Planning".to_string(),
+                            description: "Analyze user request and create execution plan".to_string(),
+                            status: StageStatus::Completed,
+                            input: Some(task.clone()),
+                            output: Some(plan_output.clone()),
+                            timestamp: Utc::now(),
+                            duration: None,
+                        };
+                        execute_stage_2_code_generation_streaming(&plan_stage, user_id, &config, &mut stage_streaming_msg, ctx).await
+            } else {
+                        Err("No planning output available for code generation".into())
+                    }
                 }
-            }
-            3 => {
-                // Stage 4: Analysis (using execution output)
-                if let Some(execution_output) = &previous_stage_output {
-                    let execution_stage = AgentStage {
-                        stage_id: 3,
-                        name: "Execution & Testing".to_string(),
-                        description: "Execute generated code and perform testing".to_string(),
-                        status: StageStatus::Completed,
-                        input: None,
-                        output: Some(execution_output.clone()),
-                        timestamp: Utc::now(),
-                        duration: None,
-                    };
-                    execute_stage_4_analysis_streaming(&execution_stage, user_id, &config, &mut stage_streaming_msg, ctx).await
-                } else {
-                    Err("No execution output available for analysis".into())
+                2 => {
+                    // Stage 3: Execution (using code generation output)
+                    if let Some(code_output) = &previous_stage_output {
+                        let code_stage = AgentStage {
+                            stage_id: 2,
+                            name: "Code Generation & Validation".to_string(),
+                            description: "Generate JavaScript code based on planning stage".to_string(),
+                            status: StageStatus::Completed,
+                            input: None,
+                            output: Some(code_output.clone()),
+                            timestamp: Utc::now(),
+                            duration: None,
+                        };
+                        execute_stage_3_execution_streaming(&code_stage, user_id, &config, &mut stage_streaming_msg, ctx).await
+                    } else {
+                        Err("No code generation output available for execution".into())
+                    }
                 }
-            }
-            4 => {
-                // Stage 5: Delivery (using analysis output)
-                if let Some(analysis_output) = &previous_stage_output {
-                    let analysis_stage = AgentStage {
-                        stage_id: 4,
-                        name: "Result Analysis & Optimization".to_string(),
-                        description: "Analyze execution results and optimize solution".to_string(),
-                        status: StageStatus::Completed,
-                        input: None,
-                        output: Some(analysis_output.clone()),
-                        timestamp: Utc::now(),
-                        duration: None,
-                    };
-                    execute_stage_5_delivery_streaming(&analysis_stage, user_id, &config, &mut stage_streaming_msg, ctx).await
-                } else {
-                    Err("No analysis output available for delivery".into())
+                3 => {
+                    // Stage 4: Analysis (using execution output)
+                    if let Some(execution_output) = &previous_stage_output {
+                        let execution_stage = AgentStage {
+                            stage_id: 3,
+                            name: "Execution & Testing".to_string(),
+                            description: "Execute generated code and perform testing".to_string(),
+                            status: StageStatus::Completed,
+                            input: None,
+                            output: Some(execution_output.clone()),
+                            timestamp: Utc::now(),
+                            duration: None,
+                        };
+                        execute_stage_4_analysis_streaming(&execution_stage, user_id, &config, &mut stage_streaming_msg, ctx).await
+                    } else {
+                        Err("No execution output available for analysis".into())
+                    }
+                }
+                4 => {
+                    // Stage 5: Delivery (using analysis output)
+                    if let Some(analysis_output) = &previous_stage_output {
+                        let analysis_stage = AgentStage {
+                            stage_id: 4,
+                            name: "Result Analysis & Optimization".to_string(),
+                            description: "Analyze execution results and optimize solution".to_string(),
+                            status: StageStatus::Completed,
+                            input: None,
+                            output: Some(analysis_output.clone()),
+                            timestamp: Utc::now(),
+                            duration: None,
+                        };
+                        execute_stage_5_delivery_streaming(&analysis_stage, user_id, &config, &mut stage_streaming_msg, ctx).await
+                    } else {
+                        Err("No analysis output available for delivery".into())
+                    }
                 }
+                _ => Err("Invalid stage index".into()),
+            }
+        };
+        let stage_result = match tokio::time::timeout(remaining_budget, stage_future).await {
+            Ok(result) => result,
+            Err(_) => {
+                agent_warn!(user_id, "execute_staged_task", "Time budget of {:?} exceeded during stage {}", staged_budget, stage_id);
+                let _ = stage_streaming_msg.edit(&ctx.http, |m| {
+                    m.content(style_output(format!("⏱️ **Stage {}: {}**\n\nStopped - the staged task exceeded its time budget.", stage_id, stage_name)))
+                }).await;
+                time_budget_exceeded = true;
+                break;
             }
-            _ => Err("Invalid stage index".into()),
         };
 
         match stage_result {
@@ -3893,8 +5376,8 @@ async fn execute_staged_task(task: String, ctx: &Context, msg: &Message) -> Comm
                     stage_id,
                     stage_name,
                     if let Some(output) = &stage_output {
-                        if output.len() > 200 {
-                            format!("{}...", &output[..200])
+                        if output.chars().count() > 200 {
+                            format!("{}...", truncate_chars(output, 200))
                         } else {
                             output.clone()
                         }
@@ -3934,6 +5417,19 @@ async fn execute_staged_task(task: String, ctx: &Context, msg: &Message) -> Comm
         current_stage_index += 1;
     }
 
+    // Anything left over after the time budget ran out is skipped
+    if time_budget_exceeded {
+        for stage in staged_task.stages.iter_mut().skip(current_stage_index) {
+            stage.status = StageStatus::Skipped;
+            stage.output = Some("Skipped - time budget exceeded".to_string());
+            stage.timestamp = Utc::now();
+        }
+        staged_task.overall_status = TaskStatus::Failed;
+        staged_task.updated_at = Utc::now();
+        save_staged_task(&staged_task).await?;
+        write_to_response_file(Some(&mut response_file), &format!("⏱️ Time budget of {}s exceeded - remaining stages skipped", config.staged_max_total_secs), user_id);
+    }
+
     // Write final summary to file
     write_to_response_file(Some(&mut response_file), "=== STAGED EXECUTION SUMMARY ===", user_id);
     for (i, stage) in staged_task.stages.iter().enumerate() {
@@ -3953,7 +5449,7 @@ async fn execute_staged_task(task: String, ctx: &Context, msg: &Message) -> Comm
         Ok(content) => content,
         Err(e) => {
             agent_error!(user_id, "execute_staged_task", "Failed to read response file: {}", e);
-            let _ = msg.reply(ctx, "❌ Failed to read response file").await;
+            let _ = msg.reply(ctx, with_error_id(style_output("❌ Failed to read response file"))).await;
             return Ok(());
         }
     };
@@ -3961,7 +5457,9 @@ async fn execute_staged_task(task: String, ctx: &Context, msg: &Message) -> Comm
     // Create final summary
     let completed_stages = staged_task.stages.iter().filter(|s| s.status == StageStatus::Completed).count();
     let total_stages = staged_task.stages.len();
-    let final_status = if staged_task.overall_status == TaskStatus::Complete {
+    let final_status = if time_budget_exceeded {
+        "⏱️ **Staged Execution Stopped - time budget exceeded**"
+    } else if staged_task.overall_status == TaskStatus::Complete {
         "✅ **Staged Execution Complete**"
     } else if staged_task.overall_status == TaskStatus::Failed {
         "❌ **Staged Execution Failed**"
@@ -3974,18 +5472,19 @@ async fn execute_staged_task(task: String, ctx: &Context, msg: &Message) -> Comm
         final_status, completed_stages, total_stages, task
     );
 
-    // Upload file to Discord
-    match msg.channel_id.send_files(&ctx.http, vec![(&*file_content.as_bytes(), response_filename.as_str())], |m| {
-        m.content(&discord_message)
-    }).await {
+    // Upload file to Discord; oversized or rate-limited uploads are split, retried or inlined
+    let max_len = config.max_discord_message_length - config.response_format_padding;
+    match crate::commands::util::send_file_with_fallback(ctx, msg.channel_id, &file_content, &response_filename, &discord_message, max_len).await {
         Ok(_) => {
             agent_info!(user_id, "execute_staged_task", "Successfully uploaded staged response file to Discord");
         }
         Err(e) => {
             agent_error!(user_id, "execute_staged_task", "Failed to upload response file: {}", e);
-            // Fallback to regular message
+            // Fallback to regular message, split so Discord can't reject it for length
             let fallback_message = format!("{} - Staged execution completed. Check logs for details.", final_status);
-            let _ = msg.channel_id.send_message(&ctx.http, |m| m.content(&fallback_message)).await;
+            if let Err(e) = crate::commands::util::send_long_message(ctx, msg.channel_id, &fallback_message, max_len, &response_filename).await {
+                agent_error!(user_id, "execute_staged_task", "Fallback message also failed: {}", e);
+            }
         }
     }
 
@@ -4005,14 +5504,54 @@ async fn execute_staged_task(task: String, ctx: &Context, msg: &Message) -> Comm
 }
 
 async fn save_staged_task(task: &StagedTask) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if is_staged_task_cancelled(&task.task_id) {
+        agent_debug!(task.user_id, "save_staged_task", "Not saving cancelled staged task {}", task.task_id);
+        return Ok(());
+    }
     let contexts = get_staged_tasks().await;
     if let Ok(mut contexts_map) = contexts.lock() {
         contexts_map.insert(task.user_id.to_string(), task.clone());
         agent_debug!(task.user_id, "save_staged_task", "Saved staged task for user: {}", task.user_id);
+
+        // Written while the lock is held so concurrent saves reach the disk in the order they were made
+        if let Err(e) = persist_staged_tasks(&contexts_map) {
+            agent_warn!(task.user_id, "save_staged_task", "Failed to persist staged tasks: {}", e);
+        }
     }
     Ok(())
 }
 
+// Remove a user's staged task from the store and the persisted file
+async fn remove_staged_task(user_id: UserId) -> Option<StagedTask> {
+    let contexts = get_staged_tasks().await;
+    let mut contexts_map = contexts.lock().ok()?;
+    let removed = contexts_map.remove(&user_id.to_string())?;
+    if let Ok(mut cancelled) = CANCELLED_STAGED_TASKS.lock() {
+        cancelled.insert(removed.task_id.clone());
+    }
+    if let Err(e) = persist_staged_tasks(&contexts_map) {
+        agent_warn!(user_id, "remove_staged_task", "Failed to persist staged tasks: {}", e);
+    }
+    Some(removed)
+}
+
+// Save the whole staged task store to STAGED_TASKS_FILE
+fn persist_staged_tasks(tasks: &HashMap<String, StagedTask>) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(tasks).map_err(|e| e.to_string())?;
+    write_staged_tasks_file(&json).map_err(|e| e.to_string())
+}
+
+// Write through a temporary file so a crash mid-write never leaves a truncated store behind
+fn write_staged_tasks_file(json: &str) -> std::io::Result<()> {
+    let path = std::path::Path::new(STAGED_TASKS_FILE);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp_path = path.with_extension("json.tmp");
+    fs::write(&tmp_path, json)?;
+    fs::rename(&tmp_path, path)
+}
+
 // ============================================================================
 // STREAMING FUNCTION CALLING
 // ============================================================================
@@ -4032,85 +5571,52 @@ async fn execute_function_calling_streaming(
     agent_trace!(user_id, "execute_function_calling_streaming", "Functions count: {}", functions.len());
     
     // Create the chat request
+    let sampling = config.sampling_for(&config.default_model);
     let chat_request = ChatRequest {
         model: config.default_model.clone(),
         messages: messages.to_vec(),
-        temperature: config.default_temperature,
-        max_tokens: config.default_max_tokens,
+        temperature: sampling.temperature,
+        max_tokens: sampling.max_tokens,
         stream: true,
-        seed: config.default_seed,
+        seed: sampling.seed,
         tools: Some(functions.to_vec()),
         tool_choice: Some("auto".to_string()),
     };
     
     agent_trace!(user_id, "execute_function_calling_streaming", "Created chat request with streaming enabled");
     
-    // Get HTTP client
-    let client = get_http_client().await;
-    
-    // Serialize the request
-    let request_body = match serde_json::to_string(&chat_request) {
-        Ok(body) => {
-            agent_trace!(user_id, "execute_function_calling_streaming", "Request body length: {} chars", body.len());
-            body
-        }
-        Err(e) => {
-            agent_error!(user_id, "execute_function_calling_streaming", "Failed to serialize request: {}", e);
-            return Err(format!("Failed to serialize request: {}", e).into());
-        }
-    };
-    
     // Update streaming message with connection status
     let _ = streaming_msg.edit(&ctx.http, |m| {
-        m.content("🤖 **Connecting to LM Studio API...**\n\n🔄 **Status:** Sending request...\n\n📝 **Live Progress:**\n")
+        m.content(style_output("🤖 **Connecting to LM Studio API...**\n\n🔄 **Status:** Sending request...\n\n📝 **Live Progress:**\n"))
     }).await;
     
-    // Send the request
-    let response = match client
-        .post(&format!("{}/v1/chat/completions", config.base_url))
-        .header("Content-Type", "application/json")
-        .body(request_body)
-        .timeout(Duration::from_secs(config.timeout))
-        .send()
-        .await
-    {
-        Ok(response) => {
+    // Send the request through the same retrying backend as ^agent
+    let backend = HttpChatBackend { max_retries: config.max_retries };
+    let api_url = format!("{}/v1/chat/completions", config.base_url);
+    let mut events = match backend.stream_chat(&api_url, &chat_request, config.timeout).await {
+        Ok(stream) => {
             agent_trace!(user_id, "execute_function_calling_streaming", "Received response from API");
-            response
+            ChatEventStream::new(stream)
         }
         Err(e) => {
-            agent_error!(user_id, "execute_function_calling_streaming", "Failed to send request: {}", e);
-            let error_msg = format!("❌ **API Connection Failed**\n\n📝 **Error:** {}\n\n🔄 **Status:** Request failed", e);
+            agent_error!(user_id, "execute_function_calling_streaming", "Request failed: {}", e);
+            let error_msg = format!("❌ **API Request Failed**\n\n{}\n\n🔄 **Status:** Request failed", e);
             let _ = streaming_msg.edit(&ctx.http, |m| m.content(&error_msg)).await;
-            return Err(format!("Failed to send request: {}", e).into());
+            return Err(e);
         }
     };
     
-    // Check if the response is successful
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-        agent_error!(user_id, "execute_function_calling_streaming", "API returned error status {}: {}", status, error_text);
-        let error_msg = format!("❌ **API Error**\n\n📝 **Status:** {}\n📝 **Error:** {}\n\n🔄 **Status:** Request failed", status, error_text);
-        let _ = streaming_msg.edit(&ctx.http, |m| m.content(&error_msg)).await;
-        return Err(format!("API returned error status {}: {}", status, error_text).into());
-    }
-    
     // Update streaming message with streaming status
     let _ = streaming_msg.edit(&ctx.http, |m| {
-        m.content("🤖 **Connected to LM Studio API**\n\n🔄 **Status:** Streaming response...\n\n📝 **Live Progress:**\n")
+        m.content(style_output("🤖 **Connected to LM Studio API**\n\n🔄 **Status:** Streaming response...\n\n📝 **Live Progress:**\n"))
     }).await;
     
-    // Get the response stream
-    let mut stream = response.bytes_stream();
-    agent_trace!(user_id, "execute_function_calling_streaming", "Successfully created response stream");
-    
     // Variables for streaming
     let mut buffer = String::new();
     let mut collected_tool_calls: Vec<String> = Vec::new();
     let mut function_call_buffer = String::new();
-    let mut last_update = Instant::now();
-    let update_interval = Duration::from_millis(500); // Update every 500ms to reduce load
+    // Update every 500ms to reduce load, backing off further if Discord rate limits the edits
+    let mut edit_throttle = EditThrottle::new(Duration::from_millis(500), "AGENT");
     
     // Accumulation buffers (keep all content)
     let mut content_buffer = String::new();
@@ -4122,153 +5628,124 @@ async fn execute_function_calling_streaming(
     let max_display_chars = 1200; // Keep display under Discord limit
     
     agent_trace!(user_id, "execute_function_calling_streaming", "Starting SSE streaming loop");
-    agent_info!(user_id, "execute_function_calling_streaming", "Streaming configuration: update_interval={:?}, max_display_chars={}", update_interval, max_display_chars);
+    agent_info!(user_id, "execute_function_calling_streaming", "Streaming configuration: update_interval={:?}, max_display_chars={}", edit_throttle.interval(), max_display_chars);
     
     // Process the stream
-    let mut chunk_count = 0;
-    while let Some(chunk_result) = stream.next().await {
-        chunk_count += 1;
-        if chunk_count % 10 == 0 {
-            agent_trace!(user_id, "execute_function_calling_streaming", "Processed {} chunks, buffer_len={}, display_len={}", chunk_count, buffer.len(), display_content.len());
-        }
-        match chunk_result {
-            Ok(chunk) => {
-                // Convert chunk to string
-                let chunk_str = match String::from_utf8(chunk.to_vec()) {
-                    Ok(s) => s,
-                    Err(e) => {
-                        agent_warn!(user_id, "execute_function_calling_streaming", "Failed to convert chunk to string: {}", e);
-                        continue;
-                    }
-                };
-                
-                // Split by lines and process each line
-                for line in chunk_str.lines() {
-                    if line.starts_with("data: ") {
-                        let data = &line[6..]; // Remove "data: " prefix
+    let mut event_count = 0;
+    while let Some(event) = events.next_event().await {
+        let json_data = match event {
+            Ok(json_data) => json_data,
+            Err(e) => {
+                agent_error!(user_id, "execute_function_calling_streaming", "Stream failed: {}", e);
+                let error_msg = format!("❌ **Streaming Error**\n\n📝 **Error:** {}\n\n🔄 **Status:** Stream reading failed", e);
+                let _ = streaming_msg.edit(&ctx.http, |m| m.content(&error_msg)).await;
+                return Err(e);
+            }
+        };
+        event_count += 1;
+        if event_count % 10 == 0 {
+            agent_trace!(user_id, "execute_function_calling_streaming", "Processed {} events, buffer_len={}, display_len={}", event_count, buffer.len(), display_content.len());
+        }
+
+        // Extract content delta
+        if let Some(choices) = json_data.get("choices").and_then(|c| c.as_array()) {
+            for choice in choices {
+                if let Some(delta) = choice.get("delta") {
+                    // Handle content delta with simplified scrolling
+                    if let Some(content) = delta.get("content").and_then(|c| c.as_str()) {
+                        content_buffer.push_str(content);
+                        buffer.push_str(content);
+                        display_content.push_str(content);
                         
-                        if data == "[DONE]" {
-                            agent_trace!(user_id, "execute_function_calling_streaming", "Received [DONE] signal");
-                            break;
+                        // Only scroll when significantly over limit to reduce frequency
+                        if display_content.len() > max_display_chars + 200 {
+                            // Scroll: keep only the most recent content
+                            let keep_chars = max_display_chars;
+                            if display_content.len() > keep_chars {
+                                display_content = tail_chars(&display_content, keep_chars).to_string();
+                            }
                         }
-                        
-                        // Parse the JSON data
-                        match serde_json::from_str::<serde_json::Value>(data) {
-                            Ok(json_data) => {
-                                // Extract content delta
-                                if let Some(choices) = json_data.get("choices").and_then(|c| c.as_array()) {
-                                    for choice in choices {
-                                        if let Some(delta) = choice.get("delta") {
-                                            // Handle content delta with simplified scrolling
-                                            if let Some(content) = delta.get("content").and_then(|c| c.as_str()) {
-                                                content_buffer.push_str(content);
-                                                buffer.push_str(content);
-                                                display_content.push_str(content);
-                                                
-                                                // Only scroll when significantly over limit to reduce frequency
-                                                if display_content.len() > max_display_chars + 200 {
-                                                    // Scroll: keep only the most recent content
-                                                    let keep_chars = max_display_chars;
-                                                    if display_content.len() > keep_chars {
-                                                        display_content = display_content[display_content.len() - keep_chars..].to_string();
-                                                    }
-                                                }
-                                            }
-                                            
-                                            // Handle tool_calls delta
-                                            if let Some(tool_calls) = delta.get("tool_calls").and_then(|tc| tc.as_array()) {
-                                                for tool_call in tool_calls {
-                                                    if let Some(function) = tool_call.get("function") {
-                                                        // Extract function name
-                                                        if let Some(name) = function.get("name").and_then(|n| n.as_str()) {
-                                                            if !collected_tool_calls.contains(&name.to_string()) {
-                                                                collected_tool_calls.push(name.to_string());
-                                                                agent_trace!(user_id, "execute_function_calling_streaming", "Detected function call: {}", name);
-                                                            }
-                                                        }
-                                                        
-                                                        // Extract arguments with simplified scrolling
-                                                        if let Some(args) = function.get("arguments").and_then(|a| a.as_str()) {
-                                                            function_call_buffer.push_str(args);
-                                                            tool_calls_buffer.push_str(args);
-                                                            display_tool_calls.push_str(args);
-                                                            
-                                                            // Only scroll tool calls when significantly over limit
-                                                            if display_tool_calls.len() > 400 {
-                                                                // Scroll: keep only the most recent tool calls
-                                                                let keep_chars = 300;
-                                                                if display_tool_calls.len() > keep_chars {
-                                                                    display_tool_calls = display_tool_calls[display_tool_calls.len() - keep_chars..].to_string();
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                            }
+                    }
+                    
+                    // Handle tool_calls delta
+                    if let Some(tool_calls) = delta.get("tool_calls").and_then(|tc| tc.as_array()) {
+                        for tool_call in tool_calls {
+                            if let Some(function) = tool_call.get("function") {
+                                // Extract function name
+                                if let Some(name) = function.get("name").and_then(|n| n.as_str()) {
+                                    if !collected_tool_calls.contains(&name.to_string()) {
+                                        collected_tool_calls.push(name.to_string());
+                                        agent_trace!(user_id, "execute_function_calling_streaming", "Detected function call: {}", name);
+                                    }
+                                }
+                                
+                                // Extract arguments with simplified scrolling
+                                if let Some(args) = function.get("arguments").and_then(|a| a.as_str()) {
+                                    function_call_buffer.push_str(args);
+                                    tool_calls_buffer.push_str(args);
+                                    display_tool_calls.push_str(args);
+                                    
+                                    // Only scroll tool calls when significantly over limit
+                                    if display_tool_calls.len() > 400 {
+                                        // Scroll: keep only the most recent tool calls
+                                        let keep_chars = 300;
+                                        if display_tool_calls.len() > keep_chars {
+                                            display_tool_calls = tail_chars(&display_tool_calls, keep_chars).to_string();
                                         }
                                     }
                                 }
                             }
-                            Err(e) => {
-                                agent_warn!(user_id, "execute_function_calling_streaming", "Failed to parse JSON: {}", e);
-                                continue;
-                            }
                         }
                     }
                 }
-                
-                // Update Discord message periodically with scrolling display
-                if last_update.elapsed() >= update_interval {
-                    let mut update_content = String::new();
-                    update_content.push_str("🤖 **Streaming from LM Studio API**\n\n");
-                    update_content.push_str("🔄 **Status:** Processing...\n\n");
-                    update_content.push_str("📝 **Live Progress:**\n");
-                    
-                    // Add scrolling content preview
-                    if !display_content.is_empty() {
-                        update_content.push_str(&format!("💭 **AI Response:**\n{}\n\n", display_content));
-                    }
-                    
-                    // Add function call preview
-                    if !collected_tool_calls.is_empty() {
-                        update_content.push_str(&format!("🔧 **Function Calls:** {}\n", collected_tool_calls.join(", ")));
-                    }
-                    
-                    // Add scrolling tool calls preview
-                    if !display_tool_calls.is_empty() {
-                        update_content.push_str(&format!("📝 **Tool Arguments:**\n{}\n", display_tool_calls));
-                    }
-                    
-                    // Check if we need to reset due to Discord limit
-                    if update_content.len() > 1800 {
-                        // Clear display buffers for fresh start
-                        display_content.clear();
-                        display_tool_calls.clear();
-                        
-                        // Start fresh display
-                        update_content = "🤖 **Streaming from LM Studio API**\n\n🔄 **Status:** Processing...\n\n📝 **Live Progress:**\n(Scrolling reset - continuing stream)\n".to_string();
-                        
-                        // Force immediate update to Discord to reset the message
-                        let _ = streaming_msg.edit(&ctx.http, |m| m.content(&update_content)).await;
-                        last_update = Instant::now();
-                        continue; // Skip the regular update cycle
-                    }
-                    
-                    let _ = streaming_msg.edit(&ctx.http, |m| m.content(&update_content)).await;
-                    last_update = Instant::now();
-                }
             }
-            Err(e) => {
-                agent_error!(user_id, "execute_function_calling_streaming", "Error reading stream chunk: {}", e);
-                let error_msg = format!("❌ **Streaming Error**\n\n📝 **Error:** {}\n\n🔄 **Status:** Stream reading failed", e);
-                let _ = streaming_msg.edit(&ctx.http, |m| m.content(&error_msg)).await;
-                return Err(format!("Error reading stream chunk: {}", e).into());
+        }
+        
+        // Update Discord message periodically with scrolling display
+        if edit_throttle.ready() {
+            let mut update_content = String::new();
+            update_content.push_str("🤖 **Streaming from LM Studio API**\n\n");
+            update_content.push_str("🔄 **Status:** Processing...\n\n");
+            update_content.push_str("📝 **Live Progress:**\n");
+            
+            // Add scrolling content preview
+            if !display_content.is_empty() {
+                update_content.push_str(&format!("💭 **AI Response:**\n{}\n\n", close_open_code_fence(&display_content)));
+            }
+            
+            // Add function call preview
+            if !collected_tool_calls.is_empty() {
+                update_content.push_str(&format!("🔧 **Function Calls:** {}\n", collected_tool_calls.join(", ")));
+            }
+            
+            // Add scrolling tool calls preview
+            if !display_tool_calls.is_empty() {
+                update_content.push_str(&format!("📝 **Tool Arguments:**\n{}\n", display_tool_calls));
+            }
+            
+            // Check if we need to reset due to Discord limit
+            if update_content.len() > 1800 {
+                // Clear display buffers for fresh start
+                display_content.clear();
+                display_tool_calls.clear();
+                
+                // Start fresh display
+                update_content = "🤖 **Streaming from LM Studio API**\n\n🔄 **Status:** Processing...\n\n📝 **Live Progress:**\n(Scrolling reset - continuing stream)\n".to_string();
+                
+                // Force immediate update to Discord to reset the message
+                let result = streaming_msg.edit(&ctx.http, |m| m.content(&update_content)).await;
+                edit_throttle.record(&result);
+                continue; // Skip the regular update cycle
             }
+            
+            let result = streaming_msg.edit(&ctx.http, |m| m.content(&update_content)).await;
+            edit_throttle.record(&result);
         }
     }
     
     // Final update with complete results
-    agent_info!(user_id, "execute_function_calling_streaming", "Streaming completed: {} chunks processed, {} chars in buffer", chunk_count, buffer.len());
+    agent_info!(user_id, "execute_function_calling_streaming", "Streaming completed: {} events processed, {} chars in buffer", event_count, buffer.len());
+    let buffer = append_finish_reason_note(buffer, events.finish_reason());
     let final_content = format!(
         "✅ **Streaming Complete**\n\n📝 **Final Response:**\n{}\n\n⏱️ **Duration:** {:?}",
         buffer, start_time.elapsed()
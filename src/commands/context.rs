@@ -0,0 +1,130 @@
+// context.rs - Context Inspection Command Module
+// This module implements the ^context command, which lets users review what the bot
+// remembers about them and export their stored conversation history.
+//
+// Key Features:
+// - Summarizes the invoker's LM and Reason contexts (message counts, last update, interactions)
+// - Exports the full stored context as a JSON file with --export
+// - Only the bot owner may inspect or export another user's context
+//
+// Used by: main.rs (command registration)
+
+// ============================================================================
+// IMPORTS
+// ============================================================================
+
+use serenity::{
+    client::Context,
+    framework::standard::{macros::command, macros::group, Args, CommandResult},
+    model::{channel::{AttachmentType, Message}, id::UserId},
+};
+use std::borrow::Cow;
+use std::env;
+use crate::{LmContextMap, ReasonContextMap, UserContext};
+
+// ============================================================================
+// COMMAND IMPLEMENTATION
+// ============================================================================
+
+#[command]
+#[aliases("mycontext", "memory")]
+/// Main ^context command handler
+/// Shows what the bot has stored for the invoking user, or exports it as JSON
+/// Supports:
+///   - ^context
+///   - ^context --export
+///   - ^context [--export] @user (owner only)
+pub async fn context(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let input = args.message().trim();
+    let export = input.split_whitespace().any(|arg| arg == "--export" || arg == "-e");
+
+    // Default to the invoker; other users can only be targeted by the owner
+    let target = msg.mentions.iter().find(|user| !user.bot).map(|user| user.id).unwrap_or(msg.author.id);
+    if target != msg.author.id && !is_bot_owner(msg.author.id) {
+        msg.reply(ctx, "❌ **Access Denied**\nYou can only view or export your own context.").await?;
+        return Ok(());
+    }
+
+    let (lm_context, reason_context) = {
+        let data = ctx.data.read().await;
+        let lm_context = data.get::<LmContextMap>().and_then(|map| map.get(&target).cloned());
+        let reason_context = data.get::<ReasonContextMap>().and_then(|map| map.get(&target).cloned());
+        (lm_context, reason_context)
+    };
+
+    println!("[CONTEXT] Context {} requested by {} ({}) for user {}",
+        if export { "export" } else { "summary" }, msg.author.name, msg.author.id, target);
+
+    if export {
+        let export_data = serde_json::json!({
+            "user_id": target.0.to_string(),
+            "exported_at": chrono::Utc::now().to_rfc3339(),
+            "lm_context": lm_context,
+            "reason_context": reason_context,
+        });
+        let json = serde_json::to_string_pretty(&export_data)?;
+        let filename = format!("context_{}.json", target.0);
+
+        msg.channel_id.send_message(&ctx.http, |m| {
+            m.content(format!("📦 **Context Export** for <@{}>", target.0))
+                .reference_message(msg)
+                .add_file(AttachmentType::Bytes {
+                    data: Cow::Owned(json.into_bytes()),
+                    filename,
+                })
+        }).await?;
+        return Ok(());
+    }
+
+    let summary = format!(
+        "🧠 **Stored Context** for <@{}>\n\n{}\n\n{}\n\nUse `^context --export` to download the full history as JSON.",
+        target.0,
+        format_context_summary("💬 LM context (`^lm`)", lm_context.as_ref()),
+        format_context_summary("🤔 Reason context (`^reason`)", reason_context.as_ref()),
+    );
+    msg.reply(ctx, summary).await?;
+
+    Ok(())
+}
+
+// ============================================================================
+// HELPER FUNCTIONS
+// ============================================================================
+
+/// Check whether the given user is the configured bot owner
+fn is_bot_owner(user_id: UserId) -> bool {
+    let bot_owner_id = env::var("BOT_OWNER_ID").unwrap_or_else(|_| {
+        env::var("BOT_USER_ID").unwrap_or_else(|_| "1385309017881968761".to_string())
+    });
+    user_id.to_string() == bot_owner_id
+}
+
+/// Format a one-block summary of a stored context
+fn format_context_summary(label: &str, context: Option<&UserContext>) -> String {
+    match context {
+        Some(context) if context.total_messages() > 0 => format!(
+            "**{}**\n• Messages: {} from you, {} from the bot\n• Total interactions: {}\n• Compacted memory: {}\n• Last updated: <t:{}:R>",
+            label,
+            context.user_messages.len(),
+            context.assistant_messages.len(),
+            context.total_interactions,
+            if context.memory.is_some() { "yes" } else { "no" },
+            context.last_updated.timestamp(),
+        ),
+        _ => format!("**{}**\n• No stored messages", label),
+    }
+}
+
+// ============================================================================
+// COMMAND GROUP
+// ============================================================================
+
+#[group]
+#[commands(context)]
+pub struct ContextCmd;
+
+impl ContextCmd {
+    pub const fn new() -> Self {
+        ContextCmd
+    }
+}
@@ -0,0 +1,148 @@
+// count.rs - Count Command Module
+// This module implements the ^count command, which reports how big a piece of text is before it goes to a model.
+//
+// Key Features:
+// - Words, characters (with and without whitespace), lines and an estimated token count
+// - Counts the argument text, an attached text file, or the message being replied to
+// - Reuses the shared text attachment helpers (util::is_text_attachment / decode_text_bytes)
+//
+// Used by: main.rs (command registration)
+
+// ============================================================================
+// IMPORTS
+// ============================================================================
+
+use serenity::{
+    client::Context,
+    framework::standard::{macros::command, macros::group, Args, CommandResult},
+    model::channel::Message,
+};
+use crate::commands::util::{decode_text_bytes, is_text_attachment};
+
+// ============================================================================
+// CONSTANTS
+// ============================================================================
+
+/// Largest text attachment ^count will download
+const MAX_COUNT_ATTACHMENT_BYTES: u64 = 5 * 1024 * 1024;
+
+/// Rough characters-per-token ratio for English text with common BPE tokenizers
+const CHARS_PER_TOKEN: usize = 4;
+
+// ============================================================================
+// COMMAND IMPLEMENTATION
+// ============================================================================
+
+#[command]
+#[aliases("wc")]
+/// Main ^count command handler
+/// Reports words, characters, lines and estimated tokens
+/// Supports:
+///   - ^count <text>
+///   - ^count (with a text file attached)
+///   - ^count (as a reply to another message)
+pub async fn count(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let text = args.message().trim();
+
+    let (source, content) = if !text.is_empty() {
+        ("your text".to_string(), text.to_string())
+    } else if let Some(attachment) = msg.attachments.first() {
+        if !is_text_attachment(&attachment.filename, attachment.content_type.as_deref()) {
+            msg.reply(ctx, format!("❌ `{}` doesn't look like a text file.", attachment.filename)).await?;
+            return Ok(());
+        }
+        if attachment.size > MAX_COUNT_ATTACHMENT_BYTES {
+            msg.reply(ctx, format!(
+                "❌ `{}` is too large ({:.1} MB). Files up to {} MB can be counted.",
+                attachment.filename,
+                attachment.size as f64 / (1024.0 * 1024.0),
+                MAX_COUNT_ATTACHMENT_BYTES / (1024 * 1024)
+            )).await?;
+            return Ok(());
+        }
+        match attachment.download().await {
+            Ok(bytes) => (format!("`{}`", attachment.filename), decode_text_bytes(&bytes)),
+            Err(e) => {
+                eprintln!("[COUNT] Failed to download attachment {}: {}", attachment.filename, e);
+                msg.reply(ctx, format!("❌ Failed to download `{}`: {}", attachment.filename, e)).await?;
+                return Ok(());
+            }
+        }
+    } else if let Some(referenced) = msg.referenced_message.as_deref() {
+        (format!("the message from {}", referenced.author.name), referenced.content.clone())
+    } else {
+        msg.reply(ctx, "Please provide text to count, attach a text file, or reply to a message with `^count`!").await?;
+        return Ok(());
+    };
+
+    let stats = TextStats::of(&content);
+    msg.reply(ctx, format!("📏 **Text Stats** for {}\n{}", source, stats.summary())).await?;
+    Ok(())
+}
+
+// ============================================================================
+// HELPER FUNCTIONS
+// ============================================================================
+
+/// Size measurements for a piece of text
+#[derive(Debug, PartialEq)]
+struct TextStats {
+    words: usize,
+    chars: usize,
+    chars_no_whitespace: usize,
+    lines: usize,
+    estimated_tokens: usize,
+}
+
+impl TextStats {
+    fn of(text: &str) -> Self {
+        let chars = text.chars().count();
+        Self {
+            words: text.split_whitespace().count(),
+            chars,
+            chars_no_whitespace: text.chars().filter(|c| !c.is_whitespace()).count(),
+            lines: text.lines().count(),
+            // Heuristic only: the real count depends on the model's tokenizer
+            estimated_tokens: chars.div_ceil(CHARS_PER_TOKEN),
+        }
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "• Words: {}\n• Characters: {} ({} without whitespace)\n• Lines: {}\n• Estimated tokens: ~{}",
+            self.words, self.chars, self.chars_no_whitespace, self.lines, self.estimated_tokens
+        )
+    }
+}
+
+// ============================================================================
+// COMMAND GROUP
+// ============================================================================
+
+#[group]
+#[commands(count)]
+pub struct Count;
+
+impl Count {
+    pub const fn new() -> Self {
+        Count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_stats() {
+        let stats = TextStats::of("Hello there,\nGeneral Kenobi 🦀\n");
+        assert_eq!(stats, TextStats {
+            words: 5,
+            chars: 30,
+            chars_no_whitespace: 25,
+            lines: 2,
+            estimated_tokens: 8,
+        });
+        assert_eq!(TextStats::of("").estimated_tokens, 0);
+    }
+}
@@ -0,0 +1,233 @@
+// error.rs - Structured Error Types
+// This module defines BotError, the error type returned by the LM request and connectivity code.
+//
+// Key Features:
+// - One variant per failure callers care about (connectivity, timeouts, missing models, rate limits, backend errors)
+// - Classifies reqwest errors by their io::ErrorKind / OS error code instead of matching message text
+// - Display output keeps the user-facing Discord guidance the string errors used to carry
+// - Travels inside Box<dyn Error + Send + Sync>; callers branch with BotError::find
+//
+// Used by: search.rs (shared send_chat_request/send_chat_stream), sum.rs (chat completions and streaming summaries), agent.rs (via send_chat_stream), rank.rs (connectivity test)
+
+use thiserror::Error;
+
+/// Windows "access permissions" socket error (WSAEACCES), usually a firewall or sandbox block
+const WSAEACCES: i32 = 10013;
+
+#[derive(Debug, Error)]
+pub enum BotError {
+    #[error("🚫 **Windows Network Permission Error**\n\n\
+        Cannot connect to LM Studio API\n\n\
+        **Quick Fixes:**\n\
+        • **Run as Administrator**: Right-click and 'Run as administrator'\n\
+        • **Windows Firewall**: Add firewall exception for this program\n\
+        • **Try localhost**: Use `http://127.0.0.1:1234` in lmapiconf.txt\n\n\
+        **Current URL:** {url}\n\
+        **Error:** {detail}")]
+    NetworkPermission { url: String, detail: String },
+
+    #[error("🚫 **Connection Refused**\n\n\
+        LM Studio is not accepting connections\n\n\
+        **Solutions:**\n\
+        • **Start LM Studio**: Make sure LM Studio is running\n\
+        • **Load Model**: Ensure a model is loaded\n\
+        • **Enable Server**: Click 'Start Server' in LM Studio\n\
+        • **Check Port**: Verify port 1234 is available\n\n\
+        **Current URL:** {url}\n\
+        **Error:** {detail}")]
+    ConnectionRefused { url: String, detail: String },
+
+    /// Any other network failure; `transient` marks dropped connections worth retrying
+    #[error("API request failed: {detail}")]
+    Connectivity { url: String, detail: String, transient: bool },
+
+    #[error("⏰ **Request Timeout**\n\nNo response from `{url}` within {secs} seconds")]
+    Timeout { url: String, secs: u64 },
+
+    #[error("🚫 **Model Not Loaded**\n\n\
+        Model `{model}` is not available on the server\n\n\
+        **Solutions:**\n\
+        • **Model Loaded**: Load `{model}` in LM Studio\n\
+        • **Model Name**: Verify the model name in lmapiconf.txt matches exactly\n\n\
+        **Response:** {detail}")]
+    ModelNotLoaded { model: String, detail: String },
+
+    #[error("⏳ **Rate Limited**\n\nThe server is throttling requests{}", .retry_after.map(|secs| format!(", retry in {}s", secs)).unwrap_or_default())]
+    RateLimited { retry_after: Option<u64> },
+
+    #[error("❌ **Bad Input**\n\n{0}")]
+    BadInput(String),
+
+    #[error("🚫 **API Error (HTTP {status})**\n\n\
+        **Response:** {body}\n\n\
+        **Solutions:**\n\
+        • **Model Loaded**: Ensure model '{model}' is loaded in LM Studio\n\
+        • **Model Name**: Verify model name matches exactly\n\
+        • **Server Status**: Check LM Studio server logs\n\
+        • **Memory**: Ensure sufficient RAM for the model\n\n\
+        **API URL:** {url}")]
+    Backend { status: u16, body: String, model: String, url: String },
+
+    #[error("🚫 **Invalid API Response**\n\n{0}")]
+    InvalidResponse(String),
+
+    /// A retryable failure that persisted through every attempt (LM_MAX_RETRIES)
+    #[error("{last}\n\n*Request failed after {attempts} attempts*")]
+    RetriesExhausted { attempts: u32, last: Box<BotError> },
+}
+
+impl BotError {
+    /// Classify a failed request by inspecting the io::Error underneath reqwest/hyper
+    pub fn from_request_error(error: &reqwest::Error, url: &str, timeout_secs: u64) -> Self {
+        let url = url.to_string();
+        let detail = error.to_string();
+        if error.is_timeout() {
+            return BotError::Timeout { url, secs: timeout_secs };
+        }
+
+        match io_error_in_chain(error) {
+            Some(io_error) if io_error.raw_os_error() == Some(WSAEACCES)
+                || io_error.kind() == std::io::ErrorKind::PermissionDenied => {
+                BotError::NetworkPermission { url, detail }
+            }
+            Some(io_error) if io_error.kind() == std::io::ErrorKind::ConnectionRefused => {
+                BotError::ConnectionRefused { url, detail }
+            }
+            Some(io_error) => {
+                let transient = matches!(
+                    io_error.kind(),
+                    std::io::ErrorKind::ConnectionReset
+                        | std::io::ErrorKind::ConnectionAborted
+                        | std::io::ErrorKind::BrokenPipe
+                        | std::io::ErrorKind::UnexpectedEof
+                        | std::io::ErrorKind::TimedOut
+                );
+                BotError::Connectivity { url, detail, transient }
+            }
+            // Connections closed mid-request surface as hyper errors without an io::Error
+            None => BotError::Connectivity { url, detail, transient: error.is_request() && !error.is_connect() },
+        }
+    }
+
+    /// Build the error for a non-success HTTP response, consuming its body
+    pub async fn from_response(response: reqwest::Response, model: &str, url: &str) -> Self {
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok());
+        let body = response.text().await.unwrap_or_else(|_| "Unable to read error response".to_string());
+        Self::from_status(status.as_u16(), retry_after, body, model, url)
+    }
+
+    /// Map an HTTP status (and the server's error body) onto a variant
+    pub fn from_status(status: u16, retry_after: Option<u64>, body: String, model: &str, url: &str) -> Self {
+        if status == 429 {
+            return BotError::RateLimited { retry_after };
+        }
+        if is_model_missing(status, &body) {
+            return BotError::ModelNotLoaded { model: model.to_string(), detail: body };
+        }
+        if matches!(status, 400 | 413 | 422) {
+            return BotError::BadInput(body);
+        }
+        BotError::Backend { status, body, model: model.to_string(), url: url.to_string() }
+    }
+
+    /// Whether repeating the same request may succeed
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            BotError::Timeout { .. } | BotError::RateLimited { .. } => true,
+            BotError::Connectivity { transient, .. } => *transient,
+            BotError::Backend { status, .. } => *status >= 500,
+            _ => false,
+        }
+    }
+
+    /// Whether the LM server could not be reached at all
+    pub fn is_connectivity(&self) -> bool {
+        match self {
+            BotError::RetriesExhausted { last, .. } => last.is_connectivity(),
+            _ => matches!(
+                self,
+                BotError::NetworkPermission { .. } | BotError::ConnectionRefused { .. } | BotError::Connectivity { .. }
+            ),
+        }
+    }
+
+    /// The failure behind exhausted retries, or the error itself
+    pub fn underlying(&self) -> &BotError {
+        match self {
+            BotError::RetriesExhausted { last, .. } => last.underlying(),
+            other => other,
+        }
+    }
+
+    /// Find a BotError inside a boxed error returned by the request helpers
+    /// Exhausted retries resolve to the last underlying failure so callers can branch on its kind
+    pub fn find<'a>(error: &'a (dyn std::error::Error + Send + Sync + 'static)) -> Option<&'a BotError> {
+        error.downcast_ref::<BotError>().map(BotError::underlying)
+    }
+}
+
+/// Walk an error's source chain looking for the underlying io::Error
+fn io_error_in_chain<'a>(error: &'a (dyn std::error::Error + 'static)) -> Option<&'a std::io::Error> {
+    let mut current: Option<&(dyn std::error::Error + 'static)> = Some(error);
+    while let Some(err) = current {
+        if let Some(io_error) = err.downcast_ref::<std::io::Error>() {
+            return Some(io_error);
+        }
+        current = err.source();
+    }
+    None
+}
+
+/// LM Studio and Ollama report unknown or unloaded models with a 404 or a 400 naming the model
+fn is_model_missing(status: u16, body: &str) -> bool {
+    let body = body.to_lowercase();
+    let mentions_missing_model = body.contains("model")
+        && (body.contains("not found") || body.contains("not loaded") || body.contains("no models loaded") || body.contains("does not exist"));
+    status == 404 || (status == 400 && mentions_missing_model)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_status_classifies_responses() {
+        let url = "http://localhost:1234/v1/chat/completions";
+        assert!(matches!(BotError::from_status(429, Some(5), String::new(), "m", url), BotError::RateLimited { retry_after: Some(5) }));
+        assert!(matches!(BotError::from_status(404, None, "Not Found".into(), "m", url), BotError::ModelNotLoaded { .. }));
+        assert!(matches!(
+            BotError::from_status(400, None, r#"{"error":"model 'qwen' not found"}"#.into(), "qwen", url),
+            BotError::ModelNotLoaded { .. }
+        ));
+        assert!(matches!(BotError::from_status(400, None, "context length exceeded".into(), "m", url), BotError::BadInput(_)));
+        assert!(matches!(BotError::from_status(503, None, String::new(), "m", url), BotError::Backend { status: 503, .. }));
+    }
+
+    #[test]
+    fn test_retryable_variants() {
+        let url = "http://localhost:1234";
+        assert!(BotError::from_status(500, None, String::new(), "m", url).is_retryable());
+        assert!(BotError::from_status(429, None, String::new(), "m", url).is_retryable());
+        assert!(!BotError::from_status(404, None, String::new(), "m", url).is_retryable());
+        assert!(BotError::Timeout { url: url.into(), secs: 30 }.is_retryable());
+        assert!(!BotError::ConnectionRefused { url: url.into(), detail: String::new() }.is_retryable());
+
+        let boxed: Box<dyn std::error::Error + Send + Sync> = BotError::BadInput("x".into()).into();
+        assert!(matches!(BotError::find(boxed.as_ref()), Some(BotError::BadInput(_))));
+
+        let exhausted = BotError::RetriesExhausted {
+            attempts: 3,
+            last: Box::new(BotError::Connectivity { url: url.into(), detail: "reset".into(), transient: true }),
+        };
+        assert!(!exhausted.is_retryable());
+        assert!(exhausted.is_connectivity());
+        assert!(exhausted.to_string().ends_with("failed after 3 attempts*"));
+        let boxed: Box<dyn std::error::Error + Send + Sync> = exhausted.into();
+        assert!(matches!(BotError::find(boxed.as_ref()), Some(BotError::Connectivity { .. })));
+    }
+}
@@ -15,13 +15,19 @@ pub async fn help(ctx: &Context, msg: &Message) -> CommandResult {
 
 **📝 Basic Commands:**
 • `^ping` - Test bot connectivity
+• `^ping --trace` - Shard latency, cache counts and memory usage
 • `^echo <message>` - Echo back your message
+• `^count <text>` - Words, characters, lines and estimated tokens (or attach a text file / reply to a message)
 • `^help` - Show this help message
 
 **🧠 AI & Language Model Commands:**
 • `^lm <prompt>` - AI chat with personal context
 • `<@Bot> <prompt>` - AI chat with global shared context
 • `^lm --seed <number> <prompt>` - Reproducible AI responses
+• `^lm --tone <preset> <prompt>` - Response style (concise, friendly, technical, formal)
+• `^lm --stop <seq> <prompt>` - Stop generating at a marker (repeatable, up to 4; `\n` for a newline)
+• `^lm --continue` - Continue your previous response from where it stopped (e.g. at the token limit)
+• `^lm --debug <prompt>` - Attach the request's model, payload, timings and finish_reason as `debug.txt`
 • `^lm -v <prompt>` - Vision analysis (attach image)
 • `^lm -s <query>` - AI-enhanced web search
 • `^lm --test` - Test API connectivity
@@ -31,11 +37,17 @@ pub async fn help(ctx: &Context, msg: &Message) -> CommandResult {
 • `^lm --clear-global` - Clear shared global context
 • `^clearcontext` - Clear your personal LM chat context
 • `^clearreasoncontext` - Clear your personal reasoning context
+• `^context` - Show what the bot remembers about you
+• `^context --export` - Download your stored context as JSON
 
 **🔍 Analysis Commands:**
 • `^reason <prompt>` - Advanced reasoning and analysis
 • `^sum <text>` - Text summarization
 • `^sum -f <file>` - Summarize uploaded document
+• `^translate <language> <text>` - Translate text (or attach a text file / reply to a message)
+• `^translate --detect <language> <text>` - Identify the source language first
+• `^quote` - Quote the message you reply to, with author, time and jump link
+• `^quote --explain` - Also add an AI commentary on the quote
 • `^vis <prompt>` - Visual analysis (attach image)
 
 **💡 Usage Examples:**
@@ -81,6 +93,7 @@ pub async fn lmhelp(ctx: &Context, msg: &Message) -> CommandResult {
 
 **🖼️ Vision Analysis:**
 • `^lm -v <prompt>` - Analyze attached images
+• `^lm -v --compare [focus]` - Describe what changed between exactly two attached images
 • `<@Bot> -v <prompt>` - Analyze images with global context
 • Supports: JPG, PNG, GIF, WebP formats
 
@@ -136,11 +149,30 @@ pub async fn reasonhelp(ctx: &Context, msg: &Message) -> CommandResult {
 • `^reason <prompt>` - Deep reasoning and analysis
 • Uses specialized reasoning models for complex problem-solving
 • Provides step-by-step thinking processes
+• `^reason --compare [--judge] <modelA> <modelB> <prompt>` - Ask two models and show both answers (optionally judged)
+• `^reason --cite <prompt>` - Ask for inline sources and list them with ✅/❌ reachability checks
+• `^reason --stream-file <prompt>` - Deliver a long analysis as an uploaded text file instead of split messages
+• `^reason --format answer|steps|both <prompt>` - Answer only, a numbered reasoning outline, or the outline then the answer
+• `^reason --stop <seq> <prompt>` - Stop generating at a marker (repeatable, up to 4)
+• `^reason --continue` - Continue your previous answer from where it stopped
+• `^reason --debug <question>` - Attach the request's model, payload, timings and finish_reason as `debug.txt`
 • `^clearreasoncontext` - Clear your personal reasoning context
 
 **📊 Text Summarization:**
 • `^sum <text>` - Summarize provided text
 • `^sum -f <file>` - Summarize uploaded document
+• `^sum --chapters <youtube url>` - Summarize a video chapter by chapter
+• `^sum --from-timestamp <youtube url with t=>` - Summarize only from the link's timestamp onward
+• `^sum --include-links <url>` - Add a list of the page's key outbound links
+• `^sum --format bullets|markdown|json <url>` - Choose the summary shape (JSON is validated, long JSON is attached)
+• `^sum --level brief|detailed <url>` - Condense a long summary into one message, or split it without condensing
+• `^sum --no-cache-write <url>` - Summarize without storing the result in the summary cache
+• `^sum --entities <url>` - Also list the people, organizations, dates, locations and key figures mentioned
+• `^sum` + attach a PDF - Summarize the PDF's text (up to 20 MB; scanned PDFs have no text to read)
+• `^sum [--items N] <feed url>` - Digest the latest N entries of an RSS/Atom feed (default 10)
+• `^sum --since <message link or id> [--mine]` - Catch up on this channel since a message (`--mine` focuses on mentions of and replies to you)
+• `^sum <url1> <url2> ...` - Summarize up to 5 links in one report
+• `^sum --compare <url1> <url2>` - Summarize two articles or videos and compare them (agreements, contradictions, unique points)
 • Supports multiple document formats
 
 **🖼️ Visual Analysis:**
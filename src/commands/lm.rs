@@ -21,7 +21,13 @@ use std::fs;
 use serde::{Deserialize, Serialize};
 use futures_util::StreamExt;
 use crate::LmContextMap; // TypeMap key defined in main.rs
-use crate::commands::search::{ChatMessage, LMConfig, load_lm_config}; // Use from search module
+use crate::commands::search::{ChatMessage, LMConfig, load_lm_config, continue_interrupted_stream, send_chat_request, tone_directive, tone_preset_names, SamplingConfig, STREAM_CONTINUE_PROMPT}; // Use from search module
+use crate::commands::util::{current_request_id, empty_response_message, empty_response_retry, finish_reason_note, invocation_request_id, is_empty_response, parse_leading_flags, strip_think_blocks, strip_think_blocks_live, tail_chars, with_error_id, with_request_id, DebugLog, EditThrottle};
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+// Minimum time between live edits of the response message while streaming
+const LIVE_EDIT_INTERVAL_MS: u64 = 750;
 
 // API structures for chat completion
 #[derive(Serialize)]
@@ -33,6 +39,9 @@ pub struct ChatRequest {
     pub stream: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub seed: Option<i64>,
+    // --stop sequences; Ollama's OpenAI-compatible endpoint maps this field to options.stop
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
 }
 
 // Multimodal chat request for vision
@@ -76,22 +85,53 @@ pub struct MessageState {
 #[derive(Serialize, Deserialize, Clone)]
 pub struct MultimodalChatMessage {
     pub role: String,
-    pub content: Vec<MessageContent>,
+    pub content: MessageBody,
 }
 
+// Message content in the OpenAI format: a plain string for text-only messages,
+// or an array of typed parts when images are attached
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(untagged)]
+pub enum MessageBody {
+    Text(String),
+    Parts(Vec<MessageContent>),
+}
+
+// A single content part, serialized as {"type": "text", ...} or {"type": "image_url", ...}
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum MessageContent {
-    Text { 
-        #[serde(rename = "type")] 
-        content_type: String, 
-        text: String 
-    },
-    Image { 
-        #[serde(rename = "type")] 
-        content_type: String, 
-        image_url: ImageUrl 
-    },
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+impl MultimodalChatMessage {
+    /// Text-only message, serialized with a plain string content
+    pub fn text(role: &str, text: &str) -> Self {
+        Self {
+            role: role.to_string(),
+            content: MessageBody::Text(text.to_string()),
+        }
+    }
+
+    /// Message with a text prompt followed by images given as (base64_image, mime_type) pairs
+    pub fn with_images(role: &str, text: &str, images: &[(String, String)]) -> Self {
+        if images.is_empty() {
+            return Self::text(role, text);
+        }
+        let mut parts = vec![MessageContent::Text { text: text.to_string() }];
+        for (base64_image, mime_type) in images {
+            parts.push(MessageContent::ImageUrl {
+                image_url: ImageUrl {
+                    url: format!("data:{};base64,{}", mime_type, base64_image),
+                },
+            });
+        }
+        Self {
+            role: role.to_string(),
+            content: MessageBody::Parts(parts),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -111,6 +151,11 @@ pub struct StreamingStats {
 /// Main ^lm command handler
 /// Handles user prompts for AI chat
 pub async fn lm(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    // Everything logged for this invocation carries the same correlation ID
+    with_request_id(invocation_request_id(msg.id.0), lm_command(ctx, msg, args)).await
+}
+
+async fn lm_command(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     let input = args.message().trim();
     
     if input.is_empty() {
@@ -118,6 +163,10 @@ pub async fn lm(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
         return Ok(());
     }
 
+    if crate::commands::util::reply_if_prompt_too_long(ctx, msg, input, "lm").await? {
+        return Ok(());
+    }
+
     // Handle special flags
     if input == "--test" || input == "-t" {
         return test_connectivity(ctx, msg).await;
@@ -131,13 +180,47 @@ pub async fn lm(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
         return list_models(ctx, msg).await;
     }
 
+    // Parse leading flags (quote-aware, so `--seed "42"` or quoted prompts work)
+    let (flags, prompt) = parse_leading_flags(
+        input,
+        &["-s", "--search", "-v", "--vision", "--compare", "--continue", "--debug"],
+        &["--seed", "--tone", "--stop"],
+    );
+    let has_flag = |names: &[&str]| flags.iter().any(|(flag, _)| names.contains(&flag.as_str()));
+    // --debug attaches this request's key log lines as debug.txt
+    let mut debug = DebugLog::new("lm", has_flag(&["--debug"]));
+    let seed_override = match flags.iter().find(|(flag, _)| flag == "--seed") {
+        Some((_, Some(value))) => match value.parse::<i64>() {
+            Ok(seed) => Some(seed),
+            Err(_) => {
+                msg.reply(ctx, "❌ `--seed` must be a whole number. Usage: `^lm --seed 42 <prompt>`").await?;
+                return Ok(());
+            }
+        },
+        Some((_, None)) => {
+            msg.reply(ctx, "❌ `--seed` needs a value. Usage: `^lm --seed 42 <prompt>`").await?;
+            return Ok(());
+        }
+        None => None,
+    };
+    let stop = match crate::commands::util::collect_stop_sequences(&flags) {
+        Ok(stop) => stop,
+        Err(e) => {
+            msg.reply(ctx, format!("❌ {}", e)).await?;
+            return Ok(());
+        }
+    };
+
+    // Global cap on simultaneous backend requests, taken just before the first one (--test, --clear and
+    // --models above stay available when the bot is busy); held until this command returns
+    let _backend_permit = match crate::commands::util::acquire_backend_permit_or_reply(ctx, msg, "lm").await? {
+        Some(permit) => permit,
+        None => return Ok(()),
+    };
+
     // Handle search flag
-    if input.starts_with("-s ") || input.starts_with("--search ") {
-        let query = if input.starts_with("-s ") {
-            &input[3..]
-        } else {
-            &input[9..]
-        };
+    if has_flag(&["-s", "--search"]) {
+        let query = prompt;
         
         if query.trim().is_empty() {
             msg.reply(ctx, "Please provide a search query! Usage: `^lm -s <query>`").await?;
@@ -148,14 +231,13 @@ pub async fn lm(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
         return handle_search(ctx, msg, query).await;
     }
 
+    // --compare describes the differences between two attached images (implies vision)
+    if has_flag(&["--compare"]) {
+        return crate::commands::vis::handle_vision_compare(ctx, msg, prompt, &msg.attachments).await;
+    }
+
     // Handle vision flag
-    if input.starts_with("-v ") || input.starts_with("--vision ") {
-        let prompt = if input.starts_with("-v ") {
-            &input[3..]
-        } else {
-            &input[9..]
-        };
-        
+    if has_flag(&["-v", "--vision"]) {
         if prompt.trim().is_empty() {
             msg.reply(ctx, "Please provide a prompt for vision analysis! Usage: `^lm -v <prompt>` with image attached.").await?;
             return Ok(());
@@ -167,33 +249,85 @@ pub async fn lm(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
             return Ok(());
         }
 
-        let attachment = &msg.attachments[0];
-        if !attachment.content_type.as_deref().unwrap_or("").starts_with("image/") {
+        if !msg.attachments.iter().any(|a| a.content_type.as_deref().unwrap_or("").starts_with("image/")) {
             msg.reply(ctx, "Please attach a valid image file!").await?;
             return Ok(());
         }
 
-        // Delegate to vision functionality
-        return crate::commands::vis::handle_vision_request(ctx, msg, prompt, attachment).await;
+        // Delegate to vision functionality (attachment limits are enforced there)
+        return crate::commands::vis::handle_vision_request(ctx, msg, prompt, &msg.attachments).await;
+    }
+
+    // --continue extends the previous reply (e.g. one cut off at max_tokens) instead of asking something new
+    let continue_previous = has_flag(&["--continue"]);
+    if continue_previous {
+        if !prompt.is_empty() {
+            msg.reply(ctx, "❌ `--continue` takes no prompt. Usage: `^lm --continue`").await?;
+            return Ok(());
+        }
+        let previous_finish = {
+            let data_map = ctx.data.read().await;
+            data_map.get::<LmContextMap>()
+                .and_then(|lm_map| lm_map.get(&msg.author.id))
+                .filter(|context| context.can_continue())
+                .map(|context| context.last_finish_reason.clone())
+        };
+        match previous_finish {
+            None => {
+                msg.reply(ctx, "ℹ️ There is no previous `^lm` response to continue.").await?;
+                return Ok(());
+            }
+            Some(reason) if reason.as_deref() != Some("length") => {
+                msg.reply(ctx, "ℹ️ Your previous response wasn't cut off at the token limit, continuing it anyway.").await?;
+            }
+            Some(_) => {}
+        }
+    } else if prompt.is_empty() {
+        msg.reply(ctx, "Please provide a prompt! Usage: `^lm <your prompt>`").await?;
+        return Ok(());
     }
 
     // Load configuration
-    let config = match load_lm_config().await {
+    let mut config = match load_lm_config().await {
         Ok(cfg) => cfg,
         Err(e) => {
-            msg.reply(ctx, &format!("❌ Configuration error: {}", e)).await?;
+            eprintln!("[LM][{}] Configuration error: {}", current_request_id(), e);
+            msg.reply(ctx, with_error_id(format!("❌ Configuration error: {}", e))).await?;
             return Ok(());
         }
     };
+    // An explicit --seed applies to every request this invocation makes, whatever the model's profile says
+    config.seed_override = seed_override;
+
+    // Resolve tone: --tone overrides the TONE config default
+    let tone = flags.iter()
+        .find(|(flag, _)| flag == "--tone")
+        .and_then(|(_, value)| value.clone())
+        .or_else(|| config.default_tone.clone());
+    let tone_prefix = match tone.as_deref() {
+        Some(tone) => match tone_directive(tone) {
+            Some(directive) => Some(directive),
+            None => {
+                msg.reply(ctx, &format!("❌ Unknown tone `{}`. Available tones: {}", tone, tone_preset_names())).await?;
+                return Ok(());
+            }
+        },
+        None => None,
+    };
 
     // Load system prompt
     let system_prompt = match load_system_prompt().await {
         Ok(prompt) => prompt,
         Err(e) => {
-            msg.reply(ctx, &format!("❌ Failed to load system prompt: {}", e)).await?;
+            eprintln!("[LM][{}] Failed to load system prompt: {}", current_request_id(), e);
+            msg.reply(ctx, with_error_id(format!("❌ Failed to load system prompt: {}", e))).await?;
             return Ok(());
         }
     };
+    let system_prompt = match tone_prefix {
+        Some(directive) => format!("{}\n\n{}", directive, system_prompt),
+        None => system_prompt,
+    };
 
     // Build messages with context
     let mut messages = vec![
@@ -215,14 +349,36 @@ pub async fn lm(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
         }
     }
 
-    // Add current user message
-    messages.push(ChatMessage {
-        role: "user".to_string(),
-        content: input.to_string(),
-    });
+    // "What did @user talk about" - ground the answer in that user's recently observed messages
+    if let (Some(guild_id), Some(subject)) = (msg.guild_id, msg.mentions.iter().find(|u| !u.bot && u.id != msg.author.id)) {
+        if is_user_history_query(prompt) {
+            if !crate::user_history_enabled() {
+                msg.reply(ctx, "ℹ️ Message history for other users is disabled on this bot.").await?;
+                return Ok(());
+            }
+            let history = crate::recent_user_history(subject.id, guild_id);
+            println!("[LM] History query about {} - {} recorded messages", subject.name, history.len());
+            messages.push(ChatMessage {
+                role: "system".to_string(),
+                content: format_user_history(&subject.name, &history),
+            });
+        }
+    }
 
-    // Record user message in context
-    {
+    if continue_previous {
+        // The previous reply is already the last context message; the instruction itself isn't stored
+        messages.push(ChatMessage {
+            role: "user".to_string(),
+            content: STREAM_CONTINUE_PROMPT.to_string(),
+        });
+    } else {
+        // Add current user message
+        messages.push(ChatMessage {
+            role: "user".to_string(),
+            content: prompt.to_string(),
+        });
+
+        // Record user message in context
         let mut data_map = ctx.data.write().await;
         let lm_map = data_map.get_mut::<LmContextMap>()
             .expect("LM context map not initialized");
@@ -230,35 +386,78 @@ pub async fn lm(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
             .or_insert_with(crate::UserContext::new);
         context.add_user_message(ChatMessage {
             role: "user".to_string(),
-            content: input.to_string(),
+            content: prompt.to_string(),
         });
     }
 
-    // Send initial message
-    let mut response_msg = msg.channel_id.send_message(&ctx.http, |m| {
-        m.content("🤔 **AI is thinking...**")
-    }).await?;
+    // Send initial message - an edited mention regenerates into the bot's earlier reply instead
+    let previous_reply = match crate::tracked_reply_id(msg.id.0) {
+        Some(reply_id) => msg.channel_id.message(&ctx.http, reply_id).await.ok(),
+        None => None,
+    };
+    let mut response_msg = match previous_reply {
+        Some(mut reply) => {
+            reply.edit(&ctx.http, |m| m.content("🤔 **AI is thinking...**")).await?;
+            reply
+        }
+        None => msg.channel_id.send_message(&ctx.http, |m| {
+            m.content("🤔 **AI is thinking...**")
+        }).await?,
+    };
+    crate::record_message_reply(msg.id.0, response_msg.id.0);
+
+    if debug.is_enabled() {
+        let sampling = config.sampling_for(&config.default_model);
+        debug.log(format!("model: {} at {}", config.default_model, config.base_url));
+        debug.log(format!(
+            "sampling: temperature={} max_tokens={} seed={:?} stop={:?} tone={:?}",
+            sampling.temperature, sampling.max_tokens, sampling.seed, stop, tone
+        ));
+        debug.log(format!(
+            "payload: {} messages, {} chars{}",
+            messages.len(),
+            messages.iter().map(|m| m.content.chars().count()).sum::<usize>(),
+            if continue_previous { " (--continue)" } else { "" }
+        ));
+    }
 
     // Stream the response
-    match stream_chat_response(messages, &config, ctx, &mut response_msg).await {
-        Ok(full_response_content) => {
-            // Record assistant response in context with the full content
+    let result = stream_chat_response(messages, &config, stop, ctx, &mut response_msg, &mut debug).await;
+    match result {
+        // Nothing visible came back even after the retry; the placeholder already explains why
+        Ok((full_response_content, _)) if full_response_content.is_empty() => {}
+        Ok((full_response_content, finish_reason)) => {
+            // Record assistant response in context with the full content; a continuation joins the reply it extends
             let mut data_map = ctx.data.write().await;
             let lm_map = data_map.get_mut::<LmContextMap>()
                 .expect("LM context map not initialized");
             if let Some(context) = lm_map.get_mut(&msg.author.id) {
-                context.add_assistant_message(ChatMessage {
-                    role: "assistant".to_string(),
-                    content: full_response_content,
-                });
+                if continue_previous {
+                    context.extend_last_assistant_message(&full_response_content);
+                } else {
+                    context.add_assistant_message(ChatMessage {
+                        role: "assistant".to_string(),
+                        content: full_response_content,
+                    });
+                }
+                context.last_finish_reason = finish_reason.clone();
+            }
+            drop(data_map);
+            crate::compact_context_if_needed::<LmContextMap>(ctx, msg.author.id, &config).await;
+
+            if let Some(note) = finish_reason_note(finish_reason.as_deref(), Some("^lm")) {
+                let _ = msg.channel_id.say(&ctx.http, note).await;
             }
         }
         Err(e) => {
+            debug.log(format!("error: {}", e));
+            eprintln!("[LM][{}] Request failed: {}", current_request_id(), e);
             let _ = response_msg.edit(&ctx.http, |m| {
-                m.content(&format!("❌ Error: {}", e))
+                m.content(with_error_id(format!("❌ Error: {}", e)))
             }).await;
         }
     }
+    debug.attach(ctx, msg.channel_id).await;
 
     Ok(())
 }
@@ -288,55 +487,156 @@ async fn load_system_prompt() -> Result<String, Box<dyn std::error::Error + Send
 }
 
 // Stream chat response
+// Returns the filtered response and the backend's finish_reason, when it reported one
+// The response is empty (and the placeholder already says so) when the model produced no visible text, even after a retry
 async fn stream_chat_response(
     messages: Vec<ChatMessage>,
     config: &LMConfig,
+    stop: Vec<String>,
+    ctx: &Context,
+    initial_msg: &mut Message,
+    debug: &mut DebugLog,
+) -> Result<(String, Option<String>), Box<dyn std::error::Error + Send + Sync>> {
+    let attempt_start = std::time::Instant::now();
+    let (raw_content, mut finish_reason) = stream_completion(messages.clone(), config, stop.clone(), ctx, initial_msg).await?;
+
+    // Strip thinking blocks from the final response before display and context storage
+    let mut accumulated_content = strip_think_blocks(&raw_content).trim().to_string();
+    debug.log(format!(
+        "attempt 1: {} ms, {} raw chars, {} visible chars, finish_reason={:?}",
+        attempt_start.elapsed().as_millis(), raw_content.chars().count(), accumulated_content.chars().count(), finish_reason
+    ));
+
+    // A reply that was nothing but a thinking block gets one retry with a nudge
+    if let Some(retry_messages) = empty_response_retry(&messages, &accumulated_content, |content| ChatMessage { role: "user".to_string(), content }) {
+        println!("[LM] Model returned no visible content (finish_reason: {:?}), retrying once", finish_reason);
+        let attempt_start = std::time::Instant::now();
+        let (retry_content, retry_finish_reason) = stream_completion(retry_messages, config, stop, ctx, initial_msg).await?;
+        accumulated_content = strip_think_blocks(&retry_content).trim().to_string();
+        finish_reason = retry_finish_reason;
+        debug.log(format!(
+            "attempt 2 (empty-reply retry): {} ms, {} raw chars, {} visible chars, finish_reason={:?}",
+            attempt_start.elapsed().as_millis(), retry_content.chars().count(), accumulated_content.chars().count(), finish_reason
+        ));
+    }
+    if is_empty_response(&accumulated_content) {
+        eprintln!("[LM] Model returned no visible content after retry (finish_reason: {:?})", finish_reason);
+        initial_msg.edit(&ctx.http, |m| m.content(empty_response_message(finish_reason.as_deref()))).await?;
+        return Ok((String::new(), finish_reason));
+    }
+
+    let char_limit = config.max_discord_message_length - config.response_format_padding;
+
+    // Split content into Discord-friendly chunks, leaving room for RESPONSE_FOOTER on the last one
+    let chunks = split_message(&accumulated_content, char_limit.saturating_sub(crate::commands::util::response_footer_len()));
+    
+    // Handle multiple messages if content is too long
+    if chunks.len() == 1 {
+        // Single message - update the initial message
+        let formatted_content = crate::commands::util::with_response_footer(&format!(
+            "**AI Response:**\n```\n{}\n```",
+            chunks[0]
+        ));
+        
+        initial_msg.edit(&ctx.http, |m| {
+            m.content(&formatted_content)
+        }).await?;
+    } else {
+        // Multiple messages - update first message and send additional ones
+        for (i, chunk) in chunks.iter().enumerate() {
+            let formatted_content = if chunks.len() == 1 {
+                format!("**AI Response:**\n```\n{}\n```", chunk)
+            } else {
+                format!("**AI Response (Part {}/{})**\n```\n{}\n```", i + 1, chunks.len(), chunk)
+            };
+            let formatted_content = if i + 1 == chunks.len() {
+                crate::commands::util::with_response_footer(&formatted_content)
+            } else {
+                formatted_content
+            };
+            
+            if i == 0 {
+                // Update the first message
+                initial_msg.edit(&ctx.http, |m| {
+                    m.content(&formatted_content)
+                }).await?;
+            } else {
+                // Send additional messages for remaining chunks
+                initial_msg.channel_id.send_message(&ctx.http, |m| {
+                    m.content(&formatted_content)
+                }).await?;
+            }
+        }
+    }
+
+    // Return the filtered content for context storage
+    Ok((accumulated_content, finish_reason))
+}
+
+// Stream one completion into the placeholder message
+// Returns the raw response (thinking blocks included) and the backend's finish_reason
+async fn stream_completion(
+    messages: Vec<ChatMessage>,
+    config: &LMConfig,
+    stop: Vec<String>,
     ctx: &Context,
     initial_msg: &mut Message,
-) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+) -> Result<(String, Option<String>), Box<dyn std::error::Error + Send + Sync>> {
     let client = reqwest::Client::builder()
         .timeout(std::time::Duration::from_secs(config.timeout))
         .build()?;
 
+    let sampling = config.sampling_for(&config.default_model);
     let chat_request = ChatRequest {
         model: config.default_model.clone(),
         messages,
-        temperature: config.default_temperature,
-        max_tokens: config.default_max_tokens,
+        temperature: sampling.temperature,
+        max_tokens: sampling.max_tokens,
         stream: true,
-        seed: config.default_seed,
+        seed: sampling.seed,
+        stop,
     };
 
     let api_url = format!("{}/v1/chat/completions", config.base_url);
     
-    let response = client
-        .post(&api_url)
-        .json(&chat_request)
-        .send()
-        .await?;
-
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-        
-        // Check for specific errors
-        if error_text.contains("No models loaded") || error_text.contains("model_not_found") {
-            return Err(format!(
-                "Model '{}' is not loaded in LM Studio. Please load the model and try again.",
-                config.default_model
-            ).into());
-        }
-        
-        return Err(format!("API error: {} - {}", status, error_text).into());
-    }
-
-    // Stream the response
+    // Missing models, HTTP errors and exhausted retries come back as a typed BotError
+    let response = send_chat_request(
+        &client,
+        &api_url,
+        &chat_request,
+        &config.default_model,
+        config.timeout,
+        config.max_retries,
+    ).await?;
+
+    // Stream the response, editing the placeholder as content arrives
     let mut stream = response.bytes_stream();
     let mut accumulated_content = String::new();
+    let mut finish_reason = None;
     let mut line_buffer = String::new();
-
-    while let Some(chunk) = stream.next().await {
-        let bytes = chunk?;
+    let char_limit = config.max_discord_message_length - config.response_format_padding;
+    let mut live_throttle = EditThrottle::new(std::time::Duration::from_millis(LIVE_EDIT_INTERVAL_MS), "LM");
+    let mut last_live_len = 0;
+
+    'stream: while let Some(chunk) = stream.next().await {
+        let bytes = match chunk {
+            Ok(bytes) => bytes,
+            // A dropped connection after some output can be resumed instead of losing the partial response
+            Err(e) if config.stream_auto_continue && !accumulated_content.is_empty() => {
+                eprintln!("[LM] Stream interrupted after {} chars: {}", accumulated_content.len(), e);
+                let continuation = continue_interrupted_stream(
+                    config,
+                    &chat_request.model,
+                    &chat_request.messages,
+                    &accumulated_content,
+                ).await?;
+                accumulated_content.push_str(&continuation);
+                // The stitched continuation doesn't report how it ended
+                finish_reason = None;
+                break 'stream;
+            }
+            Err(e) => return Err(e.into()),
+        };
         line_buffer.push_str(&String::from_utf8_lossy(&bytes));
 
         while let Some(i) = line_buffer.find('\n') {
@@ -345,7 +645,7 @@ async fn stream_chat_response(
 
             if let Some(json_str) = line.strip_prefix("data: ") {
                 if json_str.trim() == "[DONE]" {
-                    break;
+                    break 'stream;
                 }
 
                 if let Ok(response) = serde_json::from_str::<ChatResponse>(json_str) {
@@ -355,87 +655,114 @@ async fn stream_chat_response(
                                 accumulated_content.push_str(&content);
                             }
                         }
+                        if choice.finish_reason.is_some() {
+                            finish_reason = choice.finish_reason;
+                        }
                     }
                 }
             }
         }
-    }
 
-    // Split content into Discord-friendly chunks
-    let chunks = split_message(&accumulated_content, config.max_discord_message_length - config.response_format_padding);
-    
-    // Handle multiple messages if content is too long
-    if chunks.len() == 1 {
-        // Single message - update the initial message
-        let formatted_content = format!(
-            "**AI Response:**\n```\n{}\n```",
-            chunks[0]
-        );
-        
-        initial_msg.edit(&ctx.http, |m| {
-            m.content(&formatted_content)
-        }).await?;
-    } else {
-        // Multiple messages - update first message and send additional ones
-        for (i, chunk) in chunks.iter().enumerate() {
-            let formatted_content = if chunks.len() == 1 {
-                format!("**AI Response:**\n```\n{}\n```", chunk)
-            } else {
-                format!("**AI Response (Part {}/{})**\n```\n{}\n```", i + 1, chunks.len(), chunk)
-            };
-            
-            if i == 0 {
-                // Update the first message
-                initial_msg.edit(&ctx.http, |m| {
-                    m.content(&formatted_content)
-                }).await?;
+        // Throttled live update - only show text outside thinking blocks
+        if live_throttle.ready() {
+            let live_content = strip_think_blocks_live(&accumulated_content);
+            // Hidden thinking can make the visible text shrink, so only edit when it has grown
+            if live_content.len() > last_live_len {
+                let live_display = format!(
+                    "**AI Response (streaming...):**\n```\n{}\n```",
+                    tail_chars(&live_content, char_limit.saturating_sub(40))
+                );
+                let result = initial_msg.edit(&ctx.http, |m| m.content(&live_display)).await;
+                live_throttle.record(&result);
+                last_live_len = live_content.len();
             } else {
-                // Send additional messages for remaining chunks
-                initial_msg.channel_id.send_message(&ctx.http, |m| {
-                    m.content(&formatted_content)
-                }).await?;
+                live_throttle.defer();
             }
         }
     }
 
-    // Return the full accumulated content for context storage
-    Ok(accumulated_content)
+    // Not every backend honors `stop` (continuations never send it), so enforce it here too
+    if let Some(truncated) = crate::commands::util::truncate_at_stop_sequence(&accumulated_content, &chat_request.stop) {
+        println!("[LM] Response ran past a stop sequence, truncating {} -> {} chars", accumulated_content.len(), truncated.len());
+        accumulated_content = truncated;
+        finish_reason = Some("stop".to_string());
+    }
+
+    Ok((accumulated_content, finish_reason))
+}
+
+/// Matches questions about what another user has been saying
+static USER_HISTORY_QUERY_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\b(what|anything|summari[sz]e)\b.*\b(talk(ed|ing)?|say|said|saying|discuss(ed|ing)?|mention(ed)?|chat(ted|ting)?|post(ed)?|been up to)\b").unwrap()
+});
+
+/// Whether a prompt asks what a mentioned user has been talking about
+fn is_user_history_query(prompt: &str) -> bool {
+    USER_HISTORY_QUERY_REGEX.is_match(prompt)
+}
+
+/// Build the system message that grounds a history query in the user's recent messages
+fn format_user_history(name: &str, history: &[String]) -> String {
+    if history.is_empty() {
+        return format!(
+            "The user is asking about {}, but no recent messages from {} have been observed in this server. Say so rather than guessing.",
+            name, name
+        );
+    }
+    let lines: Vec<String> = history.iter().map(|line| format!("- {}", line)).collect();
+    format!(
+        "Recent messages from {} observed in this server (oldest first). Answer using only these:\n{}",
+        name,
+        lines.join("\n")
+    )
 }
 
 /// Split message content into Discord-friendly chunks
+/// Delegates to the shared splitter so an over-long line can never produce an over-limit message
 fn split_message(content: &str, max_len: usize) -> Vec<String> {
-    let lines: Vec<&str> = content.lines().collect();
-    let mut chunks = Vec::new();
-    let mut current_chunk = String::new();
-    
-    for line in lines {
-        let potential_chunk = if current_chunk.is_empty() {
-            line.to_string()
-        } else {
-            format!("{}\n{}", current_chunk, line)
-        };
-        
-        if potential_chunk.len() <= max_len {
-            current_chunk = potential_chunk;
-        } else {
-            if !current_chunk.is_empty() {
-                chunks.push(current_chunk.clone());
-            }
-            current_chunk = line.to_string();
-        }
-    }
-    
-    if !current_chunk.is_empty() {
-        chunks.push(current_chunk);
-    }
-    
-    chunks
+    crate::commands::util::split_for_discord(content, max_len)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_user_history_query_detection() {
+        assert!(is_user_history_query("what did <@123> talk about today?"));
+        assert!(is_user_history_query("Summarize what <@123> said"));
+        assert!(is_user_history_query("Anything <@123> mentioned about the release?"));
+        assert!(!is_user_history_query("write a poem for <@123>"));
+        assert!(format_user_history("alice", &[]).contains("no recent messages"));
+        assert!(format_user_history("alice", &["hi".to_string()]).contains("- hi"));
+    }
+
+    #[test]
+    fn test_extract_bot_answer() {
+        assert_eq!(extract_bot_answer("**AI Response:**\n```\nShort answer.\n```"), "Short answer.");
+        assert_eq!(extract_bot_answer("**AI Response (Part 2/3)**\n```\nline one\nline two\n```\n-# via Meri"), "line one\nline two");
+        assert_eq!(extract_bot_answer("Plain reply without formatting"), "Plain reply without formatting");
+    }
+
+    #[test]
+    fn test_multimodal_text_only_serializes_as_string() {
+        let message = MultimodalChatMessage::text("system", "You are helpful.");
+        let json = serde_json::to_value(&message).unwrap();
+        assert_eq!(json["content"], serde_json::json!("You are helpful."));
+    }
+
+    #[test]
+    fn test_multimodal_image_parts_use_openai_format() {
+        let images = vec![("aGVsbG8=".to_string(), "image/png".to_string())];
+        let message = MultimodalChatMessage::with_images("user", "Describe this", &images);
+        let json = serde_json::to_value(&message).unwrap();
+        assert_eq!(json["content"][0], serde_json::json!({ "type": "text", "text": "Describe this" }));
+        assert_eq!(
+            json["content"][1],
+            serde_json::json!({ "type": "image_url", "image_url": { "url": "data:image/png;base64,aGVsbG8=" } })
+        );
+    }
+
     #[test]
     fn test_split_message_short_content() {
         let short_content = "This is a short message that should fit in one chunk.";
@@ -458,9 +785,10 @@ mod tests {
 
     #[test]
     fn test_split_message_single_long_line() {
-        let single_long_line = "This is a very long line that should not be split because it exceeds the maximum length but we want to keep it as one chunk for testing purposes.";
+        let single_long_line = "This is a very long line that exceeds the maximum length, so it must be hard-split to stay within the Discord limit.";
         let chunks = split_message(single_long_line, 50);
-        assert_eq!(chunks.len(), 1, "Single long line should remain as one chunk");
+        assert!(chunks.len() > 1, "Single long line should be split to fit the limit");
+        assert!(chunks.iter().all(|chunk| chunk.len() <= 50));
     }
 
     #[test]
@@ -471,7 +799,7 @@ mod tests {
 
     #[test]
     fn test_split_message_exact_limit() {
-        let content = "This is exactly 25 characters";
+        let content = "This is exactly 25 chars!";
         let chunks = split_message(content, 25);
         assert_eq!(chunks.len(), 1);
         assert_eq!(chunks[0], content);
@@ -525,7 +853,8 @@ async fn test_connectivity(ctx: &Context, msg: &Message) -> CommandResult {
     let config = match load_lm_config().await {
         Ok(cfg) => cfg,
         Err(e) => {
-            msg.reply(ctx, &format!("❌ Configuration error: {}", e)).await?;
+            eprintln!("[LM][{}] Configuration error: {}", current_request_id(), e);
+            msg.reply(ctx, with_error_id(format!("❌ Configuration error: {}", e))).await?;
             return Ok(());
         }
     };
@@ -549,14 +878,14 @@ async fn test_connectivity(ctx: &Context, msg: &Message) -> CommandResult {
                     **Max Tokens:** {}",
                     config.base_url,
                     config.default_model,
-                    config.default_temperature,
-                    config.default_max_tokens
+                    config.sampling_for(&config.default_model).temperature,
+                    config.sampling_for(&config.default_model).max_tokens
                 ))
             }).await;
         }
         Err(e) => {
             let _ = test_msg.edit(&ctx.http, |m| {
-                m.content(&format!("❌ **Connection Failed**\n\nError: {}", e))
+                m.content(with_error_id(format!("❌ **Connection Failed**\n\nError: {}", e)))
             }).await;
         }
     }
@@ -585,7 +914,8 @@ async fn list_models(ctx: &Context, msg: &Message) -> CommandResult {
     let config = match load_lm_config().await {
         Ok(cfg) => cfg,
         Err(e) => {
-            msg.reply(ctx, &format!("❌ Configuration error: {}", e)).await?;
+            eprintln!("[LM][{}] Configuration error: {}", current_request_id(), e);
+            msg.reply(ctx, with_error_id(format!("❌ Configuration error: {}", e))).await?;
             return Ok(());
         }
     };
@@ -628,18 +958,18 @@ async fn list_models(ctx: &Context, msg: &Message) -> CommandResult {
                     }).await;
                 } else {
                     let _ = models_msg.edit(&ctx.http, |m| {
-                        m.content("❌ Invalid response format from server")
+                        m.content(with_error_id("❌ Invalid response format from server"))
                     }).await;
                 }
             } else {
                 let _ = models_msg.edit(&ctx.http, |m| {
-                    m.content(&format!("❌ Failed to get models: HTTP {}", response.status()))
+                    m.content(with_error_id(format!("❌ Failed to get models: HTTP {}", response.status())))
                 }).await;
             }
         }
         Err(e) => {
             let _ = models_msg.edit(&ctx.http, |m| {
-                m.content(&format!("❌ Connection failed: {}", e))
+                m.content(with_error_id(format!("❌ Connection failed: {}", e)))
             }).await;
         }
     }
@@ -652,7 +982,8 @@ async fn handle_search(ctx: &Context, msg: &Message, query: &str) -> CommandResu
     let config = match load_lm_config().await {
         Ok(cfg) => cfg,
         Err(e) => {
-            msg.reply(ctx, &format!("❌ Configuration error: {}", e)).await?;
+            eprintln!("[LM][{}] Configuration error: {}", current_request_id(), e);
+            msg.reply(ctx, with_error_id(format!("❌ Configuration error: {}", e))).await?;
             return Ok(());
         }
     };
@@ -665,8 +996,9 @@ async fn handle_search(ctx: &Context, msg: &Message, query: &str) -> CommandResu
     match crate::commands::search::perform_ai_enhanced_search(query, &config, &mut search_msg, ctx).await {
         Ok(()) => {},
         Err(e) => {
+            eprintln!("[LM][{}] Search failed: {}", current_request_id(), e);
             let _ = search_msg.edit(&ctx.http, |m| {
-                m.content(&format!("❌ Search failed: {}", e))
+                m.content(with_error_id(format!("❌ Search failed: {}", e)))
             }).await;
         }
     }
@@ -688,6 +1020,135 @@ pub async fn handle_lm_request_global(
     Ok(())
 }
 
+/// Refine one of the bot's earlier answers: the reply is sent with that answer as the assistant turn
+/// and the user's follow-up ("make it shorter") as the next user turn, on top of their ^lm context
+pub async fn handle_refinement_request(
+    ctx: &Context,
+    msg: &Message,
+    previous_answer: &str,
+    refinement: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Same MAX_PROMPT_CHARS limit as ^lm; the previous answer is the bot's own text and isn't counted
+    if crate::commands::util::reply_if_prompt_too_long(ctx, msg, refinement, "lm").await? {
+        return Ok(());
+    }
+
+    // Global cap on simultaneous backend requests; held until this request returns
+    let _backend_permit = match crate::commands::util::acquire_backend_permit_or_reply(ctx, msg, "lm").await? {
+        Some(permit) => permit,
+        None => return Ok(()),
+    };
+
+    let config = load_lm_config().await?;
+    let system_prompt = load_system_prompt().await?;
+    let system_prompt = match config.default_tone.as_deref().and_then(tone_directive) {
+        Some(directive) => format!("{}\n\n{}", directive, system_prompt),
+        None => system_prompt,
+    };
+
+    let mut messages = vec![ChatMessage {
+        role: "system".to_string(),
+        content: system_prompt,
+    }];
+    let history = {
+        let data_map = ctx.data.read().await;
+        data_map.get::<LmContextMap>()
+            .and_then(|lm_map| lm_map.get(&msg.author.id))
+            .map(|context| context.get_conversation_messages())
+            .unwrap_or_default()
+    };
+    // The answer is usually the last stored reply already; one from before a restart or --clear isn't
+    let answer_in_context = history.last()
+        .map_or(false, |last| last.role == "assistant" && last.content.contains(previous_answer));
+    messages.extend(history);
+    let previous_turn = ChatMessage {
+        role: "assistant".to_string(),
+        content: previous_answer.to_string(),
+    };
+    if !answer_in_context {
+        messages.push(previous_turn.clone());
+    }
+    messages.push(ChatMessage {
+        role: "user".to_string(),
+        content: refinement.to_string(),
+    });
+    println!("[LM] Refining an earlier answer ({} chars) for {}: {} messages", previous_answer.chars().count(), msg.author.name, messages.len());
+
+    {
+        let mut data_map = ctx.data.write().await;
+        let lm_map = data_map.get_mut::<LmContextMap>()
+            .expect("LM context map not initialized");
+        let context = lm_map.entry(msg.author.id)
+            .or_insert_with(crate::UserContext::new);
+        if !answer_in_context {
+            context.add_assistant_message(previous_turn);
+        }
+        context.add_user_message(ChatMessage {
+            role: "user".to_string(),
+            content: refinement.to_string(),
+        });
+    }
+
+    // An edited refinement regenerates into the bot's earlier reply, like an edited mention
+    let previous_reply = match crate::tracked_reply_id(msg.id.0) {
+        Some(reply_id) => msg.channel_id.message(&ctx.http, reply_id).await.ok(),
+        None => None,
+    };
+    let mut response_msg = match previous_reply {
+        Some(mut reply) => {
+            reply.edit(&ctx.http, |m| m.content("🤔 **AI is refining...**")).await?;
+            reply
+        }
+        None => msg.channel_id.send_message(&ctx.http, |m| {
+            m.content("🤔 **AI is refining...**")
+        }).await?,
+    };
+    crate::record_message_reply(msg.id.0, response_msg.id.0);
+
+    let mut debug = DebugLog::new("lm", false);
+    let (full_response_content, finish_reason) = stream_chat_response(messages, &config, Vec::new(), ctx, &mut response_msg, &mut debug).await?;
+    if full_response_content.is_empty() {
+        return Ok(());
+    }
+    {
+        let mut data_map = ctx.data.write().await;
+        let lm_map = data_map.get_mut::<LmContextMap>()
+            .expect("LM context map not initialized");
+        if let Some(context) = lm_map.get_mut(&msg.author.id) {
+            context.add_assistant_message(ChatMessage {
+                role: "assistant".to_string(),
+                content: full_response_content,
+            });
+            context.last_finish_reason = finish_reason.clone();
+        }
+    }
+    crate::compact_context_if_needed::<LmContextMap>(ctx, msg.author.id, &config).await;
+
+    if let Some(note) = finish_reason_note(finish_reason.as_deref(), Some("^lm")) {
+        let _ = msg.channel_id.say(&ctx.http, note).await;
+    }
+    Ok(())
+}
+
+/// Recover the answer text from one of the bot's own messages for a refinement prompt
+/// Drops the "**AI Response**" header, the surrounding code fence and the RESPONSE_FOOTER line
+pub fn extract_bot_answer(content: &str) -> String {
+    let mut text = content.trim();
+    if let Some(footer_start) = text.rfind("\n-# ") {
+        if !text[footer_start + 1..].contains('\n') {
+            text = text[..footer_start].trim_end();
+        }
+    }
+    if text.starts_with("**AI Response") {
+        text = text.split_once('\n').map_or("", |(_, rest)| rest).trim();
+    }
+    if let Some(fenced) = text.strip_prefix("```").and_then(|rest| rest.strip_suffix("```")) {
+        // Skip the optional language tag on the opening fence line
+        text = fenced.split_once('\n').map_or(fenced, |(_, body)| body);
+    }
+    text.trim().to_string()
+}
+
 // Logging initialization (stub for compatibility)
 pub fn init_logging() -> Result<(), std::io::Error> {
     // Logging is handled by the main module
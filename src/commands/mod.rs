@@ -4,6 +4,7 @@
 
 pub mod admin;          // Administrative commands (owner only)
 pub mod echo;           // Echo command for testing
+pub mod count;          // Word/character/token counter for text, attachments and replies
 pub mod help;           // Help system and command documentation
 pub mod ping;           // Basic ping/pong functionality
 pub mod lm;             // Language model integration (AI chat)
@@ -11,6 +12,11 @@ pub mod reason;         // Reasoning and analysis capabilities
 pub mod agent;          // LLM Agent with function calling using js-code-sandbox
 pub mod search;         // Web search and RAG (Retrieval-Augmented Generation) - Minimal placeholder
 pub mod sum;            // Text summarization capabilities
+pub mod translate;      // Translation via the LM chat completion
+pub mod quote;          // Blockquote a replied-to message with attribution
 pub mod rank;           // Content ranking and analysis capabilities
+pub mod context;        // Context inspection and export for stored conversations
 pub mod vis;            // Vision/visual analysis capabilities 
-pub mod slash;          // Slash commands for Discord application commands 
\ No newline at end of file
+pub mod slash;          // Slash commands for Discord application commands
+pub mod error;          // Structured error types for LM requests (BotError)
+pub mod util;           // Shared helpers (Discord-safe message splitting and delivery) 
\ No newline at end of file
@@ -4,6 +4,7 @@
 // Key Features:
 // - Measures round-trip latency for Discord message handling
 // - Provides immediate feedback to users
+// - --trace appends a runtime snapshot: shard id/latency, cache counts and process memory
 //
 // Used by: main.rs (command registration)
 
@@ -12,10 +13,11 @@
 // ============================================================================
 
 use serenity::{
-    client::Context,
+    client::{bridge::gateway::ShardId, Context},
     framework::standard::{macros::command, macros::group, Args, CommandResult},
     model::channel::Message,
 };
+use crate::ShardManagerContainer;
 
 // ============================================================================
 // COMMAND IMPLEMENTATION
@@ -26,27 +28,91 @@ use serenity::{
 /// Measures and displays the bot's response time in milliseconds
 /// Supports:
 ///   - ^ping
-pub async fn ping(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+///   - ^ping --trace (adds shard, cache and memory stats)
+pub async fn ping(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let trace = args.message().split_whitespace().any(|arg| arg == "--trace" || arg == "-t");
     let start_time = std::time::Instant::now();
 
-    
+
     // Send the initial response and measure the time
     let response_result = msg.reply(ctx, "Pong! Calculating delay...").await;
     let elapsed = start_time.elapsed();
-    
+
     // Update the message with the actual ping time
     if let Ok(mut response_msg) = response_result {
         let ping_ms = elapsed.as_millis();
-        let updated_content = format!("Pong! Response time: {}ms", ping_ms);
-        
+        let mut updated_content = format!("Pong! Response time: {}ms", ping_ms);
+        if trace {
+            updated_content.push_str("\n\n");
+            updated_content.push_str(&runtime_trace(ctx).await);
+        }
+
         if let Err(e) = response_msg.edit(&ctx.http, |m| m.content(updated_content)).await {
             eprintln!("[PING] Failed to update ping message with delay: {}", e);
             // If edit fails, at least we sent the initial response
         }
     }
-    
+
     Ok(())
-} 
+}
+
+// ============================================================================
+// HELPER FUNCTIONS
+// ============================================================================
+
+/// Build the --trace snapshot from the shard manager and the serenity cache
+async fn runtime_trace(ctx: &Context) -> String {
+    let shard_manager = {
+        let data = ctx.data.read().await;
+        data.get::<ShardManagerContainer>().cloned()
+    };
+
+    let (shard_total, shard_latency) = match shard_manager {
+        Some(manager) => {
+            let manager = manager.lock().await;
+            let runners = manager.runners.lock().await;
+            let latency = runners.get(&ShardId(ctx.shard_id)).and_then(|runner| runner.latency);
+            (runners.len(), latency)
+        }
+        None => (0, None),
+    };
+    let latency_text = match shard_latency {
+        Some(latency) => format!("{}ms", latency.as_millis()),
+        // The first heartbeat ACK has not arrived yet
+        None => "n/a".to_string(),
+    };
+    let memory_text = match process_memory_kb() {
+        Some(kb) => format!("{:.1} MiB", kb as f64 / 1024.0),
+        None => "n/a".to_string(),
+    };
+
+    format!(
+        "**Runtime Trace**\n• Shard: {} of {} (gateway latency: {})\n• Cached guilds: {}\n• Cached channels: {}\n• Cached users: {}\n• Process memory (RSS): {}",
+        ctx.shard_id,
+        shard_total,
+        latency_text,
+        ctx.cache.guild_count(),
+        ctx.cache.guild_channel_count(),
+        ctx.cache.user_count(),
+        memory_text,
+    )
+}
+
+/// Resident set size of this process in KiB, read from /proc where available (Linux)
+fn process_memory_kb() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    parse_vm_rss_kb(&status)
+}
+
+/// Extract the VmRSS value from /proc/self/status content
+fn parse_vm_rss_kb(status: &str) -> Option<u64> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))
+        .and_then(|value| value.split_whitespace().next())
+        .and_then(|kb| kb.parse().ok())
+}
+
 // ============================================================================
 // COMMAND GROUP
 // ============================================================================
@@ -61,6 +127,14 @@ impl Ping {
     }
 }
 
-// ============================================================================
-// TODO: Add more diagnostics or latency breakdown in future versions
-// ============================================================================ 
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_vm_rss_kb() {
+        let status = "Name:\tmeri_bot\nVmPeak:\t  204800 kB\nVmRSS:\t   51234 kB\nThreads:\t12\n";
+        assert_eq!(parse_vm_rss_kb(status), Some(51234));
+        assert_eq!(parse_vm_rss_kb("Name:\tmeri_bot\n"), None);
+    }
+}
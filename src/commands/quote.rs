@@ -0,0 +1,203 @@
+// quote.rs - Quote Command Module
+// This module implements the ^quote command, which reposts a replied-to message as a Discord blockquote.
+//
+// Key Features:
+// - Blockquote of the referenced message with its author, timestamp and a jump link
+// - Attachment-only messages are quoted by filename
+// - Mentions inside the quote are shown but never ping anyone
+// - --explain asks the LM for a short commentary on the quoted message
+//
+// Used by: main.rs (command registration)
+
+// ============================================================================
+// IMPORTS
+// ============================================================================
+
+use serenity::{
+    client::Context,
+    framework::standard::{macros::command, macros::group, Args, CommandResult},
+    model::channel::Message,
+};
+use crate::commands::search::{chat_completion, load_lm_config, ChatMessage};
+use crate::commands::util::{parse_leading_flags, strip_think_blocks, style_output, truncate_chars, with_response_footer};
+
+// ============================================================================
+// CONSTANTS
+// ============================================================================
+
+/// Longest quoted text, leaving room in one Discord message for the attribution line
+const MAX_QUOTE_CHARS: usize = 1500;
+
+/// System prompt for ^quote --explain
+const EXPLAIN_QUOTE_PROMPT: &str = "You comment on a message quoted from a Discord conversation. \
+Briefly explain what it means, any context or terms a reader might miss, and anything notable about it. \
+Keep it to a short paragraph or a few bullet points, and do not repeat the quote.";
+
+// ============================================================================
+// COMMAND IMPLEMENTATION
+// ============================================================================
+
+#[command]
+#[aliases("q")]
+/// Main ^quote command handler
+/// Reposts the replied-to message as a blockquote with attribution
+/// Supports:
+///   - ^quote (as a reply to another message)
+///   - ^quote --explain (also ask the LM to comment on the quoted message)
+pub async fn quote(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (flags, _) = parse_leading_flags(args.message().trim(), &["--explain", "-e"], &[]);
+    let explain = !flags.is_empty();
+
+    let referenced = match msg.referenced_message.as_deref() {
+        Some(referenced) => referenced,
+        None => {
+            msg.reply(ctx, "Reply to a message with `^quote` to quote it (add `--explain` for a commentary).").await?;
+            return Ok(());
+        }
+    };
+
+    let link = jump_link(msg.guild_id.map(|id| id.0), referenced.channel_id.0, referenced.id.0);
+    let quoted = format_quote(
+        &quoted_text(referenced),
+        &referenced.author.name,
+        referenced.timestamp.unix_timestamp(),
+        &link,
+    );
+    // The quote keeps mentions readable, but reposting them must not ping anyone again
+    msg.channel_id.send_message(&ctx.http, |m| {
+        m.content(&quoted).allowed_mentions(|mentions| mentions.empty_parse())
+    }).await?;
+
+    if explain {
+        explain_quote(ctx, msg, referenced).await?;
+    }
+    Ok(())
+}
+
+// ============================================================================
+// HELPER FUNCTIONS
+// ============================================================================
+
+/// Ask the LM for a commentary on the quoted message and post it below the quote
+async fn explain_quote(ctx: &Context, msg: &Message, referenced: &Message) -> CommandResult {
+    // Respect the ALLOWED_CHANNELS / DENIED_CHANNELS restrictions for heavy commands
+    if crate::commands::util::reply_if_channel_blocked(ctx, msg, "quote").await? {
+        return Ok(());
+    }
+    // Counted as in flight so shutdown can wait for it to finish
+    let _heavy_guard = crate::start_heavy_command();
+    // Global cap on simultaneous backend requests; held until this command returns
+    let _backend_permit = match crate::commands::util::acquire_backend_permit_or_reply(ctx, msg, "quote").await? {
+        Some(permit) => permit,
+        None => return Ok(()),
+    };
+
+    let config = match load_lm_config().await {
+        Ok(config) => config,
+        Err(e) => {
+            msg.reply(ctx, style_output(format!("❌ **Configuration Error**\n\n{}", e))).await?;
+            return Ok(());
+        }
+    };
+
+    let mut status_msg = msg.channel_id.say(&ctx.http, style_output("🔄 Thinking about this quote...")).await?;
+    println!("[QUOTE] Explaining message {} from {} for {}", referenced.id, referenced.author.name, msg.author.name);
+    let messages = vec![
+        ChatMessage { role: "system".to_string(), content: EXPLAIN_QUOTE_PROMPT.to_string() },
+        ChatMessage {
+            role: "user".to_string(),
+            content: format!("Message from {}:\n{}", referenced.author.name, quoted_text(referenced)),
+        },
+    ];
+    let commentary = match chat_completion(messages, &config.default_model, &config, None).await {
+        Ok(response) => strip_think_blocks(&response).trim().to_string(),
+        Err(e) => {
+            eprintln!("[QUOTE] Commentary request failed: {}", e);
+            status_msg.edit(&ctx.http, |m| m.content(style_output(format!("❌ Commentary failed: {}", e)))).await?;
+            return Ok(());
+        }
+    };
+    if commentary.is_empty() {
+        status_msg.edit(&ctx.http, |m| m.content(style_output("⚠️ The model returned no commentary for this quote."))).await?;
+        return Ok(());
+    }
+
+    let response = with_response_footer(&format!("💬 **Commentary**\n{}", commentary));
+    let max_len = config.max_discord_message_length - config.response_format_padding;
+    if response.chars().count() <= max_len {
+        status_msg.edit(&ctx.http, |m| m.content(&response)).await?;
+    } else {
+        let _ = status_msg.delete(&ctx.http).await;
+        crate::commands::util::send_long_message(ctx, msg.channel_id, &response, max_len, "commentary.txt").await?;
+    }
+    Ok(())
+}
+
+/// The text to quote: the message content, or its attachments when it has none
+fn quoted_text(message: &Message) -> String {
+    let content = message.content.trim();
+    if !content.is_empty() {
+        return content.to_string();
+    }
+    let files: Vec<&str> = message.attachments.iter().map(|a| a.filename.as_str()).collect();
+    if files.is_empty() {
+        "*(no text)*".to_string()
+    } else {
+        format!("📎 {}", files.join(", "))
+    }
+}
+
+/// Discord jump link to a message; DMs use `@me` in place of a guild ID
+fn jump_link(guild_id: Option<u64>, channel_id: u64, message_id: u64) -> String {
+    let guild = guild_id.map_or_else(|| "@me".to_string(), |id| id.to_string());
+    format!("https://discord.com/channels/{}/{}/{}", guild, channel_id, message_id)
+}
+
+/// Blockquote every line of `text` (capped at MAX_QUOTE_CHARS) and add the attribution line
+/// The timestamp uses Discord's `<t:...:f>` markup so each reader sees it in their own time zone
+fn format_quote(text: &str, author: &str, unix_timestamp: i64, link: &str) -> String {
+    let shown = truncate_chars(text, MAX_QUOTE_CHARS);
+    let ellipsis = if shown.len() < text.len() { "…" } else { "" };
+    let quoted = format!("{}{}", shown, ellipsis)
+        .lines()
+        .map(|line| format!("> {}", line))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!("{}\n— **{}** · <t:{}:f> · [Jump to message]({})", quoted, author, unix_timestamp, link)
+}
+
+// ============================================================================
+// COMMAND GROUP
+// ============================================================================
+
+#[group]
+#[commands(quote)]
+pub struct Quote;
+
+impl Quote {
+    pub const fn new() -> Self {
+        Quote
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_quote() {
+        let link = jump_link(Some(1), 2, 3);
+        assert_eq!(link, "https://discord.com/channels/1/2/3");
+        assert_eq!(jump_link(None, 2, 3), "https://discord.com/channels/@me/2/3");
+
+        let quoted = format_quote("first line\nsecond line", "alice", 1700000000, &link);
+        assert_eq!(
+            quoted,
+            "> first line\n> second line\n— **alice** · <t:1700000000:f> · [Jump to message](https://discord.com/channels/1/2/3)"
+        );
+
+        let long = "x".repeat(MAX_QUOTE_CHARS + 10);
+        let quoted = format_quote(&long, "bob", 0, &link);
+        assert!(quoted.starts_with(&format!("> {}…\n", "x".repeat(MAX_QUOTE_CHARS))));
+    }
+}
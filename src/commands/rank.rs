@@ -8,6 +8,7 @@
 // - Cleans and processes VTT/HTML content
 // - RAG (map-reduce) chunking for long content
 // - Real-time streaming of ranking analysis to Discord
+// - Score summary table comparing the per-category scores
 // - Multi-path config and prompt loading
 // - Robust error handling and logging
 // - Self-contained with no external module dependencies
@@ -25,11 +26,12 @@ use serenity::{
 };
 use std::time::Duration;
 use std::fs;
-use std::process::Command;
 use uuid::Uuid;
 use log::{info, warn, error, debug, trace};
+use crate::commands::search::SamplingConfig;
 use serde::{Deserialize, Serialize};
 use regex::Regex;
+use once_cell::sync::Lazy;
 use std::time::Instant;
 use sha2::{Sha256, Digest};
 use std::collections::HashMap;
@@ -83,165 +85,30 @@ pub struct LMConfig {
     pub response_format_padding: usize,
     pub default_vision_model: String,
     pub default_seed: Option<i64>, // Optional seed for reproducible responses
-} 
+    pub model_profiles: HashMap<String, crate::commands::search::ModelProfile>, // Per-model overrides (MODEL_PROFILES)
+}
 
-/// Enhanced connectivity test function
-pub async fn test_api_connectivity(config: &LMConfig) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let client = get_http_client().await;
-    
-    println!("[DEBUG][CONNECTIVITY] Testing API connectivity to: {}", config.base_url);
-    
-    // Test 1: Basic server connectivity
-    let basic_response = client
-        .get(&config.base_url)
-        .timeout(Duration::from_secs(10))
-        .send()
-        .await;
-    
-    match basic_response {
-        Ok(response) => {
-            println!("[DEBUG][CONNECTIVITY] Basic connectivity OK - Status: {}", response.status());
-        }
-        Err(e) => {
-            let error_msg = format!("{}", e);
-            if error_msg.contains("os error 10013") || error_msg.contains("access permissions") {
-                return Err(format!(
-                    "🚫 **Windows Network Permission Error (10013)**\n\n\
-                    Cannot connect to LM Studio at `{}`\n\n\
-                    **Solutions:**\n\
-                    • **Add Firewall Exception**: Windows Defender Firewall → Allow an app → Add this program\n\
-                    • **Run as Administrator**: Try running the bot with administrator privileges\n\
-                    • **Check LM Studio**: Ensure LM Studio is running and accessible\n\
-                    • **Try localhost**: Use `http://127.0.0.1:1234` instead of `http://localhost:1234`\n\
-                    • **Check Port**: Verify no other application is using the port\n\n\
-                    **Original error:** {}", 
-                    config.base_url, e
-                ).into());
-            } else if error_msg.contains("timeout") || error_msg.contains("timed out") {
-                return Err(format!(
-                    "⏰ **Connection Timeout**\n\n\
-                    Cannot reach LM Studio server at `{}` within 10 seconds\n\n\
-                    **Solutions:**\n\
-                    • **Check LM Studio**: Ensure LM Studio is running and responsive\n\
-                    • **Network Connection**: Verify your network connection is stable\n\
-                    • **Server Load**: LM Studio might be overloaded - wait and retry\n\
-                    • **Firewall**: Check if firewall is blocking the connection\n\n\
-                    **Original error:** {}", 
-                    config.base_url, e
-                ).into());
-            } else if error_msg.contains("refused") || error_msg.contains("connection refused") {
-                return Err(format!(
-                    "🚫 **Connection Refused**\n\n\
-                    LM Studio at `{}` is not accepting connections\n\n\
-                    **Solutions:**\n\
-                    • **Start LM Studio**: Make sure LM Studio is running\n\
-                    • **Check Port**: Verify LM Studio is listening on the correct port (usually 1234)\n\
-                    • **Load Model**: Ensure a model is loaded in LM Studio\n\
-                    • **Server Status**: Check LM Studio's server status indicator\n\
-                    • **Alternative Port**: Try port 11434 if using Ollama instead\n\n\
-                    **Original error:** {}", 
-                    config.base_url, e
-                ).into());
-            } else if error_msg.contains("dns") || error_msg.contains("name resolution") {
-                return Err(format!(
-                    "🌐 **DNS Resolution Error**\n\n\
-                    Cannot resolve hostname in `{}`\n\n\
-                    **Solutions:**\n\
-                    • **Use IP Address**: Try `http://127.0.0.1:1234` instead of `http://localhost:1234`\n\
-                    • **Check Hostname**: Verify the hostname is correct\n\
-                    • **DNS Settings**: Check your DNS configuration\n\n\
-                    **Original error:** {}", 
-                    config.base_url, e
-                ).into());
-            } else {
-                return Err(format!(
-                    "🔗 **Connection Error**\n\n\
-                    Cannot connect to LM Studio at `{}`\n\n\
-                    **Solutions:**\n\
-                    • **Check URL**: Verify the base URL in lmapiconf.txt\n\
-                    • **Start LM Studio**: Ensure LM Studio is running\n\
-                    • **Network**: Check your network connection\n\
-                    • **Firewall**: Verify firewall settings\n\n\
-                    **Original error:** {}", 
-                    config.base_url, e
-                ).into());
-            }
+impl SamplingConfig for LMConfig {
+    fn sampling_defaults(&self) -> crate::commands::search::SamplingParams {
+        crate::commands::search::SamplingParams {
+            temperature: self.default_temperature,
+            max_tokens: self.default_max_tokens,
+            seed: self.default_seed,
         }
     }
-    
-    // Test 2: API endpoint availability
-    let api_url = format!("{}/v1/chat/completions", config.base_url);
-    let test_payload = serde_json::json!({
-        "model": config.default_model,
-        "messages": [{"role": "user", "content": "test"}],
-        "max_tokens": 1,
-        "temperature": 0.1
-    });
-    
-    println!("[DEBUG][CONNECTIVITY] Testing API endpoint: {}", api_url);
-    
-    let api_response = client
-        .post(&api_url)
-        .json(&test_payload)
-        .timeout(Duration::from_secs(60)) // 1 minute for API endpoint test
-        .send()
-        .await;
-    
-    match api_response {
-        Ok(response) => {
-            let status = response.status();
-            println!("[DEBUG][CONNECTIVITY] API endpoint OK - Status: {}", status);
-            if status.is_success() {
-                println!("[DEBUG][CONNECTIVITY] API connectivity test PASSED");
-                Ok(())
-            } else {
-                let error_text = response.text().await.unwrap_or_else(|_| "Unable to read error response".to_string());
-                println!("[DEBUG][CONNECTIVITY] API endpoint returned error status {}: {}", status, error_text);
-                Err(format!("API endpoint test failed: HTTP {} - {}", status, error_text).into())
-            }
-        }
-        Err(e) => {
-            println!("[DEBUG][CONNECTIVITY] API endpoint test failed: {}", e);
-            Err(format!("API endpoint connectivity test failed: {}", e).into())
-        }
+
+    fn model_profiles(&self) -> &HashMap<String, crate::commands::search::ModelProfile> {
+        &self.model_profiles
     }
 }
 
 /// Load LM Studio configuration from lmapiconf.txt with multi-path fallback
 pub async fn load_lm_config() -> Result<LMConfig, Box<dyn std::error::Error + Send + Sync>> {
-    let config_paths = [
-        "lmapiconf.txt",
-        "../lmapiconf.txt", 
-        "../../lmapiconf.txt",
-        "src/lmapiconf.txt"
-    ];
-    
-    let mut content = String::new();
-    let mut found_file = false;
-    let mut config_source = "";
-    
-    // Try to find the config file in multiple locations
-    for config_path in &config_paths {
-        match fs::read_to_string(config_path) {
-            Ok(file_content) => {
-                content = file_content;
-                found_file = true;
-                config_source = config_path;
-                println!("Ranking command: Found config file at {}", config_path);
-                break;
-            }
-            Err(_) => {
-                continue;
-            }
-        }
-    }
-    
-    if !found_file {
-        return Err("lmapiconf.txt file not found in any expected location (., .., ../.., src/) for ranking command".into());
-    }
+    let (content, config_source) = crate::commands::util::find_and_read_config("lmapiconf.txt")
+        .ok_or("lmapiconf.txt file not found in any expected location (., .., ../.., src/) for ranking command")?;
+    println!("Ranking command: Found config file at {}", config_source);
+    crate::commands::util::record_config_source("lmapiconf.txt (rank)", &config_source);
     
-    // Remove BOM if present
-    let content = content.strip_prefix('\u{feff}').unwrap_or(&content);
     let mut config_map = HashMap::new();
 
     // Parse the config file line by line
@@ -260,6 +127,8 @@ pub async fn load_lm_config() -> Result<LMConfig, Box<dyn std::error::Error + Se
             config_map.insert(key, value);
         }
     }
+
+    crate::commands::search::apply_model_overrides(&mut config_map, &crate::commands::search::model_overrides());
     
     // Check for required keys
     let required_keys = [
@@ -333,6 +202,7 @@ pub async fn load_lm_config() -> Result<LMConfig, Box<dyn std::error::Error + Se
             .map(|s| s.parse::<i64>())
             .transpose()
             .map_err(|_| "DEFAULT_SEED must be a valid integer if specified")?,
+        model_profiles: crate::commands::search::parse_model_profiles(&config_map)?,
     };
 
     println!("Ranking command: Successfully loaded config from {} with ranking model: '{}'", config_source, config.default_ranking_model);
@@ -366,15 +236,17 @@ async fn chat_completion_with_retries(
         let client = get_http_client().await;
         let api_url = format!("{}/v1/chat/completions", config.base_url);
         
+        let sampling = config.sampling_for(model);
         let chat_request = serde_json::json!({
             "model": model,
             "messages": messages,
-            "temperature": config.default_temperature,
-            "max_tokens": max_tokens.unwrap_or(config.default_max_tokens),
+            "temperature": sampling.temperature,
+            "max_tokens": max_tokens.unwrap_or(sampling.max_tokens),
             "stream": false,
-            "seed": config.default_seed
+            "seed": sampling.seed
         });
         
+        crate::commands::util::wait_for_backend_rate_limit().await;
         let response = match client
             .post(&api_url)
             .json(&chat_request)
@@ -632,6 +504,12 @@ async fn fetch_youtube_transcript(url: &str) -> Result<String, Box<dyn std::erro
     trace!("[TRACE][RANK][fetch_youtube_transcript] Process UUID: {}", process_uuid);
     trace!("[TRACE][RANK][fetch_youtube_transcript] Input URL: '{}'", url);
     trace!("[TRACE][RANK][fetch_youtube_transcript] Current working dir: {:?}", std::env::current_dir());
+
+    // yt-dlp fetches whatever it is given, so it gets the same screen as ^sum's fetches
+    if let Err(reason) = crate::commands::util::validate_fetch_url(url).await {
+        warn!("Refusing to fetch transcript for {}: {}", url, reason);
+        return Err(format!("That URL can't be ranked: {}", reason).into());
+    }
     
     // Create subtitles directory if it doesn't exist
     let subtitles_dir = "subtitles";
@@ -664,7 +542,7 @@ async fn fetch_youtube_transcript(url: &str) -> Result<String, Box<dyn std::erro
     // Download transcript using yt-dlp
     println!("Downloading transcript for: {}", url);
     
-    let output = Command::new("yt-dlp")
+    let output = crate::commands::util::yt_dlp_command()
         .args(&[
             "--write-sub",
             "--write-auto-sub",
@@ -673,7 +551,8 @@ async fn fetch_youtube_transcript(url: &str) -> Result<String, Box<dyn std::erro
             "--output", &subtitle_file_path,
             url
         ])
-        .output()?;
+        .output()
+        .map_err(|e| crate::commands::util::describe_yt_dlp_spawn_error(&e))?;
     
     if !output.status.success() {
         let error_output = String::from_utf8_lossy(&output.stderr);
@@ -816,8 +695,8 @@ async fn stream_ranking_analysis(
     };
     
     // Prepare content for analysis
-    let content_preview = if content.len() > 1000 {
-        format!("{}...", &content[..1000])
+    let content_preview = if content.chars().count() > 1000 {
+        format!("{}...", crate::commands::util::truncate_chars(content, 1000))
     } else {
         content.to_string()
     };
@@ -871,6 +750,14 @@ Provide a comprehensive ranking analysis with:\n\
                 }
             }
             
+            // Follow up with an at-a-glance score comparison when the analysis has per-category scores
+            let scores = parse_category_scores(&analysis);
+            debug!("[RANK] Parsed {} category scores from analysis", scores.len());
+            if scores.len() >= 2 {
+                let table = format_score_table(&scores, RANK_TABLE_MAX_WIDTH, config.max_discord_message_length - config.response_format_padding);
+                msg.channel_id.send_message(&ctx.http, |m| m.content(format!("**🏆 Score Summary**\n{}", table))).await?;
+            }
+            
             Ok(())
         }
         Err(e) => {
@@ -879,34 +766,77 @@ Provide a comprehensive ranking analysis with:\n\
     }
 }
 
-/// Split message content into Discord-friendly chunks
-fn split_message(content: &str, max_len: usize) -> Vec<String> {
-    let lines: Vec<&str> = content.lines().collect();
-    let mut chunks = Vec::new();
-    let mut current_chunk = String::new();
-    
-    for line in lines {
-        let potential_chunk = if current_chunk.is_empty() {
-            line.to_string()
-        } else {
-            format!("{}\n{}", current_chunk, line)
-        };
-        
-        if potential_chunk.len() <= max_len {
-            current_chunk = potential_chunk;
-        } else {
-            if !current_chunk.is_empty() {
-                chunks.push(current_chunk.clone());
+// Score table layout limits - items are truncated so rows stay narrow enough for mobile clients
+const RANK_TABLE_ITEM_WIDTH: usize = 28;
+const RANK_TABLE_MAX_WIDTH: usize = 56;
+
+/// One "Label: 8.5/10" score line, with optional bullet, numbering and bold markers
+static CATEGORY_SCORE_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^[\s\-\*•#\d\.\)]*\**\s*([A-Za-z][A-Za-z0-9 &/'\-]{0,60}?)\s*\**\s*[:\-–]\s*\**\s*(\d+(?:\.\d+)?)\s*/\s*10\b"
+    ).expect("Invalid category score regex pattern")
+});
+
+/// Extract "Category: 7/10" style scores from a ranking analysis
+/// Accepts bullets, numbering and bold markers around the label, e.g. "- **Clarity**: 8.5/10"
+fn parse_category_scores(analysis: &str) -> Vec<(String, f32)> {
+    let mut scores: Vec<(String, f32)> = Vec::new();
+    for line in analysis.lines() {
+        if let Some(caps) = CATEGORY_SCORE_REGEX.captures(line.trim()) {
+            let label = caps[1].trim().to_string();
+            let score: f32 = match caps[2].parse() {
+                Ok(score) if (0.0..=10.0).contains(&score) => score,
+                _ => continue,
+            };
+            // Keep the first score reported for each label
+            if !scores.iter().any(|(existing, _)| existing.eq_ignore_ascii_case(&label)) {
+                scores.push((label, score));
             }
-            current_chunk = line.to_string();
         }
     }
-    
-    if !current_chunk.is_empty() {
-        chunks.push(current_chunk);
+    scores
+}
+
+/// Render scores as an aligned, fenced table ordered from highest to lowest
+/// Falls back to a plain numbered list when the table would be too wide or too long for one message
+fn format_score_table(scores: &[(String, f32)], max_width: usize, max_len: usize) -> String {
+    let mut ranked: Vec<&(String, f32)> = scores.iter().collect();
+    ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+
+    let items: Vec<String> = ranked.iter().map(|(label, _)| {
+        if label.chars().count() > RANK_TABLE_ITEM_WIDTH {
+            format!("{}…", crate::commands::util::truncate_chars(label, RANK_TABLE_ITEM_WIDTH - 1))
+        } else {
+            label.clone()
+        }
+    }).collect();
+    let item_width = items.iter().map(|item| item.chars().count()).max().unwrap_or(4).max(4);
+    let rank_width = ranked.len().to_string().len().max(1);
+
+    let mut rows = vec![
+        format!("| {:>rw$} | Score | {:<iw$} |", "#", "Item", rw = rank_width, iw = item_width),
+        format!("|{}|-------|{}|", "-".repeat(rank_width + 2), "-".repeat(item_width + 2)),
+    ];
+    for (i, ((_, score), item)) in ranked.iter().zip(&items).enumerate() {
+        rows.push(format!("| {:>rw$} | {:>5.1} | {:<iw$} |", i + 1, score, item, rw = rank_width, iw = item_width));
     }
-    
-    chunks
+    let table = format!("```\n{}\n```", rows.join("\n"));
+
+    let too_wide = rows.iter().any(|row| row.chars().count() > max_width);
+    if !too_wide && table.len() <= max_len {
+        return table;
+    }
+
+    let list: Vec<String> = ranked.iter().enumerate()
+        .map(|(i, (label, score))| format!("{}. {} — {:.1}/10", i + 1, label, score))
+        .collect();
+    list.join("\n")
+}
+
+/// Split message content into Discord-friendly chunks
+/// Delegates to the shared splitter so an over-long line can never produce an over-limit message
+fn split_message(content: &str, max_len: usize) -> Vec<String> {
+    crate::commands::util::split_for_discord(content, max_len)
 }
 
 // ============================================================================
@@ -926,6 +856,27 @@ mod tests {
         assert_eq!(cleaned, "Hello world This is a test");
     }
     
+    #[test]
+    fn test_score_table_from_analysis() {
+        let analysis = "## Overall Score: 7/10\n\n- **Clarity**: 8.5/10 - easy to follow\n2. Depth - 6/10\nSome prose mentioning 3/10 inline.";
+        let scores = parse_category_scores(analysis);
+        assert_eq!(scores, vec![
+            ("Overall Score".to_string(), 7.0),
+            ("Clarity".to_string(), 8.5),
+            ("Depth".to_string(), 6.0),
+        ]);
+
+        let table = format_score_table(&scores, 56, 1900);
+        assert!(table.starts_with("```"));
+        let rows: Vec<&str> = table.lines().collect();
+        assert!(rows[3].contains("Clarity") && rows[3].contains("8.5"));
+        assert!(rows[5].contains("Depth"));
+
+        // Too narrow for the table, so it falls back to a simple list
+        let list = format_score_table(&scores, 10, 1900);
+        assert!(list.starts_with("1. Clarity — 8.5/10"));
+    }
+    
     #[test]
     fn test_clean_html() {
         let html = "<html><body><h1>Title</h1><p>Content with <strong>bold</strong> text</p></body></html>";
@@ -968,6 +919,7 @@ mod tests {
             response_format_padding: 100,
             default_vision_model: "vision-model".to_string(),
             default_seed: Some(42),
+            model_profiles: HashMap::new(),
         };
         
         assert_eq!(config.base_url, "http://localhost:1234");
@@ -1021,9 +973,10 @@ mod tests {
         }
         
         // Test single long line
-        let single_long_line = "This is a very long line that should not be split because it exceeds the maximum length but we want to keep it as one chunk for testing purposes.";
+        let single_long_line = "This is a very long line that exceeds the maximum length, so it must be hard-split to stay within the Discord limit.";
         let chunks = split_message(single_long_line, 50);
-        assert_eq!(chunks.len(), 1, "Single long line should remain as one chunk");
+        assert!(chunks.len() > 1, "Single long line should be split to fit the limit");
+        assert!(chunks.iter().all(|chunk| chunk.len() <= 50));
     }
 }
 
@@ -1110,8 +1063,9 @@ Please provide a valid URL starting with `http://` or `https://`\n\n\
     let url = input;
     trace!("[TRACE][RANK] Validated URL: '{}'", url);
     
-    // Determine if this is a YouTube URL
-    let is_youtube = url.contains("youtube.com") || url.contains("youtu.be");
+    // Only a URL that parses as a YouTube video goes to yt-dlp, and always in its canonical form
+    let youtube_url = crate::commands::util::parse_youtube_url(url).map(|youtube_url| youtube_url.canonical_url());
+    let is_youtube = youtube_url.is_some();
     trace!("[TRACE][RANK] Content type: {}", if is_youtube { "YouTube" } else { "Webpage" });
     
     // Load LM Studio configuration
@@ -1195,7 +1149,7 @@ Failed to load LM Studio configuration: {}\n\n\
             warn!("Failed to update message for YouTube processing: {}", e);
         }
         
-        match fetch_youtube_transcript(url).await {
+        match fetch_youtube_transcript(youtube_url.as_deref().unwrap_or(url)).await {
             Ok(transcript) => {
                 trace!("[TRACE][RANK] YouTube transcript extracted successfully: {} chars", transcript.len());
                 (transcript, None::<String>)
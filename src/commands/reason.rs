@@ -5,6 +5,7 @@
 // Key Features:
 // - Dedicated reasoning model
 // - Real-time streaming with <think> tag filtering (removes internal thoughts)
+// - --format answer|steps|both to choose how much of the reasoning is shown
 // - Buffered chunking for long responses (reason -s)
 // - Analytical web search with embedded source links
 // - Multi-path config and prompt loading
@@ -28,17 +29,72 @@ use std::fs;
 use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use futures_util::StreamExt;
-use crate::commands::search::{LMConfig, ChatMessage};
+use crate::commands::search::{LMConfig, ChatMessage, resolve_command_timeout, send_chat_request, SamplingConfig};
+use crate::commands::util::{close_open_code_fence, current_request_id, empty_response_message, empty_response_retry, finish_reason_note, invocation_request_id, is_empty_response, parse_leading_flags, truncate_chars, tail_chars, with_error_id, with_request_id, DebugLog, EditThrottle, write_to_response_file, TempFileGuard, UrlScreenError};
 use crate::ReasonContextMap; // TypeMap key defined in main.rs
 use regex::Regex;
 use once_cell::sync::Lazy;
 
-// Compile regex once for better performance - matches <think> tags
-// Used to filter out internal AI thoughts from streaming output
-static THINKING_TAG_REGEX: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r"(?s)<think>.*?</think>").expect("Invalid thinking tag regex pattern")
+// Closed reasoning blocks under the tag names models commonly use, for --format answer
+static REASONING_BLOCK_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?is)<(?:think|thinking|reasoning)>.*?</(?:think|thinking|reasoning)>").expect("Invalid reasoning block regex pattern")
 });
 
+// Opening and closing reasoning tags left over once closed blocks are removed
+static REASONING_OPEN_TAG_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)<(?:think|thinking|reasoning)>").expect("Invalid reasoning open tag regex pattern")
+});
+static REASONING_CLOSE_TAG_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)</(?:think|thinking|reasoning)>").expect("Invalid reasoning close tag regex pattern")
+});
+
+// A "Final Answer:" style heading; --format answer keeps only what follows the last one
+static FINAL_ANSWER_HEADING_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?im)^[#*\s]*final answer[*:\s]*$|^[#*\s]*final answer\s*[:*]+").expect("Invalid final answer heading regex pattern")
+});
+
+// How much of the reasoning ^reason --format shows
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ReasonFormat {
+    Answer, // Only the final answer; every form of reasoning is stripped
+    Steps,  // An explicit numbered reasoning outline
+    Both,   // The outline, then a final answer
+}
+
+impl ReasonFormat {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "answer" => Some(ReasonFormat::Answer),
+            "steps" => Some(ReasonFormat::Steps),
+            "both" => Some(ReasonFormat::Both),
+            _ => None,
+        }
+    }
+
+    // Instruction appended to the system prompt
+    fn instruction(self) -> &'static str {
+        match self {
+            ReasonFormat::Answer => "Respond with only the final answer. Do not show your reasoning, working, or intermediate steps.",
+            ReasonFormat::Steps => "Write out your reasoning as a numbered outline (1., 2., 3., ...) under a **Steps** heading, one inference per step. Do not add a separate final answer section.",
+            ReasonFormat::Both => "First write out your reasoning as a numbered outline (1., 2., 3., ...) under a **Steps** heading, one inference per step. Then give the conclusion under a **Final Answer** heading.",
+        }
+    }
+}
+
+// Matches http(s) URLs cited in a response; trailing punctuation is trimmed afterwards
+static CITED_URL_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"https?://[^\s<>()\[\]"'`]+"#).expect("Invalid cited URL regex pattern")
+});
+
+// Instruction appended to the system prompt for ^reason --cite
+const CITE_INSTRUCTION: &str = "When stating facts, cite your sources inline as numbered references like [1] and include the full URL of each source. Only cite sources you are confident exist; if you are unsure of a URL, say so instead of inventing one.";
+
+// Most cited URLs checked for reachability per response
+const MAX_CHECKED_CITATIONS: usize = 10;
+
+// Per-link timeout for the reachability check
+const CITATION_CHECK_TIMEOUT_SECS: u64 = 8;
+
 // Structures for streaming API responses
 // Used to parse streaming JSON chunks from the AI API
 #[derive(Deserialize)]
@@ -67,6 +123,8 @@ struct ChatRequest {
     stream: bool,               // Whether to stream output
     #[serde(skip_serializing_if = "Option::is_none")]
     seed: Option<i64>,          // Optional seed for reproducible responses
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,          // --stop sequences (Ollama's OpenAI-compatible endpoint maps these to options.stop)
 }
 
 // Structure to track streaming statistics for reasoning
@@ -75,6 +133,7 @@ struct StreamingStats {
     total_characters: usize,    // Total characters streamed
     message_count: usize,       // Number of Discord messages sent
     filtered_characters: usize, // Characters filtered by <think> tag removal
+    finish_reason: Option<String>, // Backend finish_reason ("length" = cut off at max_tokens)
 }
 
 // Structure to track current message state during streaming
@@ -108,8 +167,24 @@ fn get_reason_context_map_read<'a>(data_map: &'a tokio::sync::RwLockReadGuard<'_
 /// Supports:
 ///   - ^reason <question> (step-by-step reasoning)
 ///   - ^reason -s <query> (analytical web search)
+///   - ^reason --compare [--judge] <modelA> <modelB> <question> (model comparison)
+///   - ^reason --cite <question> (inline citations with a checked References list)
+///   - ^reason --stream-file <question> (stream to a text file and upload it when complete)
+///   - ^reason --format answer|steps|both <question> (answer only, numbered reasoning outline, or both)
 ///   - ^reason --clear (clear context)
 pub async fn reason(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    // Everything logged for this invocation carries the same correlation ID
+    with_request_id(invocation_request_id(msg.id.0), reason_command(ctx, msg, args)).await
+}
+
+async fn reason_command(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    // Respect the ALLOWED_CHANNELS / DENIED_CHANNELS restrictions for heavy commands
+    if crate::commands::util::reply_if_channel_blocked(ctx, msg, "reason").await? {
+        return Ok(());
+    }
+    // Counted as in flight so shutdown can wait for it to finish
+    let _heavy_guard = crate::start_heavy_command();
+
     let input = args.message().trim();
     
     // Safety check: ensure input was processed correctly
@@ -124,18 +199,51 @@ pub async fn reason(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
         msg.reply(ctx, "Please provide a question! Usage: `^reason <your reasoning question>`").await?;
         return Ok(());
     }
-    
+
+    if crate::commands::util::reply_if_prompt_too_long(ctx, msg, input, "reason").await? {
+        return Ok(());
+    }
+
     // Debug: Past input check
     println!("[REASON] Past input check - proceeding with reasoning request");
 
-    // Check if this is a search request
-    if input.starts_with("-s ") || input.starts_with("--search ") {
-        // Extract search query safely
-        let search_query = if input.starts_with("-s ") {
-            input.strip_prefix("-s ").unwrap_or(input)  // Safe fallback
+    // Parse leading flags (quote-aware)
+    let (flags, flag_rest) = parse_leading_flags(input, &["-s", "--search", "-c", "--clear", "--compare", "--judge", "--cite", "--stream-file", "--continue", "--debug"], &["--stop", "--format"]);
+    let has_flag = |names: &[&str]| flags.iter().any(|(flag, _)| names.contains(&flag.as_str()));
+
+    // Check if this is a clear context request
+    if has_flag(&["-c", "--clear"]) {
+        let mut data_map = ctx.data.write().await;
+        let reason_map = get_reason_context_map(&mut data_map)?;
+        
+        let had_context = if let Some(context) = reason_map.get_mut(&msg.author.id) {
+            let message_count = context.total_messages();
+            let context_info = context.get_context_info();
+            println!("[reason] Clearing context via --clear flag for user {}: {}", msg.author.id, context_info);
+            context.clear();
+            message_count > 0
         } else {
-            input.strip_prefix("--search ").unwrap_or(input)  // Safe fallback
+            false
         };
+        
+        if had_context {
+            msg.reply(ctx, "**Reasoning Context Cleared** ✅\nYour reasoning conversation history has been reset. The next reasoning question you ask will start a brand new context.").await?;
+        } else {
+            msg.reply(ctx, "**No Reasoning Context Found** ℹ️\nYou don't have any active reasoning conversation history to clear.").await?;
+        }
+        return Ok(());
+    }
+
+    // Global cap on simultaneous backend requests, taken after --clear so it works while the bot is busy;
+    // held until this command returns
+    let _backend_permit = match crate::commands::util::acquire_backend_permit_or_reply(ctx, msg, "reason").await? {
+        Some(permit) => permit,
+        None => return Ok(()),
+    };
+
+    // Check if this is a search request
+    if has_flag(&["-s", "--search"]) {
+        let search_query = flag_rest;
 
         if search_query.trim().is_empty() {
             msg.reply(ctx, "Please provide a search query! Usage: `^reason -s <search query>`").await?;
@@ -146,8 +254,8 @@ pub async fn reason(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
         let config = match load_reasoning_config().await {
             Ok(config) => config,
             Err(e) => {
-                eprintln!("Failed to load LM Studio configuration for reasoning search: {}", e);
-                msg.reply(ctx, &format!("LM Studio configuration error: {}\n\nMake sure `lmapiconf.txt` exists and contains all required settings. Check `example_lmapiconf.txt` for reference.", e)).await?;
+                eprintln!("[REASON][{}] Failed to load LM Studio configuration for reasoning search: {}", current_request_id(), e);
+                msg.reply(ctx, with_error_id(format!("LM Studio configuration error: {}\n\nMake sure `lmapiconf.txt` exists and contains all required settings. Check `example_lmapiconf.txt` for reference.", e))).await?;
                 return Ok(());
             }
         };
@@ -170,8 +278,8 @@ pub async fn reason(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
                 println!("Reasoning-enhanced search completed successfully for query: '{}'", search_query);
             }
             Err(e) => {
-                eprintln!("Reasoning-enhanced search failed: {}", e);
-                let error_msg = format!("**Reasoning Search Failed**\n\nQuery: `{}`\nError: {}\n\nCheck your SerpAPI configuration in lmapiconf.txt", search_query, e);
+                eprintln!("[REASON][{}] Reasoning-enhanced search failed: {}", current_request_id(), e);
+                let error_msg = with_error_id(format!("**Reasoning Search Failed**\n\nQuery: `{}`\nError: {}\n\nCheck your SerpAPI configuration in lmapiconf.txt", search_query, e));
                 let _ = search_msg.edit(&ctx.http, |m| {
                     m.content(&error_msg)
                 }).await;
@@ -181,40 +289,90 @@ pub async fn reason(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
         return Ok(());
     }
 
-    // Check if this is a clear context request
-    if input.starts_with("--clear") || input == "-c" {
-        let mut data_map = ctx.data.write().await;
-        let reason_map = get_reason_context_map(&mut data_map)?;
-        
-        let had_context = if let Some(context) = reason_map.get_mut(&msg.author.id) {
-            let message_count = context.total_messages();
-            let context_info = context.get_context_info();
-            println!("[reason] Clearing context via --clear flag for user {}: {}", msg.author.id, context_info);
-            context.clear();
-            message_count > 0
-        } else {
-            false
+    // Check if this is a model comparison request
+    if has_flag(&["--compare"]) {
+        let (model_a, model_b, question) = match parse_compare_args(flag_rest) {
+            Some(parsed) => parsed,
+            None => {
+                msg.reply(ctx, "Please provide two models and a question! Usage: `^reason --compare [--judge] <modelA> <modelB> <question>`").await?;
+                return Ok(());
+            }
         };
-        
-        if had_context {
-            msg.reply(ctx, "**Reasoning Context Cleared** ✅\nYour reasoning conversation history has been reset. The next reasoning question you ask will start a brand new context.").await?;
-        } else {
-            msg.reply(ctx, "**No Reasoning Context Found** ℹ️\nYou don't have any active reasoning conversation history to clear.").await?;
+
+        let config = match load_reasoning_config().await {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("[REASON][{}] Failed to load LM Studio configuration for reasoning comparison: {}", current_request_id(), e);
+                msg.reply(ctx, with_error_id(format!("LM Studio configuration error: {}\n\nMake sure `lmapiconf.txt` exists and contains all required settings. Check `example_lmapiconf.txt` for reference.", e))).await?;
+                return Ok(());
+            }
+        };
+
+        if let Err(e) = compare_models(ctx, msg, &config, model_a, model_b, question, has_flag(&["--judge"])).await {
+            eprintln!("[REASON][{}] Reasoning comparison failed: {}", current_request_id(), e);
+            msg.reply(ctx, with_error_id(format!("**Model Comparison Failed**\n\nError: {}", e))).await?;
         }
         return Ok(());
     }
 
-    // Regular reasoning functionality
-    let question = input;
+    // Regular reasoning functionality; --cite asks for sources and checks the cited links afterwards,
+    // --stream-file delivers the answer as an uploaded text file instead of split messages
+    // --stop (repeatable) halts generation at a marker
+    let cite = has_flag(&["--cite"]);
+    let stream_file = has_flag(&["--stream-file"]);
+    // --debug attaches this request's key log lines as debug.txt
+    let mut debug = DebugLog::new("reason", has_flag(&["--debug"]));
+    let stop = match crate::commands::util::collect_stop_sequences(&flags) {
+        Ok(stop) => stop,
+        Err(e) => {
+            msg.reply(ctx, format!("❌ {}", e)).await?;
+            return Ok(());
+        }
+    };
+    let format = match flags.iter().find(|(flag, _)| flag == "--format").and_then(|(_, value)| value.as_deref()) {
+        Some(value) => match ReasonFormat::parse(value) {
+            Some(format) => Some(format),
+            None => {
+                msg.reply(ctx, format!("❌ Unknown format `{}`. Available formats: answer, steps, both", value)).await?;
+                return Ok(());
+            }
+        },
+        None => None,
+    };
+    let question = if flags.is_empty() { input } else { flag_rest };
 
-    // Safety check: ensure question is not empty after trimming
-    if question.trim().is_empty() {
+    // --continue extends the previous answer (e.g. one cut off at max_tokens) instead of asking something new
+    let continue_previous = has_flag(&["--continue"]);
+    if continue_previous {
+        if !question.trim().is_empty() {
+            msg.reply(ctx, "❌ `--continue` takes no question. Usage: `^reason --continue`").await?;
+            return Ok(());
+        }
+        let previous_finish = {
+            let data_map = ctx.data.read().await;
+            let reason_map = get_reason_context_map_read(&data_map)?;
+            reason_map.get(&msg.author.id)
+                .filter(|context| context.can_continue())
+                .map(|context| context.last_finish_reason.clone())
+        };
+        match previous_finish {
+            None => {
+                msg.reply(ctx, "ℹ️ There is no previous `^reason` answer to continue.").await?;
+                return Ok(());
+            }
+            Some(reason) if reason.as_deref() != Some("length") => {
+                msg.reply(ctx, "ℹ️ Your previous answer wasn't cut off at the token limit, continuing it anyway.").await?;
+            }
+            Some(_) => {}
+        }
+    } else if question.trim().is_empty() {
+        // Safety check: ensure question is not empty after trimming
         msg.reply(ctx, "Please provide a valid question! Usage: `^reason <your reasoning question>`").await?;
         return Ok(());
     }
 
-    // Record user question in per-user context history
-    {
+    // Record user question in per-user context history (a continuation only extends the stored answer)
+    if !continue_previous {
         let mut data_map = ctx.data.write().await;
         let reason_map = get_reason_context_map(&mut data_map)?;
         let context = reason_map.entry(msg.author.id).or_insert_with(crate::UserContext::new);
@@ -231,8 +389,8 @@ pub async fn reason(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     let config = match load_reasoning_config().await {
         Ok(config) => config,
         Err(e) => {
-            eprintln!("Failed to load LM Studio configuration: {}", e);
-            msg.reply(ctx, &format!("LM Studio configuration error: {}\n\nMake sure `lmapiconf.txt` exists and contains all required settings. Check `example_lmapiconf.txt` for reference.", e)).await?;
+            eprintln!("[REASON][{}] Failed to load LM Studio configuration: {}", current_request_id(), e);
+            msg.reply(ctx, with_error_id(format!("LM Studio configuration error: {}\n\nMake sure `lmapiconf.txt` exists and contains all required settings. Check `example_lmapiconf.txt` for reference.", e))).await?;
             return Ok(());
         }
     };
@@ -244,7 +402,7 @@ pub async fn reason(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     let system_prompt = match load_reasoning_system_prompt().await {
         Ok(prompt) => {
             println!("[REASON] Successfully loaded reasoning system prompt ({} chars):", prompt.len());
-            println!("[REASON] System prompt preview: {}", &prompt[..std::cmp::min(200, prompt.len())]);
+            println!("[REASON] System prompt preview: {}", truncate_chars(&prompt, 200));
             prompt
         },
         Err(e) => {
@@ -267,6 +425,20 @@ pub async fn reason(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     // Safety check: ensure system prompt was loaded correctly
     println!("[REASON] System prompt loaded successfully ({} chars)", system_prompt.len());
 
+    let system_prompt = if cite {
+        println!("[REASON] Citation mode enabled - requesting inline sources");
+        format!("{}\n\n{}", system_prompt, CITE_INSTRUCTION)
+    } else {
+        system_prompt
+    };
+    let system_prompt = match format {
+        Some(format) => {
+            println!("[REASON] Output format: {:?}", format);
+            format!("{}\n\n{}", system_prompt, format.instruction())
+        }
+        None => system_prompt,
+    };
+
     // Build message list including system prompt and per-user history
     let mut messages = Vec::new();
     messages.push(ChatMessage { role: "system".to_string(), content: system_prompt.clone() });
@@ -284,17 +456,20 @@ pub async fn reason(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
             println!("Reasoning command: No context history found for user {}", msg.author.name);
         }
     }
+    if continue_previous {
+        messages.push(ChatMessage { role: "user".to_string(), content: crate::commands::search::STREAM_CONTINUE_PROMPT.to_string() });
+    }
     
     println!("[REASON] Total messages prepared for API: {} (including system prompt)", messages.len());
     println!("[REASON] First message (system): role='{}', content='{}'", 
-        messages[0].role, &messages[0].content[..std::cmp::min(100, messages[0].content.len())]);
+        messages[0].role, truncate_chars(&messages[0].content, 100));
     
     // Enhanced debug: Show more of the system prompt to verify it's loaded correctly
     let system_content = &messages[0].content;
     println!("[REASON] System prompt length: {} characters", system_content.len());
     if system_content.len() > 200 {
-        println!("[REASON] System prompt preview (first 200 chars): {}", &system_content[..200]);
-        println!("[REASON] System prompt preview (last 200 chars): {}", &system_content[system_content.len()-200..]);
+        println!("[REASON] System prompt preview (first 200 chars): {}", truncate_chars(system_content, 200));
+        println!("[REASON] System prompt preview (last 200 chars): {}", tail_chars(system_content, 200));
     } else {
         println!("[REASON] Full system prompt: {}", system_content);
     }
@@ -311,7 +486,7 @@ pub async fn reason(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     // Safety check: ensure we have at least the system message and user question
     if messages.len() < 2 {
         eprintln!("[REASON] ERROR: Not enough messages for API call (need at least system + user, got {})", messages.len());
-        msg.reply(ctx, "**Error:** Failed to prepare reasoning request. Please try again.").await?;
+        msg.reply(ctx, with_error_id("**Error:** Failed to prepare reasoning request. Please try again.")).await?;
         return Ok(());
     }
 
@@ -365,8 +540,51 @@ pub async fn reason(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     // Safety check: ensure initial message was sent correctly
     println!("[REASON] Initial message sent successfully: '{}'", current_msg.content);
 
+    // --stream-file: the guard removes the temp file however this command exits
+    let response_guard = if stream_file {
+        let path = std::env::temp_dir().join(format!("reason_response_{}_{}.txt", msg.author.id, chrono::Utc::now().timestamp()));
+        match std::fs::File::create(&path) {
+            Ok(file) => Some((TempFileGuard::new(path), file)),
+            Err(e) => {
+                eprintln!("[REASON][{}] Failed to create response file {}: {}", current_request_id(), path.display(), e);
+                let _ = current_msg.edit(&ctx.http, |m| m.content(with_error_id("❌ Failed to create the response file."))).await;
+                return Ok(());
+            }
+        }
+    } else {
+        None
+    };
+    let (response_guard, mut response_file) = match response_guard {
+        Some((guard, file)) => (Some(guard), Some(file)),
+        None => (None, None),
+    };
+    if let Some(file) = response_file.as_mut() {
+        let header = format!("Reasoning Response\nUser: {} ({})\nModel: {}\nQuestion: {}\n", msg.author.name, msg.author.id, config.default_reason_model, question);
+        if let Err(e) = write_to_response_file(Some(file), &header) {
+            eprintln!("[REASON] Failed to write response file header: {}", e);
+        }
+    }
+
+    if debug.is_enabled() {
+        let sampling = config.sampling_for(&config.default_reason_model);
+        debug.log(format!("model: {} at {}", config.default_reason_model, config.base_url));
+        debug.log(format!(
+            "sampling: temperature={} max_tokens={} seed={:?} stop={:?} format={:?}",
+            sampling.temperature, sampling.max_tokens, sampling.seed, stop, format
+        ));
+        debug.log(format!(
+            "payload: {} messages, {} chars (cite={}, stream_file={}, continue={})",
+            messages.len(),
+            messages.iter().map(|m| m.content.chars().count()).sum::<usize>(),
+            cite, stream_file, continue_previous
+        ));
+    }
+
     // Stream the reasoning response
-    match stream_reasoning_response(messages, &config.default_reason_model, &config, ctx, &mut current_msg).await {
+    let result = stream_reasoning_response(messages, &config.default_reason_model, &config, ctx, &mut current_msg, response_file.as_mut(), stop, format, &mut debug).await;
+    match result {
+        // Nothing visible came back even after the retry; the placeholder already explains why
+        Ok((_, full_response_content)) if full_response_content.is_empty() => {}
         Ok((final_stats, full_response_content)) => {
             println!("Reasoning command: Streaming complete - {} total characters across {} messages", 
                 final_stats.total_characters, final_stats.message_count);
@@ -379,14 +597,37 @@ pub async fn reason(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
             let mut data_map = ctx.data.write().await;
             let reason_map = get_reason_context_map(&mut data_map)?;
             if let Some(context) = reason_map.get_mut(&msg.author.id) {
-                context.add_assistant_message(ChatMessage { 
-                    role: "assistant".to_string(), 
-                    content: full_response_content,
-                });
+                if continue_previous {
+                    context.extend_last_assistant_message(&full_response_content);
+                } else {
+                    context.add_assistant_message(ChatMessage { 
+                        role: "assistant".to_string(), 
+                        content: full_response_content,
+                    });
+                }
+                context.last_finish_reason = final_stats.finish_reason.clone();
                 
                 println!("[REASON] AI response recorded: {} total messages in context", 
                     context.total_messages());
             }
+            drop(data_map);
+            crate::compact_context_if_needed::<ReasonContextMap>(ctx, msg.author.id, &config).await;
+
+            if let Some(note) = finish_reason_note(final_stats.finish_reason.as_deref(), Some("^reason")) {
+                let _ = msg.channel_id.say(&ctx.http, note).await;
+            }
+
+            if let Some(guard) = response_guard.as_ref() {
+                drop(response_file.take());
+                upload_response_file(ctx, msg, guard, &response_content_clone, &config).await;
+            }
+
+            if cite {
+                let references = check_citations(&response_content_clone).await;
+                if let Err(e) = msg.channel_id.say(&ctx.http, references).await {
+                    eprintln!("[REASON] Failed to post references: {}", e);
+                }
+            }
 
             // Safety check: ensure context was updated successfully
             if response_content_clone.trim().is_empty() {
@@ -396,9 +637,10 @@ pub async fn reason(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
             }
         }
         Err(e) => {
-            eprintln!("Failed to stream reasoning response: {}", e);
+            eprintln!("[REASON][{}] Failed to stream reasoning response: {}", current_request_id(), e);
+            debug.log(format!("error: {}", e));
             let _ = current_msg.edit(&ctx.http, |m| {
-                m.content("Failed to get response!")
+                m.content(with_error_id("Failed to get response!"))
             }).await;
 
             // Safety check: ensure error message was sent successfully
@@ -411,6 +653,7 @@ pub async fn reason(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
             println!("[REASON] Error handling completed successfully for user {}", msg.author.name);
         }
     }
+    debug.attach(ctx, msg.channel_id).await;
 
     // Safety check: ensure command completed successfully
     println!("[REASON] Command completed successfully for user {}", msg.author.name);
@@ -477,39 +720,11 @@ pub async fn clearreasoncontext(ctx: &Context, msg: &Message, _args: Args) -> Co
 // Loads all required settings from lmapiconf.txt using multi-path fallback
 // Returns LMConfig or error
 async fn load_reasoning_config() -> Result<LMConfig, Box<dyn std::error::Error + Send + Sync>> {
-    let config_paths = [
-        "lmapiconf.txt",
-        "../lmapiconf.txt", 
-        "../../lmapiconf.txt",
-        "src/lmapiconf.txt"
-    ];
-    
-    let mut content = String::new();
-    let mut found_file = false;
-    let mut config_source = "";
-    
-    // Try to find the config file in multiple locations
-    for config_path in &config_paths {
-        match fs::read_to_string(config_path) {
-            Ok(file_content) => {
-                content = file_content;
-                found_file = true;
-                config_source = config_path;
-                println!("Reasoning command: Found config file at {}", config_path);
-                break;
-            }
-            Err(_) => {
-                continue;
-            }
-        }
-    }
-    
-    if !found_file {
-        return Err("lmapiconf.txt file not found in any expected location (., .., ../.., src/) for reasoning command".into());
-    }
+    let (content, config_source) = crate::commands::util::find_and_read_config("lmapiconf.txt")
+        .ok_or("lmapiconf.txt file not found in any expected location (., .., ../.., src/) for reasoning command")?;
+    println!("Reasoning command: Found config file at {}", config_source);
+    crate::commands::util::record_config_source("lmapiconf.txt (reason)", &config_source);
     
-    // Remove BOM if present
-    let content = content.strip_prefix('\u{feff}').unwrap_or(&content);
     let mut config_map = HashMap::new();
 
     // Parse the config file line by line
@@ -528,6 +743,8 @@ async fn load_reasoning_config() -> Result<LMConfig, Box<dyn std::error::Error +
             config_map.insert(key, value);
         }
     }
+
+    crate::commands::search::apply_model_overrides(&mut config_map, &crate::commands::search::model_overrides());
     
     // Check for required keys
     let required_keys = [
@@ -550,14 +767,21 @@ async fn load_reasoning_config() -> Result<LMConfig, Box<dyn std::error::Error +
         }
     }
     
+    let (stream_auto_continue, stream_max_continues) = crate::commands::search::parse_stream_continue(&config_map)?;
+    let (context_compaction, context_compaction_threshold) = crate::commands::search::parse_context_compaction(&config_map)?;
+    let max_retries = crate::commands::search::parse_max_retries(&config_map)?;
+    let model_profiles = crate::commands::search::parse_model_profiles(&config_map)?;
+    
     // Create config - all values must be present in lmapiconf.txt
     let config = LMConfig {
         base_url: config_map.get("LM_STUDIO_BASE_URL")
             .ok_or("LM_STUDIO_BASE_URL not found")?.clone(),
-        timeout: config_map.get("LM_STUDIO_TIMEOUT")
-            .ok_or("LM_STUDIO_TIMEOUT not found")?
-            .parse()
-            .map_err(|_| "Invalid LM_STUDIO_TIMEOUT value")?,
+        // REASON_TIMEOUT sets the timeout for reasoning requests (default 300 seconds)
+        timeout: resolve_command_timeout(
+            &config_map,
+            "REASON_TIMEOUT",
+            crate::commands::search::DEFAULT_LONG_COMMAND_TIMEOUT,
+        )?,
         default_model: config_map.get("DEFAULT_MODEL")
             .ok_or("DEFAULT_MODEL not found")?.clone(),
         default_reason_model: config_map.get("DEFAULT_REASON_MODEL")
@@ -588,6 +812,18 @@ async fn load_reasoning_config() -> Result<LMConfig, Box<dyn std::error::Error +
             .map(|s| s.parse::<i64>())
             .transpose()
             .map_err(|_| "DEFAULT_SEED must be a valid integer if specified")?,
+        vision_max_images: crate::commands::search::DEFAULT_VISION_MAX_IMAGES,
+        vision_max_total_bytes: crate::commands::search::DEFAULT_VISION_MAX_TOTAL_BYTES,
+        vision_max_dimension: crate::commands::search::DEFAULT_VISION_MAX_DIMENSION,
+        vision_max_image_bytes: crate::commands::search::DEFAULT_VISION_MAX_IMAGE_BYTES,
+        default_tone: None,
+        stream_auto_continue,
+        stream_max_continues,
+        context_compaction,
+        context_compaction_threshold,
+        max_retries,
+        model_profiles,
+        seed_override: None,
     };
 
     println!("Reasoning command: Successfully loaded config from {} with reasoning model: '{}'", config_source, config.default_reason_model);
@@ -639,7 +875,7 @@ async fn load_reasoning_system_prompt() -> Result<String, Box<dyn std::error::Er
                 }
                 
                 println!("[SUCCESS] Reasoning command: Loaded prompt from {} ({} chars)", path, trimmed_content.len());
-                println!("[DEBUG] Prompt preview: {}", &trimmed_content[..std::cmp::min(200, trimmed_content.len())]);
+                println!("[DEBUG] Prompt preview: {}", truncate_chars(&trimmed_content, 200));
                 return Ok(trimmed_content.to_string());
             }
             Err(e) => {
@@ -666,8 +902,8 @@ async fn load_reasoning_system_prompt() -> Result<String, Box<dyn std::error::Er
 // Simple and reliable thinking tag filter
 // Removes all <think>...</think> blocks from the content
 fn filter_thinking_tags(content: &str) -> String {
-    // Use pre-compiled regex to remove thinking tags and their content
-    let filtered = THINKING_TAG_REGEX.replace_all(content, "");
+    // Nesting-aware scan: removes whole (possibly nested) blocks and an unclosed trailing one
+    let filtered = crate::commands::util::strip_think_blocks(content);
     
     // Clean up whitespace and empty lines
     let lines: Vec<&str> = filtered
@@ -679,6 +915,27 @@ fn filter_thinking_tags(content: &str) -> String {
     lines.join("\n").trim().to_string()
 }
 
+// Aggressive filter for --format answer
+// Removes <think>/<thinking>/<reasoning> blocks, text before a stray closing tag (the opening tag was in the
+// prompt template), an unclosed trailing block, and anything before a "Final Answer" heading
+fn strip_all_reasoning(content: &str) -> String {
+    let without_blocks = REASONING_BLOCK_REGEX.replace_all(content, "");
+    let mut rest: &str = &without_blocks;
+    if let Some(close) = REASONING_CLOSE_TAG_REGEX.find_iter(rest).last() {
+        rest = &rest[close.end()..];
+    }
+    if let Some(open) = REASONING_OPEN_TAG_REGEX.find(rest) {
+        rest = &rest[..open.start()];
+    }
+    if let Some(heading) = FINAL_ANSWER_HEADING_REGEX.find_iter(rest).last() {
+        let answer = rest[heading.end()..].trim();
+        if !answer.is_empty() {
+            rest = answer;
+        }
+    }
+    filter_thinking_tags(rest)
+}
+
 // Simple processing function that just filters thinking tags
 // Returns filtered content or a message if only thinking content remains
 fn process_reasoning_content(content: &str) -> String {
@@ -733,31 +990,74 @@ async fn stream_reasoning_response(
     config: &LMConfig,
     ctx: &Context,
     initial_msg: &mut Message,
+    mut response_file: Option<&mut std::fs::File>,
+    stop: Vec<String>,
+    format: Option<ReasonFormat>,
+    debug: &mut DebugLog,
+) -> Result<(StreamingStats, String), Box<dyn std::error::Error + Send + Sync>> {
+    let attempt_start = std::time::Instant::now();
+    let (stats, response) = stream_reasoning_attempt(messages.clone(), model, config, ctx, initial_msg, response_file.as_deref_mut(), stop.clone(), format).await?;
+    debug.log(format!(
+        "attempt 1: {} ms, {} visible chars in {} messages, finish_reason={:?}",
+        attempt_start.elapsed().as_millis(), response.chars().count(), stats.message_count, stats.finish_reason
+    ));
+    // The model produced no visible answer - ask once more with a nudge before giving up
+    let retry_messages = match empty_response_retry(&messages, &response, |content| ChatMessage { role: "user".to_string(), content }) {
+        Some(retry_messages) => retry_messages,
+        None => return Ok((stats, response)),
+    };
+    println!("[REASON] Model returned no visible content (finish_reason: {:?}), retrying once", stats.finish_reason);
+    let attempt_start = std::time::Instant::now();
+    let (stats, response) = stream_reasoning_attempt(retry_messages, model, config, ctx, initial_msg, response_file, stop, format).await?;
+    debug.log(format!(
+        "attempt 2 (empty-reply retry): {} ms, {} visible chars in {} messages, finish_reason={:?}",
+        attempt_start.elapsed().as_millis(), response.chars().count(), stats.message_count, stats.finish_reason
+    ));
+    if is_empty_response(&response) {
+        eprintln!("[REASON] Model returned no visible content after retry (finish_reason: {:?})", stats.finish_reason);
+        initial_msg.edit(&ctx.http, |m| m.content(empty_response_message(stats.finish_reason.as_deref()))).await?;
+    }
+    Ok((stats, response))
+}
+
+// One streamed request for stream_reasoning_response
+// An empty or reasoning-only completion comes back as an empty response without touching the placeholder
+async fn stream_reasoning_attempt(
+    messages: Vec<ChatMessage>,
+    model: &str,
+    config: &LMConfig,
+    ctx: &Context,
+    initial_msg: &mut Message,
+    mut response_file: Option<&mut std::fs::File>,
+    stop: Vec<String>,
+    format: Option<ReasonFormat>,
 ) -> Result<(StreamingStats, String), Box<dyn std::error::Error + Send + Sync>> {
     println!("[DEBUG][REASONING] === STARTING REASONING STREAM RESPONSE ===");
     println!("[DEBUG][REASONING] Model: {}", model);
     println!("[DEBUG][REASONING] Messages count: {}", messages.len());
     println!("[DEBUG][REASONING] Base URL: {}", config.base_url);
-    println!("[DEBUG][REASONING] Config timeout: {} seconds, Using: 300 seconds for reasoning operations", config.timeout);
+    println!("[DEBUG][REASONING] Config timeout: {} seconds", config.timeout);
     
     // Debug: Show the messages being sent to the API
     for (i, msg) in messages.iter().enumerate() {
         println!("[DEBUG][REASONING] Message {}: role='{}', content='{}'", 
-            i, msg.role, &msg.content[..std::cmp::min(150, msg.content.len())]);
+            i, msg.role, truncate_chars(&msg.content, 150));
     }
     
     let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(300)) // 5 minutes for reasoning operations
+        .timeout(std::time::Duration::from_secs(config.timeout))
         .build()?;
     println!("[DEBUG][REASONING] HTTP client created");
         
+    let sampling = config.sampling_for(model);
     let chat_request = ChatRequest {
         model: model.to_string(),
         messages,
-        temperature: config.default_temperature,
-        max_tokens: config.default_max_tokens,
+        temperature: sampling.temperature,
+        max_tokens: sampling.max_tokens,
         stream: true,
-        seed: config.default_seed,
+        seed: sampling.seed,
+        stop,
     };
     println!("[DEBUG][REASONING] Chat request created - Temperature: {}, Max tokens: {}, Stream: {}", 
         chat_request.temperature, chat_request.max_tokens, chat_request.stream);
@@ -866,70 +1166,55 @@ async fn stream_reasoning_response(
 
     // Now attempt the actual streaming API call
     println!("[DEBUG][REASONING] === MAKING STREAMING API REQUEST ===");
-    let response = match client
-        .post(&api_url)
-        .json(&chat_request)
-        .timeout(std::time::Duration::from_secs(300)) // 5 minute timeout for the entire request
-        .send()
-        .await
-    {
+    let response = match send_chat_request(
+        &client,
+        &api_url,
+        &chat_request,
+        &chat_request.model,
+        config.timeout,
+        config.max_retries,
+    ).await {
         Ok(resp) => {
             println!("[DEBUG][REASONING] API request sent successfully - Status: {}", resp.status());
             resp
         }
         Err(e) => {
+            // Permission, refused, timeout and HTTP errors carry their own troubleshooting text
             println!("[DEBUG][REASONING] API request failed: {}", e);
-            
-            // Enhanced error handling for API requests
-            let error_msg = format!("{}", e);
-            if error_msg.contains("os error 10013") || error_msg.contains("access permissions") {
-                return Err(format!(
-                    "Windows Network Permission Error (10013): Cannot connect to AI API at {}.\n\n**Solutions:**\n\
-                    1. **Windows Firewall:** Add firewall exception for this application\n\
-                    2. **Run as Administrator:** Try running the bot as administrator\n\
-                    3. **Check AI Server:** Ensure LM Studio/Ollama is running at {}\n\
-                    4. **Port Access:** Verify port 11434 isn't blocked\n\
-                    5. **Network Config:** Try localhost (127.0.0.1) if running locally\n\n\
-                    **Original error:** {}", 
-                    api_url, config.base_url, e
-                ).into());
-            } else if error_msg.contains("timeout") || error_msg.contains("timed out") {
-                return Err(format!(
-                    "Request Timeout: The AI server at {} took too long to respond.\n\n**Check:**\n\
-                    1. AI server is not overloaded\n\
-                    2. Model is loaded and ready\n\
-                    3. Server has enough resources\n\n\
-                    **Original error:** {}", 
-                    config.base_url, e
-                ).into());
-            } else {
-                return Err(format!("Streaming API request to {} failed: {}", api_url, e).into());
-            }
+            return Err(e.into());
         }
     };
 
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_text = response.text().await.unwrap_or_else(|_| "Unable to read error response".to_string());
-        println!("[DEBUG][REASONING] API returned error status {}: {}", status, error_text);
-        return Err(format!("Streaming API request failed: HTTP {} - {}", status, error_text).into());
-    }
-
-    println!("[DEBUG][REASONING] === BUFFERING COMPLETE RESPONSE ===");
+    println!("[DEBUG][REASONING] === STREAMING RESPONSE WITH LIVE EDITS ===");
     let mut stream = response.bytes_stream();
-    
+
     let mut raw_response = String::new();
     let mut chunk_count = 0;
     let mut line_buffer = String::new();
     let mut received_any_content = false;
     let mut stream_complete = false;
+    let mut finish_reason: Option<String> = None;
     let mut last_chunk_time = std::time::Instant::now();
-    let timeout_duration = std::time::Duration::from_secs(300); // 5 minute timeout for streaming
+    let timeout_duration = std::time::Duration::from_secs(config.timeout); // Inactivity timeout for streaming
+
+    // Live edit state - the placeholder message is edited at most every 750ms, backing off on rate limits
+    let mut live_throttle = EditThrottle::new(std::time::Duration::from_millis(750), "REASONING");
+    let live_char_limit = config.max_discord_message_length - config.response_format_padding;
+    let mut last_live_len = 0;
 
-    println!("[DEBUG][REASONING] Starting to buffer response from API...");
+    println!("[DEBUG][REASONING] Starting to stream response from API...");
 
-    // STEP 1: Buffer the complete response from the API
-    while let Ok(Some(Ok(chunk))) = tokio::time::timeout(timeout_duration, stream.next()).await {
+    // STEP 1: Collect the complete response from the API, editing the placeholder live as it grows
+    let mut stream_error = None;
+    loop {
+        let chunk = match tokio::time::timeout(timeout_duration, stream.next()).await {
+            Ok(Some(Ok(chunk))) => chunk,
+            Ok(Some(Err(e))) => {
+                stream_error = Some(e);
+                break;
+            }
+            _ => break,
+        };
         last_chunk_time = std::time::Instant::now(); // Reset timeout on successful chunk
         chunk_count += 1;
         if chunk_count == 1 {
@@ -953,8 +1238,9 @@ async fn stream_reasoning_response(
 
                 if let Ok(response_chunk) = serde_json::from_str::<ChatResponse>(json_str) {
                     for choice in response_chunk.choices {
-                        if let Some(finish_reason) = choice.finish_reason {
-                            if finish_reason == "stop" {
+                        if let Some(reason) = choice.finish_reason {
+                            finish_reason = Some(reason.clone());
+                            if reason == "stop" {
                                 println!("[DEBUG][REASONING] Received finish_reason=stop, marking stream complete");
                                 stream_complete = true;
                                 break;
@@ -981,68 +1267,104 @@ async fn stream_reasoning_response(
                 }
             }
         }
-        
+
         // Break out of the outer loop if stream is complete
         if stream_complete {
             println!("[DEBUG][REASONING] Breaking out of chunk processing loop");
             break;
         }
-    }
 
-    // Handle timeout or stream end
-    match tokio::time::timeout(timeout_duration, stream.next()).await {
-        Ok(Some(Ok(_))) => {
-            // This shouldn't happen since we already handled it in the loop
-            println!("[DEBUG][REASONING] Unexpected chunk after loop");
+        // Throttled live update of the placeholder message
+        if live_throttle.ready() {
+            let live_content = crate::commands::util::strip_think_blocks_live(&raw_response);
+            // Filtering can shrink the visible text while a <think> block is open,
+            // so only edit when it has grown to keep the message from flickering backwards
+            if live_content.len() > last_live_len {
+                let preview = close_open_code_fence(tail_chars(&live_content, live_char_limit.saturating_sub(60)));
+                let live_display = format!("**Reasoning Analysis (streaming...):**\n```\n{}\n```", preview);
+                let result = initial_msg.edit(&ctx.http, |m| m.content(&live_display)).await;
+                live_throttle.record(&result);
+                last_live_len = live_content.len();
+                if let Err(e) = write_to_response_file(response_file.as_deref_mut(), &format!("🔄 Streaming... {} chars so far", live_content.len())) {
+                    eprintln!("[DEBUG][REASONING] Failed to write progress to response file: {}", e);
+                }
+            } else {
+                live_throttle.defer();
+            }
         }
-        Ok(Some(Err(e))) => {
-            eprintln!("[DEBUG][REASONING] Stream error: {}", e);
+    }
+
+    // Handle a dropped connection, timeout or stream end
+    if let Some(e) = stream_error {
+        eprintln!("[DEBUG][REASONING] Stream error: {}", e);
+        if !(config.stream_auto_continue && received_any_content) {
             return Err(e.into());
         }
-        Ok(None) => {
-            println!("[DEBUG][REASONING] Stream ended normally (no more chunks)");
-        }
-        Err(_) => {
-            println!("[DEBUG][REASONING] Stream timeout after {} seconds of inactivity", timeout_duration.as_secs());
-            if !received_any_content {
-                return Err("Streaming timeout - no content received from AI server".into());
-            } else {
-                println!("[DEBUG][REASONING] Stream timed out but we received some content, continuing with what we have");
+        // Resume from the partial output rather than discarding it
+        let continuation = crate::commands::search::continue_interrupted_stream(
+            config,
+            &chat_request.model,
+            &chat_request.messages,
+            &raw_response,
+        ).await?;
+        raw_response.push_str(&continuation);
+        // The stitched continuation doesn't report how it ended
+        finish_reason = None;
+    } else {
+        match tokio::time::timeout(timeout_duration, stream.next()).await {
+            Ok(Some(Ok(_))) => {
+                // This shouldn't happen since we already handled it in the loop
+                println!("[DEBUG][REASONING] Unexpected chunk after loop");
+            }
+            Ok(Some(Err(e))) => {
+                eprintln!("[DEBUG][REASONING] Stream error: {}", e);
+                return Err(e.into());
+            }
+            Ok(None) => {
+                println!("[DEBUG][REASONING] Stream ended normally (no more chunks)");
+            }
+            Err(_) => {
+                println!("[DEBUG][REASONING] Stream timeout after {} seconds of inactivity", timeout_duration.as_secs());
+                if !received_any_content {
+                    return Err("Streaming timeout - no content received from AI server".into());
+                } else {
+                    println!("[DEBUG][REASONING] Stream timed out but we received some content, continuing with what we have");
+                }
             }
         }
     }
 
     println!("[DEBUG][REASONING] === BUFFERING COMPLETE ===");
+    // Not every backend honors `stop` (continuations never send it), so enforce it here too
+    if let Some(truncated) = crate::commands::util::truncate_at_stop_sequence(&raw_response, &chat_request.stop) {
+        println!("[DEBUG][REASONING] Response ran past a stop sequence, truncating {} -> {} chars", raw_response.len(), truncated.len());
+        raw_response = truncated;
+        finish_reason = Some("stop".to_string());
+    }
     println!("[DEBUG][REASONING] Buffered {} chunks, total response: {} chars", chunk_count, raw_response.len());
     println!("[DEBUG][REASONING] Raw response content: '{}'", raw_response);
     
-    if !received_any_content {
-        eprintln!("[DEBUG][REASONING] No content received from API stream");
-        return Err("No content received from API stream".into());
-    }
-    
-    // Check for zero character response
-    if raw_response.is_empty() {
-        eprintln!("[DEBUG][REASONING] ERROR: API returned 0 characters in response");
-        eprintln!("[DEBUG][REASONING] Chunk count: {}", chunk_count);
-        eprintln!("[DEBUG][REASONING] Received any content flag: {}", received_any_content);
-        return Err("API returned 0 characters in response - this indicates a problem with the API or model".into());
-    }
-
-    // Safety check: ensure we have some content to process
-    if raw_response.trim().is_empty() {
-        eprintln!("[DEBUG][REASONING] ERROR: API returned only whitespace");
-        let _ = initial_msg.edit(&ctx.http, |m| {
-            m.content("**Error:** AI server returned empty response. Please try again.")
-        }).await;
-        return Err("API returned only whitespace".into());
+    // An empty or whitespace-only completion is handed back empty; stream_reasoning_response retries it once
+    if !received_any_content || is_empty_response(&raw_response) {
+        eprintln!("[DEBUG][REASONING] API returned no content ({} chunks, finish_reason: {:?})", chunk_count, finish_reason);
+        let stats = StreamingStats {
+            total_characters: raw_response.len(),
+            message_count: 0,
+            filtered_characters: raw_response.len(),
+            finish_reason,
+        };
+        return Ok((stats, String::new()));
     }
 
     // STEP 2: Process the buffered content and stream to Discord
     println!("[DEBUG][REASONING] === PROCESSING AND STREAMING TO DISCORD ===");
     
-    // Apply thinking tag filtering to the complete response
-    let filtered_response = filter_thinking_tags(&raw_response);
+    // Apply thinking tag filtering to the complete response (--format answer strips every form of reasoning)
+    let filtered_response = if format == Some(ReasonFormat::Answer) {
+        strip_all_reasoning(&raw_response)
+    } else {
+        filter_thinking_tags(&raw_response)
+    };
     println!("[DEBUG][REASONING] Filtered response length: {} chars", filtered_response.len());
     println!("[DEBUG][REASONING] Filtered response content: '{}'", filtered_response);
     
@@ -1051,13 +1373,16 @@ async fn stream_reasoning_response(
     println!("[DEBUG][REASONING] Processed response length: {} chars", processed_response.len());
     println!("[DEBUG][REASONING] Processed response content: '{}'", processed_response);
     
-    // Safety check: ensure processed response is not empty
-    if processed_response.trim().is_empty() {
-        eprintln!("[DEBUG][REASONING] ERROR: Processed response is empty after filtering");
-        let _ = initial_msg.edit(&ctx.http, |m| {
-            m.content("**Error:** AI response was filtered out completely. Please try again.")
-        }).await;
-        return Err("Processed response is empty after filtering".into());
+    // Only reasoning came back (e.g. a lone <think> block): also handed back empty for the retry
+    if is_empty_response(&processed_response) {
+        eprintln!("[DEBUG][REASONING] Processed response is empty after filtering {} raw chars", raw_response.len());
+        let stats = StreamingStats {
+            total_characters: raw_response.len(),
+            message_count: 0,
+            filtered_characters: raw_response.len(),
+            finish_reason,
+        };
+        return Ok((stats, String::new()));
     }
     
     if processed_response.is_empty() {
@@ -1070,21 +1395,40 @@ async fn stream_reasoning_response(
             total_characters: raw_response.len(),
             message_count: 1,
             filtered_characters: raw_response.len() - filtered_response.len(),
+            finish_reason,
         };
         return Ok((stats, processed_response));
     }
 
-    // Split content into Discord-friendly chunks
-    let chunks = split_message(&processed_response, config.max_discord_message_length - config.response_format_padding);
+    // --stream-file: the answer goes to the file and the caller uploads it, so nothing is split
+    if let Some(file) = response_file {
+        write_to_response_file(Some(&mut *file), "=== FINAL RESULT ===")?;
+        write_to_response_file(Some(file), &processed_response)?;
+        let _ = initial_msg.edit(&ctx.http, |m| {
+            m.content(crate::commands::util::with_response_footer(&format!("**Reasoning Analysis:** complete ({} chars) - full response attached below", processed_response.chars().count())))
+        }).await;
+        let stats = StreamingStats {
+            total_characters: raw_response.len(),
+            message_count: 1,
+            filtered_characters: raw_response.len() - filtered_response.len(),
+            finish_reason,
+        };
+        return Ok((stats, processed_response));
+    }
+
+    // Split content into Discord-friendly chunks, leaving room for RESPONSE_FOOTER on the last one
+    let chunk_limit = (config.max_discord_message_length - config.response_format_padding)
+        .saturating_sub(crate::commands::util::response_footer_len());
+    let chunks = split_message(&processed_response, chunk_limit);
     println!("[DEBUG][REASONING] Split response into {} chunks", chunks.len());
     
     // Handle multiple messages if content is too long
     if chunks.len() == 1 {
         // Single message - update the initial message
-        let formatted_content = format!(
+        let formatted_content = crate::commands::util::with_response_footer(&format!(
             "**Reasoning Analysis:**\n```\n{}\n```",
             chunks[0]
-        );
+        ));
         
         initial_msg.edit(&ctx.http, |m| {
             m.content(&formatted_content)
@@ -1097,6 +1441,11 @@ async fn stream_reasoning_response(
             } else {
                 format!("**Reasoning Analysis (Part {}/{})**\n```\n{}\n```", i + 1, chunks.len(), chunk)
             };
+            let formatted_content = if i + 1 == chunks.len() {
+                crate::commands::util::with_response_footer(&formatted_content)
+            } else {
+                formatted_content
+            };
             
             if i == 0 {
                 // Update the first message
@@ -1116,6 +1465,7 @@ async fn stream_reasoning_response(
         total_characters: raw_response.len(),
         message_count: chunks.len(),
         filtered_characters: raw_response.len() - filtered_response.len(),
+        finish_reason,
     };
 
     // Safety check: ensure stats are valid
@@ -1397,27 +1747,22 @@ async fn stream_reasoning_search_response(
     initial_msg: &mut Message,
 ) -> Result<StreamingStats, Box<dyn std::error::Error + Send + Sync>> {
     let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(300)) // 5 minutes for reasoning search operations
+        .timeout(std::time::Duration::from_secs(config.timeout))
         .build()?;
         
+    let sampling = config.sampling_for(model);
     let chat_request = ChatRequest {
         model: model.to_string(),
         messages,
-        temperature: config.default_temperature,
-        max_tokens: config.default_max_tokens,
+        temperature: sampling.temperature,
+        max_tokens: sampling.max_tokens,
         stream: true,
-        seed: config.default_seed,
+        seed: sampling.seed,
+        stop: Vec::new(),
     };
 
-    let response = client
-        .post(&format!("{}/v1/chat/completions", config.base_url))
-        .json(&chat_request)
-        .send()
-        .await?;
-
-    if !response.status().is_success() {
-        return Err(format!("API request failed: HTTP {}", response.status()).into());
-    }
+    let api_url = format!("{}/v1/chat/completions", config.base_url);
+    let response = send_chat_request(&client, &api_url, &chat_request, model, config.timeout, config.max_retries).await?;
 
     let mut stream = response.bytes_stream();
     let mut raw_response = String::new();
@@ -1425,7 +1770,7 @@ async fn stream_reasoning_search_response(
     let mut message_count = 1;
     let mut current_message = initial_msg.clone();
     let char_limit = config.max_discord_message_length - config.response_format_padding;
-    let timeout_duration = std::time::Duration::from_secs(300); // 5 minute timeout
+    let timeout_duration = std::time::Duration::from_secs(config.timeout);
 
     println!("Starting streaming for reasoning search response (buffered chunks)...");
 
@@ -1450,6 +1795,7 @@ async fn stream_reasoning_search_response(
                                         total_characters: raw_response.len(),
                                         message_count,
                                         filtered_characters: raw_response.len() - filtered_buffer.len(),
+                                        finish_reason: None,
                                     };
                                     
                                     return Ok(stats);
@@ -1470,6 +1816,7 @@ async fn stream_reasoning_search_response(
                                                     total_characters: raw_response.len(),
                                                     message_count,
                                                     filtered_characters: raw_response.len() - filtered_buffer.len(),
+                                                    finish_reason: None,
                                                 };
                                                 
                                                 return Ok(stats);
@@ -1516,7 +1863,7 @@ async fn stream_reasoning_search_response(
             }
             Err(_) => {
                 // Timeout occurred
-                eprintln!("Streaming timeout after 300 seconds of inactivity");
+                eprintln!("Streaming timeout after {} seconds of inactivity", timeout_duration.as_secs());
                 break;
             }
         }
@@ -1541,6 +1888,7 @@ async fn stream_reasoning_search_response(
         total_characters: raw_response.len(),
         message_count,
         filtered_characters: raw_response.len() - filtered_buffer.len(),
+        finish_reason: None,
     };
 
     Ok(stats)
@@ -1633,7 +1981,7 @@ async fn load_reasoning_search_analysis_prompt() -> Result<String, Box<dyn std::
                 }
                 
                 println!("[SUCCESS] Reasoning analysis: Loaded prompt from {} ({} chars)", path, trimmed_content.len());
-                println!("[DEBUG] Prompt preview: {}", &trimmed_content[..std::cmp::min(200, trimmed_content.len())]);
+                println!("[DEBUG] Prompt preview: {}", truncate_chars(&trimmed_content, 200));
                 return Ok(trimmed_content.to_string());
             }
             Err(e) => {
@@ -1659,27 +2007,22 @@ async fn chat_completion_reasoning(
     max_tokens: Option<i32>,
 ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
     let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(300)) // 5 minutes for reasoning completion operations
+        .timeout(std::time::Duration::from_secs(config.timeout))
         .build()?;
         
+    let sampling = config.sampling_for(model);
     let chat_request = ChatRequest {
         model: model.to_string(),
         messages,
         temperature: 0.5, // Slightly higher temperature for reasoning tasks
-        max_tokens: max_tokens.unwrap_or(config.default_max_tokens),
+        max_tokens: max_tokens.unwrap_or(sampling.max_tokens),
         stream: false,
-        seed: config.default_seed,
+        seed: sampling.seed,
+        stop: Vec::new(),
     };
 
-    let response = client
-        .post(&format!("{}/v1/chat/completions", config.base_url))
-        .json(&chat_request)
-        .send()
-        .await?;
-
-    if !response.status().is_success() {
-        return Err(format!("API request failed: HTTP {}", response.status()).into());
-    }
+    let api_url = format!("{}/v1/chat/completions", config.base_url);
+    let response = send_chat_request(&client, &api_url, &chat_request, model, config.timeout, config.max_retries).await?;
 
     // Parse non-streaming response
     let response_text = response.text().await?;
@@ -1699,34 +2042,208 @@ async fn chat_completion_reasoning(
     Err("Failed to extract content from reasoning API response".into())
 } 
 
-/// Split message content into Discord-friendly chunks
-fn split_message(content: &str, max_len: usize) -> Vec<String> {
-    let lines: Vec<&str> = content.lines().collect();
-    let mut chunks = Vec::new();
-    let mut current_chunk = String::new();
-    
-    for line in lines {
-        let potential_chunk = if current_chunk.is_empty() {
-            line.to_string()
-        } else {
-            format!("{}\n{}", current_chunk, line)
-        };
-        
-        if potential_chunk.len() <= max_len {
-            current_chunk = potential_chunk;
-        } else {
-            if !current_chunk.is_empty() {
-                chunks.push(current_chunk.clone());
-            }
-            current_chunk = line.to_string();
+// Caps on the work done by ^reason --compare
+const COMPARE_MAX_TOKENS: i32 = 4096;
+const COMPARE_JUDGE_ANSWER_CHARS: usize = 6000;
+
+// Reachability of a cited link, from a HEAD request through the screened citation client
+#[derive(Debug, PartialEq)]
+enum CitationStatus {
+    Live(u16),
+    Unreachable(String),
+    // Refused by the SSRF screen (internal address or outside SUM_ALLOWED_DOMAINS), so never requested
+    Skipped(String),
+}
+
+// Maximum redirects followed while checking a cited link
+const MAX_CITATION_REDIRECTS: usize = 5;
+
+// Client for citation checks: model-emitted URLs are untrusted, so every redirect hop and DNS answer is screened
+// like a ^sum fetch (the shared LM client talks to the local backend and cannot refuse private hosts)
+static CITATION_CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .redirect(crate::commands::util::screened_redirect_policy(MAX_CITATION_REDIRECTS))
+        .dns_resolver(std::sync::Arc::new(crate::commands::util::ScreenedResolver))
+        .user_agent("Meri-Bot-Rust-Client/1.0")
+        .build()
+        .expect("Failed to create citation HTTP client")
+});
+
+// Collect the distinct URLs cited in a response, in order of first appearance
+fn extract_cited_urls(content: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    for found in CITED_URL_REGEX.find_iter(&filter_thinking_tags(content)) {
+        let url = found.as_str().trim_end_matches(|c: char| matches!(c, '.' | ',' | ';' | ':' | '!' | '?' | '*' | '_'));
+        if !urls.iter().any(|existing| existing == url) {
+            urls.push(url.to_string());
         }
     }
-    
-    if !current_chunk.is_empty() {
-        chunks.push(current_chunk);
+    urls
+}
+
+// HEAD the URL, falling back to GET for servers that reject HEAD
+// Internal targets are refused up front so the check can't be used to probe the bot's network
+async fn check_citation(client: &reqwest::Client, url: &str) -> CitationStatus {
+    match crate::commands::util::validate_fetch_url(url).await {
+        Ok(()) => {}
+        Err(UrlScreenError::Refused(reason)) => return CitationStatus::Skipped(reason),
+        Err(UrlScreenError::Unresolved(_)) => return CitationStatus::Unreachable("could not resolve".to_string()),
+    }
+    let timeout = std::time::Duration::from_secs(CITATION_CHECK_TIMEOUT_SECS);
+    let mut result = client.head(url).timeout(timeout).send().await;
+    if matches!(&result, Ok(response) if response.status() == reqwest::StatusCode::METHOD_NOT_ALLOWED) {
+        result = client.get(url).timeout(timeout).send().await;
+    }
+    match result {
+        Ok(response) if response.status().is_success() || response.status().is_redirection() => {
+            CitationStatus::Live(response.status().as_u16())
+        }
+        Ok(response) => CitationStatus::Unreachable(format!("HTTP {}", response.status().as_u16())),
+        Err(e) if e.is_redirect() => CitationStatus::Skipped("redirected to a blocked address".to_string()),
+        Err(e) if e.is_timeout() => CitationStatus::Unreachable("timed out".to_string()),
+        Err(e) if e.is_connect() => CitationStatus::Unreachable("could not connect".to_string()),
+        Err(_) => CitationStatus::Unreachable("request failed".to_string()),
     }
-    
-    chunks
+}
+
+// Build the References message, marking each cited link live or unreachable
+fn format_references(checked: &[(String, CitationStatus)], unchecked: usize) -> String {
+    if checked.is_empty() {
+        return "📚 **References**\nNo URLs were cited in this response.".to_string();
+    }
+    let mut references = String::from("📚 **References** (✅ reachable, ❌ unreachable - possibly fabricated)\n");
+    for (index, (url, status)) in checked.iter().enumerate() {
+        match status {
+            CitationStatus::Live(_) => references.push_str(&format!("{}. ✅ <{}>\n", index + 1, url)),
+            CitationStatus::Unreachable(reason) => references.push_str(&format!("{}. ❌ <{}> ({})\n", index + 1, url, reason)),
+            CitationStatus::Skipped(reason) => references.push_str(&format!("{}. ⚠️ <{}> (not checked: {})\n", index + 1, url, reason)),
+        }
+    }
+    if unchecked > 0 {
+        references.push_str(&format!("*…and {} more link(s) not checked*\n", unchecked));
+    }
+    truncate_chars(&references, 1990).to_string()
+}
+
+// Check every cited URL concurrently and format the References message
+async fn check_citations(content: &str) -> String {
+    let urls = extract_cited_urls(content);
+    let unchecked = urls.len().saturating_sub(MAX_CHECKED_CITATIONS);
+    let statuses = futures_util::future::join_all(
+        urls.iter().take(MAX_CHECKED_CITATIONS).map(|url| check_citation(&CITATION_CLIENT, url)),
+    ).await;
+    println!("[REASON] Checked {} cited URL(s), {} live", statuses.len(),
+        statuses.iter().filter(|status| matches!(status, CitationStatus::Live(_))).count());
+    let checked: Vec<(String, CitationStatus)> = urls.into_iter().zip(statuses).collect();
+    format_references(&checked, unchecked)
+}
+
+/// Split `<modelA> <modelB> <question>` from the arguments of ^reason --compare
+fn parse_compare_args(input: &str) -> Option<(&str, &str, &str)> {
+    let input = input.trim_start();
+    let split_word = |text: &str| -> Option<(&str, &str)> {
+        let end = text.find(char::is_whitespace)?;
+        Some((&text[..end], text[end..].trim_start()))
+    };
+    let (model_a, rest) = split_word(input)?;
+    let (model_b, question) = split_word(rest)?;
+    if question.trim().is_empty() {
+        return None;
+    }
+    Some((model_a, model_b, question.trim()))
+}
+
+/// Run the same question against two models and post both answers
+/// With `judge` set, the default reasoning model is asked which answer is better
+/// Comparisons are one-off and are not recorded in the user's reasoning context
+async fn compare_models(
+    ctx: &Context,
+    msg: &Message,
+    config: &LMConfig,
+    model_a: &str,
+    model_b: &str,
+    question: &str,
+    judge: bool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    println!("[REASON] Comparing models '{}' and '{}' for user {} (judge: {})", model_a, model_b, msg.author.name, judge);
+
+    let status_msg = msg.channel_id.send_message(&ctx.http, |m| {
+        m.content(format!("⚖️ Asking `{}` and `{}`...", model_a, model_b))
+    }).await?;
+
+    let system_prompt = load_reasoning_system_prompt().await.unwrap_or_else(|_| {
+        "You are an advanced AI reasoning assistant. Think step-by-step and give a clear final answer.".to_string()
+    });
+    let messages = vec![
+        ChatMessage { role: "system".to_string(), content: system_prompt },
+        ChatMessage { role: "user".to_string(), content: question.to_string() },
+    ];
+    let max_tokens = Some(config.default_max_tokens.min(COMPARE_MAX_TOKENS));
+
+    let (answer_a, answer_b) = tokio::join!(
+        chat_completion_reasoning(messages.clone(), model_a, config, max_tokens),
+        chat_completion_reasoning(messages, model_b, config, max_tokens),
+    );
+    let answer_a = answer_a.map(|answer| process_reasoning_content(&answer))
+        .unwrap_or_else(|e| format!("❌ `{}` failed: {}", model_a, e));
+    let answer_b = answer_b.map(|answer| process_reasoning_content(&answer))
+        .unwrap_or_else(|e| format!("❌ `{}` failed: {}", model_b, e));
+
+    // Same per-message limit as the single-model path, so both answers leave room for formatting
+    let char_limit = config.max_discord_message_length - config.response_format_padding;
+    let _ = status_msg.delete(&ctx.http).await;
+    for (icon, label, model, answer) in [("🅰️", "a", model_a, &answer_a), ("🅱️", "b", model_b, &answer_b)] {
+        let content = format!("**{} Answer — `{}`**\n\n{}", icon, model, answer);
+        // The footer goes on the last message of the comparison: answer B, or the verdict below
+        let content = if label == "b" && !judge { crate::commands::util::with_response_footer(&content) } else { content };
+        crate::commands::util::send_long_message(ctx, msg.channel_id, &content, char_limit, &format!("answer_{}.md", label)).await?;
+    }
+
+    if judge {
+        let judge_prompt = format!(
+            "Question:\n{}\n\nAnswer A (from {}):\n{}\n\nAnswer B (from {}):\n{}\n\n\
+            Compare the two answers for correctness, reasoning quality and clarity. \
+            State which answer is better (A, B or tie) on the first line, then justify briefly.",
+            question,
+            model_a, truncate_chars(&answer_a, COMPARE_JUDGE_ANSWER_CHARS),
+            model_b, truncate_chars(&answer_b, COMPARE_JUDGE_ANSWER_CHARS),
+        );
+        let judge_messages = vec![
+            ChatMessage { role: "system".to_string(), content: "You are an impartial judge evaluating two AI answers to the same question.".to_string() },
+            ChatMessage { role: "user".to_string(), content: judge_prompt },
+        ];
+        let verdict = match chat_completion_reasoning(judge_messages, &config.default_reason_model, config, Some(1024)).await {
+            Ok(verdict) => process_reasoning_content(&verdict),
+            Err(e) => format!("❌ Judge failed: {}", e),
+        };
+        let content = crate::commands::util::with_response_footer(&format!("**⚖️ Verdict — `{}`**\n\n{}", config.default_reason_model, verdict));
+        crate::commands::util::send_long_message(ctx, msg.channel_id, &content, char_limit, "verdict.md").await?;
+    }
+
+    Ok(())
+}
+
+// Upload the --stream-file response, falling back to split messages if the upload is rejected
+async fn upload_response_file(ctx: &Context, msg: &Message, guard: &TempFileGuard, response: &str, config: &LMConfig) {
+    let file_name = guard.path().file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_else(|| "reason_response.txt".to_string());
+    let upload = match std::fs::read(guard.path()) {
+        Ok(bytes) => msg.channel_id.send_files(&ctx.http, vec![(bytes.as_slice(), file_name.as_str())], |m| {
+            m.content("📎 **Reasoning Analysis** - full response attached").reference_message(msg)
+        }).await.map(|_| ()).map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    if let Err(e) = upload {
+        eprintln!("[REASON] Failed to upload response file, sending inline instead: {}", e);
+        if let Err(e) = crate::commands::util::send_long_message(ctx, msg.channel_id, response, config.max_discord_message_length, &file_name).await {
+            eprintln!("[REASON] Inline fallback also failed: {}", e);
+        }
+    }
+}
+
+/// Split message content into Discord-friendly chunks
+/// Delegates to the shared splitter so an over-long line can never produce an over-limit message
+fn split_message(content: &str, max_len: usize) -> Vec<String> {
+    crate::commands::util::split_for_discord(content, max_len)
 }
 
 // ============================================================================
@@ -1739,6 +2256,39 @@ fn split_message(content: &str, max_len: usize) -> Vec<String> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_compare_args() {
+        assert_eq!(
+            parse_compare_args("qwen3-4b  llama3:8b What is 2+2?\nShow work."),
+            Some(("qwen3-4b", "llama3:8b", "What is 2+2?\nShow work."))
+        );
+        assert_eq!(parse_compare_args("qwen3-4b llama3:8b"), None);
+        assert_eq!(parse_compare_args("qwen3-4b llama3:8b   "), None);
+        assert_eq!(parse_compare_args(""), None);
+    }
+
+    #[test]
+    fn test_extract_cited_urls() {
+        let content = "<think>see https://hidden.example</think>Per [1] (https://example.com/a). Also https://example.com/a, and <https://docs.rs/regex>.";
+        assert_eq!(extract_cited_urls(content), vec!["https://example.com/a", "https://docs.rs/regex"]);
+        assert!(extract_cited_urls("No sources here.").is_empty());
+    }
+
+    #[test]
+    fn test_format_references() {
+        let checked = vec![
+            ("https://example.com".to_string(), CitationStatus::Live(200)),
+            ("https://made-up.invalid/paper".to_string(), CitationStatus::Unreachable("could not connect".to_string())),
+            ("http://169.254.169.254/".to_string(), CitationStatus::Skipped("private or local address".to_string())),
+        ];
+        let references = format_references(&checked, 2);
+        assert!(references.contains("1. ✅ <https://example.com>"));
+        assert!(references.contains("2. ❌ <https://made-up.invalid/paper> (could not connect)"));
+        assert!(references.contains("3. ⚠️ <http://169.254.169.254/> (not checked: private or local address)"));
+        assert!(references.contains("2 more link(s) not checked"));
+        assert!(format_references(&[], 0).contains("No URLs were cited"));
+    }
+
     #[test]
     fn test_split_message_short_content() {
         let short_content = "This is a short reasoning response that should fit in one chunk.";
@@ -1761,9 +2311,10 @@ mod tests {
 
     #[test]
     fn test_split_message_single_long_line() {
-        let single_long_line = "This is a very long line of reasoning that should not be split because it exceeds the maximum length but we want to keep it as one chunk for testing purposes.";
+        let single_long_line = "This is a very long line of reasoning that exceeds the maximum length, so it must be hard-split to stay within the Discord limit.";
         let chunks = split_message(single_long_line, 50);
-        assert_eq!(chunks.len(), 1, "Single long line should remain as one chunk");
+        assert!(chunks.len() > 1, "Single long line should be split to fit the limit");
+        assert!(chunks.iter().all(|chunk| chunk.len() <= 50));
     }
 
     #[test]
@@ -1774,12 +2325,23 @@ mod tests {
 
     #[test]
     fn test_split_message_exact_limit() {
-        let content = "This is exactly 25 characters";
+        let content = "This is exactly 25 chars!";
         let chunks = split_message(content, 25);
         assert_eq!(chunks.len(), 1);
         assert_eq!(chunks[0], content);
     }
 
+    #[test]
+    fn test_format_answer_strips_all_reasoning() {
+        assert_eq!(strip_all_reasoning("<thinking>weigh options</thinking>Use a BTreeMap."), "Use a BTreeMap.");
+        assert_eq!(strip_all_reasoning("First I consider X.</think>\n\nThe answer is 42."), "The answer is 42.");
+        assert_eq!(strip_all_reasoning("The answer is 42. <think>but maybe"), "The answer is 42.");
+        assert_eq!(strip_all_reasoning("1. Add the numbers\n2. Check\n\n**Final Answer:** 7"), "7");
+        assert_eq!(strip_all_reasoning("Steps first.\n## Final Answer\nParis"), "Paris");
+        assert_eq!(ReasonFormat::parse("Steps"), Some(ReasonFormat::Steps));
+        assert_eq!(ReasonFormat::parse("verbose"), None);
+    }
+
     #[test]
     fn test_filter_thinking_tags() {
         let content_with_tags = "Here is some content <think>This is internal thinking</think> and more content <think>More thinking</think>.";
@@ -1815,7 +2377,7 @@ mod tests {
                 assert!(prompt.len() > 100, "System prompt should be substantial (got {} chars)", prompt.len());
                 
                 println!("[TEST] Successfully loaded reasoning system prompt ({} chars)", prompt.len());
-                println!("[TEST] Prompt preview: {}", &prompt[..std::cmp::min(200, prompt.len())]);
+                println!("[TEST] Prompt preview: {}", truncate_chars(&prompt, 200));
             }
             Err(e) => {
                 panic!("Failed to load reasoning system prompt: {}", e);
@@ -3,11 +3,11 @@
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::fs;
 use std::time::Duration;
 use tokio::sync::OnceCell;
 
 use log::warn;
+use crate::commands::error::BotError;
 
 // Global HTTP client for connection pooling and reuse
 static HTTP_CLIENT: OnceCell<reqwest::Client> = OnceCell::const_new();
@@ -53,6 +53,498 @@ pub struct LMConfig {
     pub response_format_padding: usize,
     pub default_vision_model: String,
     pub default_seed: Option<i64>, // Optional seed for reproducible responses
+    pub vision_max_images: usize,      // Max image attachments processed per vision request
+    pub vision_max_total_bytes: u64,   // Max combined attachment size per vision request
+    pub vision_max_dimension: u32,     // Longest image side sent to the vision model; larger images are downscaled
+    pub vision_max_image_bytes: usize, // Max base64 size of one encoded image; larger images are downscaled
+    pub default_tone: Option<String>,  // Optional house style preset for ^lm (see TONE_PRESETS)
+    pub stream_auto_continue: bool,    // Re-request and stitch a response when the stream drops mid-way
+    pub stream_max_continues: u32,     // Max continuation attempts per interrupted response
+    pub context_compaction: bool,      // Summarize the oldest context messages instead of dropping them
+    pub context_compaction_threshold: usize, // Total stored messages that triggers compaction
+    pub max_retries: u32,              // Attempts per LM request before giving up (LM_MAX_RETRIES)
+    pub model_profiles: HashMap<String, ModelProfile>, // Per-model sampling overrides (MODEL_PROFILES)
+    pub seed_override: Option<i64>,    // Per-request --seed; never read from the file
+}
+
+impl SamplingConfig for LMConfig {
+    fn sampling_defaults(&self) -> SamplingParams {
+        SamplingParams {
+            temperature: self.default_temperature,
+            max_tokens: self.default_max_tokens,
+            seed: self.default_seed,
+        }
+    }
+
+    fn model_profiles(&self) -> &HashMap<String, ModelProfile> {
+        &self.model_profiles
+    }
+
+    fn seed_override(&self) -> Option<i64> {
+        self.seed_override
+    }
+}
+
+// Defaults for the optional vision attachment limits
+pub const DEFAULT_VISION_MAX_IMAGES: usize = 4;
+pub const DEFAULT_VISION_MAX_TOTAL_BYTES: u64 = 20 * 1024 * 1024;
+pub const DEFAULT_VISION_MAX_DIMENSION: u32 = 2048;
+pub const DEFAULT_VISION_MAX_IMAGE_BYTES: usize = 4 * 1024 * 1024;
+
+// Parses the optional VISION_MAX_IMAGES and VISION_MAX_TOTAL_BYTES keys
+pub fn parse_vision_limits(
+    config_map: &HashMap<String, String>,
+) -> Result<(usize, u64), Box<dyn std::error::Error + Send + Sync>> {
+    let max_images = match config_map.get("VISION_MAX_IMAGES").filter(|s| !s.trim().is_empty()) {
+        Some(value) => value.trim().parse::<usize>()
+            .map_err(|_| "VISION_MAX_IMAGES must be a valid number")?,
+        None => DEFAULT_VISION_MAX_IMAGES,
+    };
+    let max_total_bytes = match config_map.get("VISION_MAX_TOTAL_BYTES").filter(|s| !s.trim().is_empty()) {
+        Some(value) => value.trim().parse::<u64>()
+            .map_err(|_| "VISION_MAX_TOTAL_BYTES must be a valid number (bytes)")?,
+        None => DEFAULT_VISION_MAX_TOTAL_BYTES,
+    };
+
+    if max_images == 0 || max_total_bytes == 0 {
+        return Err("❌ VISION_MAX_IMAGES and VISION_MAX_TOTAL_BYTES must be greater than 0".into());
+    }
+
+    Ok((max_images, max_total_bytes))
+}
+
+// Parses the optional VISION_MAX_DIMENSION and VISION_MAX_IMAGE_BYTES keys (downscaling thresholds)
+pub fn parse_vision_resize_limits(
+    config_map: &HashMap<String, String>,
+) -> Result<(u32, usize), Box<dyn std::error::Error + Send + Sync>> {
+    let max_dimension = match config_map.get("VISION_MAX_DIMENSION").filter(|s| !s.trim().is_empty()) {
+        Some(value) => value.trim().parse::<u32>()
+            .map_err(|_| "VISION_MAX_DIMENSION must be a valid number (pixels)")?,
+        None => DEFAULT_VISION_MAX_DIMENSION,
+    };
+    let max_image_bytes = match config_map.get("VISION_MAX_IMAGE_BYTES").filter(|s| !s.trim().is_empty()) {
+        Some(value) => value.trim().parse::<usize>()
+            .map_err(|_| "VISION_MAX_IMAGE_BYTES must be a valid number (bytes)")?,
+        None => DEFAULT_VISION_MAX_IMAGE_BYTES,
+    };
+
+    if max_dimension == 0 || max_image_bytes == 0 {
+        return Err("❌ VISION_MAX_DIMENSION and VISION_MAX_IMAGE_BYTES must be greater than 0".into());
+    }
+
+    Ok((max_dimension, max_image_bytes))
+}
+
+// Defaults for the optional stream auto-continue settings
+pub const DEFAULT_STREAM_MAX_CONTINUES: u32 = 2;
+
+// Instruction sent after the partial assistant output when resuming an interrupted stream (also ^lm/^reason --continue)
+pub const STREAM_CONTINUE_PROMPT: &str = "Your previous response was cut off. Continue exactly where you left off, \
+without repeating anything you already wrote and without any preamble.";
+
+// Parses the optional STREAM_AUTO_CONTINUE and STREAM_MAX_CONTINUES keys
+pub fn parse_stream_continue(
+    config_map: &HashMap<String, String>,
+) -> Result<(bool, u32), Box<dyn std::error::Error + Send + Sync>> {
+    let auto_continue = parse_bool_setting(config_map, "STREAM_AUTO_CONTINUE", false)?;
+    let max_continues = match config_map.get("STREAM_MAX_CONTINUES").filter(|s| !s.trim().is_empty()) {
+        Some(value) => value.trim().parse::<u32>()
+            .map_err(|_| "STREAM_MAX_CONTINUES must be a valid number")?,
+        None => DEFAULT_STREAM_MAX_CONTINUES,
+    };
+
+    Ok((auto_continue, max_continues))
+}
+
+// Attempts per LM request when LM_MAX_RETRIES isn't set
+pub const DEFAULT_LM_MAX_RETRIES: u32 = 3;
+
+// Parses the optional LM_MAX_RETRIES key: total attempts per request, 1-10
+pub fn parse_max_retries(
+    config_map: &HashMap<String, String>,
+) -> Result<u32, Box<dyn std::error::Error + Send + Sync>> {
+    let max_retries = match config_map.get("LM_MAX_RETRIES").filter(|s| !s.trim().is_empty()) {
+        Some(value) => value.trim().parse::<u32>()
+            .map_err(|_| "LM_MAX_RETRIES must be a valid number")?,
+        None => DEFAULT_LM_MAX_RETRIES,
+    };
+    if max_retries == 0 || max_retries > 10 {
+        return Err(format!("LM_MAX_RETRIES must be between 1 and 10 (current value: {})", max_retries).into());
+    }
+    Ok(max_retries)
+}
+
+// Per-model sampling overrides from MODEL_PROFILES; unset fields keep the DEFAULT_* values
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModelProfile {
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<i32>,
+    pub seed: Option<i64>,
+}
+
+// Temperature, max_tokens and seed sent with one request
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SamplingParams {
+    pub temperature: f32,
+    pub max_tokens: i32,
+    pub seed: Option<i64>,
+}
+
+// Sampling settings shared by the lmapiconf.txt configs (this one and the agent, rank and sum variants)
+pub trait SamplingConfig {
+    // DEFAULT_TEMPERATURE, DEFAULT_MAX_TOKENS and DEFAULT_SEED as loaded
+    fn sampling_defaults(&self) -> SamplingParams;
+
+    fn model_profiles(&self) -> &HashMap<String, ModelProfile>;
+
+    // A seed chosen for this request only (^lm --seed); beats DEFAULT_SEED and any profile seed
+    fn seed_override(&self) -> Option<i64> {
+        None
+    }
+
+    // Temperature, max_tokens and seed for a request to `model`, with its MODEL_PROFILES entry applied
+    fn sampling_for(&self, model: &str) -> SamplingParams {
+        let mut sampling = self.sampling_defaults().for_model(self.model_profiles(), model);
+        if let Some(seed) = self.seed_override() {
+            sampling.seed = Some(seed);
+        }
+        sampling
+    }
+}
+
+impl SamplingParams {
+    // These defaults with the profile for `model` (exact name, then case-insensitive) applied on top
+    // Explicit command flags are applied by the caller afterwards, so they still win
+    pub fn for_model(self, profiles: &HashMap<String, ModelProfile>, model: &str) -> SamplingParams {
+        let model = model.trim();
+        let profile = profiles.get(model).or_else(|| {
+            profiles.iter().find(|(name, _)| name.eq_ignore_ascii_case(model)).map(|(_, profile)| profile)
+        });
+        match profile {
+            Some(profile) => SamplingParams {
+                temperature: profile.temperature.unwrap_or(self.temperature),
+                max_tokens: profile.max_tokens.unwrap_or(self.max_tokens),
+                seed: profile.seed.or(self.seed),
+            },
+            None => self,
+        }
+    }
+}
+
+// Parses the optional MODEL_PROFILES key: `model{temperature=0.6,max_tokens=8192,seed=42};other-model{...}`
+// Braces delimit each entry so model names may contain ':' (e.g. Ollama's qwen3:4b)
+pub fn parse_model_profiles(
+    config_map: &HashMap<String, String>,
+) -> Result<HashMap<String, ModelProfile>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut profiles = HashMap::new();
+    let value = match config_map.get("MODEL_PROFILES").map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        Some(value) => value,
+        None => return Ok(profiles),
+    };
+    for entry in value.split(';').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let (model, settings) = entry.strip_suffix('}')
+            .and_then(|entry| entry.split_once('{'))
+            .ok_or_else(|| format!("MODEL_PROFILES entry `{}` must look like model{{temperature=0.7,max_tokens=4096}}", entry))?;
+        let model = model.trim();
+        if model.is_empty() {
+            return Err(format!("MODEL_PROFILES entry `{}` has no model name", entry).into());
+        }
+        let mut profile = ModelProfile::default();
+        for setting in settings.split(',').map(str::trim).filter(|setting| !setting.is_empty()) {
+            let (key, value) = setting.split_once('=')
+                .ok_or_else(|| format!("MODEL_PROFILES setting `{}` for {} must be key=value", setting, model))?;
+            let (key, value) = (key.trim().to_lowercase(), value.trim());
+            match key.as_str() {
+                "temperature" => {
+                    let temperature = value.parse::<f32>()
+                        .map_err(|_| format!("MODEL_PROFILES temperature for {} must be a valid number", model))?;
+                    if !(0.0..=2.0).contains(&temperature) {
+                        return Err(format!("MODEL_PROFILES temperature for {} must be between 0.0 and 2.0 (current value: {})", model, temperature).into());
+                    }
+                    profile.temperature = Some(temperature);
+                }
+                "max_tokens" => {
+                    let max_tokens = value.parse::<i32>()
+                        .map_err(|_| format!("MODEL_PROFILES max_tokens for {} must be a valid number", model))?;
+                    if max_tokens <= 0 || max_tokens > 32768 {
+                        return Err(format!("MODEL_PROFILES max_tokens for {} must be between 1 and 32768 (current value: {})", model, max_tokens).into());
+                    }
+                    profile.max_tokens = Some(max_tokens);
+                }
+                "seed" => {
+                    profile.seed = Some(value.parse::<i64>()
+                        .map_err(|_| format!("MODEL_PROFILES seed for {} must be a valid integer", model))?);
+                }
+                _ => return Err(format!("Unknown MODEL_PROFILES setting `{}` for {} (use temperature, max_tokens or seed)", key, model).into()),
+            }
+        }
+        profiles.insert(model.to_string(), profile);
+    }
+    Ok(profiles)
+}
+
+// Model roles ^setmodel can reassign, with the lmapiconf.txt key each one maps to
+pub const MODEL_ROLES: [(&str, &str); 5] = [
+    ("default", "DEFAULT_MODEL"),
+    ("reason", "DEFAULT_REASON_MODEL"),
+    ("summarization", "DEFAULT_SUMMARIZATION_MODEL"),
+    ("ranking", "DEFAULT_RANKING_MODEL"),
+    ("vision", "DEFAULT_VISION_MODEL"),
+];
+
+// Runtime model assignments keyed by lmapiconf.txt key; cleared by a restart unless saved to the file
+static MODEL_OVERRIDES: std::sync::RwLock<Vec<(String, String)>> = std::sync::RwLock::new(Vec::new());
+
+// Looks up the config key for a role name (case-insensitive)
+pub fn model_role_key(role: &str) -> Option<&'static str> {
+    MODEL_ROLES.iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(role.trim()))
+        .map(|(_, key)| *key)
+}
+
+// Records a runtime model assignment for a config key
+pub fn set_model_override(key: &str, model: &str) {
+    if let Ok(mut overrides) = MODEL_OVERRIDES.write() {
+        overrides.retain(|(existing, _)| existing != key);
+        overrides.push((key.to_string(), model.to_string()));
+    }
+}
+
+// The runtime model assigned to a config key, if any
+pub fn model_override(key: &str) -> Option<String> {
+    MODEL_OVERRIDES.read().ok()?
+        .iter()
+        .find(|(existing, _)| existing == key)
+        .map(|(_, model)| model.clone())
+}
+
+// Snapshot of every runtime model assignment, for passing to a config parser
+pub fn model_overrides() -> Vec<(String, String)> {
+    MODEL_OVERRIDES.read().map(|overrides| overrides.clone()).unwrap_or_default()
+}
+
+// Replaces file values with runtime assignments; every lmapiconf.txt loader calls this after parsing
+// Roles reassigned at runtime with ^setmodel take precedence over the file until restart or ^setmodel --save
+pub fn apply_model_overrides(config_map: &mut HashMap<String, String>, overrides: &[(String, String)]) {
+    for (key, model) in overrides {
+        config_map.insert(key.clone(), model.clone());
+    }
+}
+
+// Parses an optional true/false key, treating a missing or empty value as the default
+pub fn parse_bool_setting(
+    config_map: &HashMap<String, String>,
+    key: &str,
+    default: bool,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    match config_map.get(key).map(|s| s.trim().to_lowercase()) {
+        Some(value) if value.is_empty() => Ok(default),
+        Some(value) => match value.as_str() {
+            "true" | "1" | "yes" | "on" => Ok(true),
+            "false" | "0" | "no" | "off" => Ok(false),
+            _ => Err(format!("{} must be true or false", key).into()),
+        },
+        None => Ok(default),
+    }
+}
+
+// Default total message count at which a stored context is compacted
+pub const DEFAULT_CONTEXT_COMPACTION_THRESHOLD: usize = 400;
+
+// Parses the optional CONTEXT_COMPACTION and CONTEXT_COMPACTION_THRESHOLD keys
+pub fn parse_context_compaction(
+    config_map: &HashMap<String, String>,
+) -> Result<(bool, usize), Box<dyn std::error::Error + Send + Sync>> {
+    let enabled = parse_bool_setting(config_map, "CONTEXT_COMPACTION", false)?;
+    let threshold = match config_map.get("CONTEXT_COMPACTION_THRESHOLD").filter(|s| !s.trim().is_empty()) {
+        Some(value) => value.trim().parse::<usize>()
+            .map_err(|_| "CONTEXT_COMPACTION_THRESHOLD must be a valid number")?,
+        None => DEFAULT_CONTEXT_COMPACTION_THRESHOLD,
+    };
+
+    if threshold < 10 {
+        return Err("❌ CONTEXT_COMPACTION_THRESHOLD must be at least 10 messages".into());
+    }
+
+    Ok((enabled, threshold))
+}
+
+// Tone presets for ^lm --tone and the TONE config key
+// Each maps to a short style directive prepended to the system prompt
+const TONE_PRESETS: [(&str, &str); 4] = [
+    ("concise", "Respond concisely. Prefer short sentences and bullet points, and skip preamble."),
+    ("friendly", "Respond in a warm, friendly, conversational tone."),
+    ("technical", "Respond with technical precision. Use exact terminology and include relevant details, code, or specifics."),
+    ("formal", "Respond in a formal, professional register. Avoid slang and casual phrasing."),
+];
+
+// Looks up the style directive for a tone preset (case-insensitive)
+pub fn tone_directive(tone: &str) -> Option<&'static str> {
+    TONE_PRESETS.iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(tone))
+        .map(|(_, directive)| *directive)
+}
+
+// Comma-separated list of tone preset names for help and error messages
+pub fn tone_preset_names() -> String {
+    let mut names: Vec<&str> = TONE_PRESETS.iter().map(|(name, _)| *name).collect();
+    names.sort();
+    names.join(", ")
+}
+
+// Parses the optional TONE key, rejecting names that aren't a preset
+pub fn parse_tone(
+    config_map: &HashMap<String, String>,
+) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let tone = match config_map.get("TONE").map(|s| s.trim().to_lowercase()).filter(|s| !s.is_empty()) {
+        Some(tone) => tone,
+        None => return Ok(None),
+    };
+    if tone_directive(&tone).is_none() {
+        return Err(format!("❌ TONE must be one of: {} (got '{}')", tone_preset_names(), tone).into());
+    }
+    Ok(Some(tone))
+}
+
+/// Summarize older conversation messages into a compact memory for a stored context
+/// Any earlier memory is folded in so the result replaces it
+pub async fn summarize_context_memory(
+    config: &LMConfig,
+    previous_memory: Option<&str>,
+    messages: &[ChatMessage],
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let mut transcript = String::new();
+    if let Some(memory) = previous_memory {
+        transcript.push_str(&format!("Earlier memory:\n{}\n\n", memory));
+    }
+    for message in messages {
+        transcript.push_str(&format!("{}: {}\n\n", message.role, message.content));
+    }
+
+    let prompt = vec![
+        ChatMessage {
+            role: "system".to_string(),
+            content: "You condense chat history into a memory for an assistant. Write a concise summary of the \
+                conversation below: the user's goals, preferences, facts they shared, decisions made and any open \
+                questions. Use short bullet points, no preamble. Keep it under 300 words.".to_string(),
+        },
+        ChatMessage {
+            role: "user".to_string(),
+            content: transcript,
+        },
+    ];
+
+    let summary = chat_completion(prompt, &config.default_summarization_model, config, Some(800)).await?;
+    let summary = summary.trim().to_string();
+    if summary.is_empty() {
+        return Err("Summarization model returned an empty memory".into());
+    }
+    Ok(summary)
+}
+
+/// Resume a streamed completion that dropped mid-response
+/// Sends the partial output back as assistant context with a "continue" instruction and
+/// returns only the newly generated text, so the caller can append it to what it already has.
+/// A continuation that is itself interrupted is retried from the stitched text, up to
+/// config.stream_max_continues attempts in total.
+pub async fn continue_interrupted_stream(
+    config: &LMConfig,
+    model: &str,
+    messages: &[ChatMessage],
+    partial: &str,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    use futures_util::StreamExt;
+
+    let client = get_http_client().await;
+    let api_url = format!("{}/v1/chat/completions", config.base_url);
+    let mut continuation = String::new();
+    let mut last_error = "stream interrupted".to_string();
+
+    for attempt in 1..=config.stream_max_continues {
+        println!("[DEBUG][STREAM] Stream interrupted after {} chars - requesting continuation (attempt {}/{})",
+            partial.len() + continuation.len(), attempt, config.stream_max_continues);
+
+        let mut request_messages = messages.to_vec();
+        request_messages.push(ChatMessage {
+            role: "assistant".to_string(),
+            content: format!("{}{}", partial, continuation),
+        });
+        request_messages.push(ChatMessage {
+            role: "user".to_string(),
+            content: STREAM_CONTINUE_PROMPT.to_string(),
+        });
+
+        let sampling = config.sampling_for(model);
+        let chat_request = serde_json::json!({
+            "model": model,
+            "messages": request_messages,
+            "temperature": sampling.temperature,
+            "max_tokens": sampling.max_tokens,
+            "stream": true,
+            "seed": sampling.seed
+        });
+
+        crate::commands::util::wait_for_backend_rate_limit().await;
+        let response = match client
+            .post(&api_url)
+            .json(&chat_request)
+            .timeout(Duration::from_secs(config.timeout))
+            .send()
+            .await
+        {
+            Ok(resp) if resp.status().is_success() => resp,
+            Ok(resp) => {
+                last_error = format!("HTTP {}", resp.status());
+                continue;
+            }
+            Err(e) => {
+                last_error = e.to_string();
+                continue;
+            }
+        };
+
+        let mut stream = response.bytes_stream();
+        let mut line_buffer = String::new();
+        let mut interrupted = false;
+
+        'stream: while let Some(chunk) = stream.next().await {
+            let bytes = match chunk {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    last_error = e.to_string();
+                    interrupted = true;
+                    break;
+                }
+            };
+            line_buffer.push_str(&String::from_utf8_lossy(&bytes));
+
+            while let Some(i) = line_buffer.find('\n') {
+                let line = line_buffer.drain(..=i).collect::<String>();
+                let line = line.trim();
+
+                if let Some(json_str) = line.strip_prefix("data: ") {
+                    if json_str.trim() == "[DONE]" {
+                        break 'stream;
+                    }
+                    if let Ok(value) = serde_json::from_str::<serde_json::Value>(json_str) {
+                        if let Some(content) = value["choices"][0]["delta"]["content"].as_str() {
+                            continuation.push_str(content);
+                        }
+                    }
+                }
+            }
+        }
+
+        if !interrupted {
+            println!("[DEBUG][STREAM] Continuation complete - added {} chars", continuation.len());
+            return Ok(continuation);
+        }
+    }
+
+    Err(format!(
+        "Stream interrupted and could not be resumed after {} attempt(s): {}",
+        config.stream_max_continues, last_error
+    ).into())
 }
 
 // Search result structure
@@ -63,94 +555,24 @@ pub struct SearchResult {
     pub snippet: String,
 }
 
-/// Enhanced connectivity test function
-pub async fn test_api_connectivity(config: &LMConfig) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// Checks that the server answers and that its chat completions endpoint accepts a request for `model`
+pub async fn test_api_connectivity(base_url: &str, model: &str) -> Result<(), BotError> {
     let client = get_http_client().await;
     
-    println!("[DEBUG][CONNECTIVITY] Testing API connectivity to: {}", config.base_url);
+    println!("[DEBUG][CONNECTIVITY] Testing API connectivity to: {}", base_url);
     
     // Test 1: Basic server connectivity
-    let basic_response = client
-        .get(&config.base_url)
-        .timeout(Duration::from_secs(10))
-        .send()
-        .await;
-    
-    match basic_response {
+    match client.get(base_url).timeout(Duration::from_secs(10)).send().await {
         Ok(response) => {
             println!("[DEBUG][CONNECTIVITY] Basic connectivity OK - Status: {}", response.status());
         }
-        Err(e) => {
-            let error_msg = format!("{}", e);
-            if error_msg.contains("os error 10013") || error_msg.contains("access permissions") {
-                return Err(format!(
-                    "🚫 **Windows Network Permission Error (10013)**\n\n\
-                    Cannot connect to LM Studio at `{}`\n\n\
-                    **Solutions:**\n\
-                    • **Add Firewall Exception**: Windows Defender Firewall → Allow an app → Add this program\n\
-                    • **Run as Administrator**: Try running the bot with administrator privileges\n\
-                    • **Check LM Studio**: Ensure LM Studio is running and accessible\n\
-                    • **Try localhost**: Use `http://127.0.0.1:1234` instead of `http://localhost:1234`\n\
-                    • **Check Port**: Verify no other application is using the port\n\n\
-                    **Original error:** {}", 
-                    config.base_url, e
-                ).into());
-            } else if error_msg.contains("timeout") || error_msg.contains("timed out") {
-                return Err(format!(
-                    "⏰ **Connection Timeout**\n\n\
-                    Cannot reach LM Studio server at `{}` within 10 seconds\n\n\
-                    **Solutions:**\n\
-                    • **Check LM Studio**: Ensure LM Studio is running and responsive\n\
-                    • **Network Connection**: Verify your network connection is stable\n\
-                    • **Server Load**: LM Studio might be overloaded - wait and retry\n\
-                    • **Firewall**: Check if firewall is blocking the connection\n\n\
-                    **Original error:** {}", 
-                    config.base_url, e
-                ).into());
-            } else if error_msg.contains("refused") || error_msg.contains("connection refused") {
-                return Err(format!(
-                    "🚫 **Connection Refused**\n\n\
-                    LM Studio at `{}` is not accepting connections\n\n\
-                    **Solutions:**\n\
-                    • **Start LM Studio**: Make sure LM Studio is running\n\
-                    • **Check Port**: Verify LM Studio is listening on the correct port (usually 1234)\n\
-                    • **Load Model**: Ensure a model is loaded in LM Studio\n\
-                    • **Server Status**: Check LM Studio's server status indicator\n\
-                    • **Alternative Port**: Try port 11434 if using Ollama instead\n\n\
-                    **Original error:** {}", 
-                    config.base_url, e
-                ).into());
-            } else if error_msg.contains("dns") || error_msg.contains("name resolution") {
-                return Err(format!(
-                    "🌐 **DNS Resolution Error**\n\n\
-                    Cannot resolve hostname in `{}`\n\n\
-                    **Solutions:**\n\
-                    • **Use IP Address**: Try `http://127.0.0.1:1234` instead of `http://localhost:1234`\n\
-                    • **Check Hostname**: Verify the hostname is correct\n\
-                    • **DNS Settings**: Check your DNS configuration\n\n\
-                    **Original error:** {}", 
-                    config.base_url, e
-                ).into());
-            } else {
-                return Err(format!(
-                    "🔗 **Connection Error**\n\n\
-                    Cannot connect to LM Studio at `{}`\n\n\
-                    **Solutions:**\n\
-                    • **Check URL**: Verify the base URL in lmapiconf.txt\n\
-                    • **Start LM Studio**: Ensure LM Studio is running\n\
-                    • **Network**: Check your network connection\n\
-                    • **Firewall**: Verify firewall settings\n\n\
-                    **Original error:** {}", 
-                    config.base_url, e
-                ).into());
-            }
-        }
+        Err(e) => return Err(BotError::from_request_error(&e, base_url, 10)),
     }
     
     // Test 2: API endpoint availability
-    let api_url = format!("{}/v1/chat/completions", config.base_url);
+    let api_url = format!("{}/v1/chat/completions", base_url);
     let test_payload = serde_json::json!({
-        "model": config.default_model,
+        "model": model,
         "messages": [{"role": "user", "content": "test"}],
         "max_tokens": 1,
         "temperature": 0.1
@@ -158,107 +580,65 @@ pub async fn test_api_connectivity(config: &LMConfig) -> Result<(), Box<dyn std:
     
     println!("[DEBUG][CONNECTIVITY] Testing API endpoint: {}", api_url);
     
-    let api_response = client
-        .post(&api_url)
-        .json(&test_payload)
-        .timeout(Duration::from_secs(30))
-        .send()
-        .await;
-    
-    match api_response {
+    crate::commands::util::wait_for_backend_rate_limit().await;
+    match client.post(&api_url).json(&test_payload).timeout(Duration::from_secs(30)).send().await {
         Ok(response) => {
             let status = response.status();
+            // 400/422 are acceptable - the API is responding even if it rejects this probe request
             if status.is_success() || status == 400 || status == 422 {
-                // 400/422 are acceptable - means API is responding but request format might be wrong
                 println!("[DEBUG][CONNECTIVITY] API endpoint OK - Status: {}", status);
-                return Ok(());
-            } else if status == 404 {
-                return Err(format!(
-                    "🚫 **API Endpoint Not Found (404)**\n\n\
-                    The endpoint `{}` was not found\n\n\
-                    **Solutions:**\n\
-                    • **Check LM Studio Version**: Ensure you're using a recent version that supports OpenAI API\n\
-                    • **Enable API Server**: Make sure the 'Start Server' option is enabled in LM Studio\n\
-                    • **Correct Port**: LM Studio usually uses port 1234, Ollama uses 11434\n\
-                    • **API Path**: Verify the API path is `/v1/chat/completions`\n\n\
-                    **Current URL:** {}", 
-                    api_url, config.base_url
-                ).into());
+                Ok(())
             } else {
-                let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-                return Err(format!(
-                    "🚫 **API Error (HTTP {})**\n\n\
-                    LM Studio API returned an error\n\n\
-                    **Response:** {}\n\n\
-                    **Solutions:**\n\
-                    • **Load Model**: Ensure a model is loaded in LM Studio\n\
-                    • **Check Model Name**: Verify the model name in lmapiconf.txt matches loaded model\n\
-                    • **Server Status**: Check LM Studio's status and logs\n\n\
-                    **API URL:** {}", 
-                    status, error_text, api_url
-                ).into());
+                Err(BotError::from_response(response, model, &api_url).await)
             }
         }
         Err(e) => {
-            // API test failed, but basic connectivity worked, so this might be a model/configuration issue
             println!("[DEBUG][CONNECTIVITY] API test failed but basic connectivity OK: {}", e);
-            return Err(format!(
-                "⚠️ **API Configuration Issue**\n\n\
-                Basic connectivity to `{}` works, but API test failed\n\n\
-                **Likely Issues:**\n\
-                • **Model Not Loaded**: No model is loaded in LM Studio\n\
-                • **Wrong Model Name**: Model name in lmapiconf.txt doesn't match loaded model\n\
-                • **API Not Enabled**: LM Studio server is not started\n\
-                • **Version Issue**: LM Studio version doesn't support OpenAI API\n\n\
-                **Error:** {}", 
-                config.base_url, e
-            ).into());
+            Err(BotError::from_request_error(&e, &api_url, 30))
         }
     }
 }
 
-/// Load LM Studio/Ollama configuration from lmapiconf.txt file with enhanced validation
-pub async fn load_lm_config() -> Result<LMConfig, Box<dyn std::error::Error + Send + Sync>> {
-    let config_paths = [
-        "lmapiconf.txt",
-        "../lmapiconf.txt", 
-        "../../lmapiconf.txt",
-        "src/lmapiconf.txt"
-    ];
-    
-    let mut config_content = String::new();
-    let mut config_file_found = false;
-    let mut config_file_path = "";
-    
-    // Try to read from multiple possible locations
-    for path in &config_paths {
-        match fs::read_to_string(path) {
-            Ok(content) => {
-                config_content = content;
-                config_file_found = true;
-                config_file_path = path;
-                println!("✅ Configuration loaded from: {}", path);
-                break;
-            }
-            Err(_) => continue,
-        }
-    }
-    
-    if !config_file_found {
+/// Default for REASON_TIMEOUT, AGENT_TIMEOUT and SUM_TIMEOUT: long-running requests keep
+/// the 5 minutes they always had instead of inheriting a short LM_STUDIO_TIMEOUT
+pub const DEFAULT_LONG_COMMAND_TIMEOUT: u64 = 300;
+
+// Resolves the timeout for a specific command type
+// Uses the command's own key (e.g. LM_TIMEOUT) when set, otherwise `default_timeout`
+pub fn resolve_command_timeout(
+    config_map: &HashMap<String, String>,
+    key: &str,
+    default_timeout: u64,
+) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+    let timeout = match config_map.get(key).filter(|s| !s.trim().is_empty()) {
+        Some(value) => value
+            .trim()
+            .parse::<u64>()
+            .map_err(|_| format!("{} must be a valid number (seconds)", key))?,
+        None => return Ok(default_timeout),
+    };
+
+    if timeout == 0 || timeout > 600 {
         return Err(format!(
-            "❌ **Configuration File Not Found**\n\n\
-            Could not find `lmapiconf.txt` in any of these locations:\n\
-            • ./lmapiconf.txt\n\
-            • ../lmapiconf.txt\n\
-            • ../../lmapiconf.txt\n\
-            • src/lmapiconf.txt\n\n\
-            **Solution:** Copy `example_lmapiconf.txt` to `lmapiconf.txt` and configure it for your setup."
+            "❌ **Invalid Timeout Value**\n\n\
+            {} must be between 1 and 600 seconds\n\
+            Current value: {} seconds",
+            key, timeout
         ).into());
     }
+
+    Ok(timeout)
+}
+
+/// Parse the contents of lmapiconf.txt into an LMConfig
+/// Pure counterpart of load_lm_config: no file IO and no connectivity test
+pub fn parse_lm_config(content: &str, overrides: &[(String, String)]) -> Result<LMConfig, Box<dyn std::error::Error + Send + Sync>> {
+    // Remove BOM if present (Windows text file encoding)
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
     
     // Parse configuration
     let mut config_map = HashMap::new();
-    for (line_num, line) in config_content.lines().enumerate() {
+    for (line_num, line) in content.lines().enumerate() {
         let line = line.trim();
         if line.is_empty() || line.starts_with('#') {
             continue;
@@ -269,9 +649,11 @@ pub async fn load_lm_config() -> Result<LMConfig, Box<dyn std::error::Error + Se
             let value = line[equals_pos + 1..].trim().to_string();
             config_map.insert(key, value);
         } else {
-            println!("⚠️ Warning: Invalid line {} in {}: {}", line_num + 1, config_file_path, line);
+            println!("⚠️ Warning: Invalid line {} in lmapiconf.txt: {}", line_num + 1, line);
         }
     }
+
+    apply_model_overrides(&mut config_map, overrides);
     
     // Validate required keys
     let required_keys = [
@@ -298,10 +680,9 @@ pub async fn load_lm_config() -> Result<LMConfig, Box<dyn std::error::Error + Se
     if !missing_keys.is_empty() {
         return Err(format!(
             "❌ **Missing Configuration Keys**\n\n\
-            The following required keys are missing from `{}`:\n\
+            The following required keys are missing from `lmapiconf.txt`:\n\
             {}\n\n\
             **Solution:** Add these keys to your lmapiconf.txt file. See example_lmapiconf.txt for reference.",
-            config_file_path,
             missing_keys.iter().map(|k| format!("• {}", k)).collect::<Vec<_>>().join("\n")
         ).into());
     }
@@ -340,6 +721,9 @@ pub async fn load_lm_config() -> Result<LMConfig, Box<dyn std::error::Error + Se
         ).into());
     }
     
+    // Optional per-command override for ^lm requests
+    let timeout = resolve_command_timeout(&config_map, "LM_TIMEOUT", timeout)?;
+    
     let default_model = config_map.get("DEFAULT_MODEL")
         .ok_or("DEFAULT_MODEL not found in lmapiconf.txt")?
         .clone();
@@ -411,7 +795,18 @@ pub async fn load_lm_config() -> Result<LMConfig, Box<dyn std::error::Error + Se
         .transpose()
         .map_err(|_| "DEFAULT_SEED must be a valid integer if specified")?;
     
-    let config = LMConfig {
+    let (vision_max_images, vision_max_total_bytes) = parse_vision_limits(&config_map)?;
+    let (vision_max_dimension, vision_max_image_bytes) = parse_vision_resize_limits(&config_map)?;
+    
+    // Optional tone preset applied to ^lm responses
+    let default_tone = parse_tone(&config_map)?;
+    
+    let (stream_auto_continue, stream_max_continues) = parse_stream_continue(&config_map)?;
+    let (context_compaction, context_compaction_threshold) = parse_context_compaction(&config_map)?;
+    let max_retries = parse_max_retries(&config_map)?;
+    let model_profiles = parse_model_profiles(&config_map)?;
+    
+    Ok(LMConfig {
         base_url,
         timeout,
         default_model,
@@ -424,11 +819,43 @@ pub async fn load_lm_config() -> Result<LMConfig, Box<dyn std::error::Error + Se
         response_format_padding,
         default_vision_model,
         default_seed,
+        vision_max_images,
+        vision_max_total_bytes,
+        vision_max_dimension,
+        vision_max_image_bytes,
+        default_tone,
+        stream_auto_continue,
+        stream_max_continues,
+        context_compaction,
+        context_compaction_threshold,
+        max_retries,
+        model_profiles,
+        seed_override: None,
+    })
+}
+
+/// Load LM Studio/Ollama configuration from lmapiconf.txt file with enhanced validation
+pub async fn load_lm_config() -> Result<LMConfig, Box<dyn std::error::Error + Send + Sync>> {
+    let (config_content, config_file_path) = match crate::commands::util::find_and_read_config("lmapiconf.txt") {
+        Some(found) => found,
+        None => return Err(format!(
+            "❌ **Configuration File Not Found**\n\n\
+            Could not find `lmapiconf.txt` in any of these locations:\n\
+            • ./lmapiconf.txt\n\
+            • ../lmapiconf.txt\n\
+            • ../../lmapiconf.txt\n\
+            • src/lmapiconf.txt\n\n\
+            **Solution:** Copy `example_lmapiconf.txt` to `lmapiconf.txt` and configure it for your setup."
+        ).into()),
     };
+    println!("✅ Configuration loaded from: {}", config_file_path);
+    crate::commands::util::record_config_source("lmapiconf.txt (lm/vis)", &config_file_path);
+    
+    let config = parse_lm_config(&config_content, &model_overrides())?;
     
     // Test connectivity after loading configuration
     println!("🔍 Testing API connectivity...");
-    if let Err(e) = test_api_connectivity(&config).await {
+    if let Err(e) = test_api_connectivity(&config.base_url, &config.default_model).await {
         return Err(format!(
             "❌ **Connectivity Test Failed**\n\n\
             Configuration loaded successfully from `{}`, but connectivity test failed:\n\n\
@@ -452,7 +879,80 @@ pub async fn chat_completion(
     config: &LMConfig,
     max_tokens: Option<i32>,
 ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-    chat_completion_with_retries(messages, model, config, max_tokens, 3).await
+    chat_completion_with_retries(messages, model, config, max_tokens, config.max_retries).await
+}
+
+/// Send a chat-completion request, retrying transient failures (dropped connections, timeouts, 5xx, 429)
+/// The successful response is returned with its body unread, so streaming callers can consume it as SSE
+/// Shared by every LM request path so retries and error typing behave the same everywhere
+pub async fn send_chat_request<T: Serialize + Sync + ?Sized>(
+    client: &reqwest::Client,
+    api_url: &str,
+    body: &T,
+    model: &str,
+    timeout_secs: u64,
+    max_retries: u32,
+) -> Result<reqwest::Response, BotError> {
+    let max_retries = max_retries.max(1);
+    let mut attempt = 1;
+    loop {
+        // Every attempt, retries included, is paced by BACKEND_RPS
+        crate::commands::util::wait_for_backend_rate_limit().await;
+        println!("[DEBUG][CHAT] Attempt {}/{} - Sending request to: {}", attempt, max_retries, api_url);
+        let start_time = std::time::Instant::now();
+
+        let error = match client.post(api_url).json(body).timeout(Duration::from_secs(timeout_secs)).send().await {
+            Ok(response) if response.status().is_success() => {
+                println!("[DEBUG][CHAT] Request completed in {:.2}s - Status: {}", start_time.elapsed().as_secs_f32(), response.status());
+                return Ok(response);
+            }
+            Ok(response) => BotError::from_response(response, model, api_url).await,
+            // Refused or blocked connections are configuration issues and are not retried
+            Err(e) => BotError::from_request_error(&e, api_url, timeout_secs),
+        };
+        println!("[DEBUG][CHAT] Attempt {} failed after {:.2}s: {}", attempt, start_time.elapsed().as_secs_f32(), error);
+
+        if !error.is_retryable() {
+            return Err(error);
+        }
+        if attempt >= max_retries {
+            return Err(if max_retries > 1 {
+                BotError::RetriesExhausted { attempts: max_retries, last: Box::new(error) }
+            } else {
+                error
+            });
+        }
+
+        // Linear backoff, or the server's Retry-After when it rate limits us
+        let delay = match &error {
+            BotError::RateLimited { retry_after: Some(secs) } => Duration::from_secs((*secs).min(30)),
+            _ => Duration::from_millis(1000 * attempt as u64),
+        };
+        println!("[DEBUG][CHAT] Retrying in {:.1}s...", delay.as_secs_f32());
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// Body of a streaming chat completion, as raw SSE byte chunks
+pub type ChatByteStream = std::pin::Pin<Box<dyn futures_util::Stream<Item = Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>> + Send>>;
+
+/// Streaming form of send_chat_request: retries until the server starts answering, then hands back the body
+/// Split the chunks with util::SseLineBuffer so events cut across network reads are parsed whole
+pub async fn send_chat_stream<T: Serialize + Sync + ?Sized>(
+    client: &reqwest::Client,
+    api_url: &str,
+    body: &T,
+    model: &str,
+    timeout_secs: u64,
+    max_retries: u32,
+) -> Result<ChatByteStream, BotError> {
+    use futures_util::StreamExt;
+
+    let response = send_chat_request(client, api_url, body, model, timeout_secs, max_retries).await?;
+    Ok(Box::pin(response.bytes_stream().map(|chunk| {
+        chunk.map(|bytes| bytes.to_vec()).map_err(|e| e.into())
+    })))
 }
 
 /// Chat completion with configurable retry attempts
@@ -466,181 +966,59 @@ async fn chat_completion_with_retries(
     let client = get_http_client().await;
     let api_url = format!("{}/v1/chat/completions", config.base_url);
     
+    let sampling = config.sampling_for(model);
     let chat_request = serde_json::json!({
         "model": model,
         "messages": messages,
-        "temperature": config.default_temperature,
-        "max_tokens": max_tokens.unwrap_or(config.default_max_tokens),
+        "temperature": sampling.temperature,
+        "max_tokens": max_tokens.unwrap_or(sampling.max_tokens),
         "stream": false,
-        "seed": config.default_seed
+        "seed": sampling.seed
     });
 
-    let mut last_error: Option<Box<dyn std::error::Error + Send + Sync>> = None;
-    
-    for attempt in 1..=max_retries {
-        println!("[DEBUG][CHAT] Attempt {}/{} - Sending request to: {}", attempt, max_retries, api_url);
-        
-        let start_time = std::time::Instant::now();
-        
-        let response = match client
-            .post(&api_url)
-            .json(&chat_request)
-            .timeout(Duration::from_secs(config.timeout))
-            .send()
-            .await
-        {
-            Ok(resp) => {
-                let elapsed = start_time.elapsed();
-                println!("[DEBUG][CHAT] Request completed in {:.2}s - Status: {}", elapsed.as_secs_f32(), resp.status());
-                resp
-            }
-            Err(e) => {
-                let elapsed = start_time.elapsed();
-                println!("[DEBUG][CHAT] Request failed after {:.2}s: {}", elapsed.as_secs_f32(), e);
-                
-                let error_msg = format!("{}", e);
-                
-                // Check for specific error types that might benefit from retry
-                let should_retry = attempt < max_retries && (
-                    error_msg.contains("timeout") ||
-                    error_msg.contains("connection reset") ||
-                    error_msg.contains("connection aborted") ||
-                    error_msg.contains("broken pipe") ||
-                    error_msg.contains("connection closed")
-                );
-                
-                                 if should_retry {
-                     let delay = Duration::from_millis(1000 * attempt as u64); // Exponential backoff
-                     println!("[DEBUG][CHAT] Retrying in {:.1}s...", delay.as_secs_f32());
-                     tokio::time::sleep(delay).await;
-                     last_error = Some(Box::new(std::io::Error::new(std::io::ErrorKind::Other, format!("{}", e))));
-                    continue;
-                } else {
-                    // Don't retry for these errors - they're likely configuration issues
-                    if error_msg.contains("os error 10013") || error_msg.contains("access permissions") {
-                        return Err(format!(
-                            "🚫 **Windows Network Permission Error**\n\n\
-                            Cannot connect to LM Studio API\n\n\
-                            **Quick Fixes:**\n\
-                            • **Run as Administrator**: Right-click and 'Run as administrator'\n\
-                            • **Windows Firewall**: Add firewall exception for this program\n\
-                            • **Try localhost**: Use `http://127.0.0.1:1234` in lmapiconf.txt\n\n\
-                            **Current URL:** {}\n\
-                            **Error:** {}", 
-                            config.base_url, e
-                        ).into());
-                    } else if error_msg.contains("refused") || error_msg.contains("connection refused") {
-                        return Err(format!(
-                            "🚫 **Connection Refused**\n\n\
-                            LM Studio is not accepting connections\n\n\
-                            **Solutions:**\n\
-                            • **Start LM Studio**: Make sure LM Studio is running\n\
-                            • **Load Model**: Ensure a model is loaded\n\
-                            • **Enable Server**: Click 'Start Server' in LM Studio\n\
-                            • **Check Port**: Verify port 1234 is available\n\n\
-                            **Current URL:** {}\n\
-                            **Error:** {}", 
-                            config.base_url, e
-                        ).into());
-                    } else {
-                        return Err(format!("API request failed: {}", e).into());
-                    }
-                }
-            }
-        };
+    let response = send_chat_request(client, &api_url, &chat_request, model, config.timeout, max_retries).await?;
+    let response_text = response.text().await
+        .map_err(|e| format!("Failed to read response: {}", e))?;
 
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unable to read error response".to_string());
-            
-            // Check if this is a retryable server error
-            let is_server_error = status.is_server_error();
-            let should_retry = attempt < max_retries && is_server_error;
-            
-            if should_retry {
-                                 let delay = Duration::from_millis(1000 * attempt as u64);
-                 println!("[DEBUG][CHAT] Server error ({}), retrying in {:.1}s...", status, delay.as_secs_f32());
-                 tokio::time::sleep(delay).await;
-                 last_error = Some(Box::new(std::io::Error::new(std::io::ErrorKind::Other, format!("HTTP {} - {}", status, error_text))));
-                continue;
-            } else {
-                return Err(format!(
-                    "🚫 **API Error (HTTP {})**\n\n\
-                    **Response:** {}\n\n\
-                    **Solutions:**\n\
-                    • **Model Loaded**: Ensure model '{}' is loaded in LM Studio\n\
-                    • **Model Name**: Verify model name matches exactly\n\
-                    • **Server Status**: Check LM Studio server logs\n\
-                    • **Memory**: Ensure sufficient RAM for the model\n\n\
-                    **API URL:** {}", 
-                    status, error_text, model, api_url
-                ).into());
-            }
+    let response_json: serde_json::Value = match serde_json::from_str(&response_text) {
+        Ok(json) => json,
+        Err(e) => {
+            return Err(BotError::InvalidResponse(format!(
+                "Failed to parse JSON response from LM Studio\n\n\
+                **Response:** {}\n\
+                **Parse Error:** {}\n\n\
+                **Solutions:**\n\
+                • **Update LM Studio**: Ensure you're using a recent version\n\
+                • **Check Model**: Verify the model supports chat completions\n\
+                • **Server Logs**: Check LM Studio logs for errors",
+                response_text.chars().take(500).collect::<String>(), e
+            )).into());
         }
-
-        // Parse successful response
-        let response_text = match response.text().await {
-            Ok(text) => text,
-            Err(e) => {
-                                 if attempt < max_retries {
-                     let delay = Duration::from_millis(1000 * attempt as u64);
-                     println!("[DEBUG][CHAT] Failed to read response, retrying in {:.1}s...", delay.as_secs_f32());
-                     tokio::time::sleep(delay).await;
-                     last_error = Some(Box::new(e));
-                    continue;
-                } else {
-                    return Err(format!("Failed to read response: {}", e).into());
-                }
-            }
-        };
-        
-        let response_json: serde_json::Value = match serde_json::from_str(&response_text) {
-            Ok(json) => json,
-            Err(e) => {
-                return Err(format!(
-                    "🚫 **Invalid API Response**\n\n\
-                    Failed to parse JSON response from LM Studio\n\n\
-                    **Response:** {}\n\
-                    **Parse Error:** {}\n\n\
-                    **Solutions:**\n\
-                    • **Update LM Studio**: Ensure you're using a recent version\n\
-                    • **Check Model**: Verify the model supports chat completions\n\
-                    • **Server Logs**: Check LM Studio logs for errors",
-                    response_text.chars().take(500).collect::<String>(), e
-                ).into());
-            }
-        };
-        
-        // Extract content from response
-        if let Some(choices) = response_json["choices"].as_array() {
-            if let Some(first_choice) = choices.get(0) {
-                if let Some(message) = first_choice["message"].as_object() {
-                    if let Some(content) = message["content"].as_str() {
-                        let result = content.trim().to_string();
-                        println!("[DEBUG][CHAT] Success! Generated {} characters", result.len());
-                        return Ok(result);
-                    }
+    };
+    
+    // Extract content from response
+    if let Some(choices) = response_json["choices"].as_array() {
+        if let Some(first_choice) = choices.get(0) {
+            if let Some(message) = first_choice["message"].as_object() {
+                if let Some(content) = message["content"].as_str() {
+                    let result = content.trim().to_string();
+                    println!("[DEBUG][CHAT] Success! Generated {} characters", result.len());
+                    return Ok(result);
                 }
             }
         }
-        
-        // If we reach here, the JSON structure was unexpected
-        return Err(format!(
-            "🚫 **Unexpected API Response Format**\n\n\
-            LM Studio returned a valid JSON response, but the structure was unexpected\n\n\
-            **Response:** {}\n\n\
-            **Solutions:**\n\
-            • **Update LM Studio**: Ensure compatibility with OpenAI API format\n\
-            • **Check Model**: Verify the model supports chat completions\n\
-            • **API Version**: Ensure you're using a compatible API version",
-            serde_json::to_string_pretty(&response_json).unwrap_or_else(|_| "Unable to format response".to_string())
-        ).into());
     }
     
-    // All retries exhausted
-    Err(format!("Request failed after {} attempts. Last error: {}", 
-                max_retries, 
-                last_error.map(|e| format!("{}", e)).unwrap_or_else(|| "Unknown error".to_string())
+    // If we reach here, the JSON structure was unexpected
+    Err(format!(
+        "🚫 **Unexpected API Response Format**\n\n\
+        LM Studio returned a valid JSON response, but the structure was unexpected\n\n\
+        **Response:** {}\n\n\
+        **Solutions:**\n\
+        • **Update LM Studio**: Ensure compatibility with OpenAI API format\n\
+        • **Check Model**: Verify the model supports chat completions\n\
+        • **API Version**: Ensure you're using a compatible API version",
+        serde_json::to_string_pretty(&response_json).unwrap_or_else(|_| "Unable to format response".to_string())
     ).into())
 }
 
@@ -750,4 +1128,151 @@ async fn analyze_search_results_with_ai(
     search_msg.edit(&ctx.http, |m| m.content(&final_message)).await?;
     
     Ok(())
-} 
\ No newline at end of file
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALID_CONFIG: &str = "\
+LM_STUDIO_BASE_URL=http://localhost:1234
+LM_STUDIO_TIMEOUT=60
+DEFAULT_MODEL=test-model
+DEFAULT_REASON_MODEL=reason-model
+DEFAULT_SUMMARIZATION_MODEL=sum-model
+DEFAULT_RANKING_MODEL=rank-model
+DEFAULT_TEMPERATURE=0.7
+DEFAULT_MAX_TOKENS=4096
+MAX_DISCORD_MESSAGE_LENGTH=2000
+RESPONSE_FORMAT_PADDING=50
+DEFAULT_VISION_MODEL=vision-model
+";
+
+    fn with_line(key: &str, value: &str) -> String {
+        let mut content: String = VALID_CONFIG
+            .lines()
+            .filter(|line| !line.starts_with(&format!("{}=", key)))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        content.push_str(&format!("{}={}\n", key, value));
+        content
+    }
+
+    fn parse_err(content: &str) -> String {
+        parse_lm_config(content, &[]).expect_err("config should be rejected").to_string()
+    }
+
+    #[test]
+    fn test_parse_lm_config_accepts_valid_config() {
+        let config = parse_lm_config(VALID_CONFIG, &[]).unwrap();
+        assert_eq!(config.base_url, "http://localhost:1234");
+        assert_eq!(config.timeout, 60);
+        assert_eq!(config.default_model, "test-model");
+        assert_eq!(config.default_max_tokens, 4096);
+        assert_eq!(config.vision_max_images, DEFAULT_VISION_MAX_IMAGES);
+        assert_eq!(config.vision_max_dimension, DEFAULT_VISION_MAX_DIMENSION);
+    }
+
+    #[test]
+    fn test_parse_lm_config_strips_bom_and_skips_comments() {
+        let content = format!("\u{feff}# LM settings\n\n   # indented comment\n{}", VALID_CONFIG);
+        let config = parse_lm_config(&content, &[]).unwrap();
+        assert_eq!(config.base_url, "http://localhost:1234");
+    }
+
+    #[test]
+    fn test_parse_lm_config_reports_missing_keys() {
+        let content: String = VALID_CONFIG
+            .lines()
+            .filter(|line| !line.starts_with("DEFAULT_MODEL=") && !line.starts_with("DEFAULT_VISION_MODEL="))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        let err = parse_err(&content);
+        assert!(err.contains("Missing Configuration Keys"));
+        assert!(err.contains("• DEFAULT_MODEL"));
+        assert!(err.contains("• DEFAULT_VISION_MODEL"));
+    }
+
+    #[test]
+    fn test_parse_lm_config_rejects_out_of_range_values() {
+        assert!(parse_err(&with_line("DEFAULT_TEMPERATURE", "2.5")).contains("Invalid Temperature"));
+        assert!(parse_err(&with_line("DEFAULT_TEMPERATURE", "-0.1")).contains("Invalid Temperature"));
+        assert!(parse_err(&with_line("LM_STUDIO_TIMEOUT", "0")).contains("Invalid Timeout"));
+        assert!(parse_err(&with_line("LM_STUDIO_TIMEOUT", "601")).contains("Invalid Timeout"));
+        assert!(parse_err(&with_line("DEFAULT_MAX_TOKENS", "0")).contains("Invalid Max Tokens"));
+        assert!(parse_err(&with_line("DEFAULT_MAX_TOKENS", "32769")).contains("Invalid Max Tokens"));
+        assert!(parse_lm_config(&with_line("DEFAULT_TEMPERATURE", "2.0"), &[]).is_ok());
+        assert!(parse_lm_config(&with_line("LM_STUDIO_TIMEOUT", "600"), &[]).is_ok());
+    }
+
+    #[test]
+    fn test_parse_lm_config_rejects_empty_model() {
+        assert!(parse_err(&with_line("DEFAULT_MODEL", "   ")).contains("DEFAULT_MODEL cannot be empty"));
+    }
+
+    #[test]
+    fn test_parse_lm_config_optional_seed() {
+        assert_eq!(parse_lm_config(VALID_CONFIG, &[]).unwrap().default_seed, None);
+        assert_eq!(parse_lm_config(&with_line("DEFAULT_SEED", ""), &[]).unwrap().default_seed, None);
+        assert_eq!(parse_lm_config(&with_line("DEFAULT_SEED", "42"), &[]).unwrap().default_seed, Some(42));
+        assert!(parse_err(&with_line("DEFAULT_SEED", "abc")).contains("DEFAULT_SEED"));
+    }
+
+    #[tokio::test]
+    async fn test_api_connectivity_reports_typed_errors() {
+        // Reserve a loopback port and close it again, so the first probe is refused before any request is sent
+        let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let error = test_api_connectivity(&format!("http://{}", addr), "test-model").await.unwrap_err();
+        assert!(matches!(error, BotError::ConnectionRefused { .. }), "{:?}", error);
+    }
+
+    #[test]
+    fn test_parse_lm_config_applies_model_overrides() {
+        let overrides = vec![("DEFAULT_REASON_MODEL".to_string(), "runtime-model".to_string())];
+        let config = parse_lm_config(VALID_CONFIG, &overrides).unwrap();
+        assert_eq!(config.default_reason_model, "runtime-model");
+        assert_eq!(config.default_model, "test-model");
+    }
+
+    #[test]
+    fn test_parse_lm_config_tone() {
+        assert!(tone_directive("concise").is_some());
+        assert!(tone_directive("Formal").is_some());
+        assert!(tone_directive("pirate").is_none());
+        assert_eq!(tone_preset_names(), "concise, formal, friendly, technical");
+
+        assert_eq!(parse_lm_config(VALID_CONFIG, &[]).unwrap().default_tone, None);
+        assert_eq!(parse_lm_config(&with_line("TONE", "Friendly"), &[]).unwrap().default_tone.as_deref(), Some("friendly"));
+        assert!(parse_err(&with_line("TONE", "pirate")).contains("TONE"));
+    }
+
+    #[test]
+    fn test_parse_lm_config_max_retries() {
+        assert_eq!(parse_lm_config(VALID_CONFIG, &[]).unwrap().max_retries, DEFAULT_LM_MAX_RETRIES);
+        assert_eq!(parse_lm_config(&with_line("LM_MAX_RETRIES", "5"), &[]).unwrap().max_retries, 5);
+        assert!(parse_err(&with_line("LM_MAX_RETRIES", "0")).contains("LM_MAX_RETRIES"));
+        assert!(parse_err(&with_line("LM_MAX_RETRIES", "11")).contains("LM_MAX_RETRIES"));
+    }
+
+    #[test]
+    fn test_parse_lm_config_model_profiles() {
+        let config = parse_lm_config(&with_line("MODEL_PROFILES", "qwen3:4b{temperature=0.6, max_tokens=8192}; Llama3{seed=7}"), &[]).unwrap();
+        assert_eq!(config.model_profiles.len(), 2);
+        let qwen = config.sampling_for("qwen3:4b");
+        assert_eq!((qwen.temperature, qwen.max_tokens, qwen.seed), (0.6, 8192, config.default_seed));
+        let llama = config.sampling_for("llama3");
+        assert_eq!((llama.temperature, llama.max_tokens, llama.seed), (config.default_temperature, config.default_max_tokens, Some(7)));
+        assert_eq!(config.sampling_for("other").temperature, config.default_temperature);
+
+        // A per-request --seed beats the profile seed but leaves the profile itself alone
+        let mut seeded = config.clone();
+        seeded.seed_override = Some(99);
+        assert_eq!(seeded.sampling_for("llama3").seed, Some(99));
+        assert_eq!(seeded.sampling_for("qwen3:4b").temperature, 0.6);
+        assert_eq!(seeded.model_profiles["Llama3"].seed, Some(7));
+
+        assert!(parse_lm_config(VALID_CONFIG, &[]).unwrap().model_profiles.is_empty());
+        assert!(parse_err(&with_line("MODEL_PROFILES", "qwen{temperature=3}")).contains("between 0.0 and 2.0"));
+        assert!(parse_err(&with_line("MODEL_PROFILES", "qwen{top_p=0.9}")).contains("Unknown MODEL_PROFILES setting"));
+        assert!(parse_err(&with_line("MODEL_PROFILES", "qwen temperature=0.5")).contains("must look like"));
+    }
+}
@@ -12,7 +12,7 @@
 use serenity::{
     client::Context,
     model::application::{
-        command::{Command, CommandOptionType},
+        command::{Command, CommandOptionType, CommandType},
         interaction::{application_command::ApplicationCommandInteraction, InteractionResponseType},
     },
 };
@@ -27,6 +27,27 @@ use crate::ReasonContextMap;
 pub async fn handle_slash_command(ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let command_name = &interaction.data.name;
     
+    // ^disable covers the slash and context-menu forms of a command too ("Summarize" resolves to sum)
+    if let (Some(guild_id), Some(primary_name)) = (interaction.guild_id, crate::resolve_command_name(command_name)) {
+        if crate::is_command_disabled(guild_id, primary_name) {
+            println!("🚫 Blocked disabled slash command '{}' from {} in guild {}", command_name, interaction.user.name, guild_id);
+            return reply_ephemeral(ctx, interaction, &format!("🚫 `{}` is disabled on this server.", primary_name)).await;
+        }
+    }
+    
+    // Heavy commands honor the ALLOWED_CHANNELS / DENIED_CHANNELS restrictions
+    let is_heavy_command = matches!(command_name.as_str(), "reason" | "sum" | "Summarize");
+    if is_heavy_command && !crate::commands::util::heavy_command_allowed_in(interaction.channel_id) {
+        return reply_ephemeral(ctx, interaction, "🚫 This command isn't allowed in this channel.").await;
+    }
+    
+    // Slash prompts skip the framework's before hook, so they get the moderation pre-check here
+    if let Some(pattern) = crate::commands::util::moderation_match(&option_text(interaction)) {
+        log::warn!("Moderation blocked /{} from {} ({}) in channel {}: matched /{}/",
+                   command_name, interaction.user.name, interaction.user.id, interaction.channel_id, pattern);
+        return reply_ephemeral(ctx, interaction, crate::commands::util::MODERATION_REFUSAL).await;
+    }
+    
     match command_name.as_str() {
         "ping" => handle_ping_slash(ctx, interaction).await?,
         "echo" => handle_echo_slash(ctx, interaction).await?,
@@ -37,6 +58,7 @@ pub async fn handle_slash_command(ctx: &Context, interaction: &ApplicationComman
         "help" => handle_help_slash(ctx, interaction).await?,
         "clearcontext" => handle_clearcontext_slash(ctx, interaction).await?,
         "clearreasoncontext" => handle_clearreasoncontext_slash(ctx, interaction).await?,
+        "Summarize" => handle_summarize_message_command(ctx, interaction).await?,
         _ => {
             let response_text = format!("Unknown slash command: {}", command_name);
             interaction
@@ -52,6 +74,29 @@ pub async fn handle_slash_command(ctx: &Context, interaction: &ApplicationComman
     Ok(())
 }
 
+/// Reply to an interaction with a message only the invoking user can see
+async fn reply_ephemeral(ctx: &Context, interaction: &ApplicationCommandInteraction, text: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    interaction
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| message.content(text).ephemeral(true))
+        })
+        .await?;
+    Ok(())
+}
+
+/// Every string option of a slash command joined together, for the moderation pre-check
+fn option_text(interaction: &ApplicationCommandInteraction) -> String {
+    interaction
+        .data
+        .options
+        .iter()
+        .filter_map(|option| option.value.as_ref().and_then(|value| value.as_str()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 // ============================================================================
 // INDIVIDUAL SLASH COMMAND HANDLERS
 // ============================================================================
@@ -453,6 +498,57 @@ async fn handle_clearreasoncontext_slash(ctx: &Context, interaction: &Applicatio
 // SLASH COMMAND REGISTRATION
 // ============================================================================
 
+/// Handle the "Summarize" message context menu command
+/// Runs the sum pipeline on the target message (first URL, or its text) and replies ephemerally
+async fn handle_summarize_message_command(ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let target_content = interaction
+        .data
+        .target_id
+        .and_then(|target_id| interaction.data.resolved.messages.get(&target_id.to_message_id()))
+        .map(|message| message.content.clone())
+        .unwrap_or_default();
+    
+    // Counted as in flight so shutdown can wait for it to finish
+    let _heavy_guard = crate::start_heavy_command();
+    // Global cap on simultaneous backend requests, like ^sum; held until the summary is sent
+    let _backend_permit = match crate::commands::util::try_acquire_backend_permit() {
+        Some(permit) => permit,
+        None => {
+            println!("[BUSY] Rejected Summarize from {}: all backend slots in use", interaction.user.name);
+            return reply_ephemeral(ctx, interaction, crate::commands::util::BUSY_MESSAGE).await;
+        }
+    };
+    
+    // Defer ephemerally since fetching and summarizing can take a while
+    interaction
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::DeferredChannelMessageWithSource)
+                .interaction_response_data(|message| message.ephemeral(true))
+        })
+        .await?;
+    
+    let response_content = match crate::commands::sum::summarize_message_content(&target_content).await {
+        Ok(summary) => summary,
+        Err(e) => format!("❌ Could not summarize this message: {}", e),
+    };
+    
+    let chunks = crate::commands::util::split_for_discord(&response_content, crate::commands::util::DISCORD_MESSAGE_LIMIT);
+    for (i, chunk) in chunks.iter().enumerate() {
+        if i == 0 {
+            interaction
+                .edit_original_interaction_response(&ctx.http, |response| response.content(chunk))
+                .await?;
+        } else {
+            interaction
+                .create_followup_message(&ctx.http, |followup| followup.content(chunk).ephemeral(true))
+                .await?;
+        }
+    }
+    
+    Ok(())
+}
+
 /// Register all slash commands with Discord
 pub async fn register_slash_commands(http: &serenity::http::Http) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let commands = vec![
@@ -571,6 +667,14 @@ pub async fn register_slash_commands(http: &serenity::http::Http) -> Result<(),
                 .description("Clear your personal reasoning context")
         })
         .await?,
+        
+        // Message context menu commands (right-click a message > Apps)
+        Command::create_global_application_command(http, |command| {
+            command
+                .name("Summarize")
+                .kind(CommandType::Message)
+        })
+        .await?,
     ];
     
     println!("✅ Registered {} slash commands with Discord", commands.len());
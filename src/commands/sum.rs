@@ -4,11 +4,17 @@
 //
 // Key Features:
 // - Summarizes arbitrary webpages and YouTube videos
+// - Summarizes RSS/Atom feeds as a digest of their latest entries (--items N)
 // - Uses yt-dlp for YouTube transcript extraction
 // - Cleans and processes VTT/HTML content
 // - RAG (map-reduce) chunking for long content
 // - Real-time streaming of summary to Discord
 // - Multi-path config and prompt loading
+// - On-disk summary cache per URL/model/format (skipped with --no-cache-write; ^sumcache inspects/clears it)
+// - Optional entity extraction (--entities): people, organizations, dates, locations and key figures
+// - Two-source comparison (--compare): agreements, contradictions and points unique to each source
+// - SSRF guard: refuses non-http(s) schemes and hosts resolving to private/loopback/link-local addresses,
+//   with an optional SUM_ALLOWED_DOMAINS allowlist
 // - Robust error handling and logging
 // - Self-contained with no external module dependencies
 //
@@ -25,15 +31,19 @@ use serenity::{
 };
 use std::time::Duration;
 use std::fs;
-use std::process::Command;
 use uuid::Uuid;
 use log::{info, warn, error, debug, trace};
 use serde::{Deserialize, Serialize};
 use regex::Regex;
+use once_cell::sync::Lazy;
 use std::time::Instant;
 use sha2::{Sha256, Digest};
 use std::collections::HashMap;
 use tokio::sync::OnceCell;
+use futures_util::StreamExt;
+use crate::commands::util::{current_request_id, parse_youtube_url, split_for_discord, empty_response_message, empty_response_retry, invocation_request_id, is_empty_response, strip_think_blocks, truncate_chars, style_output, with_error_id, with_request_id, with_response_footer};
+use crate::commands::error::BotError;
+use crate::commands::search::SamplingConfig;
 
 // ============================================================================
 // SELF-CONTAINED COMPONENTS FROM SEARCH.RS AND REASON.RS
@@ -45,7 +55,7 @@ static HTTP_CLIENT: OnceCell<reqwest::Client> = OnceCell::const_new();
 // Initialize shared HTTP client with optimized settings
 pub async fn get_http_client() -> &'static reqwest::Client {
     HTTP_CLIENT.get_or_init(|| async {
-        reqwest::Client::builder()
+        let builder = reqwest::Client::builder()
             .timeout(Duration::from_secs(120)) // Increased timeout for LM Studio
             .connect_timeout(Duration::from_secs(30)) // Connection timeout
             .pool_idle_timeout(Duration::from_secs(90)) // Keep connections alive
@@ -55,12 +65,35 @@ pub async fn get_http_client() -> &'static reqwest::Client {
             .http2_keep_alive_interval(Duration::from_secs(30)) // HTTP/2 keepalive
             .http2_keep_alive_timeout(Duration::from_secs(10)) // HTTP/2 keepalive timeout
             .http2_keep_alive_while_idle(true) // Keep HTTP/2 alive when idle
-            .user_agent("Meri-Bot-Rust-Client/1.0") // Identify the client
+            .user_agent("Meri-Bot-Rust-Client/1.0"); // Identify the client
+        crate::commands::util::apply_proxy_settings(builder, "Sum")
             .build()
             .expect("Failed to create HTTP client")
     }).await
 }
 
+// Separate client for fetching user-supplied URLs: redirects are re-checked against the SSRF rules and
+// hostnames that resolve to internal addresses are refused at connect time
+// (the shared client above also talks to the local LM server, so it cannot refuse private hosts)
+static FETCH_CLIENT: OnceCell<reqwest::Client> = OnceCell::const_new();
+
+// Maximum redirects followed when fetching a user-supplied URL
+const MAX_FETCH_REDIRECTS: usize = 10;
+
+pub async fn get_fetch_client() -> &'static reqwest::Client {
+    FETCH_CLIENT.get_or_init(|| async {
+        let builder = reqwest::Client::builder()
+            .timeout(Duration::from_secs(120))
+            .connect_timeout(Duration::from_secs(30))
+            .redirect(crate::commands::util::screened_redirect_policy(MAX_FETCH_REDIRECTS))
+            .dns_resolver(std::sync::Arc::new(crate::commands::util::ScreenedResolver))
+            .user_agent("Meri-Bot-Rust-Client/1.0");
+        crate::commands::util::apply_proxy_settings(builder, "Sum fetch")
+            .build()
+            .expect("Failed to create fetch HTTP client")
+    }).await
+}
+
 // Chat message structure for context
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatMessage {
@@ -83,159 +116,34 @@ pub struct LMConfig {
     pub response_format_padding: usize,
     pub default_vision_model: String,
     pub default_seed: Option<i64>, // Optional seed for reproducible responses
+    pub summary_max_tokens: i32,   // Max tokens for the final summary call (SUMMARY_MAX_TOKENS)
+    pub summary_temperature: f32,  // Temperature for the final summary call (SUMMARY_TEMPERATURE)
+    pub max_retries: u32,          // Attempts per chat request (LM_MAX_RETRIES)
+    pub model_profiles: HashMap<String, crate::commands::search::ModelProfile>, // Per-model overrides (MODEL_PROFILES)
 }
 
-/// Enhanced connectivity test function
-pub async fn test_api_connectivity(config: &LMConfig) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let client = get_http_client().await;
-    
-    println!("[DEBUG][CONNECTIVITY] Testing API connectivity to: {}", config.base_url);
-    
-    // Test 1: Basic server connectivity
-    let basic_response = client
-        .get(&config.base_url)
-        .timeout(Duration::from_secs(10))
-        .send()
-        .await;
-    
-    match basic_response {
-        Ok(response) => {
-            println!("[DEBUG][CONNECTIVITY] Basic connectivity OK - Status: {}", response.status());
-        }
-        Err(e) => {
-            let error_msg = format!("{}", e);
-            if error_msg.contains("os error 10013") || error_msg.contains("access permissions") {
-                return Err(format!(
-                    "🚫 **Windows Network Permission Error (10013)**\n\n\
-                    Cannot connect to LM Studio at `{}`\n\n\
-                    **Solutions:**\n\
-                    • **Add Firewall Exception**: Windows Defender Firewall → Allow an app → Add this program\n\
-                    • **Run as Administrator**: Try running the bot with administrator privileges\n\
-                    • **Check LM Studio**: Ensure LM Studio is running and accessible\n\
-                    • **Try localhost**: Use `http://127.0.0.1:1234` instead of `http://localhost:1234`\n\
-                    • **Check Port**: Verify no other application is using the port\n\n\
-                    **Original error:** {}", 
-                    config.base_url, e
-                ).into());
-            } else if error_msg.contains("timeout") || error_msg.contains("timed out") {
-                return Err(format!(
-                    "⏰ **Connection Timeout**\n\n\
-                    Cannot reach LM Studio server at `{}` within 10 seconds\n\n\
-                    **Solutions:**\n\
-                    • **Check LM Studio**: Ensure LM Studio is running and responsive\n\
-                    • **Network Connection**: Verify your network connection is stable\n\
-                    • **Server Load**: LM Studio might be overloaded - wait and retry\n\
-                    • **Firewall**: Check if firewall is blocking the connection\n\n\
-                    **Original error:** {}", 
-                    config.base_url, e
-                ).into());
-            } else if error_msg.contains("refused") || error_msg.contains("connection refused") {
-                return Err(format!(
-                    "🚫 **Connection Refused**\n\n\
-                    LM Studio at `{}` is not accepting connections\n\n\
-                    **Solutions:**\n\
-                    • **Start LM Studio**: Make sure LM Studio is running\n\
-                    • **Check Port**: Verify LM Studio is listening on the correct port (usually 1234)\n\
-                    • **Load Model**: Ensure a model is loaded in LM Studio\n\
-                    • **Server Status**: Check LM Studio's server status indicator\n\
-                    • **Alternative Port**: Try port 11434 if using Ollama instead\n\n\
-                    **Original error:** {}", 
-                    config.base_url, e
-                ).into());
-            } else if error_msg.contains("dns") || error_msg.contains("name resolution") {
-                return Err(format!(
-                    "🌐 **DNS Resolution Error**\n\n\
-                    Cannot resolve hostname in `{}`\n\n\
-                    **Solutions:**\n\
-                    • **Use IP Address**: Try `http://127.0.0.1:1234` instead of `http://localhost:1234`\n\
-                    • **Check Hostname**: Verify the hostname is correct\n\
-                    • **DNS Settings**: Check your DNS configuration\n\n\
-                    **Original error:** {}", 
-                    config.base_url, e
-                ).into());
-            } else {
-                return Err(format!(
-                    "🔗 **Connection Error**\n\n\
-                    Cannot connect to LM Studio at `{}`\n\n\
-                    **Solutions:**\n\
-                    • **Check URL**: Verify the base URL in lmapiconf.txt\n\
-                    • **Start LM Studio**: Ensure LM Studio is running\n\
-                    • **Network**: Check your network connection\n\
-                    • **Firewall**: Verify firewall settings\n\n\
-                    **Original error:** {}", 
-                    config.base_url, e
-                ).into());
-            }
+impl SamplingConfig for LMConfig {
+    fn sampling_defaults(&self) -> crate::commands::search::SamplingParams {
+        crate::commands::search::SamplingParams {
+            temperature: self.default_temperature,
+            max_tokens: self.default_max_tokens,
+            seed: self.default_seed,
         }
     }
-    
-    // Test 2: API endpoint availability
-    let api_url = format!("{}/v1/chat/completions", config.base_url);
-    let test_payload = serde_json::json!({
-        "model": config.default_model,
-        "messages": [{"role": "user", "content": "test"}],
-        "max_tokens": 1,
-        "temperature": 0.1
-    });
-    
-    println!("[DEBUG][CONNECTIVITY] Testing API endpoint: {}", api_url);
-    
-    let api_response = client
-        .post(&api_url)
-        .json(&test_payload)
-        .timeout(Duration::from_secs(60)) // 1 minute for API endpoint test
-        .send()
-        .await;
-    
-    match api_response {
-        Ok(response) => {
-            let status = response.status();
-            if status.is_success() || status == 400 || status == 422 {
-                // 400/422 are acceptable - means API is responding but request format might be wrong
-                println!("[DEBUG][CONNECTIVITY] API endpoint OK - Status: {}", status);
-                return Ok(());
-            } else if status == 404 {
-                return Err(format!(
-                    "🚫 **API Endpoint Not Found (404)**\n\n\
-                    The endpoint `{}` was not found\n\n\
-                    **Solutions:**\n\
-                    • **Check LM Studio Version**: Ensure you're using a recent version that supports OpenAI API\n\
-                    • **Enable API Server**: Make sure the 'Start Server' option is enabled in LM Studio\n\
-                    • **Correct Port**: LM Studio usually uses port 1234, Ollama uses 11434\n\
-                    • **API Path**: Verify the API path is `/v1/chat/completions`\n\n\
-                    **Current URL:** {}", 
-                    api_url, config.base_url
-                ).into());
-            } else {
-                let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-                return Err(format!(
-                    "🚫 **API Error (HTTP {})**\n\n\
-                    LM Studio API returned an error\n\n\
-                    **Response:** {}\n\n\
-                    **Solutions:**\n\
-                    • **Load Model**: Ensure a model is loaded in LM Studio\n\
-                    • **Check Model Name**: Verify the model name in lmapiconf.txt matches loaded model\n\
-                    • **Server Status**: Check LM Studio's status and logs\n\n\
-                    **API URL:** {}", 
-                    status, error_text, api_url
-                ).into());
-            }
-        }
-        Err(e) => {
-            // API test failed, but basic connectivity worked, so this might be a model/configuration issue
-            println!("[DEBUG][CONNECTIVITY] API test failed but basic connectivity OK: {}", e);
-            return Err(format!(
-                "⚠️ **API Configuration Issue**\n\n\
-                Basic connectivity to `{}` works, but API test failed\n\n\
-                **Likely Issues:**\n\
-                • **Model Not Loaded**: No model is loaded in LM Studio\n\
-                • **Wrong Model Name**: Model name in lmapiconf.txt doesn't match loaded model\n\
-                • **API Not Enabled**: LM Studio server is not started\n\
-                • **Version Issue**: LM Studio version doesn't support OpenAI API\n\n\
-                **Error:** {}", 
-                config.base_url, e
-            ).into());
-        }
+
+    fn model_profiles(&self) -> &HashMap<String, crate::commands::search::ModelProfile> {
+        &self.model_profiles
+    }
+}
+
+impl LMConfig {
+    // Final summary parameters: SUMMARY_TEMPERATURE / SUMMARY_MAX_TOKENS, then the model's profile
+    fn summary_sampling_for(&self, model: &str) -> crate::commands::search::SamplingParams {
+        crate::commands::search::SamplingParams {
+            temperature: self.summary_temperature,
+            max_tokens: self.summary_max_tokens,
+            seed: None,
+        }.for_model(&self.model_profiles, model)
     }
 }
 
@@ -246,48 +154,11 @@ pub async fn load_lm_config() -> Result<LMConfig, Box<dyn std::error::Error + Se
     trace!("[TRACE][SUM][load_lm_config] Function: load_lm_config()");
     trace!("[TRACE][SUM][load_lm_config] Current working dir: {:?}", std::env::current_dir());
     
-    let config_paths = [
-        "lmapiconf.txt",
-        "../lmapiconf.txt", 
-        "../../lmapiconf.txt",
-        "src/lmapiconf.txt"
-    ];
-    
-    trace!("[TRACE][SUM][load_lm_config] Config paths to try: {:?}", config_paths);
-    
-    let mut config_content = String::new();
-    let mut config_file_found = false;
-    let mut config_file_path = "";
-    
-    // Try to read from multiple possible locations
-    trace!("[TRACE][SUM][load_lm_config] === FILE SEARCH LOOP ===");
-    for (index, path) in config_paths.iter().enumerate() {
-        trace!("[TRACE][SUM][load_lm_config] Attempt {}: trying path '{}'", index + 1, path);
-        trace!("[TRACE][SUM][load_lm_config] Path exists: {}", std::path::Path::new(path).exists());
-        
-        match fs::read_to_string(path) {
-            Ok(content) => {
-                trace!("[TRACE][SUM][load_lm_config] SUCCESS: File read from '{}'", path);
-                trace!("[TRACE][SUM][load_lm_config] Content length: {} bytes", content.len());
-                trace!("[TRACE][SUM][load_lm_config] Content preview: {}", &content[..std::cmp::min(200, content.len())]);
-                
-                config_content = content;
-                config_file_found = true;
-                config_file_path = path;
-                println!("✅ Configuration loaded from: {}", path);
-                break;
-            }
-            Err(e) => {
-                trace!("[TRACE][SUM][load_lm_config] FAILED: Could not read '{}': {}", path, e);
-                continue;
-            }
-        }
-    }
-    
-    trace!("[TRACE][SUM][load_lm_config] File search complete. Found: {}", config_file_found);
+    trace!("[TRACE][SUM][load_lm_config] Config search dirs: {:?}", crate::commands::util::CONFIG_SEARCH_DIRS);
     
-    if !config_file_found {
-        return Err(format!(
+    let (config_content, config_file_path) = match crate::commands::util::find_and_read_config("lmapiconf.txt") {
+        Some(found) => found,
+        None => return Err(format!(
             "❌ **Configuration File Not Found**\n\n\
             Could not find `lmapiconf.txt` in any of these locations:\n\
             • ./lmapiconf.txt\n\
@@ -295,8 +166,12 @@ pub async fn load_lm_config() -> Result<LMConfig, Box<dyn std::error::Error + Se
             • ../../lmapiconf.txt\n\
             • src/lmapiconf.txt\n\n\
             **Solution:** Copy `example_lmapiconf.txt` to `lmapiconf.txt` and configure it for your setup."
-        ).into());
-    }
+        ).into()),
+    };
+    trace!("[TRACE][SUM][load_lm_config] SUCCESS: File read from '{}' ({} bytes)", config_file_path, config_content.len());
+    trace!("[TRACE][SUM][load_lm_config] Content preview: {}", truncate_chars(&config_content, 200));
+    println!("✅ Configuration loaded from: {}", config_file_path);
+    crate::commands::util::record_config_source("lmapiconf.txt (sum)", &config_file_path);
     
     // Parse configuration
     let mut config_map = HashMap::new();
@@ -314,6 +189,8 @@ pub async fn load_lm_config() -> Result<LMConfig, Box<dyn std::error::Error + Se
             println!("⚠️ Warning: Invalid line {} in {}: {}", line_num + 1, config_file_path, line);
         }
     }
+
+    crate::commands::search::apply_model_overrides(&mut config_map, &crate::commands::search::model_overrides());
     
     // Validate required keys
     let required_keys = [
@@ -382,6 +259,9 @@ pub async fn load_lm_config() -> Result<LMConfig, Box<dyn std::error::Error + Se
         ).into());
     }
     
+    // ^sum requests use SUM_TIMEOUT (default 300 seconds), not the short global timeout
+    let timeout = crate::commands::search::resolve_command_timeout(&config_map, "SUM_TIMEOUT", crate::commands::search::DEFAULT_LONG_COMMAND_TIMEOUT)?;
+    
     let default_model = config_map.get("DEFAULT_MODEL")
         .ok_or("DEFAULT_MODEL not found in lmapiconf.txt")?
         .clone();
@@ -453,6 +333,37 @@ pub async fn load_lm_config() -> Result<LMConfig, Box<dyn std::error::Error + Se
         .transpose()
         .map_err(|_| "DEFAULT_SEED must be a valid integer if specified")?;
     
+    // Optional summary-specific generation settings, falling back to the chat defaults
+    let summary_max_tokens = match config_map.get("SUMMARY_MAX_TOKENS").filter(|s| !s.trim().is_empty()) {
+        Some(value) => value.trim().parse::<i32>()
+            .map_err(|_| "SUMMARY_MAX_TOKENS must be a valid number")?,
+        None => default_max_tokens,
+    };
+    
+    if summary_max_tokens <= 0 || summary_max_tokens > 32768 {
+        return Err(format!(
+            "❌ **Invalid Summary Max Tokens Value**\n\n\
+            SUMMARY_MAX_TOKENS must be between 1 and 32768\n\
+            Current value: {}",
+            summary_max_tokens
+        ).into());
+    }
+    
+    let summary_temperature = match config_map.get("SUMMARY_TEMPERATURE").filter(|s| !s.trim().is_empty()) {
+        Some(value) => value.trim().parse::<f32>()
+            .map_err(|_| "SUMMARY_TEMPERATURE must be a valid number")?,
+        None => default_temperature,
+    };
+    
+    if summary_temperature < 0.0 || summary_temperature > 2.0 {
+        return Err(format!(
+            "❌ **Invalid Summary Temperature Value**\n\n\
+            SUMMARY_TEMPERATURE must be between 0.0 and 2.0\n\
+            Current value: {}",
+            summary_temperature
+        ).into());
+    }
+    
     let config = LMConfig {
         base_url,
         timeout,
@@ -466,11 +377,15 @@ pub async fn load_lm_config() -> Result<LMConfig, Box<dyn std::error::Error + Se
         response_format_padding,
         default_vision_model,
         default_seed,
+        summary_max_tokens,
+        summary_temperature,
+        max_retries: crate::commands::search::parse_max_retries(&config_map)?,
+        model_profiles: crate::commands::search::parse_model_profiles(&config_map)?,
     };
     
     // Test connectivity after loading configuration
     println!("🔍 Testing API connectivity...");
-    if let Err(e) = test_api_connectivity(&config).await {
+    if let Err(e) = crate::commands::search::test_api_connectivity(&config.base_url, &config.default_model).await {
         return Err(format!(
             "❌ **Connectivity Test Failed**\n\n\
             Configuration loaded successfully from `{}`, but connectivity test failed:\n\n\
@@ -502,7 +417,8 @@ pub async fn chat_completion(
     trace!("[TRACE][SUM][chat_completion] Config base URL: {}", config.base_url);
     trace!("[TRACE][SUM][chat_completion] Config temperature: {}", config.default_temperature);
     
-    let result = chat_completion_with_retries(messages, model, config, max_tokens, 3).await;
+    let result = chat_completion_with_retries(messages, model, config, max_tokens, config.max_retries).await
+        .map(|(content, _)| content);
     
     // Trace-level function exit
     match &result {
@@ -519,192 +435,85 @@ pub async fn chat_completion(
     result
 }
 
+/// Chat completion that also returns the backend's finish_reason, when it reported one
+async fn chat_completion_with_finish_reason(
+    messages: Vec<ChatMessage>,
+    model: &str,
+    config: &LMConfig,
+) -> Result<(String, Option<String>), Box<dyn std::error::Error + Send + Sync>> {
+    chat_completion_with_retries(messages, model, config, None, config.max_retries).await
+}
+
 /// Chat completion with configurable retry attempts
+/// Returns the reply and the backend's finish_reason, when it reported one
 async fn chat_completion_with_retries(
     messages: Vec<ChatMessage>,
     model: &str,
     config: &LMConfig,
     max_tokens: Option<i32>,
     max_retries: u32,
-) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+) -> Result<(String, Option<String>), Box<dyn std::error::Error + Send + Sync>> {
     let client = get_http_client().await;
     let api_url = format!("{}/v1/chat/completions", config.base_url);
     
+    let sampling = config.sampling_for(model);
     let chat_request = serde_json::json!({
         "model": model,
         "messages": messages,
-        "temperature": config.default_temperature,
-        "max_tokens": max_tokens.unwrap_or(config.default_max_tokens),
+        "temperature": sampling.temperature,
+        "max_tokens": max_tokens.unwrap_or(sampling.max_tokens),
         "stream": false,
-        "seed": config.default_seed
+        "seed": sampling.seed
     });
 
-    let mut last_error: Option<Box<dyn std::error::Error + Send + Sync>> = None;
-    
-    for attempt in 1..=max_retries {
-        println!("[DEBUG][CHAT] Attempt {}/{} - Sending request to: {}", attempt, max_retries, api_url);
-        
-        let start_time = std::time::Instant::now();
-        
-        let response = match client
-            .post(&api_url)
-            .json(&chat_request)
-            .timeout(Duration::from_secs(config.timeout))
-            .send()
-            .await
-        {
-            Ok(resp) => {
-                let elapsed = start_time.elapsed();
-                println!("[DEBUG][CHAT] Request completed in {:.2}s - Status: {}", elapsed.as_secs_f32(), resp.status());
-                resp
-            }
-            Err(e) => {
-                let elapsed = start_time.elapsed();
-                println!("[DEBUG][CHAT] Request failed after {:.2}s: {}", elapsed.as_secs_f32(), e);
-                
-                let error_msg = format!("{}", e);
-                
-                // Check for specific error types that might benefit from retry
-                let should_retry = attempt < max_retries && (
-                    error_msg.contains("timeout") ||
-                    error_msg.contains("connection reset") ||
-                    error_msg.contains("connection aborted") ||
-                    error_msg.contains("broken pipe") ||
-                    error_msg.contains("connection closed")
-                );
-                
-                if should_retry {
-                    let delay = Duration::from_millis(1000 * attempt as u64); // Exponential backoff
-                    println!("[DEBUG][CHAT] Retrying in {:.1}s...", delay.as_secs_f32());
-                    tokio::time::sleep(delay).await;
-                    last_error = Some(Box::new(std::io::Error::new(std::io::ErrorKind::Other, format!("{}", e))));
-                    continue;
-                } else {
-                    // Don't retry for these errors - they're likely configuration issues
-                    if error_msg.contains("os error 10013") || error_msg.contains("access permissions") {
-                        return Err(format!(
-                            "🚫 **Windows Network Permission Error**\n\n\
-                            Cannot connect to LM Studio API\n\n\
-                            **Quick Fixes:**\n\
-                            • **Run as Administrator**: Right-click and 'Run as administrator'\n\
-                            • **Windows Firewall**: Add firewall exception for this program\n\
-                            • **Try localhost**: Use `http://127.0.0.1:1234` in lmapiconf.txt\n\n\
-                            **Current URL:** {}\n\
-                            **Error:** {}", 
-                            config.base_url, e
-                        ).into());
-                    } else if error_msg.contains("refused") || error_msg.contains("connection refused") {
-                        return Err(format!(
-                            "🚫 **Connection Refused**\n\n\
-                            LM Studio is not accepting connections\n\n\
-                            **Solutions:**\n\
-                            • **Start LM Studio**: Make sure LM Studio is running\n\
-                            • **Load Model**: Ensure a model is loaded\n\
-                            • **Enable Server**: Click 'Start Server' in LM Studio\n\
-                            • **Check Port**: Verify port 1234 is available\n\n\
-                            **Current URL:** {}\n\
-                            **Error:** {}", 
-                            config.base_url, e
-                        ).into());
-                    } else {
-                        return Err(format!("API request failed: {}", e).into());
-                    }
-                }
-            }
-        };
+    // Transient failures are retried by the shared helper; exhausted retries report the attempt count
+    let response = crate::commands::search::send_chat_request(
+        client, &api_url, &chat_request, model, config.timeout, max_retries,
+    ).await?;
 
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unable to read error response".to_string());
-            
-            // Check if this is a retryable server error
-            let is_server_error = status.is_server_error();
-            let should_retry = attempt < max_retries && is_server_error;
-            
-            if should_retry {
-                let delay = Duration::from_millis(1000 * attempt as u64);
-                println!("[DEBUG][CHAT] Server error ({}), retrying in {:.1}s...", status, delay.as_secs_f32());
-                tokio::time::sleep(delay).await;
-                last_error = Some(Box::new(std::io::Error::new(std::io::ErrorKind::Other, format!("HTTP {} - {}", status, error_text))));
-                continue;
-            } else {
-                return Err(format!(
-                    "🚫 **API Error (HTTP {})**\n\n\
-                    **Response:** {}\n\n\
-                    **Solutions:**\n\
-                    • **Model Loaded**: Ensure model '{}' is loaded in LM Studio\n\
-                    • **Model Name**: Verify model name matches exactly\n\
-                    • **Server Status**: Check LM Studio server logs\n\
-                    • **Memory**: Ensure sufficient RAM for the model\n\n\
-                    **API URL:** {}", 
-                    status, error_text, model, api_url
-                ).into());
-            }
+    let response_text = response.text().await
+        .map_err(|e| format!("Failed to read response: {}", e))?;
+
+    let response_json: serde_json::Value = match serde_json::from_str(&response_text) {
+        Ok(json) => json,
+        Err(e) => {
+            return Err(BotError::InvalidResponse(format!(
+                "Failed to parse JSON response from LM Studio\n\n\
+                **Response:** {}\n\
+                **Parse Error:** {}\n\n\
+                **Solutions:**\n\
+                • **Update LM Studio**: Ensure you're using a recent version\n\
+                • **Check Model**: Verify the model supports chat completions\n\
+                • **Server Logs**: Check LM Studio logs for errors",
+                response_text.chars().take(500).collect::<String>(), e
+            )).into());
         }
+    };
 
-        // Parse successful response
-        let response_text = match response.text().await {
-            Ok(text) => text,
-            Err(e) => {
-                if attempt < max_retries {
-                    let delay = Duration::from_millis(1000 * attempt as u64);
-                    println!("[DEBUG][CHAT] Failed to read response, retrying in {:.1}s...", delay.as_secs_f32());
-                    tokio::time::sleep(delay).await;
-                    last_error = Some(Box::new(e));
-                    continue;
-                } else {
-                    return Err(format!("Failed to read response: {}", e).into());
-                }
-            }
-        };
-        
-        let response_json: serde_json::Value = match serde_json::from_str(&response_text) {
-            Ok(json) => json,
-            Err(e) => {
-                return Err(format!(
-                    "🚫 **Invalid API Response**\n\n\
-                    Failed to parse JSON response from LM Studio\n\n\
-                    **Response:** {}\n\
-                    **Parse Error:** {}\n\n\
-                    **Solutions:**\n\
-                    • **Update LM Studio**: Ensure you're using a recent version\n\
-                    • **Check Model**: Verify the model supports chat completions\n\
-                    • **Server Logs**: Check LM Studio logs for errors",
-                    response_text.chars().take(500).collect::<String>(), e
-                ).into());
-            }
-        };
-        
-        // Extract content from response
-        if let Some(choices) = response_json["choices"].as_array() {
-            if let Some(first_choice) = choices.get(0) {
-                if let Some(message) = first_choice["message"].as_object() {
-                    if let Some(content) = message["content"].as_str() {
-                        let result = content.trim().to_string();
-                        println!("[DEBUG][CHAT] Success! Generated {} characters", result.len());
-                        return Ok(result);
-                    }
+    // Extract content from response
+    if let Some(choices) = response_json["choices"].as_array() {
+        if let Some(first_choice) = choices.get(0) {
+            if let Some(message) = first_choice["message"].as_object() {
+                if let Some(content) = message["content"].as_str() {
+                    let result = content.trim().to_string();
+                    println!("[DEBUG][CHAT] Success! Generated {} characters", result.len());
+                    let finish_reason = first_choice["finish_reason"].as_str().map(str::to_string);
+                    return Ok((result, finish_reason));
                 }
             }
         }
-        
-        // If we reach here, the JSON structure was unexpected
-        return Err(format!(
-            "🚫 **Unexpected API Response Format**\n\n\
-            LM Studio returned a valid JSON response, but the structure was unexpected\n\n\
-            **Response:** {}\n\n\
-            **Solutions:**\n\
-            • **Update LM Studio**: Ensure compatibility with OpenAI API format\n\
-            • **Check Model**: Verify the model supports chat completions\n\
-            • **API Version**: Ensure you're using a compatible API version",
-            serde_json::to_string_pretty(&response_json).unwrap_or_else(|_| "Unable to format response".to_string())
-        ).into());
     }
-    
-    // All retries exhausted
-    Err(format!("Request failed after {} attempts. Last error: {}", 
-                max_retries, 
-                last_error.map(|e| format!("{}", e)).unwrap_or_else(|| "Unknown error".to_string())
+
+    // If we reach here, the JSON structure was unexpected
+    Err(format!(
+        "🚫 **Unexpected API Response Format**\n\n\
+        LM Studio returned a valid JSON response, but the structure was unexpected\n\n\
+        **Response:** {}\n\n\
+        **Solutions:**\n\
+        • **Update LM Studio**: Ensure compatibility with OpenAI API format\n\
+        • **Check Model**: Verify the model supports chat completions\n\
+        • **API Version**: Ensure you're using a compatible API version",
+        serde_json::to_string_pretty(&response_json).unwrap_or_else(|_| "Unable to format response".to_string())
     ).into())
 }
 
@@ -736,9 +545,29 @@ struct StreamDelta {
 /// Handles summarization of webpages and YouTube videos
 /// Supports:
 ///   - ^sum <url> (webpage or YouTube)
+///   - ^sum --chapters <youtube url> (per-chapter summary when the video has chapter markers)
+///   - ^sum --include-links <url> (append the page's key outbound links)
+///   - ^sum --since <message link or id> [--mine] (catch up on this channel's messages after that point)
+///   - ^sum <url1> <url2> ... (batch mode, one section per URL)
+///   - ^sum --no-cache-write <url> (summarize without storing the result in the summary cache)
+///   - ^sum --entities <url> (append the people, organizations, dates, locations and figures mentioned)
+///   - ^sum --compare <url1> <url2> (summarize both, then contrast agreements, contradictions and unique points)
 pub async fn sum(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    // Everything logged for this invocation carries the same correlation ID
+    with_request_id(invocation_request_id(msg.id.0), sum_command(ctx, msg, args)).await
+}
+
+async fn sum_command(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    // Respect the ALLOWED_CHANNELS / DENIED_CHANNELS restrictions for heavy commands
+    if crate::commands::util::reply_if_channel_blocked(ctx, msg, "sum").await? {
+        return Ok(());
+    }
+    // Counted as in flight so shutdown can wait for it to finish
+    let _heavy_guard = crate::start_heavy_command();
+
     let start_time = std::time::Instant::now();
-    let command_uuid = Uuid::new_v4();
+    // The invocation's correlation ID, also shown on error replies and in the ERROR_LOG_CHANNEL report
+    let command_uuid = current_request_id();
     
     // Trace-level function entry logging
     trace!("[TRACE][SUM] === FUNCTION ENTRY: sum() ===");
@@ -776,7 +605,87 @@ pub async fn sum(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     trace!("🔍 Command initialization details: uuid={}, author_id={}, channel_id={}, message_id={}", 
            command_uuid, msg.author.id, msg.channel_id, msg.id);
     
-    let url = args.message().trim();
+    // Parse leading flags (quote-aware), the remainder is the URL
+    let (flags, remaining_args) = crate::commands::util::parse_leading_flags(
+        args.message().trim(),
+        &["--chapters", "--youtube-chapters", "--include-links", "--links", "--from-timestamp", "--mine", "--no-cache-write", "--entities", "--compare"],
+        &["--format", "--items", "--since", "--level"],
+    );
+    let use_chapters = flags.iter().any(|(flag, _)| flag == "--chapters" || flag == "--youtube-chapters");
+    let include_links = flags.iter().any(|(flag, _)| flag == "--include-links" || flag == "--links");
+    let from_timestamp = flags.iter().any(|(flag, _)| flag == "--from-timestamp");
+    let no_cache_write = flags.iter().any(|(flag, _)| flag == "--no-cache-write");
+    let extract_entities = flags.iter().any(|(flag, _)| flag == "--entities");
+    let format = match flags.iter().find(|(flag, _)| flag == "--format") {
+        Some((_, value)) => match value.as_deref().and_then(SummaryFormat::parse) {
+            Some(format) => format,
+            None => {
+                msg.reply(ctx, "❌ Unknown `--format`. Use `markdown`, `bullets` or `json`.").await?;
+                return Ok(());
+            }
+        },
+        None => SummaryFormat::Markdown,
+    };
+    // --level: how an over-long summary is delivered (condensed to fit, or split across messages)
+    let level = match flags.iter().find(|(flag, _)| flag == "--level") {
+        Some((_, value)) => match value.as_deref().and_then(SummaryLevel::parse) {
+            Some(level) => level,
+            None => {
+                msg.reply(ctx, "❌ Unknown `--level`. Use `brief` or `detailed`.").await?;
+                return Ok(());
+            }
+        },
+        None => SummaryLevel::Standard,
+    };
+    // --items: how many feed entries go into an RSS/Atom digest
+    let feed_items = match flags.iter().find(|(flag, _)| flag == "--items") {
+        Some((_, value)) => match value.as_deref().and_then(|v| v.parse::<usize>().ok()) {
+            Some(items) if (1..=MAX_FEED_ITEMS).contains(&items) => items,
+            _ => {
+                msg.reply(ctx, format!("❌ `--items` must be a number between 1 and {}.", MAX_FEED_ITEMS)).await?;
+                return Ok(());
+            }
+        },
+        None => DEFAULT_FEED_ITEMS,
+    };
+    debug!("🔧 Flags: chapters={}, include_links={}, format={:?}, level={:?}, feed_items={}", use_chapters, include_links, format, level, feed_items);
+    let url = remaining_args.trim();
+
+    // Global cap on simultaneous backend requests; held until this command returns
+    let _backend_permit = match crate::commands::util::acquire_backend_permit_or_reply(ctx, msg, "sum").await? {
+        Some(permit) => permit,
+        None => return Ok(()),
+    };
+
+    // --since summarizes this channel's messages after a given point instead of a URL
+    if let Some((_, since)) = flags.iter().find(|(flag, _)| flag == "--since") {
+        let mine = flags.iter().any(|(flag, _)| flag == "--mine");
+        return sum_channel_since(ctx, msg, since.as_deref().unwrap_or(""), mine, format, level).await;
+    }
+
+    // With no URL given, a PDF attachment is summarized instead
+    if url.is_empty() {
+        if let Some(attachment) = msg.attachments.iter().find(|attachment| is_pdf_attachment(&attachment.filename, attachment.content_type.as_deref())) {
+            return sum_pdf_attachment(ctx, msg, attachment, format, level).await;
+        }
+    }
+
+    // Several URLs (space or newline separated) switch to batch mode
+    let batch_urls: Vec<&str> = url
+        .split_whitespace()
+        .filter(|token| token.starts_with("http://") || token.starts_with("https://"))
+        .collect();
+    // --compare summarizes exactly two sources and contrasts them
+    if flags.iter().any(|(flag, _)| flag == "--compare") {
+        if batch_urls.len() != 2 {
+            msg.reply(ctx, "❌ `--compare` needs exactly two URLs. Usage: `^sum --compare <url1> <url2>`").await?;
+            return Ok(());
+        }
+        return sum_compare(ctx, msg, batch_urls[0], batch_urls[1]).await;
+    }
+    if batch_urls.len() > 1 {
+        return sum_batch(ctx, msg, &batch_urls).await;
+    }
     
     // Trace-level URL processing
     trace!("[TRACE][SUM] === URL PROCESSING ENTRY ===");
@@ -787,7 +696,7 @@ pub async fn sum(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     trace!("[TRACE][SUM] Is empty after trim: {}", url.is_empty());
     trace!("[TRACE][SUM] Contains http://: {}", url.contains("http://"));
     trace!("[TRACE][SUM] Contains https://: {}", url.contains("https://"));
-    trace!("[TRACE][SUM] First 50 chars: '{}'", &url[..std::cmp::min(50, url.len())]);
+    trace!("[TRACE][SUM] First 50 chars: '{}'", truncate_chars(&url, 50));
     
     debug!("🔗 === URL PROCESSING ===");
     debug!("🔗 Raw URL: '{}'", url);
@@ -814,7 +723,7 @@ pub async fn sum(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
         trace!("[TRACE][SUM] Exit status: ERROR - Empty URL");
         trace!("[TRACE][SUM] Exit timestamp: {:?}", std::time::Instant::now());
         
-        msg.reply(ctx, "Please provide a URL to summarize!\n\n**Usage:** `^sum <url>`").await?;
+        msg.reply(ctx, "Please provide a URL to summarize!\n\n**Usage:** `^sum <url>` or `^sum` with a PDF attached").await?;
         debug!("✅ Error message sent successfully");
         return Ok(());
     }
@@ -823,10 +732,8 @@ pub async fn sum(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     debug!("🔍 Validating URL format: {}", url);
     debug!("🔍 URL starts with http://: {}", url.starts_with("http://"));
     debug!("🔍 URL starts with https://: {}", url.starts_with("https://"));
-    debug!("🔍 URL contains youtube.com: {}", url.contains("youtube.com"));
-    debug!("🔍 URL contains youtu.be: {}", url.contains("youtu.be"));
-    trace!("🔍 URL validation details: starts_with_http={}, starts_with_https={}, contains_youtube_com={}, contains_youtu_be={}", 
-           url.starts_with("http://"), url.starts_with("https://"), url.contains("youtube.com"), url.contains("youtu.be"));
+    trace!("🔍 URL validation details: starts_with_http={}, starts_with_https={}", 
+           url.starts_with("http://"), url.starts_with("https://"));
     
     if !url.starts_with("http://") && !url.starts_with("https://") {
         warn!("❌ === INVALID URL FORMAT ERROR ===");
@@ -885,7 +792,7 @@ pub async fn sum(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
             debug!("🔍 Configuration error type: {:?}", std::any::type_name_of_val(&e));
             trace!("🔍 Configuration error: error_type={}, command_uuid={}", 
                    std::any::type_name_of_val(&e), command_uuid);
-            msg.reply(ctx, &format!("❌ **Configuration Error**\n\n{}\n\n**Setup required:** Ensure `lmapiconf.txt` is properly configured with your LM Studio settings.", e)).await?;
+            msg.reply(ctx, with_error_id(style_output(&format!("❌ **Configuration Error**\n\n{}\n\n**Setup required:** Ensure `lmapiconf.txt` is properly configured with your LM Studio settings.", e)))).await?;
             debug!("✅ Configuration error message sent");
             return Ok(());
         }
@@ -900,23 +807,36 @@ pub async fn sum(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     trace!("[TRACE][SUM] === URL TYPE DETECTION ENTRY ===");
     trace!("[TRACE][SUM] URL to analyze: '{}'", url);
     trace!("[TRACE][SUM] URL length: {} chars", url.len());
-    trace!("[TRACE][SUM] Checking youtube.com/...");
-    let contains_youtube_com = url.contains("youtube.com/");
-    trace!("[TRACE][SUM] Contains youtube.com/: {}", contains_youtube_com);
-    trace!("[TRACE][SUM] Checking youtu.be/...");
-    let contains_youtu_be = url.contains("youtu.be/");
-    trace!("[TRACE][SUM] Contains youtu.be/: {}", contains_youtu_be);
-    let is_youtube = contains_youtube_com || contains_youtu_be;
+    // Only a URL that parses as a YouTube video goes to yt-dlp; anything else is fetched as a webpage
+    let youtube_url = parse_youtube_url(url);
+    let is_youtube = youtube_url.is_some();
     trace!("[TRACE][SUM] Final determination - is_youtube: {}", is_youtube);
+    
+    // Short links, shorts and timestamped links all resolve to the canonical watch URL
+    let canonical_url;
+    let url = match &youtube_url {
+        Some(youtube_url) => {
+            canonical_url = youtube_url.canonical_url();
+            debug!("🔗 Normalized YouTube URL: {} -> {}", url, canonical_url);
+            canonical_url.as_str()
+        }
+        None => url,
+    };
+    let transcript_start = if from_timestamp {
+        let start = youtube_url.as_ref().and_then(|youtube_url| youtube_url.start_seconds);
+        if start.is_none() {
+            warn!("⚠️ --from-timestamp given but the URL has no t= offset, summarizing the whole video");
+        }
+        start
+    } else {
+        None
+    };
     trace!("[TRACE][SUM] Content type will be: {}", if is_youtube { "YouTube video" } else { "Webpage" });
     
     debug!("🔍 === URL TYPE DETECTION ===");
     debug!("🔍 Detecting URL type...");
-    debug!("🔍 URL contains youtube.com/: {}", url.contains("youtube.com/"));
-    debug!("🔍 URL contains youtu.be/: {}", url.contains("youtu.be/"));
     debug!("🔍 Final YouTube detection: {}", is_youtube);
-    trace!("🔍 URL type detection details: contains_youtube_com={}, contains_youtu_be={}, is_youtube={}, command_uuid={}", 
-           url.contains("youtube.com/"), url.contains("youtu.be/"), is_youtube, command_uuid);
+    trace!("🔍 URL type detection details: is_youtube={}, command_uuid={}", is_youtube, command_uuid);
     info!("🎯 === CONTENT TYPE DETECTED ===");
     info!("🎯 Processing {} URL: {}", if is_youtube { "YouTube" } else { "webpage" }, url);
     debug!("📊 URL type detection: YouTube = {}", is_youtube);
@@ -931,11 +851,34 @@ pub async fn sum(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     debug!("🎯 Model selection: summarization_model={}, content_type={}", selected_model, if is_youtube { "YouTube" } else { "webpage" });
     trace!("🔍 Model selection: model={}, content_type={}, command_uuid={}", selected_model, if is_youtube { "youtube" } else { "webpage" }, command_uuid);
     
+    // Chapter, timestamp-trimmed, --items and --level summaries differ from what the cache key
+    // (url + model + format) describes, so only whole-content summaries with default settings are cached
+    let cacheable = !use_chapters && transcript_start.is_none() && feed_items == DEFAULT_FEED_ITEMS && level == SummaryLevel::Standard;
+    // --include-links needs the fetched page, so it always takes the full path
+    if cacheable && !include_links {
+        if let Some((summary, age_secs)) = read_cached_summary(url, selected_model, format) {
+            info!("🗄️ Serving cached summary for {} ({}s old)", url, age_secs);
+            let max_length = config.max_discord_message_length - config.response_format_padding;
+            for (i, chunk) in split_for_discord(&with_response_footer(&summary), max_length).iter().enumerate() {
+                if i == 0 {
+                    msg.reply(ctx, chunk).await?;
+                } else {
+                    msg.channel_id.say(ctx, chunk).await?;
+                }
+            }
+            let _ = msg.channel_id.say(ctx, style_output(format!("🗄️ *Cached summary from {} min ago*", age_secs / 60))).await;
+            if extract_entities {
+                post_summary_entities(ctx, msg, &summary, &config, selected_model).await;
+            }
+            return Ok(());
+        }
+    }
+
     // Create response message
     debug!("💬 === DISCORD MESSAGE CREATION ===");
     debug!("💬 Creating initial Discord response message...");
     trace!("🔍 Discord message creation: author={}, channel={}, command_uuid={}", msg.author.name, msg.channel_id, command_uuid);
-    let mut response_msg = msg.reply(ctx, "🔄 Fetching content...").await?;
+    let mut response_msg = msg.reply(ctx, style_output("🔄 Fetching content...")).await?;
     debug!("✅ Initial Discord message sent successfully");
     debug!("📝 Response message ID: {}", response_msg.id);
     debug!("📝 Response message channel ID: {}", response_msg.channel_id);
@@ -965,6 +908,19 @@ pub async fn sum(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
         match fetch_youtube_transcript(url).await {
             Ok(path) => {
                 info!("✅ === YOUTUBE TRANSCRIPT SUCCESS ===");
+                // --from-timestamp: drop the cues before the link's t= offset so only the rest is summarized
+                if let Some(start) = transcript_start {
+                    match fs::read_to_string(&path) {
+                        Ok(vtt) => {
+                            if let Err(e) = fs::write(&path, trim_vtt_from(&vtt, start as f64)) {
+                                warn!("⚠️ Could not trim transcript to {}s: {}", start, e);
+                            } else {
+                                info!("⏩ Transcript trimmed to start at {}", format_chapter_timestamp(start as f64));
+                            }
+                        }
+                        Err(e) => warn!("⚠️ Could not read transcript for trimming: {}", e),
+                    }
+                }
                 info!("✅ YouTube subtitle file created successfully: {}", path);
                 debug!("📁 Subtitle file path: {}", path);
                 debug!("📁 Subtitle file exists: {}", std::path::Path::new(&path).exists());
@@ -976,7 +932,7 @@ pub async fn sum(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
                 match fs::read_to_string(&path) {
                     Ok(file_content) => {
                         debug!("📖 Subtitle file read successfully: {} characters", file_content.len());
-                        debug!("📖 File content preview: {}", &file_content[..std::cmp::min(200, file_content.len())]);
+                        debug!("📖 File content preview: {}", truncate_chars(&file_content, 200));
                         trace!("🔍 Subtitle file read: path={}, length={}, command_uuid={}", path, file_content.len(), command_uuid);
                         
                         let cleaned_content = clean_vtt_content(&file_content);
@@ -984,7 +940,7 @@ pub async fn sum(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
                         debug!("🧹 Cleaning VTT content for statistics only...");
                         debug!("📝 Original subtitle content: {} characters", file_content.len());
                         debug!("📝 Cleaned subtitle content: {} characters", cleaned_content.len());
-                        debug!("📝 Content preview: {}", &cleaned_content[..std::cmp::min(200, cleaned_content.len())]);
+                        debug!("📝 Content preview: {}", truncate_chars(&cleaned_content, 200));
                         debug!("📊 Subtitle statistics: {} characters, {} words", cleaned_content.len(), cleaned_content.split_whitespace().count());
                         debug!("📁 RAG will process the original file: {}", path);
                         trace!("🔍 VTT cleaning for statistics: original_length={}, cleaned_length={}, word_count={}, command_uuid={}", 
@@ -1012,7 +968,7 @@ pub async fn sum(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
                 trace!("🔍 YouTube transcript error: error_type={}, command_uuid={}", 
                        std::any::type_name_of_val(&e), command_uuid);
                 response_msg.edit(ctx, |m| {
-                    m.content(format!("❌ Failed to fetch YouTube transcript: {}", e))
+                    m.content(with_error_id(style_output(format!("❌ Failed to fetch YouTube transcript: {}", e))))
                 }).await?;
                 debug!("✅ YouTube transcript error message sent to Discord");
                 return Ok(());
@@ -1030,12 +986,12 @@ pub async fn sum(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
         log::info!("🌐 Command UUID: {}", command_uuid);
         log::info!("🌐 Processing type: HTML file download and RAG processing");
         
-        match fetch_webpage_content(url).await {
+        match fetch_webpage_content(url, feed_items).await {
             Ok((page_content, html_file_path)) => {
                 info!("✅ === WEBPAGE CONTENT SUCCESS ===");
                 info!("✅ Webpage content fetched successfully: {} characters", page_content.len());
                 info!("💾 HTML file saved for RAG processing: {}", html_file_path);
-                debug!("📄 Content preview: {}", &page_content[..std::cmp::min(200, page_content.len())]);
+                debug!("📄 Content preview: {}", truncate_chars(&page_content, 200));
                 debug!("📊 Webpage statistics: {} characters, {} words", page_content.len(), page_content.split_whitespace().count());
                 debug!("💾 HTML file path: {}", html_file_path);
                 trace!("🔍 Webpage content success: length={}, word_count={}, preview_chars={}, file_path={}, command_uuid={}", 
@@ -1047,7 +1003,7 @@ pub async fn sum(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
                 log::info!("✅ Word count: {} words", page_content.split_whitespace().count());
                 log::info!("✅ HTML file path: {}", html_file_path);
                 log::info!("✅ File exists: {}", std::path::Path::new(&html_file_path).exists());
-                log::info!("✅ Content preview: {}", &page_content[..std::cmp::min(300, page_content.len())]);
+                log::info!("✅ Content preview: {}", truncate_chars(&page_content, 300));
                 log::info!("✅ Processing will use RAG with file: {}", html_file_path);
                 
                 (Some(html_file_path), page_content)
@@ -1060,7 +1016,7 @@ pub async fn sum(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
                 trace!("🔍 Webpage content error: error_type={}, command_uuid={}", 
                        std::any::type_name_of_val(&e), command_uuid);
                 response_msg.edit(ctx, |m| {
-                    m.content(format!("❌ Failed to fetch webpage: {}", e))
+                    m.content(with_error_id(style_output(format!("❌ Failed to fetch webpage: {}", e))))
                 }).await?;
                 debug!("✅ Webpage content error message sent to Discord");
                 return Ok(());
@@ -1068,12 +1024,80 @@ pub async fn sum(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
         }
     };
     
+    // Per-chapter summary for YouTube videos when requested
+    if use_chapters {
+        let chapter_note = if !is_youtube {
+            Some("ℹ️ `--chapters` only applies to YouTube videos - generating a normal summary instead.")
+        } else if let Some(ref path) = subtitle_file_path {
+            match fetch_youtube_chapters(url).await {
+                Ok(chapters) if !chapters.is_empty() => {
+                    info!("📑 Found {} chapters, generating per-chapter summary", chapters.len());
+                    if let Err(e) = summarize_youtube_chapters(&chapters, path, url, &config, selected_model, &mut response_msg, ctx).await {
+                        error!("❌ Chapter summary failed: {}", e);
+                        response_msg.edit(ctx, |m| {
+                            m.content(with_error_id(style_output(format!("❌ Failed to generate chapter summary: {}", e))))
+                        }).await?;
+                    }
+                    return Ok(());
+                }
+                Ok(_) => {
+                    info!("📑 No chapters found, falling back to normal summary");
+                    Some("ℹ️ This video has no chapter markers - generating a normal summary instead.")
+                }
+                Err(e) => {
+                    warn!("⚠️ Could not fetch chapters: {}", e);
+                    Some("ℹ️ Could not read chapter markers for this video - generating a normal summary instead.")
+                }
+            }
+        } else {
+            None
+        };
+        if let Some(note) = chapter_note {
+            let _ = msg.channel_id.say(ctx, note).await;
+        }
+    }
+
+    // A different URL for the same video or page (e.g. youtu.be vs youtube.com) yields identical text,
+    // so the cleaned content is hashed and an earlier summary of it is reused
+    let source_hash = if cacheable && !include_links {
+        let source_text = if is_youtube {
+            subtitle_file_path.as_deref().and_then(|path| fs::read_to_string(path).ok()).map(|vtt| clean_vtt_content(&vtt))
+        } else {
+            Some(content.clone())
+        };
+        source_text.filter(|text| !text.trim().is_empty()).map(|text| content_hash(&text))
+    } else {
+        None
+    };
+    if let Some(ref hash) = source_hash {
+        if let Some((summary, age_secs, source_url)) = read_cached_summary_by_content(hash, selected_model, format) {
+            info!("🗄️ Serving cached summary of identical content from {} for {} ({}s old)", source_url, url, age_secs);
+            let max_length = config.max_discord_message_length - config.response_format_padding;
+            for (i, chunk) in split_for_discord(&with_response_footer(&summary), max_length).iter().enumerate() {
+                if i == 0 {
+                    response_msg.edit(ctx, |m| m.content(chunk)).await?;
+                } else {
+                    msg.channel_id.say(ctx, chunk).await?;
+                }
+            }
+            let _ = msg.channel_id.say(ctx, style_output(format!("🗄️ *Cached summary of identical content from <{}>, {} min ago*", source_url, age_secs / 60))).await;
+            if !no_cache_write {
+                // Key this URL too, so the next request skips the fetch as well
+                write_cached_summary(url, selected_model, format, &summary, None);
+            }
+            if extract_entities {
+                post_summary_entities(ctx, msg, &summary, &config, selected_model).await;
+            }
+            return Ok(());
+        }
+    }
+
     // Update status
     debug!("📝 === DISCORD MESSAGE UPDATE ===");
     debug!("📝 Updating Discord message to show AI processing...");
     trace!("🔍 Discord message update: changing content to '🤖 Generating summary...', command_uuid={}", command_uuid);
     response_msg.edit(ctx, |m| {
-        m.content("🤖 Generating summary...")
+        m.content(style_output("🤖 Generating summary..."))
     }).await?;
     debug!("✅ Discord message updated to show AI processing");
     trace!("🔍 Discord message update completed: command_uuid={}", command_uuid);
@@ -1131,7 +1155,7 @@ pub async fn sum(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
                 error!("❌ === SUBTITLE FILE MISSING ERROR ===");
                 error!("❌ Subtitle file does not exist: {}", path);
                 response_msg.edit(ctx, |m| {
-                    m.content(format!("❌ Subtitle file missing: {}", path))
+                    m.content(with_error_id(style_output(format!("❌ Subtitle file missing: {}", path))))
                 }).await?;
                 return Ok(()); // Exit early if subtitle file is missing
             }
@@ -1139,7 +1163,7 @@ pub async fn sum(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
             error!("❌ === NO SUBTITLE FILE PATH ERROR ===");
             error!("❌ No subtitle file path provided for YouTube video");
             response_msg.edit(ctx, |m| {
-                m.content("❌ No subtitle file path provided for YouTube video")
+                m.content(with_error_id(style_output("❌ No subtitle file path provided for YouTube video")))
             }).await?;
             return Ok(()); // Exit early if no subtitle file path
         }
@@ -1149,8 +1173,11 @@ pub async fn sum(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
         debug!("🔧 Webpage detected - passing content directly");
         &content 
     };
-    match stream_summary(content_for_summary, url, &config, selected_model, &mut response_msg, ctx, is_youtube, subtitle_file_path.as_deref()).await {
-        Ok(_) => {
+    match stream_summary(content_for_summary, url, &config, selected_model, &mut response_msg, ctx, is_youtube, subtitle_file_path.as_deref(), format, level).await {
+        Ok(delivered) => {
+            if let Some(summary) = delivered.as_ref().filter(|d| cacheable && !no_cache_write && !d.attached) {
+                write_cached_summary(url, selected_model, format, &summary.text, source_hash.as_deref());
+            }
             let processing_time = processing_start.elapsed();
             info!("✅ === AI SUMMARIZATION SUCCESS ===");
             info!("✅ Summary streaming completed successfully in {:.2}s", processing_time.as_secs_f64());
@@ -1158,6 +1185,31 @@ pub async fn sum(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
             debug!("📊 Processing time in milliseconds: {} ms", processing_time.as_millis());
             trace!("🔍 AI summarization success: processing_time_ms={}, content_length={}, command_uuid={}", 
                    processing_time.as_millis(), content_length, command_uuid);
+
+            // Append the page's outbound links when requested
+            if include_links && !is_youtube {
+                let links = subtitle_file_path
+                    .as_deref()
+                    .and_then(|path| fs::read_to_string(path).ok())
+                    .map(|html| extract_page_links(&html, url, MAX_INCLUDED_LINKS))
+                    .unwrap_or_default();
+                info!("🔗 Extracted {} outbound links for --include-links", links.len());
+                let links_section = if links.is_empty() {
+                    "🔗 **Sources/Links**\nNo outbound links found on this page.".to_string()
+                } else {
+                    let list: Vec<String> = links.iter().map(|link| format!("• <{}>", link)).collect();
+                    format!("🔗 **Sources/Links**\n{}", list.join("\n"))
+                };
+                for chunk in split_for_discord(&links_section, crate::commands::util::DISCORD_MESSAGE_LIMIT) {
+                    msg.channel_id.say(ctx, chunk).await?;
+                }
+            } else if include_links {
+                let _ = msg.channel_id.say(ctx, style_output("ℹ️ `--include-links` only applies to webpages.")).await;
+            }
+
+            if let Some(summary) = delivered.as_ref().filter(|_| extract_entities) {
+                post_summary_entities(ctx, msg, &summary.text, &config, selected_model).await;
+            }
         },
         Err(e) => {
             error!("❌ === AI SUMMARIZATION ERROR ===");
@@ -1167,7 +1219,7 @@ pub async fn sum(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
             trace!("🔍 AI summarization error: error_type={}, command_uuid={}", 
                    std::any::type_name_of_val(&e), command_uuid);
             response_msg.edit(ctx, |m| {
-                m.content(format!("❌ Failed to generate summary: {}", e))
+                m.content(with_error_id(style_output(format!("❌ Failed to generate summary: {}", e))))
             }).await?;
             debug!("✅ AI summarization error message sent to Discord");
         }
@@ -1313,6 +1365,12 @@ async fn fetch_youtube_transcript(url: &str) -> Result<String, Box<dyn std::erro
     trace!("[TRACE][SUM][fetch_youtube_transcript] Input URL: '{}'", url);
     trace!("[TRACE][SUM][fetch_youtube_transcript] URL length: {} chars", url.len());
     trace!("[TRACE][SUM][fetch_youtube_transcript] Current working dir: {:?}", std::env::current_dir());
+
+    // yt-dlp fetches whatever it is given, so it gets the same screen as a webpage fetch
+    if let Err(reason) = crate::commands::util::validate_fetch_url(url).await {
+        warn!("🚫 Refusing to fetch transcript for {}: {}", url, reason);
+        return Err(format!("That URL can't be summarized: {}", reason).into());
+    }
     
     info!("🎥 === YOUTUBE TRANSCRIPT EXTRACTION STARTED ===");
     info!("🆔 Process UUID: {}", process_uuid);
@@ -1357,7 +1415,7 @@ async fn fetch_youtube_transcript(url: &str) -> Result<String, Box<dyn std::erro
     debug!("🔍 Checking yt-dlp availability and version...");
     trace!("🔍 yt-dlp version check started: process_uuid={}", process_uuid);
     
-    let version_output = Command::new("yt-dlp")
+    let version_output = crate::commands::util::yt_dlp_command()
         .arg("--version")
         .output()
         .map_err(|e| {
@@ -1367,7 +1425,7 @@ async fn fetch_youtube_transcript(url: &str) -> Result<String, Box<dyn std::erro
             debug!("🔍 yt-dlp PATH error type: {:?}", std::any::type_name_of_val(&e));
             trace!("🔍 yt-dlp PATH error: error_type={}, process_uuid={}", 
                    std::any::type_name_of_val(&e), process_uuid);
-            "yt-dlp is not installed. Please install yt-dlp to use YouTube summarization."
+            crate::commands::util::describe_yt_dlp_spawn_error(&e)
         })?;
     
     debug!("📊 === YT-DLP VERSION CHECK RESULTS ===");
@@ -1424,7 +1482,7 @@ async fn fetch_youtube_transcript(url: &str) -> Result<String, Box<dyn std::erro
         debug!("🔄 Method 1: Trying automatic subtitles...");
         trace!("🔍 Method 1 (automatic subtitles) started: attempt={}, process_uuid={}", attempt, process_uuid);
         
-        let mut command = Command::new("yt-dlp");
+        let mut command = crate::commands::util::yt_dlp_command();
         command
             .arg("--write-auto-sub")
             .arg("--write-sub")
@@ -1464,7 +1522,7 @@ async fn fetch_youtube_transcript(url: &str) -> Result<String, Box<dyn std::erro
         debug!("🚀 Executing yt-dlp command...");
         trace!("🔍 yt-dlp command execution started: attempt={}, process_uuid={}", attempt, process_uuid);
         
-        let output = command.output()?;
+        let output = command.output().map_err(|e| crate::commands::util::describe_yt_dlp_spawn_error(&e))?;
         
         debug!("📊 === YT-DLP COMMAND RESULTS ===");
         debug!("📊 yt-dlp command completed with exit status: {}", output.status);
@@ -1535,7 +1593,7 @@ async fn fetch_youtube_transcript(url: &str) -> Result<String, Box<dyn std::erro
             debug!("🔄 Method 2: Trying manual subtitles only...");
             trace!("🔍 Method 2 (manual subtitles) started: attempt={}, process_uuid={}", attempt, process_uuid);
             
-            let mut command2 = Command::new("yt-dlp");
+            let mut command2 = crate::commands::util::yt_dlp_command();
             command2
                 .arg("--write-sub")
                 .arg("--sub-langs").arg("en")
@@ -1573,7 +1631,7 @@ async fn fetch_youtube_transcript(url: &str) -> Result<String, Box<dyn std::erro
             debug!("🚀 Executing Method 2 yt-dlp command...");
             trace!("🔍 Method 2 command execution started: attempt={}, process_uuid={}", attempt, process_uuid);
             
-            let output2 = command2.output()?;
+            let output2 = command2.output().map_err(|e| crate::commands::util::describe_yt_dlp_spawn_error(&e))?;
             
             debug!("📊 === METHOD 2 COMMAND RESULTS ===");
             debug!("📊 Method 2 yt-dlp command completed with exit status: {}", output2.status);
@@ -1791,11 +1849,11 @@ async fn fetch_youtube_transcript(url: &str) -> Result<String, Box<dyn std::erro
     
     debug!("📖 === SUBTITLE FILE READ SUCCESS ===");
     debug!("📖 Read subtitle file: {} characters from {}", content.len(), vtt_file);
-    debug!("📖 File content preview: {}", &content[..std::cmp::min(100, content.len())]);
+    debug!("📖 File content preview: {}", truncate_chars(&content, 100));
     debug!("📖 File content contains 'WEBVTT': {}", content.contains("WEBVTT"));
     debug!("📖 File content is empty: {}", content.trim().is_empty());
     trace!("🔍 Subtitle file read: path={}, length={}, preview='{}', process_uuid={}", 
-           vtt_file, content.len(), &content[..std::cmp::min(100, content.len())], process_uuid);
+           vtt_file, content.len(), truncate_chars(&content, 100), process_uuid);
     
     // Check if content is valid
     debug!("🔍 === SUBTITLE CONTENT VALIDATION ===");
@@ -1813,7 +1871,7 @@ async fn fetch_youtube_transcript(url: &str) -> Result<String, Box<dyn std::erro
         error!("❌ === INVALID VTT FILE ERROR ===");
         error!("❌ Downloaded file is not a valid VTT subtitle file: {}", vtt_file);
         debug!("🔍 Subtitle file missing WEBVTT header: path={}", vtt_file);
-        debug!("🔍 File content starts with: {}", &content[..std::cmp::min(50, content.len())]);
+        debug!("🔍 File content starts with: {}", truncate_chars(&content, 50));
         trace!("🔍 Subtitle file missing WEBVTT header: path={}, process_uuid={}", vtt_file, process_uuid);
         return Err("Downloaded file is not a valid VTT subtitle file".into());
     }
@@ -1831,9 +1889,9 @@ async fn fetch_youtube_transcript(url: &str) -> Result<String, Box<dyn std::erro
     debug!("✅ === VTT CLEANING COMPLETED ===");
     debug!("✅ VTT content cleaned: {} characters", cleaned.len());
     debug!("✅ Cleaning ratio: {:.2}%", (cleaned.len() as f64 / content.len() as f64) * 100.0);
-    debug!("✅ Cleaned content preview: {}", &cleaned[..std::cmp::min(100, cleaned.len())]);
+    debug!("✅ Cleaned content preview: {}", truncate_chars(&cleaned, 100));
     trace!("🔍 VTT cleaning completed: cleaned_length={}, preview='{}', process_uuid={}", 
-           cleaned.len(), &cleaned[..std::cmp::min(100, cleaned.len())], process_uuid);
+           cleaned.len(), truncate_chars(&cleaned, 100), process_uuid);
     
     if cleaned.trim().is_empty() {
         error!("❌ === EMPTY CLEANED CONTENT ERROR ===");
@@ -1861,58 +1919,244 @@ async fn fetch_youtube_transcript(url: &str) -> Result<String, Box<dyn std::erro
     Ok(vtt_file)
 }
 
-// Enhanced VTT cleaner
-// Removes timestamps, tags, and empty lines from VTT subtitle content
-fn clean_vtt_content(vtt: &str) -> String {
-    debug!("🧹 === VTT CLEANING STARTED ===");
-    debug!("🧹 Cleaning VTT content...");
-    debug!("🧹 Original VTT content length: {} characters", vtt.len());
-    debug!("🧹 Original VTT line count: {}", vtt.lines().count());
-    trace!("🔍 VTT cleaning started: original_length={}, line_count={}", vtt.len(), vtt.lines().count());
-    
-    let mut lines = Vec::new();
-    let mut processed_lines = 0;
-    let mut skipped_lines = 0;
-    let mut kept_lines = 0;
-    
-    debug!("📝 === LINE PROCESSING ===");
-    for (line_num, line) in vtt.lines().enumerate() {
-        processed_lines += 1;
-        let original_line = line;
-        let line = line.trim();
-        
-        // Skip headers, timestamps, and empty lines
-        let is_empty = line.is_empty();
-        let is_webvtt = line.starts_with("WEBVTT");
-        let is_note = line.starts_with("NOTE");
-        let is_timestamp = line.contains("-->");
-        let is_numeric = line.chars().all(|c| c.is_numeric() || c == ':' || c == '.' || c == ' ');
-        
-        if is_empty || is_webvtt || is_note || is_timestamp || is_numeric {
-            skipped_lines += 1;
-            trace!("🔍 Line {} skipped: empty={}, webvtt={}, note={}, timestamp={}, numeric={}, content='{}'", 
-                   line_num + 1, is_empty, is_webvtt, is_note, is_timestamp, is_numeric, original_line);
-            continue;
-        }
-        
-        // Clean various subtitle tags
-        debug!("🧹 === TAG CLEANING ===");
-        let mut cleaned = line.to_string();
-        
-        // Track tag removals
-        let original_cleaned = cleaned.clone();
-        let mut tags_removed = 0;
-        
-        // Remove various subtitle tags
-        if cleaned.contains("<c>") {
-            cleaned = cleaned.replace("<c>", "");
-            tags_removed += 1;
-        }
-        if cleaned.contains("</c>") {
-            cleaned = cleaned.replace("</c>", "");
-            tags_removed += 1;
-        }
-        if cleaned.contains("<v ") {
+// Chapter marker from yt-dlp video metadata (times in seconds)
+#[derive(Debug, Clone, Deserialize)]
+struct YoutubeChapter {
+    start_time: f64,
+    end_time: f64,
+    title: String,
+}
+
+// Fetches the chapter list for a YouTube video using yt-dlp metadata
+// Returns an empty list when the video has no chapters
+async fn fetch_youtube_chapters(url: &str) -> Result<Vec<YoutubeChapter>, Box<dyn std::error::Error + Send + Sync>> {
+    debug!("📑 Fetching chapter metadata for: {}", url);
+    if let Err(reason) = crate::commands::util::validate_fetch_url(url).await {
+        warn!("🚫 Refusing to fetch chapters for {}: {}", url, reason);
+        return Err(format!("That URL can't be summarized: {}", reason).into());
+    }
+    let output = crate::commands::util::yt_dlp_command()
+        .arg("--dump-json")
+        .arg("--skip-download")
+        .arg("--no-playlist")
+        .arg("--no-warnings")
+        .arg(url)
+        .output()
+        .map_err(|e| crate::commands::util::describe_yt_dlp_spawn_error(&e))?;
+
+    if !output.status.success() {
+        return Err(format!("yt-dlp failed to read video metadata: {}", String::from_utf8_lossy(&output.stderr).trim()).into());
+    }
+
+    let metadata: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    let chapters = match metadata.get("chapters") {
+        Some(value) if !value.is_null() => serde_json::from_value::<Vec<YoutubeChapter>>(value.clone())?,
+        _ => Vec::new(),
+    };
+    debug!("📑 Found {} chapters", chapters.len());
+    Ok(chapters)
+}
+
+// Drops the VTT cues that start before `start_seconds`, keeping the header
+fn trim_vtt_from(vtt: &str, start_seconds: f64) -> String {
+    let normalized = vtt.replace("\r\n", "\n");
+    normalized
+        .split("\n\n")
+        .filter(|block| {
+            match block.lines().find(|line| line.contains("-->")) {
+                Some(timing) => timing
+                    .split("-->")
+                    .next()
+                    .and_then(parse_vtt_timestamp)
+                    .map_or(true, |cue_start| cue_start >= start_seconds),
+                None => true, // WEBVTT header, NOTE and STYLE blocks
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+// Parses a VTT timestamp ("01:02:03.456" or "02:03.456") into seconds
+fn parse_vtt_timestamp(timestamp: &str) -> Option<f64> {
+    let timestamp = timestamp.split_whitespace().next()?;
+    let mut seconds = 0.0;
+    for part in timestamp.split(':') {
+        seconds = seconds * 60.0 + part.parse::<f64>().ok()?;
+    }
+    Some(seconds)
+}
+
+// Formats seconds as h:mm:ss or m:ss for chapter headings
+fn format_chapter_timestamp(seconds: f64) -> String {
+    let total = seconds.max(0.0) as u64;
+    let (hours, minutes, secs) = (total / 3600, (total % 3600) / 60, total % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, secs)
+    } else {
+        format!("{}:{:02}", minutes, secs)
+    }
+}
+
+// Any markup tag, stripped from VTT cues and cleaned HTML
+static HTML_TAG_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"<[^>]+>").expect("Invalid HTML tag regex pattern")
+});
+
+// Splits VTT content into (start_seconds, text) cues for mapping onto chapters
+// Inline tags are stripped and the repeated lines of rolling auto-captions are dropped
+fn parse_vtt_segments(vtt: &str) -> Vec<(f64, String)> {
+    let mut segments = Vec::new();
+    let mut current_start: Option<f64> = None;
+    let mut current_lines: Vec<String> = Vec::new();
+    let mut last_line = String::new();
+
+    for line in vtt.lines().map(|l| l.trim()) {
+        if line.contains("-->") {
+            if let Some(start) = current_start.take() {
+                if !current_lines.is_empty() {
+                    segments.push((start, current_lines.join(" ")));
+                }
+            }
+            current_lines.clear();
+            current_start = line.split("-->").next().and_then(parse_vtt_timestamp);
+            continue;
+        }
+        if line.is_empty() || current_start.is_none() {
+            continue;
+        }
+        let text = HTML_TAG_REGEX.replace_all(line, "").trim().to_string();
+        if !text.is_empty() && text != last_line {
+            last_line = text.clone();
+            current_lines.push(text);
+        }
+    }
+    if let Some(start) = current_start {
+        if !current_lines.is_empty() {
+            segments.push((start, current_lines.join(" ")));
+        }
+    }
+    segments
+}
+
+// Summarizes a YouTube video chapter by chapter and posts one titled section per chapter
+async fn summarize_youtube_chapters(
+    chapters: &[YoutubeChapter],
+    vtt_path: &str,
+    url: &str,
+    config: &LMConfig,
+    selected_model: &str,
+    msg: &mut Message,
+    ctx: &Context,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let vtt = fs::read_to_string(vtt_path)?;
+    let segments = parse_vtt_segments(&vtt);
+    let system_prompt = load_youtube_summarization_prompt().await?;
+    info!("📑 Summarizing {} chapters from {} transcript segments", chapters.len(), segments.len());
+
+    let mut sections = Vec::new();
+    for (i, chapter) in chapters.iter().enumerate() {
+        msg.edit(ctx, |m| {
+            m.content(style_output(format!("🤖 Summarizing chapter {}/{}: {}", i + 1, chapters.len(), chapter.title)))
+        }).await?;
+
+        let chapter_text = segments
+            .iter()
+            .filter(|(start, _)| *start >= chapter.start_time && *start < chapter.end_time)
+            .map(|(_, text)| text.as_str())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let summary = if chapter_text.trim().is_empty() {
+            "*No transcript text for this chapter.*".to_string()
+        } else {
+            // Keep each chapter within a single request (~24K chars, same as the RAG chunk size)
+            let chapter_text: String = chapter_text.chars().take(24000).collect();
+            let messages = vec![
+                ChatMessage { role: "system".to_string(), content: system_prompt.clone() },
+                ChatMessage {
+                    role: "user".to_string(),
+                    content: format!("Summarize this chapter titled \"{}\" from a YouTube video. Keep it concise and focus on the key points:\n\n{}", chapter.title, chapter_text),
+                },
+            ];
+            let raw = chat_completion(messages, selected_model, config, Some(1500)).await?;
+            strip_think_blocks(&raw).trim().to_string()
+        };
+
+        sections.push(format!("### {} ({})\n{}", chapter.title, format_chapter_timestamp(chapter.start_time), summary));
+    }
+
+    let final_message = format!(
+        "**YouTube Video Summary (by chapter)**\n\n{}\n\n*Source: <{}>*",
+        sections.join("\n\n"),
+        url
+    );
+    let final_message = with_response_footer(&final_message);
+
+    let max_length = config.max_discord_message_length - config.response_format_padding;
+    let chunks = split_for_discord(&final_message, max_length);
+    for (i, chunk) in chunks.iter().enumerate() {
+        if i == 0 {
+            msg.edit(ctx, |m| m.content(chunk)).await?;
+        } else {
+            msg.channel_id.say(ctx, chunk).await?;
+        }
+    }
+
+    Ok(())
+}
+
+// Enhanced VTT cleaner
+// Removes timestamps, tags, and empty lines from VTT subtitle content
+fn clean_vtt_content(vtt: &str) -> String {
+    debug!("🧹 === VTT CLEANING STARTED ===");
+    debug!("🧹 Cleaning VTT content...");
+    debug!("🧹 Original VTT content length: {} characters", vtt.len());
+    debug!("🧹 Original VTT line count: {}", vtt.lines().count());
+    trace!("🔍 VTT cleaning started: original_length={}, line_count={}", vtt.len(), vtt.lines().count());
+    
+    let mut lines = Vec::new();
+    let mut processed_lines = 0;
+    let mut skipped_lines = 0;
+    let mut kept_lines = 0;
+    
+    debug!("📝 === LINE PROCESSING ===");
+    for (line_num, line) in vtt.lines().enumerate() {
+        processed_lines += 1;
+        let original_line = line;
+        let line = line.trim();
+        
+        // Skip headers, timestamps, and empty lines
+        let is_empty = line.is_empty();
+        let is_webvtt = line.starts_with("WEBVTT");
+        let is_note = line.starts_with("NOTE");
+        let is_timestamp = line.contains("-->");
+        let is_numeric = line.chars().all(|c| c.is_numeric() || c == ':' || c == '.' || c == ' ');
+        
+        if is_empty || is_webvtt || is_note || is_timestamp || is_numeric {
+            skipped_lines += 1;
+            trace!("🔍 Line {} skipped: empty={}, webvtt={}, note={}, timestamp={}, numeric={}, content='{}'", 
+                   line_num + 1, is_empty, is_webvtt, is_note, is_timestamp, is_numeric, original_line);
+            continue;
+        }
+        
+        // Clean various subtitle tags
+        debug!("🧹 === TAG CLEANING ===");
+        let mut cleaned = line.to_string();
+        
+        // Track tag removals
+        let original_cleaned = cleaned.clone();
+        let mut tags_removed = 0;
+        
+        // Remove various subtitle tags
+        if cleaned.contains("<c>") {
+            cleaned = cleaned.replace("<c>", "");
+            tags_removed += 1;
+        }
+        if cleaned.contains("</c>") {
+            cleaned = cleaned.replace("</c>", "");
+            tags_removed += 1;
+        }
+        if cleaned.contains("<v ") {
             cleaned = cleaned.replace("<v ", "");
             tags_removed += 1;
         }
@@ -1986,7 +2230,7 @@ fn clean_vtt_content(vtt: &str) -> String {
     debug!("🧹 VTT cleaning complete: {} lines -> {} characters", lines.len(), result.len());
     debug!("🧹 Final VTT cleaning: {} -> {} characters", result.len(), final_result.len());
     debug!("🧹 Total reduction: {:.2}%", (final_result.len() as f64 / vtt.len() as f64) * 100.0);
-    debug!("🧹 Final result preview: {}", &final_result[..std::cmp::min(100, final_result.len())]);
+    debug!("🧹 Final result preview: {}", truncate_chars(&final_result, 100));
     
     trace!("🔍 VTT cleaning final: original_length={}, processed_lines={}, kept_lines={}, final_length={}, reduction_percent={:.2}%", 
            vtt.len(), processed_lines, kept_lines, final_result.len(), 
@@ -1996,8 +2240,8 @@ fn clean_vtt_content(vtt: &str) -> String {
 }
 
 // Simple webpage fetcher with improved connectivity
-// Downloads and cleans HTML content for a given URL using the shared HTTP client
-async fn fetch_webpage_content(url: &str) -> Result<(String, String), Box<dyn std::error::Error + Send + Sync>> {
+// Downloads and cleans HTML content for a given URL using the SSRF-screened fetch client
+async fn fetch_webpage_content(url: &str, feed_items: usize) -> Result<(String, String), Box<dyn std::error::Error + Send + Sync>> {
     let fetch_uuid = Uuid::new_v4();
     
     info!("🌐 === WEBPAGE FETCHING STARTED ===");
@@ -2015,7 +2259,14 @@ async fn fetch_webpage_content(url: &str) -> Result<(String, String), Box<dyn st
     debug!("🔧 Using shared HTTP client with optimized settings...");
     trace!("🔍 HTTP client setup started: fetch_uuid={}", fetch_uuid);
     
-    let client = get_http_client().await;
+    // Refuse internal targets (cloud metadata, LAN hosts, localhost) before anything is fetched;
+    // every summarize path goes through here, so this is the one place the first request is screened
+    if let Err(reason) = crate::commands::util::validate_fetch_url(url).await {
+        warn!("🚫 Refusing to fetch {}: {}", url, reason);
+        return Err(format!("That URL can't be summarized: {}", reason).into());
+    }
+
+    let client = get_fetch_client().await;
     
     debug!("✅ Shared HTTP client obtained successfully");
     debug!("🔧 Using optimized connection pooling and settings");
@@ -2048,11 +2299,16 @@ async fn fetch_webpage_content(url: &str) -> Result<(String, String), Box<dyn st
     debug!("📄 Downloading HTML content...");
     trace!("🔍 HTML content download started: fetch_uuid={}", fetch_uuid);
     
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
     let html = response.text().await?;
     
     debug!("📄 === HTML CONTENT DOWNLOADED ===");
     debug!("📄 Downloaded HTML content: {} characters", html.len());
-    debug!("📄 HTML content preview: {}", &html[..std::cmp::min(200, html.len())]);
+    debug!("📄 HTML content preview: {}", truncate_chars(&html, 200));
     debug!("📄 HTML contains '<html': {}", html.contains("<html"));
     debug!("📄 HTML contains '<body': {}", html.contains("<body"));
     debug!("📄 HTML contains '<head': {}", html.contains("<head"));
@@ -2065,6 +2321,21 @@ async fn fetch_webpage_content(url: &str) -> Result<(String, String), Box<dyn st
     trace!("🔍 HTML file saving started: html_length={}, fetch_uuid={}", html.len(), fetch_uuid);
     
     let temp_dir = std::env::temp_dir();
+
+    // RSS/Atom feeds are summarized as a digest of their latest entries rather than cleaned as HTML
+    if looks_like_feed(content_type.as_deref(), &html) {
+        match parse_feed(&html, feed_items) {
+            Some(feed) if !feed.items.is_empty() => {
+                let digest = format_feed_digest(&feed);
+                let digest_path = temp_dir.join(format!("feed_{}.txt", fetch_uuid));
+                fs::write(&digest_path, &digest)?;
+                info!("📰 Feed detected: {} entries digested to {:?}", feed.items.len(), digest_path);
+                return Ok((digest, digest_path.to_string_lossy().to_string()));
+            }
+            _ => warn!("⚠️ Content looks like a feed but no entries could be parsed, treating it as a webpage"),
+        }
+    }
+
     let file_name = format!("webpage_{}.html", fetch_uuid);
     let file_path = temp_dir.join(&file_name);
     
@@ -2099,10 +2370,16 @@ async fn fetch_webpage_content(url: &str) -> Result<(String, String), Box<dyn st
     debug!("✅ === HTML CLEANING COMPLETED ===");
     debug!("✅ HTML content cleaned: {} characters", cleaned.len());
     debug!("✅ Cleaning ratio: {:.2}%", (cleaned.len() as f64 / html.len() as f64) * 100.0);
-    debug!("✅ Cleaned content preview: {}", &cleaned[..std::cmp::min(200, cleaned.len())]);
+    debug!("✅ Cleaned content preview: {}", truncate_chars(&cleaned, 200));
     trace!("🔍 HTML cleaning completed: original_length={}, cleaned_length={}, reduction_percent={:.2}%, fetch_uuid={}", 
            html.len(), cleaned.len(), (cleaned.len() as f64 / html.len() as f64) * 100.0, fetch_uuid);
     
+    // Refuse to summarize JS shells and paywalls, which only yield boilerplate
+    if let Some(reason) = detect_unreadable_page(&html, &cleaned) {
+        warn!("⚠️ Webpage has no readable content ({}): {}", reason, url);
+        return Err(UNREADABLE_PAGE_MESSAGE.into());
+    }
+    
     info!("✅ === WEBPAGE FETCHING COMPLETED ===");
     info!("✅ Webpage content fetched, saved to file, and cleaned successfully");
     debug!("📄 Final content length: {} characters", cleaned.len());
@@ -2113,6 +2390,495 @@ async fn fetch_webpage_content(url: &str) -> Result<(String, String), Box<dyn st
     Ok((cleaned, file_path.to_string_lossy().to_string()))
 }
 
+// Feed entries digested when --items isn't given, and the most --items accepts
+const DEFAULT_FEED_ITEMS: usize = 10;
+const MAX_FEED_ITEMS: usize = 50;
+
+// Longest per-entry summary kept in a feed digest
+const FEED_ITEM_SUMMARY_CHARS: usize = 600;
+
+struct FeedItem {
+    title: String,
+    summary: String,
+    link: Option<String>,
+    published: Option<String>,
+}
+
+struct Feed {
+    title: Option<String>,
+    items: Vec<FeedItem>,
+}
+
+// Whether a response is an RSS/Atom feed, by content type or by its root element
+fn looks_like_feed(content_type: Option<&str>, body: &str) -> bool {
+    let content_type = content_type.unwrap_or("").to_lowercase();
+    if content_type.contains("rss") || content_type.contains("atom") {
+        return true;
+    }
+    let head = truncate_chars(body.trim_start(), 1000).to_lowercase();
+    head.contains("<rss") || head.contains("<feed") || head.contains("<rdf:rdf")
+}
+
+// Feed elements read by parse_feed, each with a regex capturing its first element's inner XML
+static FEED_TAG_REGEXES: Lazy<HashMap<&'static str, Regex>> = Lazy::new(|| {
+    ["title", "link", "description", "summary", "content:encoded", "content", "pubDate", "published", "updated", "dc:date"]
+        .into_iter()
+        .map(|tag| {
+            let pattern = format!(r"(?is)<{}(?:\s[^>]*)?>(.*?)</{}>", regex::escape(tag), regex::escape(tag));
+            (tag, Regex::new(&pattern).expect("Invalid feed tag regex pattern"))
+        })
+        .collect()
+});
+// Markup left inside a feed element's text
+static FEED_MARKUP_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?s)<[^>]*>").expect("Invalid feed markup regex pattern")
+});
+// One <item> (RSS) or <entry> (Atom) block
+static FEED_ENTRY_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?is)<(item|entry)(?:\s[^>]*)?>(.*?)</(?:item|entry)>").expect("Invalid feed entry regex pattern")
+});
+// Atom's <link href="..."/>, used when an entry has no text <link>
+static FEED_ATOM_LINK_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?is)<link\b[^>]*\bhref\s*=\s*["']([^"']+)["']"#).expect("Invalid Atom link regex pattern")
+});
+
+// Text of the first <tag> element in an XML fragment, with CDATA unwrapped, markup stripped and entities decoded
+// Only the tags in FEED_TAG_REGEXES are recognized; any other tag yields None
+fn feed_tag_text(xml: &str, tag: &str) -> Option<String> {
+    let caps = FEED_TAG_REGEXES.get(tag)?.captures(xml)?;
+    let raw = caps[1].trim();
+    let raw = raw.strip_prefix("<![CDATA[").and_then(|inner| inner.strip_suffix("]]>")).unwrap_or(raw);
+    let decoded = raw
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&");
+    let text = FEED_MARKUP_REGEX.replace_all(&decoded, " ");
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.is_empty() { None } else { Some(text) }
+}
+
+// Parse the latest `max_items` entries of an RSS 2.0, RSS 1.0 (RDF) or Atom feed
+fn parse_feed(xml: &str, max_items: usize) -> Option<Feed> {
+    let first_entry = FEED_ENTRY_REGEX.find(xml)?.start();
+    let title = feed_tag_text(&xml[..first_entry], "title");
+
+    let items = FEED_ENTRY_REGEX
+        .captures_iter(xml)
+        .take(max_items)
+        .map(|caps| {
+            let block = &caps[2];
+            let summary = ["description", "summary", "content:encoded", "content"]
+                .iter()
+                .find_map(|tag| feed_tag_text(block, tag))
+                .unwrap_or_default();
+            let link = feed_tag_text(block, "link")
+                .or_else(|| FEED_ATOM_LINK_REGEX.captures(block).map(|link| link[1].replace("&amp;", "&")));
+            let published = ["pubDate", "published", "updated", "dc:date"]
+                .iter()
+                .find_map(|tag| feed_tag_text(block, tag));
+            FeedItem {
+                title: feed_tag_text(block, "title").unwrap_or_else(|| "(untitled)".to_string()),
+                summary: truncate_chars(&summary, FEED_ITEM_SUMMARY_CHARS).to_string(),
+                link,
+                published,
+            }
+        })
+        .collect();
+    Some(Feed { title, items })
+}
+
+// Plain-text digest of a feed's entries, used as the document to summarize
+fn format_feed_digest(feed: &Feed) -> String {
+    let mut digest = format!(
+        "Feed: {}\nLatest {} entries (newest first as published by the feed):\n",
+        feed.title.as_deref().unwrap_or("(untitled feed)"),
+        feed.items.len()
+    );
+    for (i, item) in feed.items.iter().enumerate() {
+        digest.push_str(&format!("\n{}. {}", i + 1, item.title));
+        if let Some(published) = &item.published {
+            digest.push_str(&format!(" ({})", published));
+        }
+        digest.push('\n');
+        if let Some(link) = &item.link {
+            digest.push_str(&format!("{}\n", link));
+        }
+        if !item.summary.is_empty() {
+            digest.push_str(&format!("{}\n", item.summary));
+        }
+    }
+    digest
+}
+
+// Largest PDF attachment ^sum will download and extract
+const MAX_PDF_BYTES: u64 = 20 * 1024 * 1024;
+
+// Extracted text shorter than this (ignoring whitespace) means the PDF is scanned images or encrypted
+const MIN_PDF_TEXT_CHARS: usize = 50;
+
+// Whether an attachment looks like a PDF, by extension or declared content type
+fn is_pdf_attachment(filename: &str, content_type: Option<&str>) -> bool {
+    filename.to_lowercase().ends_with(".pdf") || content_type.map_or(false, |kind| kind.starts_with("application/pdf"))
+}
+
+// Extract the text layer of a PDF, failing when nothing readable comes out
+// pdf-extract panics on some malformed files, so it runs on a blocking thread and a panic is reported as an error
+async fn extract_pdf_text(bytes: Vec<u8>) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let text = match tokio::task::spawn_blocking(move || pdf_extract::extract_text_from_mem(&bytes)).await {
+        Ok(Ok(text)) => text,
+        Ok(Err(e)) => {
+            warn!("⚠️ PDF text extraction failed: {}", e);
+            String::new()
+        }
+        Err(e) => {
+            warn!("⚠️ PDF text extraction panicked: {}", e);
+            String::new()
+        }
+    };
+    let readable_chars = text.chars().filter(|c| !c.is_whitespace()).count();
+    debug!("📄 Extracted {} characters ({} non-whitespace) from PDF", text.len(), readable_chars);
+    if readable_chars < MIN_PDF_TEXT_CHARS {
+        return Err("No extractable text was found in this PDF. It may be encrypted or contain only scanned images.".into());
+    }
+    Ok(text)
+}
+
+// Summarize a PDF attached to the ^sum message
+// The extracted text is saved as a .txt file and goes through the same RAG pipeline as webpages
+async fn sum_pdf_attachment(
+    ctx: &Context,
+    msg: &Message,
+    attachment: &serenity::model::channel::Attachment,
+    format: SummaryFormat,
+    level: SummaryLevel,
+) -> CommandResult {
+    info!("📄 === PDF SUMMARY: {} ({} bytes) ===", attachment.filename, attachment.size);
+    if attachment.size > MAX_PDF_BYTES {
+        msg.reply(ctx, format!(
+            "❌ `{}` is too large ({:.1} MB). PDFs up to {} MB can be summarized.",
+            attachment.filename,
+            attachment.size as f64 / (1024.0 * 1024.0),
+            MAX_PDF_BYTES / (1024 * 1024)
+        )).await?;
+        return Ok(());
+    }
+
+    let config = match load_lm_config().await {
+        Ok(config) => config,
+        Err(e) => {
+            error!("❌ Failed to load LM configuration: {}", e);
+            msg.reply(ctx, with_error_id(style_output(&format!("❌ **Configuration Error**\n\n{}\n\n**Setup required:** Ensure `lmapiconf.txt` is properly configured with your LM Studio settings.", e)))).await?;
+            return Ok(());
+        }
+    };
+
+    let mut response_msg = msg.reply(ctx, style_output("🔄 Extracting text from PDF...")).await?;
+    let bytes = match attachment.download().await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            error!("❌ Failed to download PDF attachment: {}", e);
+            response_msg.edit(ctx, |m| m.content(with_error_id(style_output(format!("❌ Failed to download `{}`: {}", attachment.filename, e))))).await?;
+            return Ok(());
+        }
+    };
+    let text = match extract_pdf_text(bytes).await {
+        Ok(text) => text,
+        Err(e) => {
+            response_msg.edit(ctx, |m| m.content(with_error_id(style_output(format!("❌ {}", e))))).await?;
+            return Ok(());
+        }
+    };
+
+    let file_path = std::env::temp_dir().join(format!("pdf_{}.txt", Uuid::new_v4()));
+    if let Err(e) = fs::write(&file_path, &text) {
+        error!("❌ Failed to save extracted PDF text: {}", e);
+        response_msg.edit(ctx, |m| m.content(with_error_id(style_output(format!("❌ Failed to save extracted text: {}", e))))).await?;
+        return Ok(());
+    }
+    let file_path = file_path.to_string_lossy().to_string();
+
+    response_msg.edit(ctx, |m| m.content(style_output("🤖 Generating summary..."))).await?;
+    // The attachment's CDN link stands in for the page URL in prompts and the "Source" line
+    let result = stream_summary("", &attachment.url, &config, &config.default_summarization_model, &mut response_msg, ctx, false, Some(&file_path), format, level).await;
+    let _ = fs::remove_file(&file_path);
+    if let Err(e) = result {
+        error!("❌ PDF summary generation failed: {}", e);
+        response_msg.edit(ctx, |m| m.content(with_error_id(style_output(format!("❌ Failed to generate summary: {}", e))))).await?;
+    }
+    Ok(())
+}
+
+// Most channel messages ^sum --since will fetch (Discord returns at most 100 per request)
+const MAX_SINCE_MESSAGES: usize = 300;
+
+// Parse a Discord message link (discord.com/channels/<guild|@me>/<channel>/<message>) or a bare message ID
+// Returns the link's channel when one was given, and the message ID
+fn parse_message_reference(input: &str) -> Option<(Option<u64>, u64)> {
+    let input = input.trim().trim_start_matches('<').trim_end_matches('>');
+    if let Ok(id) = input.parse::<u64>() {
+        return Some((None, id));
+    }
+    let path = input.split("/channels/").nth(1)?;
+    let parts: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+    match parts.as_slice() {
+        [_guild, channel, message] => Some((Some(channel.parse().ok()?), message.parse().ok()?)),
+        _ => None,
+    }
+}
+
+// Whether a message mentions the user or replies to one of their messages
+fn message_involves_user(message: &Message, user_id: serenity::model::id::UserId) -> bool {
+    message.mentions.iter().any(|user| user.id == user_id)
+        || message.referenced_message.as_ref().map_or(false, |referenced| referenced.author.id == user_id)
+}
+
+// Render fetched messages (oldest first) as a plain-text transcript for the document summary path
+// With a focus user, messages involving them are marked and the header asks for a summary centred on them
+fn format_channel_transcript(messages: &[Message], focus: Option<(serenity::model::id::UserId, &str)>) -> String {
+    let mut transcript = match focus {
+        Some((_, name)) => format!(
+            "Discord channel transcript ({} messages). Focus the summary on what concerns {}: messages marked [→ {}] mention or reply to them.\n\n",
+            messages.len(), name, name
+        ),
+        None => format!("Discord channel transcript ({} messages):\n\n", messages.len()),
+    };
+    for message in messages {
+        let time = chrono::DateTime::from_timestamp(message.timestamp.unix_timestamp(), 0)
+            .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+        let marker = match focus {
+            Some((user_id, name)) if message_involves_user(message, user_id) => format!(" [→ {}]", name),
+            _ => String::new(),
+        };
+        let mut content = message.content.clone();
+        if !message.attachments.is_empty() {
+            content.push_str(&format!(" [{} attachment(s)]", message.attachments.len()));
+        }
+        transcript.push_str(&format!("[{}] {}{}: {}\n", time, message.author.name, marker, content.trim()));
+    }
+    transcript
+}
+
+/// Summarize this channel's messages posted after a message link or ID
+/// Pages through the history with `after`, capped at MAX_SINCE_MESSAGES; --mine centres the summary on the invoker
+async fn sum_channel_since(
+    ctx: &Context,
+    msg: &Message,
+    since: &str,
+    mine: bool,
+    format: SummaryFormat,
+    level: SummaryLevel,
+) -> CommandResult {
+    let since_id = match parse_message_reference(since) {
+        Some((Some(channel), _)) if channel != msg.channel_id.0 => {
+            msg.reply(ctx, "❌ `--since` must point at a message in this channel.").await?;
+            return Ok(());
+        }
+        Some((_, id)) => id,
+        None => {
+            msg.reply(ctx, "❌ `--since` needs a message link or ID.\n\n**Usage:** `^sum --since <message link or id> [--mine]`").await?;
+            return Ok(());
+        }
+    };
+    info!("💬 === CHANNEL SUMMARY since {} (mine={}) ===", since_id, mine);
+
+    let config = match load_lm_config().await {
+        Ok(config) => config,
+        Err(e) => {
+            error!("❌ Failed to load LM configuration: {}", e);
+            msg.reply(ctx, with_error_id(style_output(&format!("❌ **Configuration Error**\n\n{}\n\n**Setup required:** Ensure `lmapiconf.txt` is properly configured with your LM Studio settings.", e)))).await?;
+            return Ok(());
+        }
+    };
+
+    let mut response_msg = msg.reply(ctx, style_output("🔄 Fetching channel messages...")).await?;
+
+    // Discord returns the 100 messages closest to `after`, so page forward from the newest one seen
+    let mut messages: Vec<Message> = Vec::new();
+    let mut after = serenity::model::id::MessageId(since_id);
+    while messages.len() < MAX_SINCE_MESSAGES {
+        let page = match msg.channel_id.messages(&ctx.http, |retriever| retriever.after(after).limit(100)).await {
+            Ok(page) => page,
+            Err(e) => {
+                error!("❌ Failed to fetch channel messages: {}", e);
+                response_msg.edit(ctx, |m| m.content(with_error_id(style_output(format!("❌ Failed to fetch channel messages: {}", e))))).await?;
+                return Ok(());
+            }
+        };
+        let newest = match page.iter().map(|message| message.id).max() {
+            Some(newest) => newest,
+            None => break,
+        };
+        let full_page = page.len() == 100;
+        messages.extend(page.into_iter().filter(|message| message.id < msg.id));
+        if !full_page || newest >= msg.id {
+            break;
+        }
+        after = newest;
+    }
+    messages.sort_by_key(|message| message.id);
+    messages.retain(|message| !message.content.trim().is_empty() || !message.attachments.is_empty());
+    let capped = messages.len() > MAX_SINCE_MESSAGES;
+    messages.truncate(MAX_SINCE_MESSAGES);
+    debug!("💬 Fetched {} messages (capped={})", messages.len(), capped);
+
+    if messages.is_empty() {
+        response_msg.edit(ctx, |m| m.content(style_output("ℹ️ No messages have been posted since that point."))).await?;
+        return Ok(());
+    }
+    if mine && !messages.iter().any(|message| message_involves_user(message, msg.author.id)) {
+        response_msg.edit(ctx, |m| m.content(style_output(format!(
+            "ℹ️ None of the {} messages since then mention or reply to you.", messages.len()
+        )))).await?;
+        return Ok(());
+    }
+
+    let focus = if mine { Some((msg.author.id, msg.author.name.as_str())) } else { None };
+    let transcript = format_channel_transcript(&messages, focus);
+    let file_path = std::env::temp_dir().join(format!("channel_{}.txt", Uuid::new_v4()));
+    if let Err(e) = fs::write(&file_path, &transcript) {
+        error!("❌ Failed to save channel transcript: {}", e);
+        response_msg.edit(ctx, |m| m.content(with_error_id(style_output(format!("❌ Failed to save channel transcript: {}", e))))).await?;
+        return Ok(());
+    }
+    let file_path = file_path.to_string_lossy().to_string();
+
+    response_msg.edit(ctx, |m| m.content(style_output(format!(
+        "🤖 Summarizing {} message(s){}...",
+        messages.len(),
+        if capped { format!(" (capped at {})", MAX_SINCE_MESSAGES) } else { String::new() }
+    )))).await?;
+    // The --since message link stands in for the page URL in prompts and the "Source" line
+    let source = format!(
+        "https://discord.com/channels/{}/{}/{}",
+        msg.guild_id.map_or_else(|| "@me".to_string(), |guild| guild.0.to_string()),
+        msg.channel_id.0,
+        since_id
+    );
+    let result = stream_summary("", &source, &config, &config.default_summarization_model, &mut response_msg, ctx, false, Some(&file_path), format, level).await;
+    let _ = fs::remove_file(&file_path);
+    if let Err(e) = result {
+        error!("❌ Channel summary generation failed: {}", e);
+        response_msg.edit(ctx, |m| m.content(with_error_id(style_output(format!("❌ Failed to generate summary: {}", e))))).await?;
+    }
+    Ok(())
+}
+
+// Pages with less extracted text than this are checked for JavaScript/paywall markers
+const MIN_READABLE_CHARS: usize = 600;
+
+// Shown instead of a summary when a page yields no readable article text
+const UNREADABLE_PAGE_MESSAGE: &str = "This page requires JavaScript or is paywalled; I couldn't extract readable content.\n\
+**Workaround:** paste the article text into a message and use the **Summarize** message command (right-click → Apps → Summarize).";
+
+// Lowercase markers of JavaScript shells, paywalls and bot checks
+const UNREADABLE_PAGE_MARKERS: &[(&str, &str)] = &[
+    ("enable javascript", "javascript required"),
+    ("javascript is required", "javascript required"),
+    ("javascript is disabled", "javascript required"),
+    ("turn on javascript", "javascript required"),
+    ("requires javascript", "javascript required"),
+    ("you need to enable js", "javascript required"),
+    ("subscribe to continue", "paywall"),
+    ("subscribe to read", "paywall"),
+    ("to continue reading", "paywall"),
+    ("already a subscriber", "paywall"),
+    ("subscriber-only", "paywall"),
+    ("paywall", "paywall"),
+    ("sign in to continue", "login wall"),
+    ("log in to continue", "login wall"),
+    ("checking your browser", "bot check"),
+    ("verify you are human", "bot check"),
+];
+
+// Detects pages whose extracted text is too thin to summarize
+// Returns the kind of wall found, or None when the text looks like real content
+fn detect_unreadable_page(html: &str, cleaned: &str) -> Option<&'static str> {
+    let readable = cleaned.trim();
+    if readable.chars().count() >= MIN_READABLE_CHARS {
+        return None;
+    }
+    if readable.is_empty() {
+        return Some("empty body");
+    }
+
+    let html_lower = html.to_lowercase();
+    let cleaned_lower = readable.to_lowercase();
+    UNREADABLE_PAGE_MARKERS
+        .iter()
+        .find(|(marker, _)| html_lower.contains(marker) || cleaned_lower.contains(marker))
+        .map(|(_, kind)| *kind)
+}
+
+// Maximum number of links listed by --include-links
+const MAX_INCLUDED_LINKS: usize = 15;
+
+// Page chrome skipped by --include-links
+static BOILERPLATE_BLOCK_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?is)<(nav|header|footer|aside)\b.*?</(nav|header|footer|aside)>").expect("Invalid boilerplate block regex pattern")
+});
+// <base href>, which relative links resolve against when present
+static BASE_HREF_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?i)<base\s[^>]*href\s*=\s*["']([^"']+)["']"#).expect("Invalid base href regex pattern")
+});
+// <a href> targets
+static ANCHOR_HREF_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?i)<a\s[^>]*href\s*=\s*["']([^"']+)["']"#).expect("Invalid anchor href regex pattern")
+});
+
+// Collects the page's outbound links for --include-links
+// Skips nav/header/footer/aside boilerplate, resolves relative hrefs against the page (or its <base>),
+// drops fragments and non-http schemes, and dedupes while keeping document order
+fn extract_page_links(html: &str, page_url: &str, max_links: usize) -> Vec<String> {
+
+    let page = match reqwest::Url::parse(page_url) {
+        Ok(page) => page,
+        Err(e) => {
+            warn!("⚠️ Cannot resolve links against invalid page URL {}: {}", page_url, e);
+            return Vec::new();
+        }
+    };
+    let base = BASE_HREF_REGEX
+        .captures(html)
+        .and_then(|caps| page.join(caps[1].trim()).ok())
+        .unwrap_or_else(|| page.clone());
+
+    let body = BOILERPLATE_BLOCK_REGEX.replace_all(html, " ");
+    let mut seen = std::collections::HashSet::new();
+    let mut links = Vec::new();
+
+    for caps in ANCHOR_HREF_REGEX.captures_iter(&body) {
+        let href = caps[1].trim().replace("&amp;", "&");
+        if href.starts_with('#') {
+            continue;
+        }
+        let mut resolved = match base.join(&href) {
+            Ok(resolved) => resolved,
+            Err(_) => continue,
+        };
+        if resolved.scheme() != "http" && resolved.scheme() != "https" {
+            continue;
+        }
+        resolved.set_fragment(None);
+        // Links back to the page itself aren't useful sources
+        if resolved.as_str().trim_end_matches('/') == page.as_str().trim_end_matches('/') {
+            continue;
+        }
+        if seen.insert(resolved.to_string()) {
+            links.push(resolved.to_string());
+            if links.len() >= max_links {
+                break;
+            }
+        }
+    }
+
+    trace!("🔍 Link extraction: page={}, links={}", page_url, links.len());
+    links
+}
+
 // Simple HTML cleaner
 // Removes script/style tags and all HTML tags, returns plain text
 fn clean_html(html: &str) -> String {
@@ -2181,8 +2947,7 @@ fn clean_html(html: &str) -> String {
     trace!("🔍 HTML tag removal started: current_length={}, clean_uuid={}", result.len(), clean_uuid);
     
     // Remove all HTML tags
-    let tag_regex = regex::Regex::new(r"<[^>]+>").unwrap();
-    let cleaned = tag_regex.replace_all(&result, " ");
+    let cleaned = HTML_TAG_REGEX.replace_all(&result, " ");
     
     debug!("✅ HTML tag removal completed");
     debug!("🧹 Content after tag removal: {} characters", cleaned.len());
@@ -2213,7 +2978,7 @@ fn clean_html(html: &str) -> String {
     debug!("🧹 === FINAL HTML CLEANING COMPLETED ===");
     debug!("🧹 HTML cleaning complete: {} -> {} characters", html.len(), final_result.len());
     debug!("🧹 Total reduction: {:.2}%", (final_result.len() as f64 / html.len() as f64) * 100.0);
-    debug!("🧹 Final result preview: {}", &final_result[..std::cmp::min(100, final_result.len())]);
+    debug!("🧹 Final result preview: {}", truncate_chars(&final_result, 100));
     debug!("🧹 Clean UUID: {}", clean_uuid);
     
     trace!("🔍 HTML cleaning final: original_length={}, script_removals={}, style_removals={}, final_length={}, total_reduction_percent={:.2}%, clean_uuid={}", 
@@ -2223,8 +2988,265 @@ fn clean_html(html: &str) -> String {
     final_result
 }
 
+// Output shape selected with ^sum --format
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SummaryFormat {
+    Markdown, // Headed prose summary (default)
+    Bullets,  // Bulleted key points
+    Json,     // { "title", "summary", "key_points": [...] }
+}
+
+impl SummaryFormat {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "markdown" | "md" | "prose" => Some(SummaryFormat::Markdown),
+            "bullets" | "bullet" | "points" => Some(SummaryFormat::Bullets),
+            "json" => Some(SummaryFormat::Json),
+            _ => None,
+        }
+    }
+
+    // Closing instruction for the final (reduce) summarization request
+    fn instruction(&self) -> &'static str {
+        match self {
+            SummaryFormat::Markdown => "Please organize the summary with clear sections and highlight the most important takeaways.",
+            SummaryFormat::Bullets => "Respond only with a bulleted list of the key points, one concise point per line starting with \"- \". No headings, introduction or conclusion.",
+            SummaryFormat::Json => "Respond only with a JSON object of the form {\"title\": string, \"summary\": string, \"key_points\": [string, ...]} and nothing else - no code fences, no commentary.",
+        }
+    }
+}
+
+// Delivery of an over-long summary, selected with ^sum --level
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SummaryLevel {
+    Standard, // Condense only when it would take more than SUMMARY_MAX_MESSAGES messages (default)
+    Brief,    // Condense whenever it doesn't fit in one message
+    Detailed, // Never condense; split across messages instead
+}
+
+impl SummaryLevel {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "brief" | "short" => Some(SummaryLevel::Brief),
+            "detailed" | "full" => Some(SummaryLevel::Detailed),
+            _ => None,
+        }
+    }
+
+    // Most messages a summary may take before it gets a compression pass (None = always split)
+    fn max_messages(&self) -> Option<usize> {
+        match self {
+            SummaryLevel::Standard => Some(SUMMARY_MAX_MESSAGES),
+            SummaryLevel::Brief => Some(1),
+            SummaryLevel::Detailed => None,
+        }
+    }
+}
+
+// Structured summary produced by --format json
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonSummary {
+    title: String,
+    summary: String,
+    key_points: Vec<String>,
+}
+
+// Extracts and validates the JSON object from a --format json response
+// Tolerates code fences and text around the object, but requires all three fields
+fn parse_json_summary(response: &str) -> Result<JsonSummary, String> {
+    let start = response.find('{').ok_or("response contains no JSON object")?;
+    let end = response.rfind('}').ok_or("response contains no JSON object")?;
+    if end < start {
+        return Err("response contains no JSON object".to_string());
+    }
+    let summary: JsonSummary = serde_json::from_str(&response[start..=end])
+        .map_err(|e| format!("invalid summary JSON: {}", e))?;
+    if summary.summary.trim().is_empty() {
+        return Err("summary JSON has an empty \"summary\" field".to_string());
+    }
+    Ok(summary)
+}
+
+// Named entities and key figures pulled out of a finished summary by --entities
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SummaryEntities {
+    #[serde(default)]
+    people: Vec<String>,
+    #[serde(default)]
+    organizations: Vec<String>,
+    #[serde(default)]
+    dates: Vec<String>,
+    #[serde(default)]
+    locations: Vec<String>,
+    #[serde(default)]
+    figures: Vec<String>,
+}
+
+// Entries shown per group; the rest are counted
+const MAX_ENTITIES_PER_GROUP: usize = 10;
+
+const ENTITIES_INSTRUCTION: &str = "Extract the named entities and key figures from the summary you are given. \
+Respond only with a JSON object of the form {\"people\": [string], \"organizations\": [string], \"dates\": [string], \"locations\": [string], \"figures\": [string]} and nothing else - no code fences, no commentary. \
+`figures` holds important numbers with what they measure, e.g. \"$4.2B annual revenue\". Use empty arrays for groups with nothing to list and never invent entries.";
+
+impl SummaryEntities {
+    fn groups(&self) -> [(&'static str, &Vec<String>); 5] {
+        [
+            ("👤 People", &self.people),
+            ("🏢 Organizations", &self.organizations),
+            ("📅 Dates", &self.dates),
+            ("📍 Locations", &self.locations),
+            ("🔢 Key Figures", &self.figures),
+        ]
+    }
+
+    // Bullet groups for Discord; empty groups are left out and repeated entries shown once
+    fn render(&self) -> String {
+        let mut sections = Vec::new();
+        for (heading, items) in self.groups() {
+            let mut seen = std::collections::HashSet::new();
+            let unique: Vec<&str> = items.iter()
+                .map(|item| item.trim())
+                .filter(|item| !item.is_empty() && seen.insert(item.to_lowercase()))
+                .collect();
+            if unique.is_empty() {
+                continue;
+            }
+            let mut lines: Vec<String> = unique.iter().take(MAX_ENTITIES_PER_GROUP).map(|item| format!("• {}", item)).collect();
+            if unique.len() > MAX_ENTITIES_PER_GROUP {
+                lines.push(format!("• *…and {} more*", unique.len() - MAX_ENTITIES_PER_GROUP));
+            }
+            sections.push(format!("**{}**\n{}", heading, lines.join("\n")));
+        }
+        if sections.is_empty() {
+            "🏷️ **Key Entities**\nNo named entities or key figures found in this summary.".to_string()
+        } else {
+            format!("🏷️ **Key Entities**\n\n{}", sections.join("\n\n"))
+        }
+    }
+}
+
+// Extracts and validates the JSON object from an --entities response; missing groups count as empty
+fn parse_summary_entities(response: &str) -> Result<SummaryEntities, String> {
+    let start = response.find('{').ok_or("response contains no JSON object")?;
+    let end = response.rfind('}').ok_or("response contains no JSON object")?;
+    if end < start {
+        return Err("response contains no JSON object".to_string());
+    }
+    serde_json::from_str(&response[start..=end]).map_err(|e| format!("invalid entities JSON: {}", e))
+}
+
+// Second prompt for --entities, retried once when the model's JSON doesn't parse
+async fn extract_summary_entities(
+    summary: &str,
+    config: &LMConfig,
+    model: &str,
+) -> Result<SummaryEntities, Box<dyn std::error::Error + Send + Sync>> {
+    let mut messages = vec![
+        ChatMessage { role: "system".to_string(), content: ENTITIES_INSTRUCTION.to_string() },
+        ChatMessage { role: "user".to_string(), content: summary.to_string() },
+    ];
+    let mut last_error = String::new();
+    for attempt in 1..=2 {
+        let response = chat_completion(messages.clone(), model, config, None).await?;
+        let response = strip_think_blocks(&response);
+        match parse_summary_entities(&response) {
+            Ok(entities) => return Ok(entities),
+            Err(e) => {
+                warn!("⚠️ Entity extraction attempt {} did not return valid JSON: {}", attempt, e);
+                messages.push(ChatMessage { role: "assistant".to_string(), content: response });
+                messages.push(ChatMessage {
+                    role: "user".to_string(),
+                    content: format!("That was not valid JSON ({}). Reply again with only the JSON object.", e),
+                });
+                last_error = e;
+            }
+        }
+    }
+    Err(last_error.into())
+}
+
+// Posts the --entities section below a delivered summary; failures are reported but never fail ^sum itself
+async fn post_summary_entities(ctx: &Context, msg: &Message, summary: &str, config: &LMConfig, model: &str) {
+    info!("🏷️ Extracting entities from {} char summary", summary.len());
+    let section = match extract_summary_entities(summary, config, model).await {
+        Ok(entities) => entities.render(),
+        Err(e) => {
+            warn!("⚠️ Entity extraction failed: {}", e);
+            format!("⚠️ Could not extract entities from this summary: {}", e)
+        }
+    };
+    let max_length = config.max_discord_message_length - config.response_format_padding;
+    for chunk in crate::commands::util::split_for_discord(&section, max_length) {
+        if let Err(e) = msg.channel_id.say(ctx, style_output(chunk)).await {
+            warn!("⚠️ Failed to send entities section: {}", e);
+            return;
+        }
+    }
+}
+
+// Shows map/reduce progress in the ^sum status message
+// A failed edit only costs the progress display, so it is logged rather than returned
+async fn report_progress(ctx: &Context, msg: &mut Message, text: String) {
+    if let Err(e) = msg.edit(ctx, |m| m.content(style_output(text))).await {
+        warn!("⚠️ Failed to update summary progress: {}", e);
+    }
+}
+
+// A summary stream_summary delivered, either posted as messages or attached as a file
+struct DeliveredSummary {
+    text: String,
+    // Attached summaries are too long to replay from the cache as messages
+    attached: bool,
+}
+
+// System prompt for the map step of RAG summarization
+const CHUNK_SUMMARY_SYSTEM_PROMPT: &str = "You are an expert content summarizer. Create comprehensive summaries that capture all important details, key points, and main topics from the provided content. Aim for summaries that are informative and detailed while remaining concise.";
+// Cap on the combined chunk summaries sent to the reduce step (~20K tokens)
+const MAX_FINAL_PROMPT_CHARS: usize = 80000;
+
+// Splits content into whitespace-delimited chunks of at most chunk_size bytes for map-reduce summarization
+// Words longer than half a chunk are dropped as likely corrupted data
+fn split_into_chunks(content: &str, chunk_size: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current_chunk = String::new();
+    let words: Vec<&str> = content.split_whitespace().collect();
+
+    // Safety check for extremely long content
+    if words.len() > 100000 {
+        warn!("⚠️ === EXTREMELY LONG CONTENT WARNING ===");
+        warn!("⚠️ Content has {} words, this may cause performance issues", words.len());
+    }
+
+    for word in words {
+        // Check if a single word is too long (might be corrupted data)
+        if word.len() > chunk_size / 2 {
+            warn!("⚠️ Skipping extremely long word: {} characters", word.len());
+            continue;
+        }
+
+        if current_chunk.len() + word.len() + 1 > chunk_size && !current_chunk.is_empty() {
+            chunks.push(current_chunk.trim().to_string());
+            current_chunk = String::new();
+        }
+
+        if !current_chunk.is_empty() {
+            current_chunk.push(' ');
+        }
+        current_chunk.push_str(word);
+    }
+
+    // Add the last chunk if it's not empty
+    if !current_chunk.is_empty() {
+        chunks.push(current_chunk.trim().to_string());
+    }
+
+    chunks
+}
+
 // Stream summary using SSE (like lm command approach)
 // Streams the AI's summary response, chunking and updating Discord messages as needed
+// Returns the delivered summary, or None when an error notice was sent instead
 async fn stream_summary(
     content: &str,
     url: &str,
@@ -2234,9 +3256,13 @@ async fn stream_summary(
     ctx: &Context,
     is_youtube: bool,
     file_path: Option<&str>,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    format: SummaryFormat,
+    level: SummaryLevel,
+) -> Result<Option<DeliveredSummary>, Box<dyn std::error::Error + Send + Sync>> {
     
     let stream_uuid = Uuid::new_v4();
+    // Plain-text files (extracted PDF text) are summarized as documents rather than HTML
+    let is_document = !is_youtube && file_path.map_or(false, |path| path.ends_with(".txt"));
     
     // Trace-level function entry
     trace!("[TRACE][SUM][stream_summary] === FUNCTION ENTRY ===");
@@ -2314,7 +3340,7 @@ async fn stream_summary(
     };
     
     debug!("📄 System prompt loaded successfully: {} characters", system_prompt.len());
-    debug!("📄 System prompt preview: {}", &system_prompt[..std::cmp::min(200, system_prompt.len())]);
+    debug!("📄 System prompt preview: {}", truncate_chars(&system_prompt, 200));
     trace!("🔍 System prompt loaded: length={}, stream_uuid={}", system_prompt.len(), stream_uuid);
     
     // FIXED: Properly handle content processing for YouTube vs webpage
@@ -2352,7 +3378,7 @@ async fn stream_summary(
         let file_content = match fs::read_to_string(file_path) {
             Ok(content) => {
                 debug!("✅ File read successfully: {} characters", content.len());
-                debug!("📖 File content preview: {}", &content[..std::cmp::min(200, content.len())]);
+                debug!("📖 File content preview: {}", truncate_chars(&content, 200));
                 trace!("🔍 File read success: path={}, length={}, stream_uuid={}", file_path, content.len(), stream_uuid);
                 
                 // Enhanced logging for file reading success
@@ -2360,7 +3386,7 @@ async fn stream_summary(
                 log::info!("📖 File path: {}", file_path);
                 log::info!("📖 File size: {} characters", content.len());
                 log::info!("📖 File size in bytes: {} bytes", content.as_bytes().len());
-                log::info!("📖 Content preview: {}", &content[..std::cmp::min(500, content.len())]);
+                log::info!("📖 Content preview: {}", truncate_chars(&content, 500));
                 log::info!("📖 Content type indicators:");
                 log::info!("📖   - Contains HTML tags: {}", content.contains("<html"));
                 log::info!("📖   - Contains VTT timestamps: {}", content.contains("-->"));
@@ -2389,7 +3415,7 @@ async fn stream_summary(
             let cleaned = clean_vtt_content(&file_content);
             
             debug!("✅ VTT content cleaned for RAG: {} characters", cleaned.len());
-            debug!("🧹 Content preview: {}", &cleaned[..std::cmp::min(200, cleaned.len())]);
+            debug!("🧹 Content preview: {}", truncate_chars(&cleaned, 200));
             debug!("🧹 Cleaning ratio: {:.2}%", (cleaned.len() as f64 / file_content.len() as f64) * 100.0);
             trace!("🔍 VTT cleaning for RAG completed: original_length={}, cleaned_length={}, stream_uuid={}", 
                    file_content.len(), cleaned.len(), stream_uuid);
@@ -2399,9 +3425,12 @@ async fn stream_summary(
             log::info!("🧹 Original size: {} characters", file_content.len());
             log::info!("🧹 Cleaned size: {} characters", cleaned.len());
             log::info!("🧹 Reduction: {:.2}%", (cleaned.len() as f64 / file_content.len() as f64) * 100.0);
-            log::info!("🧹 Cleaned preview: {}", &cleaned[..std::cmp::min(400, cleaned.len())]);
+            log::info!("🧹 Cleaned preview: {}", truncate_chars(&cleaned, 400));
             
             cleaned
+        } else if is_document {
+            debug!("📄 Plain-text document, no cleaning needed: {} characters", file_content.len());
+            file_content.clone()
         } else {
             debug!("🧹 === HTML CLEANING FOR RAG ===");
             debug!("🧹 Cleaning HTML content for RAG processing...");
@@ -2410,7 +3439,7 @@ async fn stream_summary(
             let cleaned = clean_html(&file_content);
             
             debug!("✅ HTML content cleaned for RAG: {} characters", cleaned.len());
-            debug!("🧹 Content preview: {}", &cleaned[..std::cmp::min(200, cleaned.len())]);
+            debug!("🧹 Content preview: {}", truncate_chars(&cleaned, 200));
             debug!("🧹 Cleaning ratio: {:.2}%", (cleaned.len() as f64 / file_content.len() as f64) * 100.0);
             trace!("🔍 HTML cleaning for RAG completed: original_length={}, cleaned_length={}, stream_uuid={}", 
                    file_content.len(), cleaned.len(), stream_uuid);
@@ -2420,7 +3449,7 @@ async fn stream_summary(
             log::info!("🧹 Original size: {} characters", file_content.len());
             log::info!("🧹 Cleaned size: {} characters", cleaned.len());
             log::info!("🧹 Reduction: {:.2}%", (cleaned.len() as f64 / file_content.len() as f64) * 100.0);
-            log::info!("🧹 Cleaned preview: {}", &cleaned[..std::cmp::min(400, cleaned.len())]);
+            log::info!("🧹 Cleaned preview: {}", truncate_chars(&cleaned, 400));
             log::info!("🧹 Word count: {} words", cleaned.split_whitespace().count());
             
             cleaned
@@ -2437,14 +3466,14 @@ async fn stream_summary(
         
         let prompt = format!(
             "Please analyze and summarize this {} from {}:\n\n{}",
-            if is_youtube { "YouTube video subtitle file" } else { "webpage HTML content" },
+            if is_youtube { "YouTube video subtitle file" } else if is_document { "document" } else { "webpage HTML content" },
             url, cleaned_content
         );
         
         debug!("📝 === USER PROMPT CREATION FOR RAG ===");
         debug!("📝 Created user prompt with {} content: {} characters", 
                if is_youtube { "subtitle" } else { "HTML" }, prompt.len());
-        debug!("📝 Prompt preview: {}", &prompt[..std::cmp::min(300, prompt.len())]);
+        debug!("📝 Prompt preview: {}", truncate_chars(&prompt, 300));
         trace!("🔍 User prompt created: prompt_length={}, cleaned_content_length={}, content_type={}, stream_uuid={}", 
                prompt.len(), cleaned_content.len(), if is_youtube { "youtube" } else { "webpage" }, stream_uuid);
         
@@ -2497,7 +3526,7 @@ async fn stream_summary(
         
         debug!("📝 === USER PROMPT CREATION FOR WEBPAGE ===");
         debug!("📝 Created user prompt with webpage content: {} characters", prompt.len());
-        debug!("📝 Prompt preview: {}", &prompt[..std::cmp::min(300, prompt.len())]);
+        debug!("📝 Prompt preview: {}", truncate_chars(&prompt, 300));
         trace!("🔍 User prompt created: prompt_length={}, truncated_content_length={}, stream_uuid={}", 
                prompt.len(), truncated_content.len(), stream_uuid);
         
@@ -2546,39 +3575,8 @@ async fn stream_summary(
         debug!("📄 === CONTENT CHUNKING ===");
         debug!("📄 Splitting content into chunks using character-based splitting...");
         
-        // Use character-based splitting to avoid breaking UTF-8 characters
-        let mut chunks = Vec::new();
-        let mut current_chunk = String::new();
-        let words: Vec<&str> = content_to_process.split_whitespace().collect();
-        
-        // Safety check for extremely long content
-        if words.len() > 100000 {
-            warn!("⚠️ === EXTREMELY LONG CONTENT WARNING ===");
-            warn!("⚠️ Content has {} words, this may cause performance issues", words.len());
-        }
-        
-        for word in words {
-            // Check if a single word is too long (might be corrupted data)
-            if word.len() > chunk_size / 2 {
-                warn!("⚠️ Skipping extremely long word: {} characters", word.len());
-                continue;
-            }
-            
-            if current_chunk.len() + word.len() + 1 > chunk_size && !current_chunk.is_empty() {
-                chunks.push(current_chunk.trim().to_string());
-                current_chunk = String::new();
-            }
-            
-            if !current_chunk.is_empty() {
-                current_chunk.push(' ');
-            }
-            current_chunk.push_str(word);
-        }
-        
-        // Add the last chunk if it's not empty
-        if !current_chunk.is_empty() {
-            chunks.push(current_chunk.trim().to_string());
-        }
+        // Use word-based splitting to avoid breaking UTF-8 characters
+        let chunks = split_into_chunks(&content_to_process, chunk_size);
         
         // Safety check for too many chunks
         if chunks.len() > 50 {
@@ -2599,28 +3597,29 @@ async fn stream_summary(
         }
         
         for (i, chunk) in chunks.iter().enumerate() {
+            report_progress(ctx, msg, format!("📄 Summarizing chunk {} of {}...", i+1, chunks.len())).await;
             info!("🤖 === CHUNK {} PROCESSING ===", i+1);
             info!("🤖 Summarizing chunk {} of {} ({} chars)", i+1, chunks.len(), chunk.len());
-            debug!("🤖 Chunk {} preview: {}", i+1, &chunk[..std::cmp::min(100, chunk.len())]);
+            debug!("🤖 Chunk {} preview: {}", i+1, truncate_chars(&chunk, 100));
             trace!("🔍 Chunk {} processing: chunk_length={}, stream_uuid={}", i+1, chunk.len(), stream_uuid);
             
             // FIXED: Create a more specific prompt for each chunk with actual content
             let chunk_prompt = format!(
                 "Create a detailed summary of this content chunk from {}. Focus on key points, topics, and important information:\n\n{}",
-                if is_youtube { "a YouTube video" } else { "a webpage" },
+                if is_youtube { "a YouTube video" } else if is_document { "a document" } else { "a webpage" },
                 chunk
             );
             
             debug!("📝 === CHUNK PROMPT CREATION ===");
             debug!("📝 Created chunk prompt: {} characters", chunk_prompt.len());
-            debug!("📝 Chunk prompt preview: {}", &chunk_prompt[..std::cmp::min(200, chunk_prompt.len())]);
+            debug!("📝 Chunk prompt preview: {}", truncate_chars(&chunk_prompt, 200));
             trace!("🔍 Chunk prompt created: chunk={}, prompt_length={}, stream_uuid={}", 
                    i+1, chunk_prompt.len(), stream_uuid);
             
             let chunk_messages = vec![
                 ChatMessage { 
                     role: "system".to_string(), 
-                    content: "You are an expert content summarizer. Create comprehensive summaries that capture all important details, key points, and main topics from the provided content. Aim for summaries that are informative and detailed while remaining concise.".to_string() 
+                    content: CHUNK_SUMMARY_SYSTEM_PROMPT.to_string() 
                 },
                 ChatMessage { 
                     role: "user".to_string(), 
@@ -2639,7 +3638,7 @@ async fn stream_summary(
             let chunk_summary = match chat_completion(chunk_messages, selected_model, config, Some(2000)).await {
                 Ok(summary) => {
                     debug!("✅ Chunk {} summary received: {} characters", i+1, summary.len());
-                    debug!("📝 Chunk {} summary preview: {}", i+1, &summary[..std::cmp::min(200, summary.len())]);
+                    debug!("📝 Chunk {} summary preview: {}", i+1, truncate_chars(&summary, 200));
                     trace!("🔍 Chunk {} summary completed: summary_length={}, stream_uuid={}", 
                            i+1, summary.len(), stream_uuid);
                     summary
@@ -2653,8 +3652,17 @@ async fn stream_summary(
                            i+1, std::any::type_name_of_val(&e), stream_uuid);
                     
                     // Enhanced error handling with user-friendly messages
-                    let error_msg = format!("{}", e);
-                    if error_msg.contains("Connection refused") || error_msg.contains("Connection Error") {
+                    let bot_error = BotError::find(e.as_ref());
+                    if matches!(bot_error, Some(BotError::NetworkPermission { .. })) {
+                        return Err(format!(
+                            "❌ **Windows Permission Error During Processing**\n\n\
+                            Network access was denied while processing chunk {} of {}\n\n\
+                            **Quick Fix:**\n\
+                            • **Restart as Administrator**: Close the bot and run as administrator\n\n\
+                            **Progress:** Successfully processed {} of {} chunks before failure",
+                            i+1, chunks.len(), i, chunks.len()
+                        ).into());
+                    } else if bot_error.map_or(false, BotError::is_connectivity) {
                         return Err(format!(
                             "❌ **LM Studio Connection Lost During Chunk Processing**\n\n\
                             Failed to process chunk {} of {}\n\n\
@@ -2666,15 +3674,6 @@ async fn stream_summary(
                             **Progress:** Successfully processed {} of {} chunks before failure",
                             i+1, chunks.len(), selected_model, i, chunks.len()
                         ).into());
-                    } else if error_msg.contains("Windows Permission Error") || error_msg.contains("os error 10013") {
-                        return Err(format!(
-                            "❌ **Windows Permission Error During Processing**\n\n\
-                            Network access was denied while processing chunk {} of {}\n\n\
-                            **Quick Fix:**\n\
-                            • **Restart as Administrator**: Close the bot and run as administrator\n\n\
-                            **Progress:** Successfully processed {} of {} chunks before failure",
-                            i+1, chunks.len(), i, chunks.len()
-                        ).into());
                     } else {
                         return Err(format!(
                             "❌ **Chunk Processing Error**\n\n\
@@ -2712,6 +3711,7 @@ async fn stream_summary(
         }
         
         // FIXED: Combine chunk summaries for final prompt with better structure
+        report_progress(ctx, msg, format!("🧩 Combining {} partial summaries...", chunk_summaries.len())).await;
         debug!("📝 === CHUNK SUMMARIES COMBINATION ===");
         debug!("📝 Combining {} chunk summaries...", chunk_summaries.len());
         
@@ -2756,8 +3756,7 @@ async fn stream_summary(
                         error!("❌ Failed to summarize section {}: {}", section_idx + 1, e);
                         
                         // Enhanced error handling for section processing
-                        let error_msg = format!("{}", e);
-                        if error_msg.contains("Connection refused") || error_msg.contains("Connection Error") {
+                        if BotError::find(e.as_ref()).map_or(false, BotError::is_connectivity) {
                             return Err(format!(
                                 "❌ **LM Studio Connection Lost During Section Processing**\n\n\
                                 Failed to process section {} during hierarchical summarization\n\n\
@@ -2791,32 +3790,31 @@ async fn stream_summary(
             // For normal videos, use direct combination
             let combined = chunk_summaries.join("\n\n---\n\n");
             debug!("📝 Combined chunk summaries: {} characters", combined.len());
-            debug!("📝 Combined summaries preview: {}", &combined[..std::cmp::min(300, combined.len())]);
+            debug!("📝 Combined summaries preview: {}", truncate_chars(&combined, 300));
             trace!("🔍 Chunk summaries combined: combined_length={}, chunk_count={}, stream_uuid={}", 
                    combined.len(), chunk_summaries.len(), stream_uuid);
             combined
         };
         
         // Limit final prompt size to prevent context overflow
-        let max_final_prompt_size = 80000; // ~20K tokens
-        let final_content = if combined.len() > max_final_prompt_size {
+        let final_content = if combined.len() > MAX_FINAL_PROMPT_CHARS {
             warn!("⚠️ === FINAL PROMPT TOO LARGE ===");
-            warn!("⚠️ Combined content too large ({} chars), truncating to {} chars", combined.len(), max_final_prompt_size);
+            warn!("⚠️ Combined content too large ({} chars), truncating to {} chars", combined.len(), MAX_FINAL_PROMPT_CHARS);
             format!("{} [Content truncated due to size - showing first {} characters]", 
-                    &combined[0..max_final_prompt_size], max_final_prompt_size)
+                    truncate_chars(&combined, MAX_FINAL_PROMPT_CHARS), MAX_FINAL_PROMPT_CHARS)
         } else {
             combined
         };
         
         let final_user_prompt = format!(
-            "Create a comprehensive, well-structured summary of this {} from {}. Use the following detailed chunk summaries to build a complete overview that covers all major topics, key points, and important information:\n\n{}\n\nPlease organize the summary with clear sections and highlight the most important takeaways.",
-            if is_youtube { "YouTube video" } else { "webpage" },
-            url, final_content
+            "Create a comprehensive, well-structured summary of this {} from {}. Use the following detailed chunk summaries to build a complete overview that covers all major topics, key points, and important information:\n\n{}\n\n{}",
+            if is_youtube { "YouTube video" } else if is_document { "document" } else { "webpage" },
+            url, final_content, format.instruction()
         );
         
         debug!("📝 === FINAL RAG PROMPT CREATION ===");
         debug!("📝 Created final RAG prompt: {} characters", final_user_prompt.len());
-        debug!("📝 Final prompt preview: {}", &final_user_prompt[..std::cmp::min(300, final_user_prompt.len())]);
+        debug!("📝 Final prompt preview: {}", truncate_chars(&final_user_prompt, 300));
         trace!("🔍 Final RAG prompt created: final_prompt_length={}, stream_uuid={}", final_user_prompt.len(), stream_uuid);
         
         let final_messages = vec![
@@ -2829,13 +3827,15 @@ async fn stream_summary(
         trace!("🔍 Final RAG prompt created: final_prompt_length={}, message_count={}, stream_uuid={}", 
                final_user_prompt.len(), final_messages.len(), stream_uuid);
         
+        let sampling = config.summary_sampling_for(selected_model);
         request_payload = serde_json::json!(
             {
                 "model": selected_model,
                 "messages": final_messages,
-                "temperature": config.default_temperature,
-                "max_tokens": config.default_max_tokens,
-                "stream": true
+                "temperature": sampling.temperature,
+                "max_tokens": sampling.max_tokens,
+                "stream": true,
+                "seed": sampling.seed
             }
         );
     } else {
@@ -2852,7 +3852,11 @@ async fn stream_summary(
             },
             ChatMessage {
                 role: "user".to_string(),
-                content: user_prompt,
+                content: if format == SummaryFormat::Markdown {
+                    user_prompt
+                } else {
+                    format!("{}\n\n{}", user_prompt, format.instruction())
+                },
             },
         ];
         
@@ -2864,13 +3868,15 @@ async fn stream_summary(
         trace!("🔍 Direct summarization: message_count={}, system_length={}, user_length={}, content_type={}, stream_uuid={}", 
                messages.len(), messages[0].content.len(), messages[1].content.len(), if is_youtube { "youtube" } else { "webpage" }, stream_uuid);
         
+        let sampling = config.summary_sampling_for(selected_model);
         request_payload = serde_json::json!(
             {
                 "model": selected_model,
                 "messages": messages,
-                "temperature": config.default_temperature,
-                "max_tokens": config.default_max_tokens,
-                "stream": true
+                "temperature": sampling.temperature,
+                "max_tokens": sampling.max_tokens,
+                "stream": true,
+                "seed": sampling.seed
             }
         );
     }
@@ -2880,8 +3886,8 @@ async fn stream_summary(
     debug!("🔧 Using shared HTTP client for streaming request...");
     trace!("🔍 HTTP client setup started: stream_uuid={}", stream_uuid);
     
-    // Calculate appropriate timeout for content complexity
-    let timeout_seconds = 300; // 5 minutes for all content types
+    // Timeout comes from SUM_TIMEOUT (default 300 seconds)
+    let timeout_seconds = config.timeout;
     
     let client = get_http_client().await;
     
@@ -2900,60 +3906,42 @@ async fn stream_summary(
     debug!("🚀 API URL: {}", api_url);
     debug!("🚀 Payload size: {} bytes", payload_size);
     debug!("🚀 Model: {}", selected_model);
-    debug!("🚀 Temperature: {}", config.default_temperature);
-    debug!("🚀 Max tokens: {}", config.default_max_tokens);
+    debug!("🚀 Temperature: {}", config.summary_temperature);
+    debug!("🚀 Max tokens: {}", config.summary_max_tokens);
     debug!("🚀 Streaming: true");
     trace!("🔍 API request preparation: url={}, payload_size={}, stream_uuid={}", api_url, payload_size, stream_uuid);
     
     debug!("🚀 Sending streaming request to LLM...");
     trace!("🔍 Streaming request started: stream_uuid={}", stream_uuid);
     
-    let mut response = match client
-        .post(&api_url)
-        .json(&request_payload)
-        .timeout(Duration::from_secs(timeout_seconds))
-        .send()
-        .await {
+    // The shared helper retries dropped connections, timeouts and 5xx responses before we give up
+    let mut response = match crate::commands::search::send_chat_request(
+        client, &api_url, &request_payload, selected_model, timeout_seconds, config.max_retries,
+    ).await {
             Ok(resp) => resp,
-            Err(e) => {
-                error!("❌ === HTTP REQUEST ERROR ===");
-                error!("❌ Failed to send HTTP request: {}", e);
+            Err(request_error) => {
+                error!("❌ === STREAMING REQUEST ERROR ===");
+                error!("❌ Streaming request failed: {}", request_error);
                 
-                let error_msg = format!("{}", e);
-                let error_message = if e.is_timeout() {
-                    format!("⏰ **Request Timeout**\n\nThe request to LM Studio timed out after {} seconds.\n\n**Solutions:**\n• **Reduce Content**: Try a shorter video/webpage\n• **Check LM Studio**: Ensure LM Studio is running and responsive\n• **Model Performance**: Consider using a faster model\n• **System Resources**: Check if your system has enough RAM/CPU\n\n**Current Setup:**\n• LM Studio URL: `{}`\n• Model: `{}`\n• Content Length: {} characters\n\n*Source: <{}>*", 
-                            timeout_seconds, config.base_url, selected_model, content_to_process.len(), url)
-                } else if e.is_connect() {
-                                          format!("🚫 **Connection Error**\n\nCannot connect to LM Studio at `{}`.\n\n**Solutions:**\n• **Start LM Studio**: Ensure LM Studio is running\n• **Load Model**: Load model `{}` in LM Studio\n• **Enable Server**: Click 'Start Server' in LM Studio\n• **Check Configuration**: Verify URL in lmapiconf.txt\n• **Firewall**: Check Windows Defender/firewall settings\n• **Try localhost**: Use `http://127.0.0.1:1234` instead of localhost\n\n*Source: <{}>*", 
-                            config.base_url, selected_model, url)
-                } else if error_msg.contains("os error 10013") {
-                    format!("🚫 **Windows Permission Error (10013)**\n\nNetwork access denied by Windows.\n\n**Quick Fixes:**\n• **Run as Administrator**: Right-click and 'Run as administrator'\n• **Windows Firewall**: Add firewall exception for this program\n• **Use IP Address**: Try `http://127.0.0.1:1234` in lmapiconf.txt\n\n**Current URL:** `{}`\n*Source: <{}>*", 
-                            config.base_url, url)
-                } else {
-                    format!("🔗 **Network Error**\n\nUnexpected network error occurred.\n\n**Error:** {}\n\n**Solutions:**\n• **Check Connection**: Verify your internet connection\n• **Restart LM Studio**: Try restarting LM Studio\n• **Check Configuration**: Verify settings in lmapiconf.txt\n• **Try Again**: Network issues are often temporary\n\n**Current Setup:**\n• LM Studio URL: `{}`\n• Model: `{}`\n\n*Source: <{}>*", 
-                            e, config.base_url, selected_model, url)
+                let error_message = match request_error.underlying() {
+                    BotError::Timeout { .. } => format!("⏰ **Request Timeout**\n\nThe request to LM Studio timed out after {} seconds.\n\n**Solutions:**\n• **Reduce Content**: Try a shorter video/webpage\n• **Check LM Studio**: Ensure LM Studio is running and responsive\n• **Model Performance**: Consider using a faster model\n• **System Resources**: Check if your system has enough RAM/CPU\n\n**Current Setup:**\n• LM Studio URL: `{}`\n• Model: `{}`\n• Content Length: {} characters\n\n*Source: <{}>*", 
+                            timeout_seconds, config.base_url, selected_model, content_to_process.len(), url),
+                    BotError::NetworkPermission { .. } => format!("🚫 **Windows Permission Error (10013)**\n\nNetwork access denied by Windows.\n\n**Quick Fixes:**\n• **Run as Administrator**: Right-click and 'Run as administrator'\n• **Windows Firewall**: Add firewall exception for this program\n• **Use IP Address**: Try `http://127.0.0.1:1234` in lmapiconf.txt\n\n**Current URL:** `{}`\n*Source: <{}>*", 
+                            config.base_url, url),
+                    error if error.is_connectivity() => format!("🚫 **Connection Error**\n\nCannot connect to LM Studio at `{}`.\n\n**Solutions:**\n• **Start LM Studio**: Ensure LM Studio is running\n• **Load Model**: Load model `{}` in LM Studio\n• **Enable Server**: Click 'Start Server' in LM Studio\n• **Check Configuration**: Verify URL in lmapiconf.txt\n• **Firewall**: Check Windows Defender/firewall settings\n• **Try localhost**: Use `http://127.0.0.1:1234` instead of localhost\n\n*Source: <{}>*", 
+                            config.base_url, selected_model, url),
+                    // HTTP errors (missing model, bad input, 5xx) already carry their own guidance
+                    _ => return Err(request_error.into()),
                 };
                 
                 msg.edit(ctx, |m| m.content(&error_message)).await?;
-                return Ok(());
+                return Ok(None);
             }
         };
     
     debug!("📡 === STREAMING RESPONSE RECEIVED ===");
     debug!("📡 HTTP Response Status: {}", response.status());
-    debug!("📡 HTTP Response Status Code: {}", response.status().as_u16());
-    debug!("📡 HTTP Response Success: {}", response.status().is_success());
-    trace!("🔍 Streaming response received: status={}, success={}, stream_uuid={}", 
-           response.status(), response.status().is_success(), stream_uuid);
-    
-    if !response.status().is_success() {
-        error!("❌ === STREAMING API ERROR ===");
-        error!("❌ API request failed: HTTP {}", response.status());
-        debug!("🔍 API error details: status_code={}, status_text={}", response.status().as_u16(), response.status().as_str());
-        trace!("🔍 API request failed: status={}, status_code={}, stream_uuid={}", 
-               response.status(), response.status().as_u16(), stream_uuid);
-        return Err(format!("API returned error: {}", response.status()).into());
-    }
+    trace!("🔍 Streaming response received: status={}, stream_uuid={}", response.status(), stream_uuid);
     
     debug!("✅ API request successful: HTTP {}", response.status());
     trace!("🔍 API request successful: status={}, stream_uuid={}", response.status(), stream_uuid);
@@ -2963,6 +3951,7 @@ async fn stream_summary(
     trace!("🔍 Streaming processing started: stream_uuid={}", stream_uuid);
     
     let mut accumulated = String::new();
+    let mut finish_reason: Option<String> = None;
     let start_time = Instant::now();
     let mut last_update = Instant::now();
     let mut chunk_count = 0;
@@ -2985,7 +3974,7 @@ async fn stream_summary(
             );
             
             msg.edit(ctx, |m| m.content(&error_message)).await?;
-            return Ok(());
+            return Ok(None);
         }
     } {
         chunk_count += 1;
@@ -2995,7 +3984,7 @@ async fn stream_summary(
         
         let chunk_str = String::from_utf8_lossy(&chunk);
         debug!("📡 Chunk {} as string: {} characters", chunk_count, chunk_str.len());
-        debug!("📡 Chunk {} preview: {}", chunk_count, &chunk_str[..std::cmp::min(100, chunk_str.len())]);
+        debug!("📡 Chunk {} preview: {}", chunk_count, truncate_chars(&chunk_str, 100));
         
         for (line_num, line) in chunk_str.lines().enumerate() {
             debug!("📝 === LINE {} PROCESSING ===", line_num + 1);
@@ -3030,6 +4019,7 @@ async fn stream_summary(
                                        content.len(), accumulated.len(), stream_uuid);
                             }
                             if choice.finish_reason.is_some() {
+                                finish_reason = choice.finish_reason.clone();
                                 debug!("✅ === STREAM FINISHED ===");
                                 debug!("✅ Received finish_reason: {:?}", choice.finish_reason);
                                 trace!("🔍 Received finish_reason: {:?}, ending stream: stream_uuid={}", 
@@ -3061,7 +4051,7 @@ async fn stream_summary(
             trace!("🔍 Periodic Discord update: elapsed_seconds={}, accumulated_length={}, stream_uuid={}", 
                    elapsed, accumulated.len(), stream_uuid);
             
-            msg.edit(ctx, |m| m.content(format!("🤖 Generating summary... ({}s)", elapsed))).await?;
+            msg.edit(ctx, |m| m.content(style_output(format!("🤖 Generating summary... ({}s)", elapsed)))).await?;
             last_update = Instant::now();
             debug!("✅ Discord message updated successfully");
         }
@@ -3079,15 +4069,44 @@ async fn stream_summary(
     debug!("🧹 Removing <think> tags from accumulated content...");
     let before_stripping = accumulated.len();
     
-    let re = Regex::new(r"(?s)<think>.*?</think>").unwrap();
-    let stripped = re.replace_all(&accumulated, "").to_string();
+    let stripped = strip_think_blocks(&accumulated);
+
+    // An empty or reasoning-only summary gets one non-streamed retry with a nudge
+    let retry_messages = match serde_json::from_value::<Vec<ChatMessage>>(request_payload["messages"].clone()) {
+        Ok(messages) => empty_response_retry(&messages, &stripped, |content| ChatMessage { role: "user".to_string(), content }),
+        Err(e) => {
+            warn!("⚠️ Could not rebuild the summary request for a retry: {}", e);
+            None
+        }
+    };
+    let stripped = if let Some(messages) = retry_messages {
+        warn!("⚠️ Model returned no visible summary (finish_reason: {:?}), retrying once", finish_reason);
+        match chat_completion_with_finish_reason(messages, selected_model, config).await {
+            Ok((retry, retry_finish_reason)) => {
+                // The notice below must name the retry's finish_reason, not the first attempt's
+                finish_reason = retry_finish_reason;
+                strip_think_blocks(&retry)
+            }
+            Err(e) => {
+                warn!("⚠️ Retry after an empty summary failed: {}", e);
+                stripped
+            }
+        }
+    } else {
+        stripped
+    };
+    if is_empty_response(&stripped) {
+        error!("❌ Model returned no visible summary after retry (finish_reason: {:?})", finish_reason);
+        msg.edit(ctx, |m| m.content(style_output(format!("{}\n\n*Source: <{}>*", empty_response_message(finish_reason.as_deref()), url)))).await?;
+        return Ok(None);
+    }
     
     debug!("✅ === THINKING TAG REMOVAL COMPLETED ===");
     debug!("✅ Thinking tag removal completed");
     debug!("📊 Before stripping: {} characters", before_stripping);
     debug!("📊 After stripping: {} characters", stripped.len());
     debug!("📊 Stripping reduction: {:.2}%", (stripped.len() as f64 / before_stripping as f64) * 100.0);
-    debug!("📊 Content preview: {}", &stripped[..std::cmp::min(300, stripped.len())]);
+    debug!("📊 Content preview: {}", truncate_chars(&stripped, 300));
     trace!("🔍 Content processing: original_accumulated={}, stripped_length={}, chunk_count={}, stream_uuid={}", 
            accumulated.len(), stripped.len(), chunk_count, stream_uuid);
     
@@ -3114,7 +4133,7 @@ async fn stream_summary(
         msg.edit(ctx, |m| m.content(&error_message)).await?;
         debug!("✅ Fallback error message sent successfully");
         trace!("🔍 Fallback error message sent: stream_uuid={}", stream_uuid);
-        return Ok(());
+        return Ok(None);
     }
     
     // Check if we got meaningful content
@@ -3140,9 +4159,65 @@ async fn stream_summary(
         msg.edit(ctx, |m| m.content(&error_message)).await?;
         debug!("✅ Insufficient content error message sent successfully");
         trace!("🔍 Insufficient content error message sent: stream_uuid={}", stream_uuid);
-        return Ok(());
+        return Ok(None);
+    }
+    
+    // JSON summaries are validated and sent as a code block, or as a .json file when long
+    if format == SummaryFormat::Json {
+        let summary = match parse_json_summary(&stripped) {
+            Ok(summary) => summary,
+            Err(e) => {
+                warn!("⚠️ --format json response failed validation: {}", e);
+                return Err(format!("The model did not return a valid JSON summary ({})", e).into());
+            }
+        };
+        let json = serde_json::to_string_pretty(&summary)?;
+        let max_length = config.max_discord_message_length - config.response_format_padding;
+        let inline = format!("```json\n{}\n```\n*Source: <{}>*", json, url);
+        if inline.len() <= max_length {
+            msg.edit(ctx, |m| m.content(&inline)).await?;
+            info!("✅ JSON summary delivered for {}", url);
+            return Ok(Some(DeliveredSummary { text: inline, attached: false }));
+        } else {
+            info!("📎 JSON summary is {} chars, attaching as a file", json.len());
+            msg.edit(ctx, |m| m.content(style_output(format!("📎 **{}** - JSON summary attached\n*Source: <{}>*", summary.title, url)))).await?;
+            msg.channel_id.send_message(ctx, |m| {
+                m.add_file(serenity::model::channel::AttachmentType::Bytes {
+                    data: std::borrow::Cow::Owned(json.clone().into_bytes()),
+                    filename: "summary.json".to_string(),
+                })
+            }).await?;
+        }
+        info!("✅ JSON summary delivered for {}", url);
+        return Ok(Some(DeliveredSummary { text: json, attached: true }));
     }
     
+    // Summaries longer than the --level allows get one compression pass; shorter overflow (and --level detailed) is split
+    let max_length = config.max_discord_message_length - config.response_format_padding;
+    let compress_over = level.max_messages().filter(|&messages| needs_summary_compression(stripped.trim(), max_length, messages));
+    let stripped = if let Some(max_messages) = compress_over {
+        // Aim well under the limit; a brief summary must leave room for the header and source line
+        let target_chars = if max_messages == 1 { max_length * 3 / 4 } else { max_length * max_messages / 2 };
+        info!("📏 Summary is {} chars (over {} messages), compressing to ~{} chars", stripped.trim().chars().count(), max_messages, target_chars);
+        msg.edit(ctx, |m| m.content(style_output("🗜️ Summary is long, condensing it to fit..."))).await?;
+        match compress_summary(stripped.trim(), format, target_chars, config, selected_model).await {
+            Ok(compressed) if !compressed.is_empty() && compressed.chars().count() < stripped.trim().chars().count() => {
+                info!("✅ Compressed summary from {} to {} chars", stripped.trim().chars().count(), compressed.chars().count());
+                compressed
+            }
+            Ok(_) => {
+                warn!("⚠️ Compression pass did not shorten the summary, splitting the original instead");
+                stripped
+            }
+            Err(e) => {
+                warn!("⚠️ Compression pass failed, splitting the original instead: {}", e);
+                stripped
+            }
+        }
+    } else {
+        stripped
+    };
+
     // Final update
     debug!("📝 === FINAL MESSAGE CREATION ===");
     debug!("📝 Creating final Discord message...");
@@ -3153,14 +4228,16 @@ async fn stream_summary(
         stripped.trim(),
         url
     );
+    // The cache keeps the summary without the footer; it is added again whenever one is shown
+    let cached_summary = final_message.clone();
+    let final_message = with_response_footer(&final_message);
     
     debug!("📝 Final message created: {} characters", final_message.len());
-    debug!("📝 Final message preview: {}", &final_message[..std::cmp::min(300, final_message.len())]);
+    debug!("📝 Final message preview: {}", truncate_chars(&final_message, 300));
     trace!("🔍 Final message created: length={}, is_youtube={}, stream_uuid={}", 
            final_message.len(), is_youtube, stream_uuid);
     
     // Split if too long
-    let max_length = config.max_discord_message_length - config.response_format_padding;
     debug!("📏 === MESSAGE LENGTH CHECK ===");
     debug!("📏 Final message length: {} characters", final_message.len());
     debug!("📏 Max Discord message length: {}", config.max_discord_message_length);
@@ -3178,7 +4255,8 @@ async fn stream_summary(
         trace!("🔍 Message splitting started: original_length={}, max_chunk_length={}, stream_uuid={}", 
                final_message.len(), max_length, stream_uuid);
         
-        let chunks = split_message(&final_message, max_length);
+        // The shared splitter also hard-splits single lines longer than a message, so every part can be sent
+        let chunks = crate::commands::util::split_for_discord(&final_message, max_length);
         debug!("📄 Split into {} chunks", chunks.len());
         debug!("📄 Chunk sizes: {:?}", chunks.iter().map(|c| c.len()).collect::<Vec<_>>());
         trace!("🔍 Message split completed: chunk_count={}, stream_uuid={}", chunks.len(), stream_uuid);
@@ -3220,85 +4298,530 @@ async fn stream_summary(
     debug!("📊   - Content type: {}", if is_youtube { "YouTube" } else { "Webpage" });
     trace!("🔍 Stream summary completed successfully: stream_uuid={}", stream_uuid);
     
+    Ok(Some(DeliveredSummary { text: cached_summary, attached: false }))
+}
+
+// Summaries longer than this many Discord messages get a compression pass before delivery (default --level)
+const SUMMARY_MAX_MESSAGES: usize = 3;
+
+// Whether a finished summary is too long to post as `max_messages` split messages
+fn needs_summary_compression(summary: &str, max_length: usize, max_messages: usize) -> bool {
+    summary.chars().count() > max_length * max_messages
+}
+
+// Second reduce pass: ask the model to condense an over-long summary to roughly `target_chars`
+async fn compress_summary(
+    summary: &str,
+    format: SummaryFormat,
+    target_chars: usize,
+    config: &LMConfig,
+    model: &str,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let messages = vec![
+        ChatMessage {
+            role: "system".to_string(),
+            content: "You are an expert editor. Condense summaries without losing their most important points, and never add new information.".to_string(),
+        },
+        ChatMessage {
+            role: "user".to_string(),
+            content: format!(
+                "This summary is {} characters, which is too long to post in chat. Rewrite it in at most {} characters, \
+                keeping the key points and the same structure and dropping minor details.\n\n{}\n\n{}",
+                summary.chars().count(), target_chars, summary, format.instruction()
+            ),
+        },
+    ];
+    let response = chat_completion(messages, model, config, None).await?;
+    Ok(strip_think_blocks(&response).trim().to_string())
+}
+
+// ============================================================================
+// SUMMARY CACHE
+// ============================================================================
+
+// Finished ^sum summaries are kept on disk per URL, model and format so repeat requests skip the fetch and LLM
+const SUMMARY_CACHE_DIR: &str = "sum_cache";
+// Entries older than this are ignored on lookup and overwritten by the next summary
+const SUMMARY_CACHE_TTL_SECS: i64 = 24 * 60 * 60;
+
+// One cached summary plus the metadata ^sumcache stats reports
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SummaryCacheEntry {
+    url: String,
+    model: String,
+    format: String,
+    created_at: i64, // Unix seconds
+    hits: u64,
+    summary: String,
+    // Set on the copy keyed by content hash rather than URL
+    #[serde(default)]
+    by_content: bool,
+}
+
+// Aggregate view of the cache for ^sumcache stats
+#[derive(Debug, Default)]
+pub struct SummaryCacheStats {
+    pub entries: usize,
+    pub total_bytes: u64,
+    pub total_hits: u64,
+    pub oldest_age_secs: Option<i64>,
+    pub newest_age_secs: Option<i64>,
+    // (url, model, age in seconds, hits) for the most-hit entries
+    pub top_entries: Vec<(String, String, i64, u64)>,
+}
+
+// Cache file for a URL/model/format combination
+fn summary_cache_path(url: &str, model: &str, format: SummaryFormat) -> std::path::PathBuf {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{}\n{}\n{:?}", url, model, format).as_bytes());
+    std::path::Path::new(SUMMARY_CACHE_DIR).join(format!("{:x}.json", hasher.finalize()))
+}
+
+// Cache file for a summary of identical content reached through any URL
+fn content_cache_path(content_hash: &str, model: &str, format: SummaryFormat) -> std::path::PathBuf {
+    summary_cache_path(&format!("content:{}", content_hash), model, format)
+}
+
+// Hash of the cleaned transcript or page text; whitespace is collapsed so re-fetches that only differ in spacing match
+fn content_hash(text: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(text.split_whitespace().collect::<Vec<_>>().join(" ").as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+// Whether an entry created at `created_at` is still usable at `now`
+fn is_cache_entry_fresh(created_at: i64, now: i64) -> bool {
+    now - created_at < SUMMARY_CACHE_TTL_SECS
+}
+
+// Look up a fresh cached summary, counting the hit; returns the summary and its age in seconds
+fn read_cached_summary(url: &str, model: &str, format: SummaryFormat) -> Option<(String, i64)> {
+    read_cache_entry(&summary_cache_path(url, model, format))
+        .map(|(entry, age_secs)| (entry.summary, age_secs))
+}
+
+// Look up a fresh summary of identical content, counting the hit; also returns the URL it was made from
+fn read_cached_summary_by_content(content_hash: &str, model: &str, format: SummaryFormat) -> Option<(String, i64, String)> {
+    read_cache_entry(&content_cache_path(content_hash, model, format))
+        .map(|(entry, age_secs)| (entry.summary, age_secs, entry.url))
+}
+
+// Read a cache file if it is still fresh, bumping its hit count
+fn read_cache_entry(path: &std::path::Path) -> Option<(SummaryCacheEntry, i64)> {
+    let mut entry: SummaryCacheEntry = serde_json::from_str(&fs::read_to_string(path).ok()?).ok()?;
+    let now = chrono::Utc::now().timestamp();
+    if !is_cache_entry_fresh(entry.created_at, now) {
+        debug!("🗄️ Cached summary for {} has expired", entry.url);
+        return None;
+    }
+    entry.hits += 1;
+    if let Ok(json) = serde_json::to_string(&entry) {
+        if let Err(e) = fs::write(path, json) {
+            warn!("⚠️ Could not update summary cache hit count: {}", e);
+        }
+    }
+    let age_secs = now - entry.created_at;
+    Some((entry, age_secs))
+}
+
+// Store a delivered summary, replacing any earlier entry for the same URL/model/format
+// With a content hash, a second copy is keyed on the content so other URLs for it can reuse the summary
+fn write_cached_summary(url: &str, model: &str, format: SummaryFormat, summary: &str, content_hash: Option<&str>) {
+    let mut entry = SummaryCacheEntry {
+        url: url.to_string(),
+        model: model.to_string(),
+        format: format!("{:?}", format).to_lowercase(),
+        created_at: chrono::Utc::now().timestamp(),
+        hits: 0,
+        summary: summary.to_string(),
+        by_content: false,
+    };
+    let mut targets = vec![summary_cache_path(url, model, format)];
+    targets.extend(content_hash.map(|hash| content_cache_path(hash, model, format)));
+    for path in targets {
+        let result = fs::create_dir_all(SUMMARY_CACHE_DIR)
+            .and_then(|_| serde_json::to_string(&entry).map_err(std::io::Error::from))
+            .and_then(|json| fs::write(&path, json));
+        match result {
+            Ok(()) if entry.by_content => debug!("🗄️ Cached summary for {} by content hash", url),
+            Ok(()) => info!("🗄️ Cached summary for {}", url),
+            Err(e) => warn!("⚠️ Could not write summary cache entry: {}", e),
+        }
+        entry.by_content = true;
+    }
+}
+
+// Size, age and hit counts of the on-disk summary cache
+pub fn summary_cache_stats(top: usize) -> SummaryCacheStats {
+    let mut stats = SummaryCacheStats::default();
+    let now = chrono::Utc::now().timestamp();
+    let mut entries = Vec::new();
+    for file in fs::read_dir(SUMMARY_CACHE_DIR).into_iter().flatten().flatten() {
+        let content = match fs::read_to_string(file.path()) {
+            Ok(content) => content,
+            Err(_) => continue,
+        };
+        let entry: SummaryCacheEntry = match serde_json::from_str(&content) {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        let age = now - entry.created_at;
+        stats.entries += 1;
+        stats.total_bytes += content.len() as u64;
+        stats.total_hits += entry.hits;
+        stats.oldest_age_secs = Some(stats.oldest_age_secs.map_or(age, |oldest| oldest.max(age)));
+        stats.newest_age_secs = Some(stats.newest_age_secs.map_or(age, |newest| newest.min(age)));
+        let label = if entry.by_content { format!("{} (by content)", entry.url) } else { entry.url };
+        entries.push((label, entry.model, age, entry.hits));
+    }
+    entries.sort_by(|a, b| b.3.cmp(&a.3).then(a.2.cmp(&b.2)));
+    entries.truncate(top);
+    stats.top_entries = entries;
+    stats
+}
+
+// Delete every cached summary, returning how many entries and bytes were removed
+pub fn clear_summary_cache() -> std::io::Result<(usize, u64)> {
+    let mut removed = (0, 0);
+    let dir = match fs::read_dir(SUMMARY_CACHE_DIR) {
+        Ok(dir) => dir,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(removed),
+        Err(e) => return Err(e),
+    };
+    for file in dir.flatten() {
+        let path = file.path();
+        if path.extension().map_or(false, |ext| ext == "json") {
+            let size = file.metadata().map(|meta| meta.len()).unwrap_or(0);
+            fs::remove_file(&path)?;
+            removed.0 += 1;
+            removed.1 += size;
+        }
+    }
+    info!("🗄️ Summary cache cleared: {} entries, {} bytes", removed.0, removed.1);
+    Ok(removed)
+}
+
+// ============================================================================
+// MESSAGE CONTEXT MENU SUMMARIZATION
+// ============================================================================
+
+// Messages without a URL need at least this much text to be worth summarizing
+const CONTEXT_MENU_MIN_TEXT_CHARS: usize = 200;
+// Cap on the source text sent in the single summarization request
+const CONTEXT_MENU_MAX_INPUT_CHARS: usize = 24000;
+// Batch mode limits for ^sum <url1> <url2> ...
+const MAX_BATCH_URLS: usize = 5;
+const BATCH_CONCURRENCY: usize = 2;
+
+// Summarizes several URLs with bounded concurrency and posts one combined report
+// A failing URL gets its error in its own section instead of aborting the batch
+async fn sum_batch(ctx: &Context, msg: &Message, urls: &[&str]) -> CommandResult {
+    info!("📚 === SUM BATCH MODE: {} URLs ===", urls.len());
+
+    if urls.len() > MAX_BATCH_URLS {
+        msg.reply(ctx, style_output(format!("❌ Too many URLs - batch mode supports at most {} per command.", MAX_BATCH_URLS))).await?;
+        return Ok(());
+    }
+
+    let config = match load_lm_config().await {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            msg.reply(ctx, with_error_id(style_output(&format!("❌ **Configuration Error**\n\n{}", e)))).await?;
+            return Ok(());
+        }
+    };
+
+    let mut response_msg = msg.reply(ctx, style_output(format!("🔄 Summarizing {} URLs...", urls.len()))).await?;
+
+    // buffered() keeps results in input order while running up to BATCH_CONCURRENCY at once
+    let results: Vec<(String, Result<String, String>)> = futures_util::stream::iter(urls.iter().map(|url| {
+        let config = &config;
+        async move {
+            let result = summarize_url_quick(url, config).await.map_err(|e| e.to_string());
+            (url.to_string(), result)
+        }
+    }))
+    .buffered(BATCH_CONCURRENCY)
+    .collect()
+    .await;
+
+    let succeeded = results.iter().filter(|(_, r)| r.is_ok()).count();
+    let sections = results
+        .iter()
+        .enumerate()
+        .map(|(i, (url, result))| match result {
+            Ok(summary) => format!("### {}. <{}>\n{}", i + 1, url, summary),
+            Err(e) => {
+                warn!("⚠️ Batch URL failed: {} - {}", url, e);
+                format!("### {}. <{}>\n❌ Failed to summarize: {}", i + 1, url, e)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    let final_message = format!(
+        "**Batch Summary ({}/{} succeeded)**\n\n{}",
+        succeeded,
+        urls.len(),
+        sections
+    );
+    let final_message = with_response_footer(&final_message);
+
+    let max_length = config.max_discord_message_length - config.response_format_padding;
+    let chunks = split_for_discord(&final_message, max_length);
+    for (i, chunk) in chunks.iter().enumerate() {
+        if i == 0 {
+            response_msg.edit(ctx, |m| m.content(chunk)).await?;
+        } else {
+            msg.channel_id.say(ctx, chunk).await?;
+        }
+    }
+
     Ok(())
 }
 
-// Split long messages into Discord-sized chunks
-// Used to avoid exceeding Discord's message length limit
-fn split_message(content: &str, max_len: usize) -> Vec<String> {
-    let split_uuid = Uuid::new_v4();
-    
-    debug!("📄 === MESSAGE SPLITTING STARTED ===");
-    debug!("🆔 Split UUID: {}", split_uuid);
-    debug!("📄 Original content length: {} characters", content.len());
-    debug!("📄 Max chunk length: {} characters", max_len);
-    debug!("📄 Needs splitting: {}", content.len() > max_len);
-    trace!("🔍 Message splitting started: content_length={}, max_len={}, split_uuid={}", 
-           content.len(), max_len, split_uuid);
-    
-    let mut chunks = Vec::new();
-    let mut current = String::new();
-    let mut line_count = 0;
-    let mut chunk_count = 0;
-    
-    debug!("📝 === LINE PROCESSING ===");
-    debug!("📝 Processing content line by line...");
-    
-    for (line_num, line) in content.lines().enumerate() {
-        line_count += 1;
-        debug!("📝 === LINE {} PROCESSING ===", line_num + 1);
-        debug!("📝 Line {} length: {} characters", line_num + 1, line.len());
-        debug!("📝 Line {} content: '{}'", line_num + 1, line);
-        debug!("📝 Current chunk length: {} characters", current.len());
-        debug!("📝 Would exceed limit: {}", current.len() + line.len() + 1 > max_len);
-        
-        if current.len() + line.len() + 1 > max_len && !current.is_empty() {
-            chunk_count += 1;
-            debug!("📄 === CHUNK {} CREATED ===", chunk_count);
-            debug!("📄 Creating chunk {}: {} characters", chunk_count, current.len());
-            debug!("📄 Chunk {} content: '{}'", chunk_count, current.trim());
-            chunks.push(current.trim().to_string());
-            trace!("🔍 Chunk {} created: length={}, split_uuid={}", chunk_count, current.len(), split_uuid);
-            current = String::new();
-            debug!("📄 Reset current chunk for next content");
+// Reduce step for ^sum --compare: contrasts the two per-source summaries
+const COMPARE_INSTRUCTION: &str = "You compare two sources on the same topic using their summaries. \
+Respond in markdown with exactly these sections: **Agreements** (points both sources make), \
+**Contradictions** (where they disagree or report different facts), **Unique to Source A** and **Unique to Source B**. \
+Use short bullet points, refer to the sources as Source A and Source B, and write \"None\" under a section with nothing to list. \
+Do not add anything that is not in the summaries.";
+
+// Summarizes two URLs with the chunked map-reduce pipeline, then contrasts the summaries in one reduce request
+// Each source fails independently: a failed source is reported and the other's summary is still posted
+async fn sum_compare(ctx: &Context, msg: &Message, url_a: &str, url_b: &str) -> CommandResult {
+    info!("⚖️ === SUM COMPARE MODE: {} vs {} ===", url_a, url_b);
+
+    // Refuse internal targets up front, so a blocked source stops the comparison instead of being reported as a failed fetch
+    for (label, url) in [("A", url_a), ("B", url_b)] {
+        if let Err(reason) = crate::commands::util::validate_fetch_url(url).await {
+            warn!("🚫 Refusing compare source {}: {} - {}", label, url, reason);
+            msg.reply(ctx, style_output(format!("🚫 Source {} can't be summarized: {}", label, reason))).await?;
+            return Ok(());
         }
-        
-        if !current.is_empty() {
-            debug!("📝 Adding newline to current chunk");
-            current.push('\n');
+    }
+
+    let config = match load_lm_config().await {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            msg.reply(ctx, with_error_id(style_output(&format!("❌ **Configuration Error**\n\n{}", e)))).await?;
+            return Ok(());
+        }
+    };
+
+    let mut response_msg = msg.reply(ctx, style_output("🔄 Summarizing both sources...")).await?;
+
+    let (result_a, result_b) = tokio::join!(
+        summarize_url_chunked(url_a, &config),
+        summarize_url_chunked(url_b, &config)
+    );
+    let results = vec![
+        (url_a.to_string(), result_a.map_err(|e| e.to_string())),
+        (url_b.to_string(), result_b.map_err(|e| e.to_string())),
+    ];
+    for (url, result) in &results {
+        if let Err(e) = result {
+            warn!("⚠️ Compare source failed: {} - {}", url, e);
         }
-        
-        debug!("📝 Adding line {} to current chunk", line_num + 1);
-        current.push_str(line);
-        debug!("📝 Current chunk after adding line: {} characters", current.len());
-        trace!("🔍 Line {} processed: line_length={}, current_chunk_length={}, split_uuid={}", 
-               line_num + 1, line.len(), current.len(), split_uuid);
     }
-    
-    if !current.is_empty() {
-        chunk_count += 1;
-        debug!("📄 === FINAL CHUNK {} CREATED ===", chunk_count);
-        debug!("📄 Creating final chunk {}: {} characters", chunk_count, current.len());
-        debug!("📄 Final chunk content: '{}'", current.trim());
-        chunks.push(current.trim().to_string());
-        trace!("🔍 Final chunk {} created: length={}, split_uuid={}", chunk_count, current.len(), split_uuid);
+
+    let final_message = match (&results[0].1, &results[1].1) {
+        (Ok(summary_a), Ok(summary_b)) => {
+            report_progress(ctx, &mut response_msg, "⚖️ Comparing the two summaries...".to_string()).await;
+            let messages = vec![
+                ChatMessage { role: "system".to_string(), content: COMPARE_INSTRUCTION.to_string() },
+                ChatMessage { role: "user".to_string(), content: comparison_request(url_a, summary_a, url_b, summary_b) },
+            ];
+            match chat_completion(messages, &config.default_summarization_model, &config, Some(2000)).await {
+                Ok(raw) => {
+                    format!(
+                        "**⚖️ Comparison**\nSource A: <{}>\nSource B: <{}>\n\n{}",
+                        url_a,
+                        url_b,
+                        strip_think_blocks(&raw).trim()
+                    )
+                }
+                Err(e) => {
+                    error!("❌ Comparison step failed: {}", e);
+                    format!("❌ The comparison step failed: {}\n\n{}", e, format_compare_sources(&results))
+                }
+            }
+        }
+        _ => format!(
+            "⚠️ Comparison skipped - both sources are needed to compare.\n\n{}",
+            format_compare_sources(&results)
+        ),
+    };
+    let final_message = with_response_footer(&final_message);
+
+    let max_length = config.max_discord_message_length - config.response_format_padding;
+    let chunks = split_for_discord(&final_message, max_length);
+    for (i, chunk) in chunks.iter().enumerate() {
+        if i == 0 {
+            response_msg.edit(ctx, |m| m.content(chunk)).await?;
+        } else {
+            msg.channel_id.say(ctx, chunk).await?;
+        }
     }
-    
-    debug!("✅ === MESSAGE SPLITTING COMPLETED ===");
-    debug!("✅ Message splitting completed successfully");
-    debug!("📊 Final statistics:");
-    debug!("📊   - Split UUID: {}", split_uuid);
-    debug!("📊   - Total lines processed: {}", line_count);
-    debug!("📊   - Total chunks created: {}", chunks.len());
-    debug!("📊   - Original content length: {} characters", content.len());
-    debug!("📊   - Total chunked content length: {} characters", chunks.iter().map(|c| c.len()).sum::<usize>());
-    debug!("📊   - Chunk sizes: {:?}", chunks.iter().map(|c| c.len()).collect::<Vec<_>>());
-    debug!("📊   - Efficiency: {:.2}%", (chunks.iter().map(|c| c.len()).sum::<usize>() as f64 / content.len() as f64) * 100.0);
-    
-    trace!("🔍 Message splitting completed: line_count={}, chunk_count={}, original_length={}, total_chunked_length={}, split_uuid={}", 
-           line_count, chunks.len(), content.len(), chunks.iter().map(|c| c.len()).sum::<usize>(), split_uuid);
-    
-    chunks
+
+    Ok(())
+}
+
+// User message for the comparison reduce step
+fn comparison_request(url_a: &str, summary_a: &str, url_b: &str, summary_b: &str) -> String {
+    format!(
+        "Source A ({}):\n{}\n\nSource B ({}):\n{}",
+        url_a, summary_a, url_b, summary_b
+    )
+}
+
+// Per-source sections for a comparison that couldn't run, each with its summary or error
+fn format_compare_sources(results: &[(String, Result<String, String>)]) -> String {
+    results
+        .iter()
+        .zip(["A", "B"])
+        .map(|((url, result), label)| match result {
+            Ok(summary) => format!("### Source {}: <{}>\n{}", label, url, summary),
+            Err(e) => format!("### Source {}: <{}>\n❌ Failed to summarize: {}", label, url, e),
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+// http(s) URLs in message text
+static MESSAGE_URL_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"https?://[^\s<>]+").expect("Invalid message URL regex pattern")
+});
+
+// Extracts http(s) URLs from message text, stripping Discord's <...> wrapping and trailing punctuation
+pub fn extract_urls(text: &str) -> Vec<String> {
+    MESSAGE_URL_REGEX
+        .find_iter(text)
+        .map(|m| m.as_str().trim_end_matches(|c: char| matches!(c, '.' | ',' | ')' | '>' | '!' | '?' | ';' | ':')).to_string())
+        .collect()
+}
+
+// Fetches a URL (webpage or YouTube transcript) and returns its readable text with the matching system prompt
+async fn fetch_source_text(url: &str) -> Result<(String, String, bool), Box<dyn std::error::Error + Send + Sync>> {
+    if let Some(youtube_url) = parse_youtube_url(url) {
+        let url = youtube_url.canonical_url();
+        let vtt_path = fetch_youtube_transcript(&url).await?;
+        let transcript = clean_vtt_content(&fs::read_to_string(&vtt_path)?);
+        Ok((transcript, load_youtube_summarization_prompt().await?, true))
+    } else {
+        let (page_content, _html_file_path) = fetch_webpage_content(url, DEFAULT_FEED_ITEMS).await?;
+        Ok((page_content, load_summarization_prompt().await?, false))
+    }
+}
+
+// Fetches a URL (webpage or YouTube transcript) and summarizes it in a single request
+// Lighter than the full ^sum pipeline: no RAG chunking, input is capped at CONTEXT_MENU_MAX_INPUT_CHARS
+async fn summarize_url_quick(url: &str, config: &LMConfig) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let (source_text, system_prompt, _is_youtube) = fetch_source_text(url).await?;
+    summarize_text_quick(&source_text, system_prompt, config).await
+}
+
+// Fetches a URL and summarizes it without truncation, mapping over chunks like ^sum does for long content
+// Content that fits in one chunk is summarized in a single request; the reply is not streamed
+async fn summarize_url_chunked(url: &str, config: &LMConfig) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let (source_text, system_prompt, is_youtube) = fetch_source_text(url).await?;
+    if source_text.trim().is_empty() {
+        return Err("No readable content was found to summarize.".into());
+    }
+
+    let model = &config.default_summarization_model;
+    let chunk_size = if is_youtube { 24000 } else { 16000 };
+    let content = if source_text.len() > chunk_size {
+        let chunks = split_into_chunks(&source_text, chunk_size);
+        info!("📄 Summarizing {} in {} chunks", url, chunks.len());
+        let mut chunk_summaries = Vec::with_capacity(chunks.len());
+        for (i, chunk) in chunks.iter().enumerate() {
+            let messages = vec![
+                ChatMessage { role: "system".to_string(), content: CHUNK_SUMMARY_SYSTEM_PROMPT.to_string() },
+                ChatMessage {
+                    role: "user".to_string(),
+                    content: format!(
+                        "Create a detailed summary of this content chunk from {}. Focus on key points, topics, and important information:\n\n{}",
+                        if is_youtube { "a YouTube video" } else { "a webpage" },
+                        chunk
+                    ),
+                },
+            ];
+            let raw = chat_completion(messages, model, config, Some(2000))
+                .await
+                .map_err(|e| format!("chunk {} of {} failed: {}", i + 1, chunks.len(), e))?;
+            chunk_summaries.push(strip_think_blocks(&raw).trim().to_string());
+        }
+        let combined = chunk_summaries.join("\n\n---\n\n");
+        truncate_chars(&combined, MAX_FINAL_PROMPT_CHARS).to_string()
+    } else {
+        source_text
+    };
+
+    let messages = vec![
+        ChatMessage { role: "system".to_string(), content: system_prompt },
+        ChatMessage {
+            role: "user".to_string(),
+            content: format!("Please summarize the following content from {}:\n\n{}", url, content),
+        },
+    ];
+    let raw = chat_completion(messages, model, config, Some(2000)).await?;
+    Ok(strip_think_blocks(&raw).trim().to_string())
+}
+
+// Summarizes already-extracted text in a single request and strips <think> blocks from the reply
+async fn summarize_text_quick(
+    source_text: &str,
+    system_prompt: String,
+    config: &LMConfig,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    if source_text.trim().is_empty() {
+        return Err("No readable content was found to summarize.".into());
+    }
+
+    let source_text: String = source_text.chars().take(CONTEXT_MENU_MAX_INPUT_CHARS).collect();
+    debug!("📋 Summarizing {} characters with model {}", source_text.len(), config.default_summarization_model);
+
+    let messages = vec![
+        ChatMessage { role: "system".to_string(), content: system_prompt },
+        ChatMessage {
+            role: "user".to_string(),
+            content: format!("Please summarize the following content:\n\n{}", source_text),
+        },
+    ];
+    let raw = chat_completion(messages, &config.default_summarization_model, config, Some(2000)).await?;
+    Ok(strip_think_blocks(&raw).trim().to_string())
+}
+
+// Summarizes a Discord message for the "Summarize" context menu command
+// Uses the first URL in the message (webpage or YouTube) or the message text itself, and returns the formatted summary
+pub async fn summarize_message_content(content: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    info!("📋 === CONTEXT MENU SUMMARIZATION ===");
+    let urls = extract_urls(content);
+    debug!("📋 Found {} URLs in target message", urls.len());
+
+    let config = load_lm_config().await?;
+
+    let (summary, title, source) = if let Some(url) = urls.first() {
+        let title = if parse_youtube_url(url).is_some() { "YouTube Video Summary" } else { "Webpage Summary" };
+        (summarize_url_quick(url, &config).await?, title, Some(url.clone()))
+    } else if content.chars().count() >= CONTEXT_MENU_MIN_TEXT_CHARS {
+        let system_prompt = load_summarization_prompt().await?;
+        (summarize_text_quick(content, system_prompt, &config).await?, "Message Summary", None)
+    } else {
+        return Err("This message has no URL and is too short to summarize.".into());
+    };
+
+    let mut result = format!("**{}**\n\n{}", title, summary);
+    if let Some(url) = source {
+        result.push_str(&format!("\n\n*Source: <{}>*", url));
+    }
+    if urls.len() > 1 {
+        let others = urls[1..].iter().map(|u| format!("• <{}>", u)).collect::<Vec<_>>().join("\n");
+        result.push_str(&format!("\n\n**Other links in this message** (use `^sum <url>` to summarize):\n{}", others));
+    }
+
+    Ok(result)
 }
 
 // ============================================================================
@@ -3310,6 +4833,29 @@ fn split_message(content: &str, max_len: usize) -> Vec<String> {
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    #[test]
+    fn test_summary_cache_keys_and_freshness() {
+        let url = "https://example.com/article";
+        let markdown = summary_cache_path(url, "qwen", SummaryFormat::Markdown);
+        assert_eq!(markdown, summary_cache_path(url, "qwen", SummaryFormat::Markdown));
+        assert_ne!(markdown, summary_cache_path(url, "qwen", SummaryFormat::Bullets));
+        assert_ne!(markdown, summary_cache_path(url, "llama", SummaryFormat::Markdown));
+        assert!(markdown.starts_with(SUMMARY_CACHE_DIR));
+
+        assert!(is_cache_entry_fresh(1000, 1000 + SUMMARY_CACHE_TTL_SECS - 1));
+        assert!(!is_cache_entry_fresh(1000, 1000 + SUMMARY_CACHE_TTL_SECS));
+    }
+
+    #[test]
+    fn test_content_hash_ignores_whitespace() {
+        let hash = content_hash("never gonna give you up\nnever gonna let you down");
+        assert_eq!(hash, content_hash("  never gonna give you up never  gonna let you down\n"));
+        assert_ne!(hash, content_hash("never gonna run around"));
+        let by_content = content_cache_path(&hash, "qwen", SummaryFormat::Markdown);
+        assert!(by_content.starts_with(SUMMARY_CACHE_DIR));
+        assert_ne!(by_content, summary_cache_path(&hash, "qwen", SummaryFormat::Markdown));
+    }
     
     #[test]
     fn test_clean_vtt() {
@@ -3325,6 +4871,115 @@ This is a test"#;
         assert_eq!(cleaned, "Hello world This is a test");
     }
     
+    #[test]
+    fn test_parse_json_summary_validates_fields() {
+        let fenced = "```json\n{\"title\": \"Rust 2024\", \"summary\": \"Edition notes.\", \"key_points\": [\"a\", \"b\"]}\n```";
+        let summary = parse_json_summary(fenced).unwrap();
+        assert_eq!(summary.title, "Rust 2024");
+        assert_eq!(summary.key_points.len(), 2);
+        assert!(parse_json_summary("{\"title\": \"x\", \"summary\": \"y\"}").is_err());
+        assert!(parse_json_summary("No JSON here").is_err());
+        assert_eq!(SummaryFormat::parse("Bullets"), Some(SummaryFormat::Bullets));
+        assert_eq!(SummaryFormat::parse("yaml"), None);
+    }
+    
+    #[test]
+    fn test_extract_urls_from_message() {
+        let urls = extract_urls("Check <https://example.com/a> and https://youtu.be/abc123, thanks!");
+        assert_eq!(urls, vec!["https://example.com/a".to_string(), "https://youtu.be/abc123".to_string()]);
+        assert!(extract_urls("no links here").is_empty());
+    }
+
+    #[test]
+    fn test_trim_vtt_from_offset() {
+        let vtt = "WEBVTT\nKind: captions\n\n00:00:01.000 --> 00:00:04.000\nintro\n\n00:01:30.000 --> 00:01:33.000\nmain point\n\n01:00:00.000 --> 01:00:02.000\noutro";
+        let trimmed = trim_vtt_from(vtt, 90.0);
+        assert!(trimmed.starts_with("WEBVTT"));
+        assert!(!trimmed.contains("intro"));
+        assert!(trimmed.contains("main point"));
+        assert!(trimmed.contains("outro"));
+    }
+
+    #[test]
+    fn test_parse_rss_and_atom_feeds() {
+        let rss = r#"<?xml version="1.0"?><rss version="2.0"><channel><title>Example News</title>
+            <item><title>First &amp; foremost</title><link>https://example.com/1</link>
+            <description><![CDATA[<p>Big <b>news</b> today.</p>]]></description><pubDate>Mon, 01 Jan 2024 10:00:00 GMT</pubDate></item>
+            <item><title>Second</title><description>&lt;p&gt;More&lt;/p&gt;</description></item>
+            <item><title>Third</title></item></channel></rss>"#;
+        assert!(looks_like_feed(Some("text/xml"), rss));
+        let feed = parse_feed(rss, 2).unwrap();
+        assert_eq!(feed.title.as_deref(), Some("Example News"));
+        assert_eq!(feed.items.len(), 2);
+        assert_eq!(feed.items[0].title, "First & foremost");
+        assert_eq!(feed.items[0].summary, "Big news today.");
+        assert_eq!(feed.items[0].link.as_deref(), Some("https://example.com/1"));
+        assert_eq!(feed.items[1].summary, "More");
+
+        let atom = r#"<feed xmlns="http://www.w3.org/2005/Atom"><title>Blog</title>
+            <entry><title>Post</title><link href="https://example.com/post"/><summary>Short summary</summary><updated>2024-01-02</updated></entry></feed>"#;
+        let feed = parse_feed(atom, DEFAULT_FEED_ITEMS).unwrap();
+        assert_eq!(feed.items[0].link.as_deref(), Some("https://example.com/post"));
+        assert!(format_feed_digest(&feed).contains("1. Post (2024-01-02)\nhttps://example.com/post\nShort summary"));
+
+        assert!(!looks_like_feed(Some("text/html"), "<!DOCTYPE html><html><body>Hi</body></html>"));
+    }
+
+    #[test]
+    fn test_is_pdf_attachment() {
+        assert!(is_pdf_attachment("Report.PDF", None));
+        assert!(is_pdf_attachment("download", Some("application/pdf")));
+        assert!(!is_pdf_attachment("notes.txt", Some("text/plain; charset=utf-8")));
+    }
+
+    #[test]
+    fn test_detect_unreadable_page() {
+        let js_shell = "<html><body><noscript>Please enable JavaScript to view this site.</noscript><div id=\"root\"></div></body></html>";
+        assert_eq!(detect_unreadable_page(js_shell, "Please enable JavaScript to view this site."), Some("javascript required"));
+        assert_eq!(detect_unreadable_page("<html><body></body></html>", "  "), Some("empty body"));
+        assert_eq!(detect_unreadable_page("<p>Short note.</p>", "Short note."), None);
+
+        // Long articles mentioning JavaScript are still summarized
+        let article = "Learn why you should enable JavaScript. ".repeat(40);
+        assert_eq!(detect_unreadable_page(&article, &article), None);
+    }
+
+    #[test]
+    fn test_extract_page_links_resolves_and_filters() {
+        let html = r##"<html><body>
+            <nav><a href="/home">Home</a><a href="/about">About</a></nav>
+            <p>See <a href="/docs/guide#intro">the guide</a>, <a href='https://other.org/paper'>this paper</a>
+            and <a href="/docs/guide">the guide again</a>.</p>
+            <a href="#top">Top</a> <a href="mailto:me@example.com">Mail</a> <a href="javascript:void(0)">JS</a>
+            <footer><a href="/privacy">Privacy</a></footer>
+        </body></html>"##;
+
+        let links = extract_page_links(html, "https://example.com/articles/post", 10);
+        assert_eq!(links, vec![
+            "https://example.com/docs/guide".to_string(),
+            "https://other.org/paper".to_string(),
+        ]);
+        assert_eq!(extract_page_links(html, "https://example.com/articles/post", 1).len(), 1);
+    }
+
+    #[test]
+    fn test_parse_vtt_segments_for_chapters() {
+        let vtt = r#"WEBVTT
+
+00:00:01.000 --> 00:00:03.000 align:start position:0%
+Intro <c>line</c>
+
+01:02:03.500 --> 01:02:05.000
+Later line"#;
+
+        let segments = parse_vtt_segments(vtt);
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0], (1.0, "Intro line".to_string()));
+        assert_eq!(segments[1].0, 3723.5);
+        assert_eq!(format_chapter_timestamp(3723.5), "1:02:03");
+        assert_eq!(format_chapter_timestamp(75.0), "1:15");
+    }
+
     #[test]
     fn test_clean_html() {
         let html = "<p>Hello <b>world</b></p><script>alert('test');</script>";
@@ -3390,6 +5045,21 @@ This is a test"#;
         assert!(!cleaned.contains("<b>"));
     }
     
+    #[test]
+    fn test_parse_message_reference() {
+        assert_eq!(parse_message_reference("1234567890"), Some((None, 1234567890)));
+        assert_eq!(
+            parse_message_reference("https://discord.com/channels/111/222/333"),
+            Some((Some(222), 333))
+        );
+        assert_eq!(
+            parse_message_reference("<https://ptb.discord.com/channels/@me/222/333>"),
+            Some((Some(222), 333))
+        );
+        assert_eq!(parse_message_reference("https://discord.com/channels/111/222"), None);
+        assert_eq!(parse_message_reference("yesterday"), None);
+    }
+
     #[test]
     fn test_lm_config_structure() {
         // Test that the LMConfig structure can be created and has all expected fields
@@ -3406,6 +5076,13 @@ This is a test"#;
             response_format_padding: 100,
             default_vision_model: "test-vision-model".to_string(),
             default_seed: Some(42),
+            summary_max_tokens: 1000,
+            summary_temperature: 0.3,
+            max_retries: 3,
+            model_profiles: HashMap::from([(
+                "test-sum-model".to_string(),
+                crate::commands::search::ModelProfile { temperature: Some(0.2), max_tokens: None, seed: None },
+            )]),
         };
         
         assert_eq!(config.base_url, "http://localhost:1234");
@@ -3415,6 +5092,9 @@ This is a test"#;
         assert_eq!(config.default_temperature, 0.7);
         assert_eq!(config.default_max_tokens, 2000);
         assert_eq!(config.default_seed, Some(42));
+        assert_eq!(config.summary_sampling_for("test-sum-model").temperature, 0.2);
+        assert_eq!(config.summary_sampling_for("test-sum-model").max_tokens, 1000);
+        assert_eq!(config.sampling_for("test-model").temperature, 0.7);
     }
     
     #[test]
@@ -3440,54 +5120,51 @@ This is a test"#;
     }
     
     #[test]
-    fn test_split_message_functionality() {
-        // Test the message splitting functionality with multi-line content
-        // The split_message function splits by lines, not character count
-        let lines = vec![
-            "This is line 1 with some text that makes it moderately long to test splitting.",
-            "This is line 2 with additional content that should also be quite lengthy.",
-            "This is line 3 with even more text to ensure we exceed the character limit.",
-            "This is line 4 which continues to add content for comprehensive testing.",
-            "This is line 5 that should definitely push us over the max_len limit.",
-            "This is line 6 with final content to ensure proper multi-chunk splitting.",
-            "This is line 7 with additional verification content for thorough testing.",
-            "This is line 8 providing the last bit of content for split verification."
+    fn test_summary_entities_parse_and_render() {
+        let response = "```json\n{\"people\": [\"Ada Lovelace\", \"ada lovelace\"], \"organizations\": [], \"figures\": [\"$4.2B revenue\"]}\n```";
+        let entities = parse_summary_entities(response).unwrap();
+        assert!(entities.dates.is_empty());
+        let rendered = entities.render();
+        assert!(rendered.contains("**👤 People**\n• Ada Lovelace"));
+        assert_eq!(rendered.matches("Lovelace").count(), 1);
+        assert!(rendered.contains("• $4.2B revenue"));
+        assert!(!rendered.contains("Organizations"));
+
+        assert!(parse_summary_entities("no json").is_err());
+        assert!(parse_summary_entities("{\"people\": \"Ada\"}").is_err());
+        assert!(SummaryEntities::default().render().contains("No named entities"));
+    }
+
+    #[test]
+    fn test_compare_sources_report_failures_independently() {
+        let results = vec![
+            ("https://a.example".to_string(), Ok("Summary of A".to_string())),
+            ("https://b.example".to_string(), Err("HTTP 404".to_string())),
         ];
-        let long_content = lines.join("\n");
-        let max_len = 200; // Small limit to force splitting
-        
-        // Verify the content is actually long enough to require splitting
-        assert!(long_content.len() > max_len, "Test content should be longer than max_len for proper testing");
-        
-        let chunks = split_message(&long_content, max_len);
-        
-        // Should create multiple chunks since we have multiple lines that exceed the limit
-        assert!(chunks.len() > 1, "Content of {} chars with {} lines should split with max_len {}", 
-                long_content.len(), lines.len(), max_len);
-        
-        // Each chunk should be within the limit (except possibly the last one with remaining content)
-        for (i, chunk) in chunks.iter().enumerate() {
-            if i < chunks.len() - 1 {
-                assert!(chunk.len() <= max_len, "Chunk {} exceeds max length: {} > {}", i, chunk.len(), max_len);
-            }
-        }
-        
-        // All chunks combined should contain the original content (minus whitespace differences)
-        let combined = chunks.join("\n");
-        let original_words: Vec<&str> = long_content.split_whitespace().collect();
-        let combined_words: Vec<&str> = combined.split_whitespace().collect();
-        assert_eq!(original_words.len(), combined_words.len());
-        
-        // Test with very short content that shouldn't be split
-        let short_content = "Short message";
-        let short_chunks = split_message(short_content, max_len);
-        assert_eq!(short_chunks.len(), 1, "Short content should not be split");
-        assert_eq!(short_chunks[0], short_content);
-        
-        // Test with single long line (should not be split since split_message works by lines)
-        let single_long_line = "This is a very long single line that exceeds the max_len but has no line breaks so it cannot be split by the line-based splitting algorithm.".repeat(5);
-        let single_line_chunks = split_message(&single_long_line, max_len);
-        assert_eq!(single_line_chunks.len(), 1, "Single long line should not be split");
+        let report = format_compare_sources(&results);
+        assert!(report.contains("### Source A: <https://a.example>\nSummary of A"));
+        assert!(report.contains("### Source B: <https://b.example>\n❌ Failed to summarize: HTTP 404"));
+
+        let request = comparison_request("https://a.example", "one", "https://b.example", "two");
+        assert!(request.starts_with("Source A (https://a.example):\none"));
+        assert!(request.contains("Source B (https://b.example):\ntwo"));
+    }
+
+    #[test]
+    fn test_needs_summary_compression() {
+        let max_length = 1900;
+        assert!(!needs_summary_compression(&"a".repeat(max_length), max_length, SUMMARY_MAX_MESSAGES));
+        assert!(!needs_summary_compression(&"a".repeat(max_length * SUMMARY_MAX_MESSAGES), max_length, SUMMARY_MAX_MESSAGES));
+        assert!(needs_summary_compression(&"a".repeat(max_length * SUMMARY_MAX_MESSAGES + 1), max_length, SUMMARY_MAX_MESSAGES));
+        // Counted in characters, so multi-byte text isn't compressed early
+        assert!(!needs_summary_compression(&"ä".repeat(max_length * SUMMARY_MAX_MESSAGES), max_length, SUMMARY_MAX_MESSAGES));
+
+        // --level brief condenses anything over one message, --level detailed never condenses
+        assert_eq!(SummaryLevel::parse("Brief"), Some(SummaryLevel::Brief));
+        assert_eq!(SummaryLevel::parse("detailed"), Some(SummaryLevel::Detailed));
+        assert_eq!(SummaryLevel::parse("medium"), None);
+        assert!(needs_summary_compression(&"a".repeat(max_length + 1), max_length, SummaryLevel::Brief.max_messages().unwrap()));
+        assert_eq!(SummaryLevel::Detailed.max_messages(), None);
     }
 }
 
@@ -3500,4 +5177,4 @@ impl Sum {
     pub const fn new() -> Self {
         Sum
     }
-} 
\ No newline at end of file
+}
@@ -0,0 +1,270 @@
+// translate.rs - Translate Command Module
+// This module implements the ^translate command, a focused wrapper around the LM chat completion.
+//
+// Key Features:
+// - Translates the argument text, an attached text file, or the message being replied to
+// - Returns only the translated text (thinking blocks and commentary are not requested or shown)
+// - --detect identifies the source language first and reports it
+// - Long documents are translated in paragraph-aligned chunks and delivered as a file when over the message limit
+//
+// Used by: main.rs (command registration)
+
+// ============================================================================
+// IMPORTS
+// ============================================================================
+
+use serenity::{
+    client::Context,
+    framework::standard::{macros::command, macros::group, Args, CommandResult},
+    model::channel::Message,
+};
+use crate::commands::search::{chat_completion, load_lm_config, ChatMessage};
+use crate::commands::util::{decode_text_bytes, is_text_attachment, next_arg, parse_leading_flags, strip_think_blocks, style_output, with_response_footer};
+
+// ============================================================================
+// CONSTANTS
+// ============================================================================
+
+/// Largest text attachment ^translate will download
+const MAX_TRANSLATE_ATTACHMENT_BYTES: u64 = 1024 * 1024;
+
+/// Source text is translated in pieces of at most this many characters
+const TRANSLATE_CHUNK_CHARS: usize = 4000;
+
+/// How much of the source text --detect shows the model
+const DETECT_SAMPLE_CHARS: usize = 1000;
+
+// ============================================================================
+// COMMAND IMPLEMENTATION
+// ============================================================================
+
+#[command]
+#[aliases("tr")]
+/// Main ^translate command handler
+/// Translates text into the requested language
+/// Supports:
+///   - ^translate <language> <text>
+///   - ^translate <language> (with a text file attached, or as a reply to a message)
+///   - ^translate --detect <language> <text> (identify the source language first)
+pub async fn translate(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    // Respect the ALLOWED_CHANNELS / DENIED_CHANNELS restrictions for heavy commands
+    if crate::commands::util::reply_if_channel_blocked(ctx, msg, "translate").await? {
+        return Ok(());
+    }
+    // Counted as in flight so shutdown can wait for it to finish
+    let _heavy_guard = crate::start_heavy_command();
+
+    let input = args.message().trim();
+    let (flags, rest) = parse_leading_flags(input, &["--detect", "-d"], &[]);
+    let detect = !flags.is_empty();
+
+    let (language, text) = match next_arg(rest) {
+        Some((language, text)) if !language.is_empty() => (language, text.trim()),
+        _ => {
+            msg.reply(ctx, "Please provide a target language! Usage: `^translate [--detect] <language> <text>` (or attach a text file / reply to a message)").await?;
+            return Ok(());
+        }
+    };
+
+    if crate::commands::util::reply_if_prompt_too_long(ctx, msg, text, "translate").await? {
+        return Ok(());
+    }
+
+    let source_text = if !text.is_empty() {
+        text.to_string()
+    } else if let Some(attachment) = msg.attachments.first() {
+        if !is_text_attachment(&attachment.filename, attachment.content_type.as_deref()) {
+            msg.reply(ctx, format!("❌ `{}` doesn't look like a text file.", attachment.filename)).await?;
+            return Ok(());
+        }
+        if attachment.size > MAX_TRANSLATE_ATTACHMENT_BYTES {
+            msg.reply(ctx, format!(
+                "❌ `{}` is too large. Text files up to {} KB can be translated.",
+                attachment.filename,
+                MAX_TRANSLATE_ATTACHMENT_BYTES / 1024
+            )).await?;
+            return Ok(());
+        }
+        match attachment.download().await {
+            Ok(bytes) => decode_text_bytes(&bytes),
+            Err(e) => {
+                eprintln!("[TRANSLATE] Failed to download attachment {}: {}", attachment.filename, e);
+                msg.reply(ctx, format!("❌ Failed to download `{}`: {}", attachment.filename, e)).await?;
+                return Ok(());
+            }
+        }
+    } else if let Some(referenced) = msg.referenced_message.as_deref() {
+        referenced.content.clone()
+    } else {
+        String::new()
+    };
+    if source_text.trim().is_empty() {
+        msg.reply(ctx, "Please provide text to translate, attach a text file, or reply to a message with `^translate <language>`!").await?;
+        return Ok(());
+    }
+
+    // Global cap on simultaneous backend requests; held until this command returns
+    let _backend_permit = match crate::commands::util::acquire_backend_permit_or_reply(ctx, msg, "translate").await? {
+        Some(permit) => permit,
+        None => return Ok(()),
+    };
+
+    let config = match load_lm_config().await {
+        Ok(config) => config,
+        Err(e) => {
+            msg.reply(ctx, style_output(format!("❌ **Configuration Error**\n\n{}", e))).await?;
+            return Ok(());
+        }
+    };
+
+    let mut status_msg = msg.reply(ctx, style_output(format!("🔄 Translating to {}...", language))).await?;
+
+    let detected = if detect {
+        match detect_language(&source_text, &config).await {
+            Ok(detected) => Some(detected),
+            Err(e) => {
+                eprintln!("[TRANSLATE] Language detection failed: {}", e);
+                status_msg.edit(&ctx.http, |m| m.content(style_output(format!("❌ Language detection failed: {}", e)))).await?;
+                return Ok(());
+            }
+        }
+    } else {
+        None
+    };
+
+    let chunks = split_for_translation(&source_text, TRANSLATE_CHUNK_CHARS);
+    println!("[TRANSLATE] {} -> {} ({} chars in {} chunks) for {}", detected.as_deref().unwrap_or("auto"), language, source_text.chars().count(), chunks.len(), msg.author.name);
+    let mut translated = Vec::with_capacity(chunks.len());
+    for (i, chunk) in chunks.iter().enumerate() {
+        if chunks.len() > 1 {
+            let _ = status_msg.edit(&ctx.http, |m| m.content(style_output(format!("🔄 Translating to {}... (part {}/{})", language, i + 1, chunks.len())))).await;
+        }
+        let messages = vec![
+            ChatMessage { role: "system".to_string(), content: translation_system_prompt(&language, detected.as_deref()) },
+            ChatMessage { role: "user".to_string(), content: chunk.clone() },
+        ];
+        match chat_completion(messages, &config.default_model, &config, None).await {
+            Ok(response) => translated.push(strip_thinking(&response)),
+            Err(e) => {
+                eprintln!("[TRANSLATE] Translation of part {} failed: {}", i + 1, e);
+                status_msg.edit(&ctx.http, |m| m.content(style_output(format!("❌ Translation failed: {}", e)))).await?;
+                return Ok(());
+            }
+        }
+    }
+
+    let header = match &detected {
+        Some(source) => format!("🌐 **{} → {}**\n", source, language),
+        None => format!("🌐 **→ {}**\n", language),
+    };
+    let response = with_response_footer(&format!("{}{}", header, translated.join("\n\n")));
+    let max_len = config.max_discord_message_length - config.response_format_padding;
+    if response.chars().count() <= max_len {
+        status_msg.edit(&ctx.http, |m| m.content(&response)).await?;
+    } else {
+        let _ = status_msg.delete(&ctx.http).await;
+        crate::commands::util::send_long_message(ctx, msg.channel_id, &response, max_len, "translation.txt").await?;
+    }
+    Ok(())
+}
+
+// ============================================================================
+// HELPER FUNCTIONS
+// ============================================================================
+
+/// System prompt asking for the translation alone, so the reply can be posted as-is
+fn translation_system_prompt(language: &str, source: Option<&str>) -> String {
+    let from = source.map(|source| format!(" from {}", source)).unwrap_or_default();
+    format!(
+        "You are a professional translator. Translate the user's text{} into {}. \
+        Preserve meaning, tone, formatting, line breaks, code blocks and URLs. \
+        Reply with only the translated text - no notes, explanations, quotation marks or transliterations.",
+        from, language
+    )
+}
+
+/// Ask the model which language a sample of the text is written in
+async fn detect_language(
+    text: &str,
+    config: &crate::commands::search::LMConfig,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let sample = crate::commands::util::truncate_chars(text, DETECT_SAMPLE_CHARS);
+    let messages = vec![
+        ChatMessage {
+            role: "system".to_string(),
+            content: "Identify the language of the user's text. Reply with only the language name in English, e.g. \"Finnish\".".to_string(),
+        },
+        ChatMessage { role: "user".to_string(), content: sample.to_string() },
+    ];
+    let response = strip_thinking(&chat_completion(messages, &config.default_model, config, Some(200)).await?);
+    let language = response.lines().next().unwrap_or_default().trim().trim_matches(|c: char| c == '"' || c == '.' || c == '*').to_string();
+    if language.is_empty() {
+        return Err("the model did not name a language".into());
+    }
+    Ok(language)
+}
+
+/// Drop <think> blocks (nested, or an unclosed trailing one) from a completion
+fn strip_thinking(response: &str) -> String {
+    strip_think_blocks(response).trim().to_string()
+}
+
+/// Split text into pieces of at most `max_chars`, preferring paragraph, then line, then word boundaries
+fn split_for_translation(text: &str, max_chars: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut rest = text.trim();
+    while rest.chars().count() > max_chars {
+        let limit = rest.char_indices().nth(max_chars).map(|(idx, _)| idx).unwrap_or(rest.len());
+        let window = &rest[..limit];
+        let cut = window.rfind("\n\n")
+            .or_else(|| window.rfind('\n'))
+            .or_else(|| window.rfind(' '))
+            .filter(|&cut| cut > 0)
+            .unwrap_or(limit);
+        chunks.push(rest[..cut].trim().to_string());
+        rest = rest[cut..].trim_start();
+    }
+    if !rest.is_empty() {
+        chunks.push(rest.to_string());
+    }
+    chunks
+}
+
+// ============================================================================
+// COMMAND GROUP
+// ============================================================================
+
+#[group]
+#[commands(translate)]
+pub struct Translate;
+
+impl Translate {
+    pub const fn new() -> Self {
+        Translate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_for_translation_prefers_paragraphs() {
+        let text = "First paragraph here.\n\nSecond paragraph that is longer.\nWith two lines.";
+        let chunks = split_for_translation(text, 40);
+        assert_eq!(chunks[0], "First paragraph here.");
+        assert!(chunks.iter().all(|chunk| chunk.chars().count() <= 40));
+        assert_eq!(chunks.join(" ").split_whitespace().count(), text.split_whitespace().count());
+
+        assert_eq!(split_for_translation("short", 40), vec!["short".to_string()]);
+        assert!(split_for_translation("   ", 40).is_empty());
+    }
+
+    #[test]
+    fn test_translation_prompt_names_languages() {
+        let prompt = translation_system_prompt("Japanese", Some("Finnish"));
+        assert!(prompt.contains("from Finnish into Japanese"));
+        assert!(!translation_system_prompt("German", None).contains(" from "));
+        assert_eq!(strip_thinking("<think>hmm</think>Hallo Welt"), "Hallo Welt");
+    }
+}
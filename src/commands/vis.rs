@@ -5,15 +5,17 @@
 // Key Features:
 // - Processes image and GIF attachments for vision models
 // - Converts GIFs to PNG (first frame) for compatibility
+// - Downscales images over VISION_MAX_DIMENSION / VISION_MAX_IMAGE_BYTES (aspect ratio preserved)
 // - Encodes images as base64 data URIs for multimodal AI
 // - Streams vision model responses to Discord
+// - Compare mode: structured diff of exactly two images (^lm -v --compare)
 // - Handles errors and provides user feedback
 //
 // Used by: lm.rs (vision command), main.rs (user ID mention vision)
 
 use serenity::{client::Context, model::channel::Message};
-use crate::commands::lm::{MultimodalChatMessage, MessageContent, ImageUrl, StreamingStats, MessageState, update_chat_message, finalize_chat_message};
-use crate::commands::search::LMConfig;
+use crate::commands::lm::{MultimodalChatMessage, StreamingStats, MessageState, update_chat_message, finalize_chat_message};
+use crate::commands::search::{LMConfig, SamplingConfig};
 use reqwest;
 use std::path::Path;
 use std::io::{Write, Cursor};
@@ -21,12 +23,19 @@ use base64::{Engine as _, engine::general_purpose};
 use uuid::Uuid;
 use futures_util::StreamExt;
 
-use image::{ImageFormat, ImageError};
+use image::{DynamicImage, GenericImageView, ImageError, ImageFormat, ImageOutputFormat};
+use image::imageops::FilterType;
+
+/// Downscaling never goes below this longest side, even if the encoded image is still over the byte limit
+const VISION_MIN_DIMENSION: u32 = 512;
+
+/// JPEG quality used when re-encoding downscaled images without transparency
+const VISION_RESIZE_JPEG_QUALITY: u8 = 85;
 
 /// Enhanced image processing with GIF support
-/// Downloads image attachment, processes GIFs (extracts first frame), and encodes as base64
+/// Downloads image attachment, processes GIFs (extracts first frame), downscales oversized images and encodes as base64
 /// Returns (base64_image, content_type) tuple for multimodal AI
-pub async fn process_image_attachment(attachment: &serenity::model::channel::Attachment) -> Result<(String, String), Box<dyn std::error::Error + Send + Sync>> {
+pub async fn process_image_attachment(attachment: &serenity::model::channel::Attachment, config: &LMConfig) -> Result<(String, String), Box<dyn std::error::Error + Send + Sync>> {
     let temp_file = format!("temp_image_{}", Uuid::new_v4());
     let temp_path = Path::new(&temp_file);
     
@@ -70,6 +79,17 @@ pub async fn process_image_attachment(attachment: &serenity::model::channel::Att
         (image_bytes, content_type)
     };
     
+    // Trust the actual bytes over the attachment metadata for the data URI MIME type
+    let final_content_type = detect_image_mime_type(&processed_bytes, &final_content_type);
+    
+    // Phone-camera uploads can exceed what the vision model accepts
+    // Decoding, resizing and re-encoding are CPU-bound, so they run off the async runtime
+    let max_dimension = config.vision_max_dimension;
+    let max_image_bytes = config.vision_max_image_bytes;
+    let (processed_bytes, final_content_type) = tokio::task::spawn_blocking(move || {
+        fit_image_to_vision_limits(processed_bytes, final_content_type, max_dimension, max_image_bytes)
+    }).await?;
+    
     let base64_image = general_purpose::STANDARD.encode(&processed_bytes);
     
     // Clean up temp file
@@ -80,6 +100,57 @@ pub async fn process_image_attachment(attachment: &serenity::model::channel::Att
     Ok((base64_image, final_content_type))
 }
 
+/// Size of `len` bytes once base64-encoded (with padding)
+fn base64_encoded_len(len: usize) -> usize {
+    len.div_ceil(3) * 4
+}
+
+/// Downscale an image whose dimensions or base64 size exceed the vision limits, preserving aspect ratio
+/// Images that already fit, or that can't be decoded, are returned unchanged
+fn fit_image_to_vision_limits(bytes: Vec<u8>, content_type: String, max_dimension: u32, max_base64_bytes: usize) -> (Vec<u8>, String) {
+    let img = match image::load_from_memory(&bytes) {
+        Ok(img) => img,
+        Err(e) => {
+            println!("[VISION] Could not decode image to check its size, sending it unchanged: {}", e);
+            return (bytes, content_type);
+        }
+    };
+    let (width, height) = img.dimensions();
+    if width.max(height) <= max_dimension && base64_encoded_len(bytes.len()) <= max_base64_bytes {
+        return (bytes, content_type);
+    }
+    
+    // Transparency needs PNG; everything else re-encodes as the much smaller JPEG
+    let has_alpha = img.color().has_alpha();
+    let mut target = max_dimension.min(width.max(height));
+    loop {
+        let resized = img.resize(target, target, FilterType::Triangle);
+        let mut encoded = Vec::new();
+        let (result, mime) = if has_alpha {
+            (resized.write_to(&mut Cursor::new(&mut encoded), ImageOutputFormat::Png), "image/png")
+        } else {
+            let rgb = DynamicImage::ImageRgb8(resized.to_rgb8());
+            (rgb.write_to(&mut Cursor::new(&mut encoded), ImageOutputFormat::Jpeg(VISION_RESIZE_JPEG_QUALITY)), "image/jpeg")
+        };
+        if let Err(e) = result {
+            println!("[VISION] Failed to re-encode resized image, sending the original: {}", e);
+            return (bytes, content_type);
+        }
+        
+        let fits = base64_encoded_len(encoded.len()) <= max_base64_bytes;
+        if fits || target <= VISION_MIN_DIMENSION {
+            let (new_width, new_height) = resized.dimensions();
+            println!(
+                "[VISION] Downscaled image from {}x{} ({} bytes) to {}x{} ({} bytes, {}){}",
+                width, height, bytes.len(), new_width, new_height, encoded.len(), mime,
+                if fits { "" } else { " - still over VISION_MAX_IMAGE_BYTES at the minimum size" }
+            );
+            return (encoded, mime.to_string());
+        }
+        target = (target / 4 * 3).max(VISION_MIN_DIMENSION);
+    }
+}
+
 /// Process GIF files for vision model compatibility
 /// Extracts first frame from animated GIFs and converts to PNG (base64)
 async fn process_gif_file(file_path: &Path) -> Result<(Vec<u8>, String), Box<dyn std::error::Error + Send + Sync>> {
@@ -162,37 +233,75 @@ async fn decode_gif_first_frame(gif_bytes: &[u8]) -> Result<(Vec<u8>, String), B
     result
 }
 
-/// Prepare multimodal message with image (for vision model)
-/// Builds a multimodal message with text prompt and base64-encoded image
-pub fn create_vision_message(prompt: &str, base64_image: &str, content_type: &str) -> Vec<MultimodalChatMessage> {
-    println!("[GIF_VISION] Creating vision message with content type: {}", content_type);
+/// Prepare multimodal message with images (for vision model)
+/// Builds a multimodal message with text prompt and one or more base64-encoded images
+/// Each image is a (base64_image, content_type) tuple from process_image_attachment
+pub fn create_vision_message(prompt: &str, images: &[(String, String)]) -> Vec<MultimodalChatMessage> {
+    println!("[GIF_VISION] Creating vision message with {} image(s)", images.len());
     
     vec![
-        MultimodalChatMessage {
-            role: "system".to_string(),
-            content: vec![MessageContent::Text {
-                content_type: "text".to_string(),
-                text: "You are a vision-capable AI assistant. You can analyze images including static images and frames from animated GIFs.".to_string(),
-            }],
-        },
-        MultimodalChatMessage {
-            role: "user".to_string(),
-            content: vec![
-                MessageContent::Text {
-                    content_type: "text".to_string(),
-                    text: prompt.to_string(),
-                },
-                MessageContent::Image {
-                    content_type: "image_url".to_string(),
-                    image_url: ImageUrl {
-                        url: format!("data:{};base64,{}", content_type, base64_image),
-                    },
-                },
-            ],
-        },
+        MultimodalChatMessage::text(
+            "system",
+            "You are a vision-capable AI assistant. You can analyze images including static images and frames from animated GIFs.",
+        ),
+        MultimodalChatMessage::with_images("user", prompt, images),
     ]
 }
 
+/// Detect the MIME type of image bytes from their magic numbers
+/// Falls back to the provided type when the format isn't recognized
+fn detect_image_mime_type(bytes: &[u8], fallback: &str) -> String {
+    match image::guess_format(bytes) {
+        Ok(ImageFormat::Png) => "image/png".to_string(),
+        Ok(ImageFormat::Jpeg) => "image/jpeg".to_string(),
+        Ok(ImageFormat::Gif) => "image/gif".to_string(),
+        Ok(ImageFormat::WebP) => "image/webp".to_string(),
+        Ok(ImageFormat::Bmp) => "image/bmp".to_string(),
+        _ => fallback.to_string(),
+    }
+}
+
+/// Select the image attachments to process within the configured limits
+/// Keeps attachments in order until either the image count or the total byte budget is reached
+/// Returns the selected attachments and a user-facing note when some were skipped
+pub fn select_vision_attachments<'a>(
+    attachments: &'a [serenity::model::channel::Attachment],
+    max_images: usize,
+    max_total_bytes: u64,
+) -> (Vec<&'a serenity::model::channel::Attachment>, Option<String>) {
+    let images: Vec<&serenity::model::channel::Attachment> = attachments
+        .iter()
+        .filter(|a| a.content_type.as_deref().unwrap_or("").starts_with("image/"))
+        .collect();
+    
+    let mut selected = Vec::new();
+    let mut total_bytes: u64 = 0;
+    for attachment in &images {
+        if selected.len() >= max_images {
+            break;
+        }
+        if total_bytes + attachment.size > max_total_bytes {
+            break;
+        }
+        total_bytes += attachment.size;
+        selected.push(*attachment);
+    }
+    
+    let note = if selected.len() < images.len() {
+        Some(format!(
+            "⚠️ **Vision limit reached** - processing {} of {} images (limit: {} images, {:.1} MB total).",
+            selected.len(),
+            images.len(),
+            max_images,
+            max_total_bytes as f64 / (1024.0 * 1024.0)
+        ))
+    } else {
+        None
+    };
+    
+    (selected, note)
+}
+
 /// Stream vision response (adapted from stream_chat_response)
 /// Streams the AI's vision response, chunking and updating Discord messages as needed
 pub async fn stream_vision_response(
@@ -207,13 +316,14 @@ pub async fn stream_vision_response(
     
     let client = reqwest::Client::builder().timeout(std::time::Duration::from_secs(60)).build()?;
     
+    let sampling = config.sampling_for(&config.default_vision_model);
     let chat_request = crate::commands::lm::MultimodalChatRequest {
         model: config.default_vision_model.clone(),
         messages,
-        temperature: config.default_temperature,
-        max_tokens: config.default_max_tokens,
+        temperature: sampling.temperature,
+        max_tokens: sampling.max_tokens,
         stream: true,
-        seed: config.default_seed,
+        seed: sampling.seed,
     };
     
     println!("[VISION_STREAM] ChatRequest created:");
@@ -226,6 +336,7 @@ pub async fn stream_vision_response(
     let api_url = format!("{}/v1/chat/completions", config.base_url);
     println!("[VISION_STREAM] Making POST request to: {}", api_url);
     
+    crate::commands::util::wait_for_backend_rate_limit().await;
     let response = client.post(&api_url).json(&chat_request).send().await?;
     
     if !response.status().is_success() {
@@ -329,25 +440,107 @@ pub async fn stream_vision_response(
     Ok(StreamingStats { total_characters: raw_response.len(), message_count: message_state.message_index })
 } 
 
+/// Instructions sent with ^lm -v --compare; the user's own prompt, when given, narrows what to look for
+pub fn build_compare_prompt(focus: &str) -> String {
+    let mut prompt = String::from(
+        "You are given two images: image 1 (the first attached) and image 2 (the second attached). \
+        Describe what changed between image 1 and image 2. Structure the answer as:\n\
+        **Added** - elements present only in image 2\n\
+        **Removed** - elements present only in image 1\n\
+        **Changed** - elements present in both that differ (position, color, text, size, state)\n\
+        **Unchanged** - a one-line summary of what stayed the same\n\
+        Write \"None\" under a heading with nothing to report. Do not describe each image separately.",
+    );
+    if !focus.trim().is_empty() {
+        prompt.push_str(&format!("\n\nFocus on: {}", focus.trim()));
+    }
+    prompt
+}
+
+/// Compare mode: describe the differences between exactly two attached images
+/// Both images must fit within the vision limits, otherwise the request is rejected instead of comparing one image
+pub async fn handle_vision_compare(
+    ctx: &Context,
+    msg: &Message,
+    focus: &str,
+    attachments: &[serenity::model::channel::Attachment],
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let images: Vec<serenity::model::channel::Attachment> = attachments
+        .iter()
+        .filter(|a| a.content_type.as_deref().unwrap_or("").starts_with("image/"))
+        .cloned()
+        .collect();
+    println!("[VISION] Compare mode with {} image attachment(s)", images.len());
+    if images.len() != 2 {
+        msg.reply(ctx, format!(
+            "❌ `--compare` needs exactly two images attached (found {}). Usage: `^lm -v --compare [what to focus on]` with two images.",
+            images.len()
+        )).await?;
+        return Ok(());
+    }
+
+    let config = crate::commands::search::load_lm_config().await?;
+    let total_bytes: u64 = images.iter().map(|a| a.size).sum();
+    if config.vision_max_images < 2 || total_bytes > config.vision_max_total_bytes {
+        msg.reply(ctx, format!(
+            "❌ These two images exceed the vision limits ({} images, {:.1} MB total), so they can't be compared.",
+            config.vision_max_images,
+            config.vision_max_total_bytes as f64 / (1024.0 * 1024.0)
+        )).await?;
+        return Ok(());
+    }
+
+    handle_vision_request(ctx, msg, &build_compare_prompt(focus), &images).await
+}
+
 /// Main entry point for vision analysis requests
 /// Handles downloading, processing, and streaming vision model responses for image/GIF attachments
+/// Attachment count and total size are checked against the configured limits before downloading
 pub async fn handle_vision_request(
     ctx: &Context,
     msg: &Message,
     prompt: &str,
-    attachment: &serenity::model::channel::Attachment,
+    attachments: &[serenity::model::channel::Attachment],
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     println!("[VISION] Starting vision request handling");
     println!("[VISION] Prompt: '{}'", prompt);
-    println!("[VISION] Attachment: {} ({})", attachment.filename, attachment.content_type.as_deref().unwrap_or("unknown"));
+    println!("[VISION] Attachments: {}", attachments.len());
     
-    // Check if this is a GIF file for specialized user feedback
-    let content_type = attachment.content_type.as_deref().unwrap_or("");
-    let is_gif = content_type == "image/gif" || 
-                 attachment.filename.to_lowercase().ends_with(".gif");
+    println!("[VISION] Loading LM config from lmapiconf.txt...");
+    let config = crate::commands::search::load_lm_config().await?;
+    println!("[VISION] Config loaded successfully:");
+    println!("[VISION]   - Base URL: {}", config.base_url);
+    println!("[VISION]   - Default Model: {}", config.default_model);
+    println!("[VISION]   - Default Reason Model: {}", config.default_reason_model);
+    println!("[VISION]   - Default Ranking Model: {}", config.default_ranking_model);
+    println!("[VISION]   - Default Vision Model: {}", config.default_vision_model);
+    println!("[VISION]   - Temperature: {}", config.default_temperature);
+    println!("[VISION]   - Max Tokens: {}", config.default_max_tokens);
+    println!("[VISION]   - Limits: {} images, {} bytes", config.vision_max_images, config.vision_max_total_bytes);
+    println!("[VISION]   - Per-image limits: {}px longest side, {} base64 bytes", config.vision_max_dimension, config.vision_max_image_bytes);
+    
+    // Enforce attachment limits before anything is downloaded
+    let (selected, limit_note) = select_vision_attachments(attachments, config.vision_max_images, config.vision_max_total_bytes);
+    if selected.is_empty() {
+        let reply = match limit_note {
+            Some(note) => format!("{}\nThe first image alone exceeds the size limit.", note),
+            None => "Please attach a valid image file!".to_string(),
+        };
+        msg.reply(ctx, reply).await?;
+        return Ok(());
+    }
+    if let Some(note) = limit_note {
+        println!("[VISION] {}", note);
+        msg.reply(ctx, note).await?;
+    }
+    
+    // Check if any attachment is a GIF for specialized user feedback
+    let has_gif = selected.iter().any(|a| {
+        a.content_type.as_deref().unwrap_or("") == "image/gif" || a.filename.to_lowercase().ends_with(".gif")
+    });
     
     // Create initial message with appropriate content for GIF vs regular image
-    let initial_content = if is_gif {
+    let initial_content = if has_gif {
         "**GIF Vision Analysis (Part 1):**\n```\nProcessing GIF file (extracting frame for analysis)...\n\n```"
     } else {
         "**Vision Analysis (Part 1):**\n```\n\n```"
@@ -357,32 +550,63 @@ pub async fn handle_vision_request(
         m.content(initial_content)
     }).await?;
     
-    let (base64_image, processed_content_type) = process_image_attachment(attachment).await?;
-    println!("[VISION] Image processed - base64 length: {}, content_type: {}", base64_image.len(), processed_content_type);
+    let mut images = Vec::new();
+    for attachment in &selected {
+        println!("[VISION] Attachment: {} ({}, {} bytes)", attachment.filename, attachment.content_type.as_deref().unwrap_or("unknown"), attachment.size);
+        let (base64_image, processed_content_type) = process_image_attachment(attachment, &config).await?;
+        println!("[VISION] Image processed - base64 length: {}, content_type: {}", base64_image.len(), processed_content_type);
+        images.push((base64_image, processed_content_type));
+    }
     
-    // Update message if GIF was converted
-    if is_gif && processed_content_type != "image/gif" {
+    // Update message if a GIF was converted
+    if has_gif && images.iter().all(|(_, content_type)| content_type != "image/gif") {
         let _ = initial_msg.edit(&ctx.http, |m| {
             m.content("**GIF Vision Analysis (Part 1):**\n```\nGIF converted to PNG for analysis...\n\n```")
         }).await;
     }
     
-    let messages = create_vision_message(prompt, &base64_image, &processed_content_type);
+    let messages = create_vision_message(prompt, &images);
     println!("[VISION] Created {} multimodal messages", messages.len());
     
-    println!("[VISION] Loading LM config from lmapiconf.txt...");
-    let config = crate::commands::search::load_lm_config().await?;
-    println!("[VISION] Config loaded successfully:");
-    println!("[VISION]   - Base URL: {}", config.base_url);
-    println!("[VISION]   - Default Model: {}", config.default_model);
-    println!("[VISION]   - Default Reason Model: {}", config.default_reason_model);
-    println!("[VISION]   - Default Ranking Model: {}", config.default_ranking_model);
-    println!("[VISION]   - Default Vision Model: {}", config.default_vision_model);
-    println!("[VISION]   - Temperature: {}", config.default_temperature);
-    println!("[VISION]   - Max Tokens: {}", config.default_max_tokens);
-    
     println!("[VISION] About to call stream_vision_response with model: {}", config.default_vision_model);
     stream_vision_response(messages, &config, ctx, &mut initial_msg).await?;
     println!("[VISION] Vision request completed successfully");
     Ok(())
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage, Rgba, RgbaImage};
+
+    fn encode_png(img: DynamicImage) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        img.write_to(&mut Cursor::new(&mut bytes), ImageOutputFormat::Png).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_fit_image_to_vision_limits() {
+        // Within both limits: returned untouched
+        let small = encode_png(DynamicImage::ImageRgb8(RgbImage::from_pixel(100, 50, Rgb([10, 20, 30]))));
+        let (bytes, mime) = fit_image_to_vision_limits(small.clone(), "image/png".to_string(), 1024, usize::MAX);
+        assert_eq!((bytes, mime.as_str()), (small, "image/png"));
+
+        // Over the dimension limit without transparency: downscaled with its aspect ratio, re-encoded as JPEG
+        let wide = encode_png(DynamicImage::ImageRgb8(RgbImage::from_pixel(2000, 1000, Rgb([10, 20, 30]))));
+        let (bytes, mime) = fit_image_to_vision_limits(wide, "image/png".to_string(), 1024, usize::MAX);
+        assert_eq!(mime, "image/jpeg");
+        assert_eq!(image::guess_format(&bytes).unwrap(), ImageFormat::Jpeg);
+        assert_eq!(image::load_from_memory(&bytes).unwrap().dimensions(), (1024, 512));
+
+        // Transparency is kept by re-encoding as PNG
+        let clear = encode_png(DynamicImage::ImageRgba8(RgbaImage::from_pixel(1000, 2000, Rgba([10, 20, 30, 128]))));
+        let (bytes, mime) = fit_image_to_vision_limits(clear, "image/png".to_string(), 1024, usize::MAX);
+        assert_eq!(mime, "image/png");
+        assert_eq!(image::load_from_memory(&bytes).unwrap().dimensions(), (512, 1024));
+
+        // Undecodable bytes pass through unchanged
+        let (bytes, mime) = fit_image_to_vision_limits(b"not an image".to_vec(), "image/jpeg".to_string(), 1024, 1);
+        assert_eq!((bytes.as_slice(), mime.as_str()), (&b"not an image"[..], "image/jpeg"));
+    }
+}
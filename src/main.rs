@@ -15,10 +15,11 @@ mod commands;           // All command modules (see src/commands/)
 use serenity::{
     async_trait,
     client::{Client, Context, EventHandler},
-    framework::standard::{StandardFramework, Args, Delimiter},
-    model::gateway::Ready,
+    framework::standard::{StandardFramework, CommandGroup, Args, Delimiter},
+    model::gateway::{Activity, Ready},
     model::channel::Message,
-    model::id::UserId,
+    model::event::MessageUpdateEvent,
+    model::id::{ChannelId, GuildId, UserId},
     model::application::interaction::Interaction,
     model::guild::Guild,
     prelude::GatewayIntents,
@@ -46,6 +47,7 @@ use chrono::{DateTime, Utc};         // Date/time handling
 
 // Local imports
 use crate::commands::search::ChatMessage;  // Chat message structure for context
+use crate::commands::util::{truncate_chars, tail_chars, style_output, moderation_match, MODERATION_REFUSAL, find_and_read_config, record_config_source, levenshtein, load_prompt_template, render_prompt_template}; // Log previews, status output style, moderation, config diagnostics, command suggestions and prompt templates
 
 // Command imports (auto-generated by Serenity)
 // Commands are auto-registered by the #[command] macro
@@ -65,8 +67,33 @@ pub struct UserContext {
     pub assistant_messages: Vec<ChatMessage>, // Messages sent by the bot
     pub last_updated: DateTime<Utc>,          // Timestamp of last interaction
     pub total_interactions: usize,            // Total number of interactions
+    #[serde(default)]
+    pub memory: Option<String>,               // Summary of compacted older messages (CONTEXT_COMPACTION)
+    #[serde(default = "legacy_context_version")]
+    pub version: u32,                         // On-disk format version (CONTEXT_FORMAT_VERSION)
+    #[serde(default)]
+    user_sequence: Vec<u64>,                  // Insertion order of the newest user messages
+    #[serde(default)]
+    assistant_sequence: Vec<u64>,             // Insertion order of the newest assistant messages
+    #[serde(default)]
+    next_sequence: u64,                       // Last insertion number handed out
+    #[serde(default)]
+    pub last_finish_reason: Option<String>,   // finish_reason of the newest reply ("length" = cut off at max_tokens)
 }
 
+/// Current on-disk format of UserContext
+/// Bump this when fields change and teach migrate_context_value how to upgrade older files
+/// v3 numbers every stored message, so files from v2 and earlier get insertion numbers on load
+pub const CONTEXT_FORMAT_VERSION: u32 = 3;
+
+/// Files written before versioning was added have no version field
+fn legacy_context_version() -> u32 {
+    1
+}
+
+/// Number of oldest user/assistant pairs folded into the memory per compaction
+const CONTEXT_COMPACTION_BATCH: usize = 20;
+
 impl UserContext {
     /// Create a new empty user context
     pub fn new() -> Self {
@@ -75,6 +102,12 @@ impl UserContext {
             assistant_messages: Vec::new(),
             last_updated: Utc::now(),
             total_interactions: 0,
+            memory: None,
+            version: CONTEXT_FORMAT_VERSION,
+            user_sequence: Vec::new(),
+            assistant_sequence: Vec::new(),
+            next_sequence: 0,
+            last_finish_reason: None,
         }
     }
 
@@ -87,6 +120,9 @@ impl UserContext {
         }
         
         self.user_messages.push(message);
+        self.next_sequence += 1;
+        self.user_sequence.push(self.next_sequence);
+        self.trim_sequences();
         self.last_updated = Utc::now();
         self.total_interactions += 1;
         
@@ -103,30 +139,116 @@ impl UserContext {
         }
         
         self.assistant_messages.push(message);
+        self.next_sequence += 1;
+        self.assistant_sequence.push(self.next_sequence);
+        self.trim_sequences();
         self.last_updated = Utc::now();
         
         println!("[CONTEXT] Added assistant message. Total: {} user, {} assistant messages", 
             self.user_messages.len(), self.assistant_messages.len());
     }
 
+    /// Whether the newest stored message is an assistant reply that --continue can extend
+    pub fn can_continue(&self) -> bool {
+        match (self.assistant_sequence.last(), self.user_sequence.last()) {
+            (Some(assistant), Some(user)) => assistant > user,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+
+    /// Append a --continue continuation to the newest assistant reply so it is stored as one message
+    pub fn extend_last_assistant_message(&mut self, continuation: &str) {
+        if let Some(last) = self.assistant_messages.last_mut() {
+            last.content.push_str(continuation);
+            self.last_updated = Utc::now();
+        }
+    }
+
     /// Get all conversation messages in chronological order
-    /// This interleaves user and assistant messages for proper context
+    /// Messages are ordered by insertion; older ones stored before ordering was tracked come first, interleaved by index
     pub fn get_conversation_messages(&self) -> Vec<ChatMessage> {
         let mut messages = Vec::new();
-        let user_len = self.user_messages.len();
-        let assistant_len = self.assistant_messages.len();
-        let max_len = std::cmp::max(user_len, assistant_len);
+        // Compacted history goes first, standing in for the messages it replaced
+        if let Some(memory) = &self.memory {
+            messages.push(ChatMessage {
+                role: "system".to_string(),
+                content: format!("Summary of earlier conversation with this user:\n{}", memory),
+            });
+        }
+        messages.extend(self.chronological_order().into_iter().map(|(is_user, i)| self.stored_message(is_user, i).clone()));
 
-        for i in 0..max_len {
-            if i < user_len {
-                messages.push(self.user_messages[i].clone());
+        messages
+    }
+
+    /// Stored messages in chronological order as (is_user, index) pairs
+    /// Untracked messages sort first by index (user before assistant), then tracked ones by insertion number
+    fn chronological_order(&self) -> Vec<(bool, usize)> {
+        let mut ordered: Vec<((u8, u64, u8), (bool, usize))> = Vec::with_capacity(self.total_messages());
+        for (role_rank, stored, sequence) in [
+            (0, &self.user_messages, &self.user_sequence),
+            (1, &self.assistant_messages, &self.assistant_sequence),
+        ] {
+            for i in 0..stored.len() {
+                let key = match aligned_sequence(stored.len(), sequence, i) {
+                    Some(number) => (1, number, 0),
+                    None => (0, i as u64, role_rank),
+                };
+                ordered.push((key, (role_rank == 0, i)));
             }
-            if i < assistant_len {
-                messages.push(self.assistant_messages[i].clone());
+        }
+        ordered.sort_by_key(|(key, _)| *key);
+        ordered.into_iter().map(|(_, position)| position).collect()
+    }
+
+    /// Message `index` of the user or assistant list
+    fn stored_message(&self, is_user: bool, index: usize) -> &ChatMessage {
+        if is_user {
+            &self.user_messages[index]
+        } else {
+            &self.assistant_messages[index]
+        }
+    }
+
+    /// Give every stored message an insertion number, keeping the current chronological order
+    fn renumber_sequences(&mut self) {
+        let order = self.chronological_order();
+        self.user_sequence.clear();
+        self.assistant_sequence.clear();
+        self.next_sequence = 0;
+        for (is_user, _) in order {
+            self.next_sequence += 1;
+            if is_user {
+                self.user_sequence.push(self.next_sequence);
+            } else {
+                self.assistant_sequence.push(self.next_sequence);
             }
         }
+    }
 
-        messages
+    /// Bring a context loaded from an older file up to CONTEXT_FORMAT_VERSION
+    /// Returns false if it was already current
+    fn upgrade_format(&mut self) -> bool {
+        if self.version >= CONTEXT_FORMAT_VERSION {
+            return false;
+        }
+        // v2 and earlier left older messages unnumbered, which compaction can't pair by insertion
+        self.renumber_sequences();
+        self.version = CONTEXT_FORMAT_VERSION;
+        true
+    }
+
+    /// Drop insertion numbers for messages that have been removed from the front of either list
+    fn trim_sequences(&mut self) {
+        for (count, sequence) in [
+            (self.user_messages.len(), &mut self.user_sequence),
+            (self.assistant_messages.len(), &mut self.assistant_sequence),
+        ] {
+            if sequence.len() > count {
+                let excess = sequence.len() - count;
+                sequence.drain(0..excess);
+            }
+        }
     }
 
     /// Maintain 50/50 balance between user and assistant messages
@@ -143,6 +265,7 @@ impl UserContext {
             println!("[CONTEXT] Removing {} oldest assistant messages to maintain balance", removed);
             self.assistant_messages.drain(0..removed);
         }
+        self.trim_sequences();
     }
 
     /// Check if context is getting too large and needs cleanup
@@ -160,9 +283,49 @@ impl UserContext {
     pub fn clear(&mut self) {
         self.user_messages.clear();
         self.assistant_messages.clear();
+        self.user_sequence.clear();
+        self.assistant_sequence.clear();
+        self.memory = None;
+        self.last_finish_reason = None;
         self.last_updated = Utc::now();
     }
 
+    /// The oldest messages to summarize once the context reaches the compaction threshold
+    /// Returns up to CONTEXT_COMPACTION_BATCH exchanges in insertion order, ending on an assistant reply
+    pub fn compaction_candidates(&self, threshold: usize) -> Option<Vec<ChatMessage>> {
+        if self.total_messages() < threshold {
+            return None;
+        }
+        let order = self.chronological_order();
+        let window = &order[..order.len().min(CONTEXT_COMPACTION_BATCH * 2)];
+        // Stop after the last reply in the window so no question is summarized without its answer
+        let end = window.iter().rposition(|(is_user, _)| !is_user)? + 1;
+        Some(window[..end].iter().map(|&(is_user, i)| self.stored_message(is_user, i).clone()).collect())
+    }
+
+    /// Replace the compacted messages with their summary
+    /// Skipped (returns false) if the context changed underneath, e.g. it was cleared while summarizing
+    pub fn apply_compaction(&mut self, compacted: &[ChatMessage], summary: String) -> bool {
+        let order = self.chronological_order();
+        let unchanged = !compacted.is_empty()
+            && order.len() >= compacted.len()
+            && order.iter().zip(compacted).all(|(&(is_user, i), message)| {
+                let stored = self.stored_message(is_user, i);
+                stored.role == message.role && stored.content == message.content
+            });
+        if !unchanged {
+            return false;
+        }
+        // The compacted messages are the oldest in insertion order, so they sit at the front of both lists
+        let users = order[..compacted.len()].iter().filter(|(is_user, _)| *is_user).count();
+        self.user_messages.drain(0..users);
+        self.assistant_messages.drain(0..compacted.len() - users);
+        self.trim_sequences();
+        self.memory = Some(summary);
+        println!("[CONTEXT] Compacted {} oldest messages into memory. {}", compacted.len(), self.get_context_info());
+        true
+    }
+
     /// Get total number of messages in context
     pub fn total_messages(&self) -> usize {
         self.user_messages.len() + self.assistant_messages.len()
@@ -189,12 +352,59 @@ impl UserContext {
             0
         };
         
+        self.trim_sequences();
         if user_removed > 0 || assistant_removed > 0 {
             println!("[CONTEXT] Force cleanup completed: removed {} user, {} assistant messages", user_removed, assistant_removed);
         }
     }
 }
 
+/// Insertion number of message `index` in a list of `count` messages
+/// Numbers are aligned with the newest messages, so older messages from before ordering was tracked get None
+fn aligned_sequence(count: usize, sequence: &[u64], index: usize) -> Option<u64> {
+    let untracked = count.saturating_sub(sequence.len());
+    let skipped = sequence.len().saturating_sub(count);
+    if index < untracked {
+        return None;
+    }
+    sequence.get(index - untracked + skipped).copied()
+}
+
+/// Compact a user's stored context when CONTEXT_COMPACTION is on and it has reached the threshold
+/// The oldest messages are summarized with the summarization model without holding the data lock
+pub async fn compact_context_if_needed<K>(ctx: &Context, user_id: UserId, config: &crate::commands::search::LMConfig)
+where
+    K: TypeMapKey<Value = HashMap<UserId, UserContext>>,
+{
+    if !config.context_compaction {
+        return;
+    }
+
+    let (candidates, previous_memory) = {
+        let data = ctx.data.read().await;
+        match data.get::<K>().and_then(|map| map.get(&user_id)) {
+            Some(context) => match context.compaction_candidates(config.context_compaction_threshold) {
+                Some(candidates) => (candidates, context.memory.clone()),
+                None => return,
+            },
+            None => return,
+        }
+    };
+
+    println!("[CONTEXT] Compacting {} messages for user {}", candidates.len(), user_id);
+    match crate::commands::search::summarize_context_memory(config, previous_memory.as_deref(), &candidates).await {
+        Ok(summary) => {
+            let mut data = ctx.data.write().await;
+            if let Some(context) = data.get_mut::<K>().and_then(|map| map.get_mut(&user_id)) {
+                if !context.apply_compaction(&candidates, summary) {
+                    println!("[CONTEXT] Context for user {} changed during compaction, skipping", user_id);
+                }
+            }
+        }
+        Err(e) => eprintln!("[CONTEXT] Compaction failed for user {}: {} - keeping messages as-is", user_id, e),
+    }
+}
+
 // ============================================================================
 // TYPEMAP KEYS
 // ============================================================================
@@ -211,10 +421,57 @@ impl TypeMapKey for ReasonContextMap {
     type Value = HashMap<UserId, UserContext>;
 }
 
-/// TypeMap key for storing user conversation histories to enable context-aware queries about other users' conversations
-pub struct UserConversationHistoryMap;
-impl TypeMapKey for UserConversationHistoryMap {
-    type Value = HashMap<UserId, Vec<ChatMessage>>;
+/// Default number of observed messages kept per user (USER_HISTORY_LIMIT in botconfig.txt)
+const DEFAULT_USER_HISTORY_LIMIT: usize = 20;
+
+/// Whether observed messages are recorded (USER_HISTORY_ENABLED in botconfig.txt, default false)
+pub fn user_history_enabled() -> bool {
+    matches!(
+        env::var("USER_HISTORY_ENABLED").map(|v| v.trim().to_lowercase()).as_deref(),
+        Ok("true") | Ok("1") | Ok("yes") | Ok("on")
+    )
+}
+
+/// Record a guild message in USER_HISTORY, keeping only the newest messages per user
+/// Entries keep their guild so history is never surfaced in another server
+fn record_observed_message(msg: &Message) {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return,
+    };
+    if msg.author.bot || msg.content.trim().is_empty() || !user_history_enabled() {
+        return;
+    }
+    let limit = env::var("USER_HISTORY_LIMIT")
+        .ok()
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .unwrap_or(DEFAULT_USER_HISTORY_LIMIT);
+
+    if let Ok(mut history_guard) = USER_HISTORY.lock() {
+        let history = history_guard.get_or_insert_with(HashMap::new).entry(msg.author.id).or_default();
+        history.push(ObservedMessage { guild_id, content: msg.content.trim().to_string() });
+        if history.len() > limit {
+            let excess = history.len() - limit;
+            history.drain(0..excess);
+        }
+    }
+}
+
+/// The recorded messages of a user in the given guild, oldest first
+pub fn recent_user_history(user_id: UserId, guild_id: GuildId) -> Vec<String> {
+    let history_guard = match USER_HISTORY.lock() {
+        Ok(guard) => guard,
+        Err(_) => return Vec::new(),
+    };
+    history_guard.as_ref()
+        .and_then(|map| map.get(&user_id))
+        .map(|history| {
+            history.iter()
+                .filter(|message| message.guild_id == guild_id)
+                .map(|message| message.content.clone())
+                .collect()
+        })
+        .unwrap_or_default()
 }
 
 /// TypeMap key for global LM chat context - stores conversation history shared across all users
@@ -224,6 +481,12 @@ impl TypeMapKey for GlobalLmContextMap {
     type Value = UserContext;
 }
 
+/// TypeMap key for the gateway shard manager - lets commands read per-shard latency (^ping --trace)
+pub struct ShardManagerContainer;
+impl TypeMapKey for ShardManagerContainer {
+    type Value = std::sync::Arc<serenity::prelude::Mutex<serenity::client::bridge::gateway::ShardManager>>;
+}
+
 // ============================================================================
 // COMMAND GROUP
 // ============================================================================
@@ -290,52 +553,93 @@ fn load_server_blacklist() -> std::collections::HashSet<u64> {
     std::collections::HashSet::new()
 }
 
+/// Parse botconfig.txt contents into KEY=VALUE pairs
+/// Strips a leading BOM and skips blank lines, comments and lines without '='
+fn parse_bot_config(content: &str) -> HashMap<String, String> {
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    let mut config = HashMap::new();
+    for line in content.lines() {
+        let line = line.trim();
+        // Skip empty lines and comments
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        // Parse KEY=VALUE format
+        if let Some(equals_pos) = line.find('=') {
+            let key = line[..equals_pos].trim().to_string();
+            let value = line[equals_pos + 1..].trim().to_string();
+            config.insert(key, value);
+        }
+    }
+    config
+}
+
+/// Split an ACTIVITY setting into its kind and text
+/// "watching the logs" -> ("watching", "the logs"); without a known kind word the whole value is a "playing" status
+fn parse_activity(value: &str) -> Option<(&'static str, &str)> {
+    let value = value.trim();
+    if value.is_empty() {
+        return None;
+    }
+    let (first, rest) = match value.split_once(char::is_whitespace) {
+        Some((first, rest)) => (first, rest.trim_start()),
+        None => (value, ""),
+    };
+    let kind = match first.to_lowercase().as_str() {
+        "playing" => "playing",
+        "watching" => "watching",
+        "listening" => "listening",
+        "competing" => "competing",
+        _ => return Some(("playing", value)),
+    };
+    // "listening to music" reads naturally, but Discord already shows "Listening to"
+    let rest = if kind == "listening" { rest.strip_prefix("to ").unwrap_or(rest) } else { rest };
+    if rest.is_empty() { None } else { Some((kind, rest)) }
+}
+
 /// Load bot configuration from botconfig.txt file with multi-path fallback
 /// This searches multiple locations for the configuration file
 fn load_bot_config() -> Result<HashMap<String, String>, String> {
-    let config_paths = [
-        "botconfig.txt",           // Current directory
-        "../botconfig.txt",        // Parent directory
-        "../../botconfig.txt",     // Grandparent directory
-        "src/botconfig.txt"        // Source directory
-    ];
-    
     // Clear any existing relevant environment variables
     env::remove_var("DISCORD_TOKEN");
     env::remove_var("PREFIX");
     env::remove_var("RUST_LOG");
+    env::remove_var("ALLOWED_CHANNELS");
+    env::remove_var("DENIED_CHANNELS");
+    env::remove_var("OUTPUT_EMOJI");
+    env::remove_var("MODERATION_ENABLED");
+    env::remove_var("MODERATION_PATTERNS_FILE");
+    env::remove_var("SHUTDOWN_GRACE_SECS");
+    env::remove_var("USER_HISTORY_ENABLED");
+    env::remove_var("USER_HISTORY_LIMIT");
+    env::remove_var("SUM_ALLOWED_DOMAINS");
+    env::remove_var("MAX_PROMPT_CHARS");
+    env::remove_var("TIMEZONE");
+    env::remove_var("YT_DLP_PATH");
+    env::remove_var("ERROR_LOG_CHANNEL");
+    env::remove_var("GLOBAL_MAX_CONCURRENT");
+    env::remove_var("BACKEND_RPS");
+    env::remove_var("BACKEND_BURST");
+    env::remove_var("RESPONSE_FOOTER");
+    env::remove_var("AUTO_VISION");
     
-    // Try each possible config file location
-    for config_path in &config_paths {
-        match read_text_file(config_path) {
-            Ok(content) => {
-                let mut config = HashMap::new();
-                // Parse the config file line by line
-                for line in content.lines() {
-                    let line = line.trim();
-                    // Skip empty lines and comments
-                    if line.is_empty() || line.starts_with('#') {
-                        continue;
-                    }
-                    // Parse KEY=VALUE format
-                    if let Some(equals_pos) = line.find('=') {
-                        let key = line[..equals_pos].trim().to_string();
-                        let value = line[equals_pos + 1..].trim().to_string();
-                        // Set environment variable for compatibility
-                        env::set_var(&key, &value);
-                        config.insert(key, value);
-                    }
+    match find_and_read_config("botconfig.txt") {
+        Some((content, config_path)) => {
+            let config = parse_bot_config(&content);
+            // Set environment variables for compatibility; the proxy keys stay out of the environment
+            // so only the clients that opt in (agent, ^sum, yt-dlp) use them
+            for (key, value) in &config {
+                if !crate::commands::util::PROXY_CONFIG_KEYS.contains(&key.as_str()) {
+                    env::set_var(key, value);
                 }
-                println!("Configuration loaded from {}", config_path);
-                return Ok(config);
-            }
-            Err(_) => {
-                // Try next path
-                continue;
             }
+            crate::commands::util::set_proxy_config(&config);
+            println!("Configuration loaded from {}", config_path);
+            record_config_source("botconfig.txt", &config_path);
+            Ok(config)
         }
+        None => Err("No botconfig.txt file found in any expected location (., .., ../.., src/)".to_string()),
     }
-    Err("No botconfig.txt file found in any expected location (., .., ../.., src/)".to_string())
 }
 
 /// Validate Discord token from configuration and log details
@@ -345,7 +649,7 @@ fn validate_discord_token() -> Result<String, String> {
             let trimmed = token.trim();
             // Log partial token for debugging (never full token)
             if trimmed.len() >= 12 {
-                log::info!("Using Discord token: {}...{} ({} chars)", &trimmed[..6], &trimmed[trimmed.len()-6..], trimmed.len());
+                log::info!("Using Discord token: {}...{} ({} chars)", truncate_chars(trimmed, 6), tail_chars(trimmed, 6), trimmed.len());
             } else {
                 log::info!("Using Discord token: {} ({} chars)", trimmed, trimmed.len());
             }
@@ -406,6 +710,117 @@ pub async fn save_contexts_to_disk(
     Ok(())
 }
 
+/// Parse a per-user context file, migrating older formats instead of discarding them
+fn parse_context_map(content: &str, label: &str) -> Result<HashMap<UserId, UserContext>, Box<dyn std::error::Error + Send + Sync>> {
+    if let Ok(mut contexts) = serde_json::from_str::<HashMap<UserId, UserContext>>(content) {
+        let outdated = contexts.values_mut().map(|context| context.upgrade_format()).filter(|upgraded| *upgraded).count();
+        if outdated > 0 {
+            println!("[CONTEXT] Upgraded {} contexts in {} to format version {}", outdated, label, CONTEXT_FORMAT_VERSION);
+        }
+        return Ok(contexts);
+    }
+
+    // The current shape didn't match - migrate entry by entry so one bad record doesn't lose the rest
+    let raw: HashMap<String, serde_json::Value> = serde_json::from_str(content)
+        .map_err(|e| format!("{} is not a valid context file: {}", label, e))?;
+    let mut contexts = HashMap::new();
+    for (key, value) in raw {
+        let user_id = match key.parse::<u64>() {
+            Ok(id) => UserId(id),
+            Err(_) => {
+                log::warn!("Skipping context with invalid user id '{}' in {}", key, label);
+                continue;
+            }
+        };
+        match migrate_context_value(value) {
+            Some(context) => {
+                contexts.insert(user_id, context);
+            }
+            None => log::warn!("Could not migrate context for user {} in {}, skipping it", user_id, label),
+        }
+    }
+    println!("[CONTEXT] Migrated {} contexts in {} to format version {}", contexts.len(), label, CONTEXT_FORMAT_VERSION);
+    Ok(contexts)
+}
+
+/// Parse a single context file (the global LM context), migrating older formats
+fn parse_context(content: &str, label: &str) -> Result<UserContext, Box<dyn std::error::Error + Send + Sync>> {
+    if let Ok(mut context) = serde_json::from_str::<UserContext>(content) {
+        if context.upgrade_format() {
+            println!("[CONTEXT] Upgraded {} to format version {}", label, CONTEXT_FORMAT_VERSION);
+        }
+        return Ok(context);
+    }
+
+    let value: serde_json::Value = serde_json::from_str(content)
+        .map_err(|e| format!("{} is not a valid context file: {}", label, e))?;
+    let context = migrate_context_value(value)
+        .ok_or_else(|| format!("{} has an unrecognized context format", label))?;
+    println!("[CONTEXT] Migrated {} to format version {}", label, CONTEXT_FORMAT_VERSION);
+    Ok(context)
+}
+
+/// Upgrade a context in a legacy shape to the current UserContext
+/// Accepts the split user/assistant lists or a single mixed `messages` list, skips malformed
+/// messages, and fills missing timestamps/counters; returns None if no history can be recovered
+fn migrate_context_value(value: serde_json::Value) -> Option<UserContext> {
+    let object = value.as_object()?;
+    let parse_messages = |key: &str| -> Vec<ChatMessage> {
+        object
+            .get(key)
+            .and_then(|messages| messages.as_array())
+            .map(|messages| {
+                messages
+                    .iter()
+                    .filter_map(|message| serde_json::from_value::<ChatMessage>(message.clone()).ok())
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    let mut context = UserContext::new();
+    context.user_messages = parse_messages("user_messages");
+    context.assistant_messages = parse_messages("assistant_messages");
+    // The legacy mixed list is already chronological, so its order is kept as insertion numbers
+    for message in parse_messages("messages") {
+        match message.role.as_str() {
+            "user" => {
+                context.user_messages.push(message);
+                context.next_sequence += 1;
+                context.user_sequence.push(context.next_sequence);
+            }
+            "assistant" => {
+                context.assistant_messages.push(message);
+                context.next_sequence += 1;
+                context.assistant_sequence.push(context.next_sequence);
+            }
+            _ => {}
+        }
+    }
+
+    let has_history = !context.user_messages.is_empty() || !context.assistant_messages.is_empty();
+    let memory = object.get("memory").and_then(|memory| memory.as_str()).map(|memory| memory.to_string());
+    if !has_history && memory.is_none() && !object.contains_key("last_updated") {
+        return None;
+    }
+
+    context.memory = memory;
+    if let Some(last_updated) = object.get("last_updated").and_then(|value| value.as_str()) {
+        if let Ok(timestamp) = DateTime::parse_from_rfc3339(last_updated) {
+            context.last_updated = timestamp.with_timezone(&Utc);
+        }
+    }
+    context.total_interactions = object
+        .get("total_interactions")
+        .and_then(|value| value.as_u64())
+        .map(|count| count as usize)
+        .unwrap_or(context.user_messages.len());
+    context.maintain_balance();
+    // The split user/assistant lists carry no insertion numbers, so number everything in its current order
+    context.renumber_sequences();
+    Some(context)
+}
+
 /// Load all user contexts from disk on bot startup
 pub async fn load_contexts_from_disk() -> Result<(HashMap<UserId, UserContext>, HashMap<UserId, UserContext>, UserContext), Box<dyn std::error::Error + Send + Sync>> {
     let contexts_dir = Path::new("contexts");
@@ -414,7 +829,7 @@ pub async fn load_contexts_from_disk() -> Result<(HashMap<UserId, UserContext>,
     let lm_file = contexts_dir.join("lm_contexts.json");
     let lm_contexts = if lm_file.exists() {
         let lm_content = std::fs::read_to_string(&lm_file)?;
-        serde_json::from_str::<HashMap<UserId, UserContext>>(&lm_content)?
+        parse_context_map(&lm_content, "lm_contexts.json")?
     } else {
         HashMap::new()
     };
@@ -423,7 +838,7 @@ pub async fn load_contexts_from_disk() -> Result<(HashMap<UserId, UserContext>,
     let reason_file = contexts_dir.join("reason_contexts.json");
     let reason_contexts = if reason_file.exists() {
         let reason_content = std::fs::read_to_string(&reason_file)?;
-        serde_json::from_str::<HashMap<UserId, UserContext>>(&reason_content)?
+        parse_context_map(&reason_content, "reason_contexts.json")?
     } else {
         HashMap::new()
     };
@@ -432,7 +847,7 @@ pub async fn load_contexts_from_disk() -> Result<(HashMap<UserId, UserContext>,
     let global_lm_file = contexts_dir.join("global_lm_context.json");
     let global_lm_context = if global_lm_file.exists() {
         let global_lm_content = std::fs::read_to_string(&global_lm_file)?;
-        serde_json::from_str::<UserContext>(&global_lm_content)?
+        parse_context(&global_lm_content, "global_lm_context.json")?
     } else {
         UserContext::new()
     };
@@ -447,19 +862,198 @@ pub async fn load_contexts_from_disk() -> Result<(HashMap<UserId, UserContext>,
 // GLOBAL STATE
 // ============================================================================
 
-use std::sync::atomic::AtomicBool;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Mutex;
 
 
 /// Global flag to track if the bot has successfully connected to Discord
 static BOT_CONNECTED: AtomicBool = AtomicBool::new(false);
 
+/// Number of heavy commands (^agent, ^sum, ^reason) currently running
+static ACTIVE_HEAVY_COMMANDS: AtomicUsize = AtomicUsize::new(0);
+
+/// Default time shutdown waits for in-flight heavy commands (SHUTDOWN_GRACE_SECS in botconfig.txt)
+const DEFAULT_SHUTDOWN_GRACE_SECS: u64 = 30;
+
+/// Marks a heavy command as in flight until dropped, so every early return is counted correctly
+pub struct HeavyCommandGuard;
+
+impl Drop for HeavyCommandGuard {
+    fn drop(&mut self) {
+        ACTIVE_HEAVY_COMMANDS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Register a heavy command as running; hold the guard for the lifetime of the command
+pub fn start_heavy_command() -> HeavyCommandGuard {
+    ACTIVE_HEAVY_COMMANDS.fetch_add(1, Ordering::SeqCst);
+    HeavyCommandGuard
+}
+
+/// Wait up to the configured grace period for in-flight heavy commands to finish
+/// Logs how many were still running if the grace period elapses
+pub async fn wait_for_heavy_commands() {
+    let grace_secs = env::var("SHUTDOWN_GRACE_SECS")
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .unwrap_or(DEFAULT_SHUTDOWN_GRACE_SECS);
+    let active = ACTIVE_HEAVY_COMMANDS.load(Ordering::SeqCst);
+    if active == 0 {
+        return;
+    }
+
+    println!("Waiting up to {}s for {} in-flight command(s) to finish...", grace_secs, active);
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(grace_secs);
+    while std::time::Instant::now() < deadline {
+        if ACTIVE_HEAVY_COMMANDS.load(Ordering::SeqCst) == 0 {
+            println!("✅ All in-flight commands finished");
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+    }
+
+    let remaining = ACTIVE_HEAVY_COMMANDS.load(Ordering::SeqCst);
+    if remaining > 0 {
+        log::warn!("Shutdown grace period of {}s elapsed with {} command(s) still running", grace_secs, remaining);
+        println!("⚠️ Grace period elapsed - {} command(s) still running will be cut off", remaining);
+    }
+}
+
 /// Global Discord HTTP client reference for admin commands
 static DISCORD_HTTP: Mutex<Option<std::sync::Arc<serenity::http::Http>>> = Mutex::new(None);
 
 /// Global server blacklist
 static SERVER_BLACKLIST: Mutex<Option<std::collections::HashSet<u64>>> = Mutex::new(None);
 
+/// Global per-guild disabled command names (primary command names, lowercase)
+static DISABLED_COMMANDS: Mutex<Option<HashMap<GuildId, std::collections::HashSet<String>>>> = Mutex::new(None);
+
+/// File the per-guild disabled command lists are persisted to
+const DISABLED_COMMANDS_FILE: &str = "disabled_commands.json";
+
+/// Recently handled message IDs with the time they were first seen (duplicate event debounce)
+static RECENT_MESSAGE_IDS: Mutex<Option<HashMap<u64, std::time::Instant>>> = Mutex::new(None);
+
+/// How long a message ID is remembered for duplicate detection
+const MESSAGE_DEDUP_WINDOW: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Record a message ID and report whether it was already seen within the dedup window
+/// Expired entries are pruned on each call so the cache stays small
+pub fn is_duplicate_message(message_id: u64) -> bool {
+    if let Ok(mut recent_guard) = RECENT_MESSAGE_IDS.lock() {
+        let recent = recent_guard.get_or_insert_with(HashMap::new);
+        let now = std::time::Instant::now();
+        recent.retain(|_, seen_at| now.duration_since(*seen_at) < MESSAGE_DEDUP_WINDOW);
+        if recent.contains_key(&message_id) {
+            return true;
+        }
+        recent.insert(message_id, now);
+    }
+    false
+}
+
+/// Bot replies keyed by the user message that triggered them, so an edited mention regenerates in place
+static MESSAGE_REPLIES: Mutex<Option<HashMap<u64, TrackedReply>>> = Mutex::new(None);
+
+/// How long after the bot answers an edit to the triggering message still regenerates the reply
+const EDIT_REGENERATE_WINDOW: std::time::Duration = std::time::Duration::from_secs(120);
+
+/// Minimum gap between regenerations of the same reply (edit bursts, Discord rate limits)
+const EDIT_REGENERATE_COOLDOWN: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Regenerations allowed per reply before further edits are ignored
+const MAX_EDIT_REGENERATIONS: u32 = 3;
+
+/// When each user last had an answer refined by replying to the bot
+static RECENT_REFINEMENTS: Mutex<Option<HashMap<UserId, std::time::Instant>>> = Mutex::new(None);
+
+/// A guild message recorded for "what did @user talk about" questions
+struct ObservedMessage {
+    guild_id: GuildId,
+    content: String,
+}
+
+/// Recently observed messages per user; its own lock so recording never waits on ctx.data
+static USER_HISTORY: Mutex<Option<HashMap<UserId, Vec<ObservedMessage>>>> = Mutex::new(None);
+
+/// Minimum gap between refinements for one user, so reply chains can't turn into a loop
+const REFINEMENT_COOLDOWN: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Claim a refinement for a user; false while they are still inside REFINEMENT_COOLDOWN
+fn claim_refinement(user_id: UserId) -> bool {
+    let mut recent_guard = match RECENT_REFINEMENTS.lock() {
+        Ok(guard) => guard,
+        Err(_) => return false,
+    };
+    let recent = recent_guard.get_or_insert_with(HashMap::new);
+    let now = std::time::Instant::now();
+    recent.retain(|_, at| now.duration_since(*at) < REFINEMENT_COOLDOWN);
+    if recent.contains_key(&user_id) {
+        return false;
+    }
+    recent.insert(user_id, now);
+    true
+}
+
+/// A bot reply that an edit of its triggering message may regenerate
+struct TrackedReply {
+    reply_id: u64,
+    answered_at: std::time::Instant,
+    last_regenerated: Option<std::time::Instant>,
+    regenerations: u32,
+}
+
+/// Remember the bot's reply to a message; re-recording the same message keeps its original window
+pub fn record_message_reply(message_id: u64, reply_id: u64) {
+    if let Ok(mut replies_guard) = MESSAGE_REPLIES.lock() {
+        let replies = replies_guard.get_or_insert_with(HashMap::new);
+        let now = std::time::Instant::now();
+        replies.retain(|_, reply| now.duration_since(reply.answered_at) < EDIT_REGENERATE_WINDOW);
+        replies.entry(message_id).or_insert(TrackedReply {
+            reply_id,
+            answered_at: now,
+            last_regenerated: None,
+            regenerations: 0,
+        }).reply_id = reply_id;
+    }
+}
+
+/// The bot's earlier reply to a message, if it is still inside the edit window
+pub fn tracked_reply_id(message_id: u64) -> Option<u64> {
+    let replies_guard = MESSAGE_REPLIES.lock().ok()?;
+    replies_guard.as_ref()?
+        .get(&message_id)
+        .filter(|reply| reply.answered_at.elapsed() < EDIT_REGENERATE_WINDOW)
+        .map(|reply| reply.reply_id)
+}
+
+/// Claim a regeneration slot for an edited message
+/// Returns false outside the window, during the cooldown, or once the regeneration cap is reached
+fn claim_edit_regeneration(message_id: u64) -> bool {
+    let mut replies_guard = match MESSAGE_REPLIES.lock() {
+        Ok(guard) => guard,
+        Err(_) => return false,
+    };
+    let reply = match replies_guard.as_mut().and_then(|replies| replies.get_mut(&message_id)) {
+        Some(reply) => reply,
+        None => return false,
+    };
+    let now = std::time::Instant::now();
+    if !can_regenerate(reply, now) {
+        return false;
+    }
+    reply.regenerations += 1;
+    reply.last_regenerated = Some(now);
+    true
+}
+
+/// Window, cooldown and cap checks for regenerating a tracked reply
+fn can_regenerate(reply: &TrackedReply, now: std::time::Instant) -> bool {
+    now.duration_since(reply.answered_at) < EDIT_REGENERATE_WINDOW
+        && reply.regenerations < MAX_EDIT_REGENERATIONS
+        && reply.last_regenerated.map_or(true, |at| now.duration_since(at) >= EDIT_REGENERATE_COOLDOWN)
+}
+
 /// Set the global Discord HTTP client reference
 pub fn set_discord_http(http: std::sync::Arc<serenity::http::Http>) {
     if let Ok(mut http_guard) = DISCORD_HTTP.lock() {
@@ -530,6 +1124,83 @@ pub fn save_server_blacklist() -> Result<(), Box<dyn std::error::Error + Send +
     }
 }
 
+/// Load the per-guild disabled command lists from disabled_commands.json
+/// A missing or unreadable file results in no commands being disabled
+fn load_disabled_commands() -> HashMap<GuildId, std::collections::HashSet<String>> {
+    match fs::read_to_string(DISABLED_COMMANDS_FILE) {
+        Ok(json) => match serde_json::from_str::<HashMap<GuildId, std::collections::HashSet<String>>>(&json) {
+            Ok(disabled) => {
+                println!("Disabled commands loaded from {} for {} server(s)", DISABLED_COMMANDS_FILE, disabled.len());
+                disabled
+            }
+            Err(e) => {
+                eprintln!("⚠️ Failed to parse {}: {} - no commands disabled", DISABLED_COMMANDS_FILE, e);
+                HashMap::new()
+            }
+        },
+        Err(_) => HashMap::new(),
+    }
+}
+
+/// Set the global per-guild disabled command lists
+pub fn set_disabled_commands(disabled: HashMap<GuildId, std::collections::HashSet<String>>) {
+    if let Ok(mut disabled_guard) = DISABLED_COMMANDS.lock() {
+        *disabled_guard = Some(disabled);
+    }
+}
+
+/// Check if a command is disabled in the given guild
+pub fn is_command_disabled(guild_id: GuildId, command_name: &str) -> bool {
+    if let Ok(disabled_guard) = DISABLED_COMMANDS.lock() {
+        if let Some(ref disabled) = *disabled_guard {
+            return disabled.get(&guild_id)
+                .map(|commands| commands.contains(&command_name.to_lowercase()))
+                .unwrap_or(false);
+        }
+    }
+    false
+}
+
+/// Get the sorted list of commands disabled in the given guild
+pub fn disabled_commands_for(guild_id: GuildId) -> Vec<String> {
+    let mut commands: Vec<String> = DISABLED_COMMANDS.lock().ok()
+        .and_then(|guard| guard.as_ref().and_then(|disabled| disabled.get(&guild_id).cloned()))
+        .map(|commands| commands.into_iter().collect())
+        .unwrap_or_default();
+    commands.sort();
+    commands
+}
+
+/// Disable or enable a command in a guild and persist the change to disabled_commands.json
+/// Returns whether the stored state actually changed
+pub fn set_command_disabled(
+    guild_id: GuildId,
+    command_name: &str,
+    disabled: bool,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let json = {
+        let mut disabled_guard = DISABLED_COMMANDS.lock().map_err(|_| "Failed to access disabled commands")?;
+        let all_disabled = disabled_guard.get_or_insert_with(HashMap::new);
+        let commands = all_disabled.entry(guild_id).or_default();
+        let changed = if disabled {
+            commands.insert(command_name.to_lowercase())
+        } else {
+            commands.remove(&command_name.to_lowercase())
+        };
+        if commands.is_empty() {
+            all_disabled.remove(&guild_id);
+        }
+        if !changed {
+            return Ok(false);
+        }
+        serde_json::to_string_pretty(&*all_disabled)?
+    };
+
+    fs::write(DISABLED_COMMANDS_FILE, json)?;
+    println!("✅ Disabled commands saved to {}", DISABLED_COMMANDS_FILE);
+    Ok(true)
+}
+
 // ============================================================================
 // DISCORD EVENT HANDLER
 // ============================================================================
@@ -540,8 +1211,35 @@ struct Handler;
 #[async_trait]
 impl EventHandler for Handler {
     /// Called when the bot successfully connects to Discord
-    async fn ready(&self, _: Context, ready: Ready) {
+    async fn ready(&self, ctx: Context, ready: Ready) {
         println!("Bot connected as {}!", ready.user.name);
+
+        // Presence is lost when the gateway re-identifies, so it is set on every ready event
+        if let Some((kind, name)) = env::var("ACTIVITY").ok().as_deref().and_then(parse_activity) {
+            let activity = match kind {
+                "watching" => Activity::watching(name),
+                "listening" => Activity::listening(name),
+                "competing" => Activity::competing(name),
+                _ => Activity::playing(name),
+            };
+            ctx.set_activity(activity).await;
+            println!("[READY] Activity set: {} {}", kind, name);
+        }
+
+        // Reconnects fire ready again; only the first connection announces itself
+        if BOT_CONNECTED.swap(true, Ordering::SeqCst) {
+            println!("[READY] Reconnected to Discord");
+            return;
+        }
+        if let Some(channel_id) = env::var("READY_CHANNEL").ok().and_then(|value| value.trim().parse::<u64>().ok()) {
+            let version = match option_env!("GIT_COMMIT") {
+                Some(commit) => format!("v{} ({})", env!("CARGO_PKG_VERSION"), truncate_chars(commit, 7)),
+                None => format!("v{}", env!("CARGO_PKG_VERSION")),
+            };
+            if let Err(e) = ChannelId(channel_id).say(&ctx.http, format!("🟢 **Bot online** - {}", version)).await {
+                log::warn!("Could not post the ready message to READY_CHANNEL {}: {}", channel_id, e);
+            }
+        }
         
         // Generate and display invite link
         let bot_user_id = env::var("BOT_USER_ID").unwrap_or_else(|_| "1385309017881968761".to_string());
@@ -559,6 +1257,15 @@ impl EventHandler for Handler {
     /// Handle incoming Discord messages
     /// This is the main message processing logic for the bot
     async fn message(&self, ctx: Context, msg: Message) {
+        // Ignore message events Discord delivered more than once
+        if is_duplicate_message(msg.id.0) {
+            println!("[MAIN] Ignoring duplicate message event for message {}", msg.id);
+            return;
+        }
+        
+        // Remember what users say so ^lm can answer "what did @user talk about"
+        record_observed_message(&msg);
+        
         // Check if this is a user mention (like <@bot_id>)
         let bot_user_id = env::var("BOT_USER_ID").unwrap_or_else(|_| "1385309017881968761".to_string());
         let is_mentioned_by_id = msg.content.contains(&format!("<@{}>", bot_user_id));
@@ -566,7 +1273,12 @@ impl EventHandler for Handler {
         // Check if this message is a reply to a bot message
         if let Some(referenced_message) = &msg.referenced_message {
             if referenced_message.author.id.to_string() == bot_user_id {
-                // This is a reply to a bot message, ignore it
+                // A person addressing the bot in reply to one of its answers refines that answer;
+                // other replies to the bot (including from bots) are ignored so bots can't loop
+                let addresses_bot = is_mentioned_by_id || msg.mentions.iter().any(|user| user.id.to_string() == bot_user_id);
+                if addresses_bot && !msg.author.bot {
+                    handle_user_mention(&ctx, &msg, &bot_user_id).await;
+                }
                 return;
             }
         }
@@ -581,6 +1293,39 @@ impl EventHandler for Handler {
         // We don't need to do anything here for prefix commands
     }
 
+    /// Handle message edits - an edited mention the bot already answered is re-run into the same reply
+    async fn message_update(&self, ctx: Context, _old: Option<Message>, new: Option<Message>, event: MessageUpdateEvent) {
+        // Embed unfurls and pins also arrive as updates; only content edits matter
+        if event.content.is_none() || event.author.as_ref().map_or(false, |author| author.bot) {
+            return;
+        }
+        if tracked_reply_id(event.id.0).is_none() {
+            return;
+        }
+
+        let bot_user_id = env::var("BOT_USER_ID").unwrap_or_else(|_| "1385309017881968761".to_string());
+        let msg = match new {
+            Some(msg) => msg,
+            None => match event.channel_id.message(&ctx.http, event.id).await {
+                Ok(msg) => msg,
+                Err(e) => {
+                    log::warn!("Could not fetch edited message {}: {}", event.id, e);
+                    return;
+                }
+            },
+        };
+        if msg.author.bot || !msg.content.contains(&format!("<@{}>", bot_user_id)) {
+            return;
+        }
+        if !claim_edit_regeneration(msg.id.0) {
+            println!("[MAIN] Ignoring edit of message {} (outside the edit window, cooling down, or regeneration limit reached)", msg.id);
+            return;
+        }
+
+        println!("[MAIN] Mention {} was edited - regenerating the reply", msg.id);
+        handle_user_mention(&ctx, &msg, &bot_user_id).await;
+    }
+
     /// Handle Discord interactions (slash commands, buttons, etc.)
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
         match interaction {
@@ -638,26 +1383,124 @@ impl EventHandler for Handler {
     }
 }
 
+/// Built-in mention prompt templates, overridden by prompts/<name>.txt when present
+const DEFAULT_REPLY_TEMPLATE: &str = "The user is replying to this message:\n{original}\n\n{question}";
+const DEFAULT_DIRECT_TEMPLATE: &str = "{question}";
+const DEFAULT_MENTION_TEMPLATE: &str = "{question}";
+
+/// Vision prompt used when an image arrives with no question attached
+const DEFAULT_VISION_PROMPT: &str = "Describe this image.";
+
+/// Longest mention text still treated as a caption for auto-vision rather than a separate request
+const AUTO_VISION_MAX_PROMPT_CHARS: usize = 200;
+
+/// Whether mentions carrying images go to the vision model without -v (AUTO_VISION in botconfig.txt, default true)
+fn auto_vision_enabled() -> bool {
+    !matches!(
+        env::var("AUTO_VISION").map(|v| v.trim().to_lowercase()).as_deref(),
+        Ok("false") | Ok("0") | Ok("no") | Ok("off")
+    )
+}
+
+/// Whether a mention should be routed to vision: it has an image and at most a short caption
+fn should_auto_vision(prompt: &str, attachments: &[serenity::model::channel::Attachment]) -> bool {
+    let has_images = attachments.iter()
+        .any(|a| a.content_type.as_deref().unwrap_or("").starts_with("image/"));
+    has_images && !prompt.starts_with('-') && prompt.chars().count() <= AUTO_VISION_MAX_PROMPT_CHARS
+}
+
 /// Handle user ID mentions - functions as ^lm command but with global context
 async fn handle_user_mention(ctx: &Context, msg: &Message, bot_user_id: &str) {
     // Log the mention
     log_mention(msg, bot_user_id);
+    
+    // A mention is ^lm by another name, so ^disable lm turns it off too
+    if let Some(guild_id) = msg.guild_id {
+        if is_command_disabled(guild_id, "lm") {
+            println!("🚫 Blocked mention from {} in guild {}: lm is disabled", msg.author.name, guild_id);
+            let _ = msg.reply(ctx, style_output("🚫 `lm` is disabled on this server.")).await;
+            return;
+        }
+    }
             
     // Extract the prompt after removing the user ID mention
-    let prompt = msg.content
+    let mut prompt = msg.content
         .replace(&format!("<@{}>", bot_user_id), "")
         .trim()
         .to_string();
     
+    // Mentions bypass the framework hooks, so run the moderation pre-check here too
+    if let Some(pattern) = moderation_match(&prompt) {
+        log::warn!("Moderation blocked mention from {} ({}) in channel {}: matched /{}/",
+                   msg.author.name, msg.author.id, msg.channel_id, pattern);
+        let _ = msg.reply(ctx, style_output(MODERATION_REFUSAL)).await;
+        return;
+    }
+    // ...and the MAX_PROMPT_CHARS limit, before the text is wrapped in a template or routed anywhere
+    if !matches!(crate::commands::util::reply_if_prompt_too_long(ctx, msg, &prompt, "lm").await, Ok(false)) {
+        return;
+    }
+    
+    // An image with no or only a short caption is almost always "what's in this image?"
+    if auto_vision_enabled() && should_auto_vision(&prompt, &msg.attachments) {
+        println!("[MAIN] Mention has image attachments - routing to vision (AUTO_VISION)");
+        handle_mention_vision(ctx, msg, &prompt, &msg.attachments).await;
+        return;
+    }
+
+    // Replies carry the referenced message as context; image attachments go straight to vision
+    let is_flag_command = prompt.starts_with('-');
+    if let Some(referenced) = msg.referenced_message.as_deref() {
+        // A reply to one of the bot's own answers is a follow-up on that answer, not a question about a message
+        if referenced.author.id.to_string() == bot_user_id && !is_flag_command {
+            handle_refinement_reply(ctx, msg, referenced, &prompt).await;
+            return;
+        }
+        let has_images = referenced.attachments.iter()
+            .any(|a| a.content_type.as_deref().unwrap_or("").starts_with("image/"));
+        if has_images && !is_flag_command {
+            println!("[MAIN] Mention replies to a message with images - routing to vision");
+            handle_mention_vision(ctx, msg, &prompt, &referenced.attachments).await;
+            return;
+        }
+        if !is_flag_command {
+            let template = load_prompt_template("reply", DEFAULT_REPLY_TEMPLATE);
+            let original = describe_referenced_message(referenced);
+            let question = if prompt.is_empty() { "What can you tell me about it?" } else { prompt.as_str() };
+            prompt = render_prompt_template(&template, &[
+                ("user", msg.author.name.as_str()),
+                ("original", original.as_str()),
+                ("question", question),
+            ]);
+        }
+    } else if !is_flag_command && !prompt.is_empty() {
+        // DMs and channel mentions get their own operator-editable templates
+        let (name, default) = if msg.guild_id.is_none() {
+            ("direct", DEFAULT_DIRECT_TEMPLATE)
+        } else {
+            ("mention", DEFAULT_MENTION_TEMPLATE)
+        };
+        let template = load_prompt_template(name, default);
+        prompt = render_prompt_template(&template, &[
+            ("user", msg.author.name.as_str()),
+            ("original", ""),
+            ("question", prompt.as_str()),
+        ]);
+    }
+    
     // Check for special flags that need to be handled by the regular lm command
     if prompt.starts_with("-s ") || prompt.starts_with("--search ") || 
+       prompt.starts_with("-v ") || prompt.starts_with("--vision ") ||
        prompt.starts_with("--test") || prompt == "-t" ||
        prompt.starts_with("--clear") || prompt == "-c" ||
        prompt.starts_with("--clear-global") || prompt == "-cg" {
-        // For search, test, and clear commands, use the regular lm command
-        // These don't need global context
+        // For search, vision, test, and clear commands, use the regular lm command
+        // These don't need global context, and vision applies the attachment limits
         let args = Args::new(&prompt, &[Delimiter::Single(' ')]);
-        if let Err(e) = crate::commands::lm::lm(ctx, msg, args).await {
+        let result = crate::commands::lm::lm(ctx, msg, args).await;
+        // Mentions bypass the framework's after hook, so release the invocation's id here
+        crate::commands::util::finish_invocation(msg.id.0);
+        if let Err(e) = result {
             log_error("User mention request failed", &e);
             let _ = msg.reply(ctx, format!("LM error: {}", e)).await;
         } else {
@@ -674,6 +1517,76 @@ async fn handle_user_mention(ctx: &Context, msg: &Message, bot_user_id: &str) {
     }
 }
 
+/// Answer a mention about images with the vision model
+/// The ^lm/^vis commands take a backend slot themselves; this path calls vision directly, so it takes one here
+async fn handle_mention_vision(ctx: &Context, msg: &Message, prompt: &str, attachments: &[serenity::model::channel::Attachment]) {
+    let _backend_permit = match crate::commands::util::acquire_backend_permit_or_reply(ctx, msg, "vis").await {
+        Ok(Some(permit)) => permit,
+        _ => return,
+    };
+    let vision_prompt = if prompt.is_empty() { DEFAULT_VISION_PROMPT } else { prompt };
+    if let Err(e) = crate::commands::vis::handle_vision_request(ctx, msg, vision_prompt, attachments).await {
+        log_error("Vision mention request failed", &e);
+        let _ = msg.reply(ctx, format!("Vision error: {}", e)).await;
+    }
+}
+
+/// Refine a bot answer the user replied to, e.g. "make it shorter"
+async fn handle_refinement_reply(ctx: &Context, msg: &Message, referenced: &Message, refinement: &str) {
+    if refinement.is_empty() {
+        let _ = msg.reply(ctx, "ℹ️ Reply to one of my answers with what to change, e.g. \"make it shorter\".").await;
+        return;
+    }
+    if !claim_refinement(msg.author.id) {
+        println!("[MAIN] Ignoring refinement from {} - within the {}s cooldown", msg.author.name, REFINEMENT_COOLDOWN.as_secs());
+        return;
+    }
+    let previous_answer = crate::commands::lm::extract_bot_answer(&referenced.content);
+    if previous_answer.is_empty() {
+        let _ = msg.reply(ctx, "ℹ️ That message has no text answer to refine.").await;
+        return;
+    }
+    println!("[MAIN] {} is refining bot message {}", msg.author.name, referenced.id);
+    if let Err(e) = crate::commands::lm::handle_refinement_request(ctx, msg, &previous_answer, refinement).await {
+        log_error("Refinement request failed", &e);
+        let _ = msg.reply(ctx, format!("LM error: {}", e)).await;
+    } else {
+        log_success("Refinement request completed successfully");
+    }
+}
+
+/// Describe a replied-to message for use as prompt context
+/// Embed- and attachment-only messages have empty content, so embed titles/descriptions
+/// and attachment filenames/URLs are included to give the model something to work with
+fn describe_referenced_message(referenced: &Message) -> String {
+    let mut parts = vec![format!("Author: {}", referenced.author.name)];
+    if !referenced.content.trim().is_empty() {
+        parts.push(format!("Content: \"{}\"", referenced.content.trim()));
+    }
+    for embed in &referenced.embeds {
+        let mut embed_parts = Vec::new();
+        if let Some(title) = &embed.title {
+            embed_parts.push(format!("title \"{}\"", title));
+        }
+        if let Some(description) = &embed.description {
+            embed_parts.push(format!("description \"{}\"", truncate_chars(description, 1000)));
+        }
+        if let Some(url) = &embed.url {
+            embed_parts.push(format!("url {}", url));
+        }
+        if !embed_parts.is_empty() {
+            parts.push(format!("Embed: {}", embed_parts.join(", ")));
+        }
+    }
+    for attachment in &referenced.attachments {
+        parts.push(format!("Attachment: {} ({})", attachment.filename, attachment.url));
+    }
+    if parts.len() == 1 {
+        parts.push("(The message has no text, embeds, or attachments)".to_string());
+    }
+    parts.join("\n")
+}
+
 // ============================================================================
 // LOGGING HELPERS
 // ============================================================================
@@ -800,6 +1713,9 @@ async fn main() {
     let server_blacklist = load_server_blacklist();
     set_server_blacklist(server_blacklist);
     
+    // Load per-server disabled commands
+    set_disabled_commands(load_disabled_commands());
+    
     // Get and validate Discord token from configuration
     let token = match validate_discord_token() {
         Ok(token) => token,
@@ -830,7 +1746,7 @@ async fn main() {
     
     // Log connection attempt details before creating client
     log::info!("Bot token length: {} characters", token.len());
-    log::info!("Bot token starts with: {}", &token[..std::cmp::min(10, token.len())]);
+    log::info!("Bot token starts with: {}", truncate_chars(&token, 10));
     log::info!("Command prefix: '{}'", prefix);
     log::info!("Gateway intents configured: MESSAGE_CONTENT, non_privileged");
 
@@ -862,6 +1778,15 @@ async fn main() {
     initialize_bot_data(&mut client).await;
     log::info!("Bot data initialized successfully");
 
+    // One-time yt-dlp check so a missing binary shows up in the log, not on the first ^sum
+    match crate::commands::util::check_yt_dlp() {
+        Ok(version) => log::info!("yt-dlp {} available for YouTube summaries", version),
+        Err(e) => {
+            log::warn!("YouTube support in ^sum and ^rank is unavailable: {}", e);
+            println!("⚠️  {}", e);
+        }
+    }
+
     // Register slash commands with Discord
     log::info!("Registering slash commands with Discord");
     if let Err(e) = register_slash_commands(&client.cache_and_http.http).await {
@@ -924,43 +1849,151 @@ async fn main() {
 
 /// Create the command framework
 fn create_command_framework(prefix: &str) -> StandardFramework {
-    StandardFramework::new()
+    let framework = StandardFramework::new()
         .configure(|c| {
             c.prefix(prefix)           // Set command prefix
             .case_insensitivity(true)   // Commands are case-insensitive
             .no_dm_prefix(true)         // No prefix needed in DMs
             .with_whitespace(true)      // Allow whitespace in commands
         })
-        .after(|_ctx, msg, command_name, result| Box::pin(async move {
+        .before(|ctx, msg, command_name| Box::pin(async move {
+            // Abort commands that have been disabled for this server with ^disable
+            if let Some(guild_id) = msg.guild_id {
+                if is_command_disabled(guild_id, command_name) {
+                    println!("🚫 Blocked disabled command '{}' from {} in guild {}", command_name, msg.author.name, guild_id);
+                    let _ = msg.reply(ctx, style_output(format!("🚫 `{}` is disabled on this server.", command_name))).await;
+                    return false;
+                }
+            }
+            // Opt-in moderation pre-check against the moderation_patterns.txt denylist
+            if let Some(pattern) = moderation_match(&msg.content) {
+                log::warn!("Moderation blocked '{}' from {} ({}) in channel {}: matched /{}/",
+                           command_name, msg.author.name, msg.author.id, msg.channel_id, pattern);
+                let _ = msg.reply(ctx, style_output(MODERATION_REFUSAL)).await;
+                return false;
+            }
+            true
+        }))
+        .after(|ctx, msg, command_name, result| Box::pin(async move {
+            // The same id the command body tagged its log lines with
+            let request_id = crate::commands::util::finish_invocation(msg.id.0);
             // Post-command execution logging
             match result {
                 Ok(()) => {
                     // Command executed successfully
                 },
                 Err(e) => {
-                    log::error!("Command '{}' failed for user {} ({}): {:?}", 
-                               command_name, msg.author.name, msg.author.id, e);
+                    // One id ties together the command's log lines, the user's reply and the ERROR_LOG_CHANNEL notice
+                    let error_id = request_id;
+                    log::error!("Command '{}' failed for user {} ({}) [error id {}]: {:?}", 
+                               command_name, msg.author.name, msg.author.id, error_id, e);
+                    let _ = msg.reply(ctx, style_output(format!(
+                        "❌ Sorry, `^{}` ran into a problem. It has been logged for the maintainers.\n*error id: `{}`*",
+                        command_name, error_id
+                    ))).await;
+                    report_command_error(ctx, msg, command_name, &error_id, &e.to_string()).await;
                 }
             }
         }))
-        .unrecognised_command(|_ctx, _msg, _unrecognized_command_name| Box::pin(async move {
-            // Handle unrecognized commands (currently silent)
-        }))
-        // Explicitly register all commands
-        .group(&crate::commands::ping::PING_GROUP)
-        .group(&crate::commands::echo::ECHO_GROUP)
-        .group(&crate::commands::lm::LM_GROUP)
-        .group(&crate::commands::reason::REASON_GROUP)
-        .group(&crate::commands::agent::AGENT_GROUP)
-        .group(&crate::commands::sum::SUM_GROUP)
-        .group(&crate::commands::rank::RANK_GROUP)
-        .group(&crate::commands::help::HELP_GROUP)
-        .group(&crate::commands::admin::ADMIN_GROUP)
+        .unrecognised_command(|ctx, msg, unrecognized_command_name| Box::pin(async move {
+            // Suggest the closest command for likely typos; anything else stays silent
+            let prefix = env::var("PREFIX").unwrap_or_else(|_| "^".to_string());
+            // no_dm_prefix treats every DM as a command, so only typed-out commands get a suggestion there
+            if msg.guild_id.is_none() && !msg.content.starts_with(&prefix) {
+                return;
+            }
+            if let Some(suggestion) = suggest_command(unrecognized_command_name) {
+                let _ = msg.reply(ctx, style_output(format!(
+                    "❓ Unknown command `{}`. Did you mean `{}{}`?", unrecognized_command_name, prefix, suggestion
+                ))).await;
+            }
+        }));
+
+    // Explicitly register all commands
+    COMMAND_GROUPS.iter().fold(framework, |framework, group| framework.group(group))
+}
+
+/// All prefix command groups registered with the framework
+static COMMAND_GROUPS: &[&CommandGroup] = &[
+    &crate::commands::ping::PING_GROUP,
+    &crate::commands::echo::ECHO_GROUP,
+    &crate::commands::count::COUNT_GROUP,
+    &crate::commands::lm::LM_GROUP,
+    &crate::commands::reason::REASON_GROUP,
+    &crate::commands::agent::AGENT_GROUP,
+    &crate::commands::sum::SUM_GROUP,
+    &crate::commands::translate::TRANSLATE_GROUP,
+    &crate::commands::quote::QUOTE_GROUP,
+    &crate::commands::rank::RANK_GROUP,
+    &crate::commands::context::CONTEXTCMD_GROUP,
+    &crate::commands::help::HELP_GROUP,
+    &crate::commands::admin::ADMIN_GROUP,
+];
+
+/// Resolve a command name or alias to the primary command name the framework reports
+pub fn resolve_command_name(name: &str) -> Option<&'static str> {
+    let name = name.trim().trim_start_matches('^').to_lowercase();
+    COMMAND_GROUPS.iter()
+        .flat_map(|group| group.options.commands.iter())
+        .find(|command| command.options.names.iter().any(|n| n.eq_ignore_ascii_case(&name)))
+        .map(|command| command.options.names[0])
+}
+
+/// Longest error summary posted to ERROR_LOG_CHANNEL
+const ERROR_NOTICE_MAX_CHARS: usize = 300;
+
+/// Post a failure notice to ERROR_LOG_CHANNEL, if one is configured
+async fn report_command_error(ctx: &Context, msg: &Message, command_name: &str, error_id: &str, error: &str) {
+    let channel_id = match env::var("ERROR_LOG_CHANNEL").ok().and_then(|value| value.trim().parse::<u64>().ok()) {
+        Some(channel_id) => channel_id,
+        None => return,
+    };
+    let notice = format_error_notice(command_name, msg.author.id.0, msg.channel_id.0, error_id, error);
+    // The notice names the user without pinging them
+    let sent = ChannelId(channel_id).send_message(&ctx.http, |m| {
+        m.content(notice).allowed_mentions(|mentions| mentions.empty_parse())
+    }).await;
+    if let Err(e) = sent {
+        log::warn!("Could not post error {} to ERROR_LOG_CHANNEL {}: {}", error_id, channel_id, e);
+    }
+}
+
+/// Concise failure notice for maintainers: ids and the first line of the error, never the user's message
+fn format_error_notice(command_name: &str, user_id: u64, channel_id: u64, error_id: &str, error: &str) -> String {
+    let summary = error.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or("(no error message)");
+    let summary = if summary.chars().count() > ERROR_NOTICE_MAX_CHARS {
+        format!("{}…", truncate_chars(summary, ERROR_NOTICE_MAX_CHARS))
+    } else {
+        summary.to_string()
+    };
+    format!(
+        "⚠️ **Command failed** `^{}`\n• User: <@{}>\n• Channel: <#{}>\n• Error id: `{}`\n• Error: {}",
+        command_name, user_id, channel_id, error_id, summary.replace('`', "'")
+    )
+}
+
+/// Closest registered command name (or alias) to an unknown one, if it is plausibly a typo
+/// Only short edit distances count, so ordinary text after the prefix (e.g. "^^" or "^_^") is ignored
+fn suggest_command(name: &str) -> Option<&'static str> {
+    let name = name.trim().to_lowercase();
+    if name.len() < 2 || !name.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    let max_distance = if name.len() <= 4 { 1 } else { 2 };
+    COMMAND_GROUPS.iter()
+        .flat_map(|group| group.options.commands.iter())
+        .flat_map(|command| command.options.names.iter().copied())
+        .map(|candidate| (levenshtein(&name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
 }
 
 /// Initialize bot data structures
 async fn initialize_bot_data(client: &mut Client) {
+        let shard_manager = client.shard_manager.clone();
         let mut data = client.data.write().await;
+        data.insert::<ShardManagerContainer>(shard_manager);
         
         // Load existing contexts from disk
         match load_contexts_from_disk().await {
@@ -979,7 +2012,6 @@ async fn initialize_bot_data(client: &mut Client) {
             }
         }
         
-        data.insert::<UserConversationHistoryMap>(HashMap::new());
 }
 
 
@@ -1021,7 +2053,7 @@ async fn check_and_update_restart_messages(client: &Client) {
                 // Try to update the restart message
                 if let Ok(mut message) = channel_id.message(&client.cache_and_http.http, message_id).await {
                     if let Err(e) = message.edit(&client.cache_and_http.http, |m| {
-                        m.content("✅ **Bot Successfully Restarted!**\n\nThe bot has been restarted and is now back online.\n\n🟢 **Status**: Connected and ready")
+                        m.content(style_output("✅ **Bot Successfully Restarted!**\n\nThe bot has been restarted and is now back online.\n\n🟢 **Status**: Connected and ready"))
                     }).await {
                         eprintln!("[RESTART] Failed to update restart message: {}", e);
                     } else {
@@ -1044,6 +2076,9 @@ async fn check_and_update_restart_messages(client: &Client) {
 async fn cleanup_and_shutdown(client: &Client) {
     println!("Shutting down bot...");
     
+    // Let running ^agent/^sum/^reason commands finish before contexts are saved
+    wait_for_heavy_commands().await;
+    
     // Save contexts to disk before shutting down
     // This preserves conversation history across bot restarts
     {
@@ -1066,3 +2101,159 @@ async fn cleanup_and_shutdown(client: &Client) {
     
     println!("Shutdown complete. Goodbye!");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_error_notice() {
+        let notice = format_error_notice("sum", 42, 7, "abcd1234", "\nyt-dlp failed: `exit 1`\nstderr dump");
+        assert!(notice.contains("`^sum`"));
+        assert!(notice.contains("<@42>") && notice.contains("<#7>") && notice.contains("`abcd1234`"));
+        assert!(notice.ends_with("Error: yt-dlp failed: 'exit 1'"));
+
+        let long = "x".repeat(ERROR_NOTICE_MAX_CHARS + 50);
+        assert!(format_error_notice("lm", 1, 2, "id", &long).ends_with('…'));
+    }
+
+    #[test]
+    fn test_suggest_command_only_for_close_typos() {
+        assert_eq!(suggest_command("reaso"), Some("reason"));
+        assert_eq!(suggest_command("REASN"), Some("reason"));
+        assert_eq!(suggest_command("sumarize"), Some("summarize"));
+        assert_eq!(suggest_command("_^"), None);
+        assert_eq!(suggest_command("completelyrandom"), None);
+    }
+
+    #[test]
+    fn test_conversation_messages_follow_insertion_order() {
+        let message = |role: &str, content: &str| ChatMessage { role: role.to_string(), content: content.to_string() };
+        let mut context = UserContext::new();
+        // Untracked history from an older file, followed by a turn whose reply failed and two newer turns
+        context.user_messages.push(message("user", "old q"));
+        context.assistant_messages.push(message("assistant", "old a"));
+        context.add_user_message(message("user", "q1"));
+        context.add_user_message(message("user", "q2"));
+        context.add_assistant_message(message("assistant", "a2"));
+        context.add_user_message(message("user", "q3"));
+        context.add_assistant_message(message("assistant", "a3"));
+
+        let order: Vec<String> = context.get_conversation_messages().into_iter().map(|m| m.content).collect();
+        assert_eq!(order, ["old q", "old a", "q1", "q2", "a2", "q3", "a3"]);
+
+        // Trimming the oldest messages keeps the remaining order intact
+        context.user_messages.drain(0..2);
+        context.trim_sequences();
+        let order: Vec<String> = context.get_conversation_messages().into_iter().map(|m| m.content).collect();
+        assert_eq!(order, ["old a", "q2", "a2", "q3", "a3"]);
+    }
+
+    #[test]
+    fn test_continue_extends_newest_reply() {
+        let message = |role: &str, content: &str| ChatMessage { role: role.to_string(), content: content.to_string() };
+        let mut context = UserContext::new();
+        assert!(!context.can_continue());
+        context.add_user_message(message("user", "write a story"));
+        assert!(!context.can_continue());
+        context.add_assistant_message(message("assistant", "Once upon"));
+        assert!(context.can_continue());
+
+        context.extend_last_assistant_message(" a time.");
+        let order: Vec<String> = context.get_conversation_messages().into_iter().map(|m| m.content).collect();
+        assert_eq!(order, ["write a story", "Once upon a time."]);
+
+        context.add_user_message(message("user", "another"));
+        assert!(!context.can_continue());
+    }
+
+    #[test]
+    fn test_parse_context_map_migrates_legacy_shapes() {
+        // Pre-versioning file: current fields, no version
+        let unversioned = r#"{"42": {"user_messages": [{"role": "user", "content": "hi"}], "assistant_messages": [],
+            "last_updated": "2024-01-01T00:00:00Z", "total_interactions": 1}}"#;
+        let contexts = parse_context_map(unversioned, "test").unwrap();
+        assert_eq!(contexts[&UserId(42)].version, CONTEXT_FORMAT_VERSION);
+        assert_eq!(contexts[&UserId(42)].user_messages.len(), 1);
+
+        // Legacy shape: a single mixed message list, a malformed message and a missing timestamp
+        let legacy = r#"{"7": {"messages": [{"role": "user", "content": "q"}, {"role": "assistant", "content": "a"}, {"content": "bad"}]},
+            "8": "garbage"}"#;
+        let contexts = parse_context_map(legacy, "test").unwrap();
+        assert_eq!(contexts.len(), 1);
+        let context = &contexts[&UserId(7)];
+        assert_eq!(context.user_messages[0].content, "q");
+        assert_eq!(context.assistant_messages[0].content, "a");
+        assert_eq!(context.total_interactions, 1);
+        assert_eq!(context.version, CONTEXT_FORMAT_VERSION);
+    }
+
+    #[test]
+    fn test_compaction_pairs_messages_by_insertion() {
+        let message = |role: &str, content: &str| ChatMessage { role: role.to_string(), content: content.to_string() };
+        // v2 file: an old unnumbered exchange, then a turn whose reply failed and a full turn
+        let v2 = r#"{"version": 2, "user_messages": [{"role": "user", "content": "old q"}], "assistant_messages": [{"role": "assistant", "content": "old a"}],
+            "last_updated": "2024-01-01T00:00:00Z", "total_interactions": 1}"#;
+        let mut context = parse_context(v2, "test").unwrap();
+        assert_eq!(context.version, CONTEXT_FORMAT_VERSION);
+        assert_eq!((context.user_sequence.clone(), context.assistant_sequence.clone()), (vec![1], vec![2]));
+        context.add_user_message(message("user", "q1"));
+        context.add_user_message(message("user", "q2"));
+        context.add_assistant_message(message("assistant", "a2"));
+        context.add_user_message(message("user", "q3"));
+
+        // The window ends on the newest reply, so the unanswered q3 stays
+        let candidates = context.compaction_candidates(0).unwrap();
+        let contents: Vec<&str> = candidates.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, ["old q", "old a", "q1", "q2", "a2"]);
+
+        assert!(context.apply_compaction(&candidates, "summary".to_string()));
+        let order: Vec<String> = context.get_conversation_messages().into_iter().map(|m| m.content).collect();
+        assert_eq!(order, ["Summary of earlier conversation with this user:\nsummary", "q3"]);
+        assert!(!context.apply_compaction(&candidates, "again".to_string()));
+    }
+
+    #[test]
+    fn test_can_regenerate_enforces_window_cooldown_and_cap() {
+        let now = std::time::Instant::now();
+        let mut reply = TrackedReply { reply_id: 1, answered_at: now, last_regenerated: None, regenerations: 0 };
+        assert!(can_regenerate(&reply, now));
+
+        reply.last_regenerated = Some(now);
+        reply.regenerations = 1;
+        assert!(!can_regenerate(&reply, now + std::time::Duration::from_secs(1)));
+        assert!(can_regenerate(&reply, now + EDIT_REGENERATE_COOLDOWN));
+
+        reply.regenerations = MAX_EDIT_REGENERATIONS;
+        assert!(!can_regenerate(&reply, now + EDIT_REGENERATE_COOLDOWN));
+
+        reply.regenerations = 0;
+        assert!(!can_regenerate(&reply, now + EDIT_REGENERATE_WINDOW));
+    }
+
+    #[test]
+    fn test_claim_refinement_enforces_cooldown() {
+        let user = UserId(4242);
+        assert!(claim_refinement(user));
+        assert!(!claim_refinement(user));
+        assert!(claim_refinement(UserId(4243)));
+    }
+
+    #[test]
+    fn test_parse_activity() {
+        assert_eq!(parse_activity("watching the logs"), Some(("watching", "the logs")));
+        assert_eq!(parse_activity("listening to lo-fi"), Some(("listening", "lo-fi")));
+        assert_eq!(parse_activity("^help for commands"), Some(("playing", "^help for commands")));
+        assert_eq!(parse_activity("playingcards"), Some(("playing", "playingcards")));
+        assert_eq!(parse_activity("  "), None);
+    }
+
+    #[test]
+    fn test_parse_bot_config_strips_bom_and_comments() {
+        let content = "\u{feff}# Discord settings\nDISCORD_TOKEN = abc=def\n\n  # PREFIX=!\nPREFIX=^\nnot a setting\n";
+        let config = parse_bot_config(content);
+        assert_eq!(config.len(), 2);
+        assert_eq!(config.get("DISCORD_TOKEN").map(String::as_str), Some("abc=def"));
+        assert_eq!(config.get("PREFIX").map(String::as_str), Some("^"));
+    }
+}